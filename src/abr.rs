@@ -0,0 +1,344 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{asset::Variant, options::HlsBitrate};
+
+/// Share of the measured throughput a variant's bitrate may take, as the estimate is rough.
+const HEADROOM: f64 = 0.8;
+/// Reading time a throughput sample covers at least.
+const SAMPLE_TIME: Duration = Duration::from_millis(500);
+/// Weight of a new sample in the estimate.
+const SMOOTHING: f64 = 0.3;
+/// Time after a switch before switching up again, or down.
+const UP_SWITCH_DELAY: Duration = Duration::from_secs(10);
+const DOWN_SWITCH_DELAY: Duration = Duration::from_secs(2);
+
+/// The variant `policy` starts on: the lowest for `Auto`, to be switched up from once the
+/// throughput is known. `None` without variants.
+pub fn pick(variants: &[Variant], policy: HlsBitrate) -> Option<&Variant> {
+    match policy {
+        HlsBitrate::Auto | HlsBitrate::Worst => variants.first(),
+        HlsBitrate::Best => variants.last(),
+        HlsBitrate::Pinned(bitrate) => variants
+            .iter()
+            .rev()
+            .find(|variant| variant.bitrate <= bitrate)
+            .or_else(|| variants.first()),
+    }
+}
+
+/// Picks the variant of an adaptive HLS or DASH stream to play from the download
+/// throughput, measured as the time spent waiting on the demuxer for the bytes it read.
+///
+/// A switch takes over at the new variant's first keyframe, the old one playing on until
+/// then; the demuxer only stops downloading it at the end of its segment. The new variant's
+/// audio takes over along with its video.
+pub struct Abr {
+    /// From lowest to highest bitrate.
+    variants: Vec<Variant>,
+    policy: HlsBitrate,
+    playing: usize,
+    /// The audio stream played, kept on through variants without audio of their own.
+    audio: Option<usize>,
+    /// The audio stream the player was set up with, which every variant's audio goes out as.
+    output_audio: Option<usize>,
+    /// Switched to, but without a keyframe yet.
+    pending: Option<usize>,
+    /// Bits per second.
+    estimate: Option<f64>,
+    sample_bytes: usize,
+    sample_time: Duration,
+    last_switch: Option<Instant>,
+    shared: Arc<Mutex<Option<Variant>>>,
+}
+
+impl Abr {
+    /// Starts on `playing`, the variant the input was opened with. `None` without variants to
+    /// choose from.
+    pub fn new(variants: Vec<Variant>, playing: usize, policy: HlsBitrate) -> Option<Self> {
+        let playing = variants
+            .iter()
+            .position(|variant| variant.stream_index == playing)?;
+        let shared = Arc::new(Mutex::new(Some(variants[playing])));
+        let audio = variants[playing].audio_stream_index;
+        Some(Abr {
+            variants,
+            policy,
+            playing,
+            audio,
+            output_audio: audio,
+            pending: None,
+            estimate: None,
+            sample_bytes: 0,
+            sample_time: Duration::default(),
+            last_switch: None,
+            shared,
+        })
+    }
+
+    /// The variant playing, for the main loop to show.
+    pub fn playing(&self) -> Arc<Mutex<Option<Variant>>> {
+        Arc::clone(&self.shared)
+    }
+
+    pub fn is_variant(&self, index: usize) -> bool {
+        self.variants
+            .iter()
+            .any(|variant| variant.stream_index == index)
+    }
+
+    /// Whether stream `index` is the audio of one of the variants.
+    pub fn is_variant_audio(&self, index: usize) -> bool {
+        self.variants
+            .iter()
+            .any(|variant| variant.audio_stream_index == Some(index))
+    }
+
+    /// The stream a packet of stream `index` goes out as: the variants' audio streams all
+    /// go to the decoder set up for the first one's, the others stay as they are.
+    pub fn output_index(&self, index: usize) -> usize {
+        match self.output_audio {
+            Some(audio) if self.is_variant_audio(index) => audio,
+            _ => index,
+        }
+    }
+
+    /// Counts `bytes` read while waiting `elapsed` on the demuxer, returning the variant to
+    /// switch to when the estimate calls for another one.
+    pub fn measure(&mut self, bytes: usize, elapsed: Duration) -> Option<Variant> {
+        self.sample_bytes += bytes;
+        self.sample_time += elapsed;
+        if self.sample_time < SAMPLE_TIME {
+            return None;
+        }
+
+        let sample = self.sample_bytes as f64 * 8_f64 / self.sample_time.as_secs_f64();
+        let estimate = match self.estimate {
+            Some(estimate) => estimate + SMOOTHING * (sample - estimate),
+            None => sample,
+        };
+        self.estimate = Some(estimate);
+        self.sample_bytes = 0;
+        self.sample_time = Duration::default();
+
+        if self.policy != HlsBitrate::Auto || self.pending.is_some() {
+            return None;
+        }
+        let target = self
+            .variants
+            .iter()
+            .rposition(|variant| variant.bitrate as f64 <= estimate * HEADROOM)
+            .unwrap_or(0);
+        let since = self
+            .last_switch
+            .map_or(Duration::MAX, |last| last.elapsed());
+        let switch = (target > self.playing && since >= UP_SWITCH_DELAY)
+            || (target < self.playing && since >= DOWN_SWITCH_DELAY);
+        if !switch {
+            return None;
+        }
+
+        info!(
+            "switching to the {} bps variant at {:.0} bps measured",
+            self.variants[target].bitrate, estimate
+        );
+        self.pending = Some(target);
+        self.last_switch = Some(Instant::now());
+        Some(self.variants[target])
+    }
+
+    /// Whether a packet of stream `index` is played: the variant switched to takes over,
+    /// audio included, at its first keyframe, and other variants' packets are dropped.
+    pub fn accept(&mut self, index: usize, key: bool) -> bool {
+        if let Some(pending) = self.pending {
+            if self.variants[pending].stream_index == index {
+                if !key {
+                    return false;
+                }
+                self.playing = pending;
+                self.pending = None;
+                if let Some(audio) = self.variants[pending].audio_stream_index {
+                    self.audio = Some(audio);
+                }
+                *self.shared.lock().unwrap() = Some(self.variants[pending]);
+                return true;
+            }
+        }
+        if self.is_variant(index) {
+            self.variants[self.playing].stream_index == index
+        } else if self.is_variant_audio(index) {
+            self.audio == Some(index)
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOW: Variant = variant(0, 1, 1_000_000);
+    const MIDDLE: Variant = variant(2, 3, 2_000_000);
+    const HIGH: Variant = variant(4, 5, 4_000_000);
+    /// A stream of none of the variants, e.g. subtitles.
+    const OTHER: usize = 7;
+
+    const fn variant(stream_index: usize, audio: usize, bitrate: u64) -> Variant {
+        Variant {
+            stream_index,
+            audio_stream_index: Some(audio),
+            bitrate,
+            width: 0,
+            height: 0,
+        }
+    }
+
+    fn starting_on(playing: &Variant, policy: HlsBitrate) -> Abr {
+        Abr::new(vec![LOW, MIDDLE, HIGH], playing.stream_index, policy).unwrap()
+    }
+
+    /// Measures a throughput of `bitrate` bits per second over a whole sample.
+    fn measure(abr: &mut Abr, bitrate: u64) -> Option<Variant> {
+        let bytes = (bitrate as f64 / 8_f64 * SAMPLE_TIME.as_secs_f64()) as usize;
+        abr.measure(bytes, SAMPLE_TIME)
+    }
+
+    /// Pretends the last switch was `ago`.
+    fn switched(abr: &mut Abr, ago: Duration) {
+        abr.last_switch = Some(Instant::now().checked_sub(ago).unwrap());
+    }
+
+    #[test]
+    fn picks_the_variant_to_start_on() {
+        let variants = [LOW, MIDDLE, HIGH];
+        assert_eq!(pick(&variants, HlsBitrate::Auto), Some(&LOW));
+        assert_eq!(pick(&variants, HlsBitrate::Worst), Some(&LOW));
+        assert_eq!(pick(&variants, HlsBitrate::Best), Some(&HIGH));
+        assert_eq!(
+            pick(&variants, HlsBitrate::Pinned(3_000_000)),
+            Some(&MIDDLE)
+        );
+        assert_eq!(
+            pick(&variants, HlsBitrate::Pinned(2_000_000)),
+            Some(&MIDDLE)
+        );
+        assert_eq!(pick(&variants, HlsBitrate::Pinned(500_000)), Some(&LOW));
+        assert_eq!(pick(&[], HlsBitrate::Best), None);
+    }
+
+    #[test]
+    fn starts_on_a_variant() {
+        assert!(Abr::new(vec![LOW, MIDDLE, HIGH], OTHER, HlsBitrate::Auto).is_none());
+        assert_eq!(
+            *starting_on(&MIDDLE, HlsBitrate::Auto)
+                .playing()
+                .lock()
+                .unwrap(),
+            Some(MIDDLE)
+        );
+    }
+
+    #[test]
+    fn waits_for_a_whole_sample() {
+        let mut abr = starting_on(&LOW, HlsBitrate::Auto);
+        assert_eq!(abr.measure(1_000_000, SAMPLE_TIME / 2), None);
+        assert_eq!(abr.estimate, None);
+        assert_eq!(abr.measure(1_000_000, SAMPLE_TIME / 2), Some(HIGH));
+    }
+
+    #[test]
+    fn leaves_headroom() {
+        let mut abr = starting_on(&LOW, HlsBitrate::Auto);
+        // the middle variant would take all of it
+        assert_eq!(measure(&mut abr, 2_000_000), None);
+
+        let mut abr = starting_on(&LOW, HlsBitrate::Auto);
+        assert_eq!(measure(&mut abr, 2_500_000), Some(MIDDLE));
+    }
+
+    #[test]
+    fn switches_at_the_next_keyframe() {
+        let mut abr = starting_on(&LOW, HlsBitrate::Auto);
+        assert_eq!(measure(&mut abr, 10_000_000), Some(HIGH));
+        // nothing else while the switch is pending
+        assert_eq!(measure(&mut abr, 10_000_000), None);
+
+        // the variant switched from plays on, audio and all
+        assert!(abr.accept(LOW.stream_index, false));
+        assert!(abr.accept(1, true));
+        assert!(!abr.accept(5, true));
+        assert!(!abr.accept(HIGH.stream_index, false));
+        assert_eq!(*abr.playing().lock().unwrap(), Some(LOW));
+
+        assert!(abr.accept(HIGH.stream_index, true));
+        assert_eq!(*abr.playing().lock().unwrap(), Some(HIGH));
+        assert!(abr.accept(HIGH.stream_index, false));
+        assert!(abr.accept(5, false));
+        assert!(!abr.accept(LOW.stream_index, true));
+        assert!(!abr.accept(1, true));
+        assert!(!abr.accept(MIDDLE.stream_index, true));
+        assert!(abr.accept(OTHER, false));
+    }
+
+    #[test]
+    fn every_variant_audio_goes_out_as_the_first() {
+        let abr = starting_on(&LOW, HlsBitrate::Auto);
+        assert_eq!(abr.output_index(5), 1);
+        assert_eq!(abr.output_index(1), 1);
+        assert_eq!(abr.output_index(HIGH.stream_index), HIGH.stream_index);
+        assert_eq!(abr.output_index(OTHER), OTHER);
+    }
+
+    #[test]
+    fn keeps_the_audio_of_variants_without_any() {
+        let silent = Variant {
+            audio_stream_index: None,
+            ..MIDDLE
+        };
+        let mut abr = Abr::new(vec![LOW, silent], LOW.stream_index, HlsBitrate::Auto).unwrap();
+        assert_eq!(measure(&mut abr, 10_000_000), Some(silent));
+        assert!(abr.accept(silent.stream_index, true));
+        assert!(abr.accept(1, true));
+    }
+
+    #[test]
+    fn holds_off_switching_up() {
+        let mut abr = starting_on(&LOW, HlsBitrate::Auto);
+        switched(&mut abr, UP_SWITCH_DELAY / 2);
+        assert_eq!(measure(&mut abr, 10_000_000), None);
+        switched(&mut abr, UP_SWITCH_DELAY);
+        assert_eq!(measure(&mut abr, 10_000_000), Some(HIGH));
+    }
+
+    #[test]
+    fn holds_off_switching_down() {
+        let mut abr = starting_on(&HIGH, HlsBitrate::Auto);
+        switched(&mut abr, DOWN_SWITCH_DELAY / 2);
+        assert_eq!(measure(&mut abr, 1_000_000), None);
+        switched(&mut abr, DOWN_SWITCH_DELAY);
+        // below every variant, the lowest it is
+        assert_eq!(measure(&mut abr, 1_000_000), Some(LOW));
+    }
+
+    #[test]
+    fn smooths_the_estimate() {
+        let mut abr = starting_on(&HIGH, HlsBitrate::Auto);
+        assert_eq!(measure(&mut abr, 10_000_000), None);
+        // a single slow sample only takes the estimate part of the way down
+        assert_eq!(measure(&mut abr, 0), None);
+        assert!((abr.estimate.unwrap() - 7_000_000_f64).abs() < 1_f64);
+    }
+
+    #[test]
+    fn stays_on_a_pinned_variant() {
+        for policy in [HlsBitrate::Best, HlsBitrate::Pinned(2_000_000)].iter() {
+            let mut abr = starting_on(&MIDDLE, *policy);
+            assert_eq!(measure(&mut abr, 100_000_000), None);
+            assert_eq!(measure(&mut abr, 0), None);
+            assert!(abr.estimate.is_some());
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::{ToSocketAddrs, UdpSocket},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    software::scaling::{self, Flags},
+};
+
+/// WLED's port for realtime UDP, used when the target leaves it out.
+const WLED_PORT: u16 = 21324;
+
+/// WLED goes back to its own effects this many seconds after the last colors, e.g. once
+/// playback stopped.
+const WLED_TIMEOUT: u8 = 2;
+
+/// Controllers struggle with updates any faster than this.
+const MAX_RATE: f64 = 50_f64;
+
+/// Where the LED colors go, set with `--ambilight`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AmbilightTarget {
+    /// `wled:host[:port]`, WLED's realtime UDP protocol (DRGB).
+    Wled(String),
+    /// `hyperion:host:port`, raw RGB triplets for a Hyperion UDP listener.
+    Hyperion(String),
+    /// `adalight:/dev/ttyUSB0`, the Adalight serial protocol most LED controllers take. The
+    /// port is written to as it is, its baud rate set beforehand, e.g. with `stty`.
+    Adalight(PathBuf),
+}
+
+impl FromStr for AmbilightTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid ambilight target '{}', expected wled:host, hyperion:host:port or adalight:port",
+                value
+            )
+        };
+
+        let (kind, address) = value.split_once(':').ok_or_else(invalid)?;
+        if address.is_empty() {
+            return Err(invalid());
+        }
+        match kind {
+            "wled" if address.contains(':') => Ok(AmbilightTarget::Wled(address.to_string())),
+            "wled" => Ok(AmbilightTarget::Wled(format!("{}:{}", address, WLED_PORT))),
+            "hyperion" => Ok(AmbilightTarget::Hyperion(address.to_string())),
+            "adalight" => Ok(AmbilightTarget::Adalight(PathBuf::from(address))),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// LEDs along the top and bottom edges each, and down the left and right edges each. They
+/// are addressed clockwise from the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LedLayout {
+    pub horizontal: u32,
+    pub vertical: u32,
+}
+
+impl LedLayout {
+    pub fn count(&self) -> u32 {
+        2 * (self.horizontal + self.vertical)
+    }
+}
+
+impl FromStr for LedLayout {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid LED layout '{}', expected e.g. 30x18", value);
+
+        let mut parts = value.split('x');
+        let horizontal = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let vertical = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() || horizontal == 0 || vertical == 0 {
+            return Err(invalid());
+        }
+
+        Ok(LedLayout {
+            horizontal,
+            vertical,
+        })
+    }
+}
+
+enum Output {
+    Wled(UdpSocket),
+    Hyperion(UdpSocket),
+    Adalight(File),
+}
+
+/// Sends the colors along the edges of every presented frame to LEDs around the screen.
+///
+/// The frame is scaled down to one pixel per LED along each edge, averaging the picture
+/// towards its middle, and the outermost pixels are sent.
+pub struct Ambilight {
+    layout: LedLayout,
+    output: Output,
+    scaler: Option<scaling::Context>,
+    last_sent: Option<Instant>,
+    /// Whether the last send failed, so a controller gone away is reported once.
+    failing: bool,
+}
+
+impl Ambilight {
+    pub fn open(target: &AmbilightTarget, layout: LedLayout) -> io::Result<Self> {
+        let output = match target {
+            AmbilightTarget::Wled(address) => Output::Wled(connect(address)?),
+            AmbilightTarget::Hyperion(address) => Output::Hyperion(connect(address)?),
+            AmbilightTarget::Adalight(port) => {
+                Output::Adalight(OpenOptions::new().write(true).open(port)?)
+            }
+        };
+        Ok(Ambilight {
+            layout,
+            output,
+            scaler: None,
+            last_sent: None,
+            failing: false,
+        })
+    }
+
+    pub fn on_frame(&mut self, frame: &Video) {
+        // frames beyond what the controller takes are skipped
+        let interval = Duration::from_secs_f64(1_f64 / MAX_RATE);
+        if self
+            .last_sent
+            .map_or(false, |sent| sent.elapsed() < interval)
+        {
+            return;
+        }
+
+        if let Some(colors) = self.sample(frame) {
+            self.last_sent = Some(Instant::now());
+            match self.send(&colors) {
+                Ok(()) => self.failing = false,
+                Err(e) if !self.failing => {
+                    eprintln!("failed to send ambilight colors: {}", e);
+                    self.failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// RGB triplets per LED, clockwise from the top left corner.
+    fn sample(&mut self, frame: &Video) -> Option<Vec<u8>> {
+        let (width, height) = (self.layout.horizontal, self.layout.vertical);
+        let matches = self.scaler.as_ref().map_or(false, |scaler| {
+            let input = scaler.input();
+            input.format == frame.format()
+                && input.width == frame.width()
+                && input.height == frame.height()
+        });
+        if !matches {
+            self.scaler = scaling::Context::get(
+                frame.format(),
+                frame.width(),
+                frame.height(),
+                Pixel::RGB24,
+                width,
+                height,
+                Flags::AREA,
+            )
+            .ok();
+        }
+
+        let mut picture = Video::empty();
+        self.scaler.as_mut()?.run(frame, &mut picture).ok()?;
+
+        let stride = picture.stride(0);
+        let data = picture.data(0);
+        let pixel = |x: u32, y: u32| {
+            let offset = y as usize * stride + x as usize * 3;
+            &data[offset..offset + 3]
+        };
+
+        let mut colors = Vec::with_capacity(self.layout.count() as usize * 3);
+        for x in 0..width {
+            colors.extend_from_slice(pixel(x, 0));
+        }
+        for y in 0..height {
+            colors.extend_from_slice(pixel(width - 1, y));
+        }
+        for x in (0..width).rev() {
+            colors.extend_from_slice(pixel(x, height - 1));
+        }
+        for y in (0..height).rev() {
+            colors.extend_from_slice(pixel(0, y));
+        }
+        Some(colors)
+    }
+
+    fn send(&mut self, colors: &[u8]) -> io::Result<()> {
+        match &mut self.output {
+            Output::Wled(socket) => {
+                let mut packet = vec![2, WLED_TIMEOUT];
+                packet.extend_from_slice(colors);
+                socket.send(&packet).map(|_| ())
+            }
+            Output::Hyperion(socket) => socket.send(colors).map(|_| ()),
+            Output::Adalight(port) => {
+                let last = (colors.len() / 3).saturating_sub(1);
+                let (high, low) = ((last >> 8) as u8, last as u8);
+                port.write_all(&[b'A', b'd', b'a', high, low, high ^ low ^ 0x55])?;
+                port.write_all(colors)?;
+                port.flush()
+            }
+        }
+    }
+}
+
+fn connect(address: &str) -> io::Result<UdpSocket> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address didn't resolve"))?;
+    let local = if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    Ok(socket)
+}
@@ -0,0 +1,598 @@
+use std::{path::Path, ptr};
+
+use ffmpeg_next::{
+    codec::{self, packet::side_data::Type as SideDataType, threading},
+    decoder, ffi,
+    format::{
+        context::{input::PacketIter, Input},
+        stream::Disposition,
+    },
+    media::Type,
+    Dictionary, Discard, Error, Stream,
+};
+
+use crate::{
+    abr,
+    capture::CaptureDevice,
+    hwdec,
+    options::{HwDecode, Options, ReplayGain},
+    ordered_chapters::{ChapterMark, Editions, OrderedChapters},
+    remux::RemuxStream,
+    visualizer::{VISUALIZER_HEIGHT, VISUALIZER_WIDTH},
+};
+
+/// R128 gain tags are relative to -23 LUFS, ReplayGain's reference is 5dB louder.
+const R128_TO_REPLAYGAIN: f64 = 5_f64;
+
+#[derive(Clone, Copy)]
+pub struct PlaybackAssetMetadata {
+    video_stream_index: Option<usize>,
+    audio_stream_index: Option<usize>,
+    width: u32,
+    height: u32,
+    video_time_base: f64,
+    audio_time_base: f64,
+    frame_rate: Option<f64>,
+    duration: Option<f64>,
+    spherical: bool,
+    cover_art: bool,
+}
+
+impl PlaybackAssetMetadata {
+    /// `None` for audio, where the player draws the audio instead; the size and time base
+    /// are then those of the pictures drawn.
+    pub fn video_stream_index(&self) -> Option<usize> {
+        self.video_stream_index
+    }
+
+    pub fn has_video(&self) -> bool {
+        self.video_stream_index.is_some()
+    }
+
+    /// `None` for video alone, e.g. a camera; the audio time base is then the video's.
+    pub fn audio_stream_index(&self) -> Option<usize> {
+        self.audio_stream_index
+    }
+
+    pub fn has_audio(&self) -> bool {
+        self.audio_stream_index.is_some()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn video_time_base(&self) -> f64 {
+        self.video_time_base
+    }
+
+    pub fn audio_time_base(&self) -> f64 {
+        self.audio_time_base
+    }
+
+    /// Average frames per second, where the container or codec says.
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.frame_rate
+    }
+
+    /// Length in seconds, unknown for live streams.
+    pub fn duration(&self) -> Option<f64> {
+        self.duration
+    }
+
+    /// Whether the video is tagged as 360° equirectangular.
+    pub fn is_spherical(&self) -> bool {
+        self.spherical
+    }
+
+    /// Whether there's no picture but cover art, if any, i.e. the input is music.
+    pub fn is_audio_only(&self) -> bool {
+        self.cover_art || !self.has_video()
+    }
+
+    /// The same input on a timeline `duration` long, e.g. another edition's.
+    pub(crate) fn with_duration(mut self, duration: Option<f64>) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+/// One rendition of an adaptive HLS or DASH stream, by its video stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Variant {
+    pub stream_index: usize,
+    /// The audio muxed in with the video, or the audio rendition the variant plays with.
+    pub audio_stream_index: Option<usize>,
+    /// Bits per second, as the playlist or manifest says.
+    pub bitrate: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct PlaybackAsset {
+    path: String,
+    input: Input,
+    metadata: PlaybackAssetMetadata,
+    editions: Option<Editions>,
+}
+
+impl PlaybackAsset {
+    pub fn new(path: &str, options: &Options) -> Self {
+        Self::open(path, options).expect("Failed to open input video")
+    }
+
+    /// Opens `path`, or the capture device it names, failing if it can't be read or has
+    /// neither audio nor video to play.
+    pub fn open(path: &str, options: &Options) -> Result<Self, Error> {
+        // Init ffmpeg
+        ffmpeg_next::init().expect("Failed to initialize ffmpeg");
+
+        // Read input video
+        let mut input_options = Dictionary::new();
+        if options.low_latency {
+            // don't let the demuxer buffer or probe ahead of what we need
+            input_options.set("fflags", "nobuffer");
+            input_options.set("flags", "low_delay");
+            input_options.set("probesize", "32768");
+            input_options.set("analyzeduration", "0");
+        }
+        let mut input = match path.parse::<CaptureDevice>() {
+            Ok(device) => device.open(options)?,
+            Err(_) => ffmpeg_next::format::input_with_dictionary(&Path::new(path), input_options)?,
+        };
+
+        // Get streams; adaptive streams start on the variant `--hls-bitrate` asks for
+        let variants = find_variants(&input);
+        let video_stream = match abr::pick(&variants, options.hls_bitrate) {
+            Some(variant) => input.stream(variant.stream_index),
+            None => input.streams().best(Type::Video),
+        };
+        let audio_stream = related_audio(&input, video_stream.as_ref().map(Stream::index));
+
+        let time_base = |stream: &Stream| {
+            let time_base = stream.time_base();
+            time_base.numerator() as f64 / time_base.denominator() as f64
+        };
+        let audio_time_base = match (&audio_stream, &video_stream) {
+            (Some(stream), _) | (None, Some(stream)) => time_base(stream),
+            (None, None) => return Err(Error::StreamNotFound),
+        };
+
+        let (width, height) = match &video_stream {
+            Some(video_stream) => {
+                let video_decoder = video_stream.codec().decoder().video()?;
+                (video_decoder.width(), video_decoder.height())
+            }
+            None => (VISUALIZER_WIDTH, VISUALIZER_HEIGHT),
+        };
+
+        let video_time_base = video_stream.as_ref().map_or(audio_time_base, time_base);
+
+        let frame_rate = video_stream.as_ref().and_then(|video_stream| {
+            [video_stream.avg_frame_rate(), video_stream.rate()]
+                .iter()
+                .find(|rate| rate.numerator() > 0 && rate.denominator() > 0)
+                .map(|rate| rate.numerator() as f64 / rate.denominator() as f64)
+        });
+
+        // an edition may order the chapters, playing the file and others next to it in an
+        // order of their own
+        let local = Path::new(path).is_file();
+        let editions = if local && input.format().name().contains("matroska") {
+            Editions::read(Path::new(path))
+        } else {
+            None
+        };
+        let duration = match editions.as_ref().and_then(Editions::timeline) {
+            Some(timeline) => Some(timeline.duration()),
+            None => input_duration(&input),
+        };
+
+        // the side data starts with an AVSphericalProjection; cubemaps (1) aren't supported
+        let spherical = video_stream.as_ref().map_or(false, |video_stream| {
+            video_stream.side_data().any(|side_data| {
+                side_data.kind() == SideDataType::DataSpherical
+                    && side_data.data().get(..4).map_or(false, |projection| {
+                        u32::from_ne_bytes([
+                            projection[0],
+                            projection[1],
+                            projection[2],
+                            projection[3],
+                        ]) != 1
+                    })
+            })
+        });
+
+        let cover_art = video_stream.as_ref().map_or(false, |video_stream| {
+            video_stream
+                .disposition()
+                .contains(Disposition::ATTACHED_PIC)
+        });
+
+        let metadata = PlaybackAssetMetadata {
+            video_stream_index: video_stream.as_ref().map(Stream::index),
+            audio_stream_index: audio_stream.as_ref().map(Stream::index),
+            width,
+            height,
+            video_time_base,
+            audio_time_base,
+            frame_rate,
+            duration,
+            spherical,
+            cover_art,
+        };
+
+        // the demuxer only downloads the variants whose streams aren't all discarded, and
+        // skips subtitles and data
+        discard_unused(&mut input, &metadata);
+
+        Ok(PlaybackAsset {
+            path: path.to_string(),
+            input,
+            metadata,
+            editions,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn metadata(&self) -> PlaybackAssetMetadata {
+        self.metadata
+    }
+
+    /// The timeline of the edition played when its chapters are ordered, which the
+    /// duration is that of, for `OrderedSource` to play.
+    pub fn ordered_chapters(&self) -> Option<OrderedChapters> {
+        self.editions.as_ref()?.timeline().cloned()
+    }
+
+    /// Chapters to jump to, on the timeline of the edition played for Matroska files.
+    pub fn chapters(&self) -> Vec<ChapterMark> {
+        if let Some(editions) = &self.editions {
+            return editions.chapters();
+        }
+        self.input
+            .chapters()
+            .map(|chapter| ChapterMark {
+                position: chapter.start() as f64 * f64::from(chapter.time_base()),
+                title: chapter.metadata().get("title").map(str::to_string),
+            })
+            .collect()
+    }
+
+    /// A Matroska file's editions, for the player to switch between. `None` for other
+    /// inputs.
+    pub fn editions(&self) -> Option<Editions> {
+        self.editions.clone()
+    }
+
+    /// Plays the edition `editions` has selected from now on, see `Editions::switch`; the
+    /// duration becomes that of its timeline where it's ordered.
+    pub fn set_editions(&mut self, editions: Editions) {
+        self.metadata.duration = match editions.timeline() {
+            Some(timeline) => Some(timeline.duration()),
+            None => input_duration(&self.input),
+        };
+        self.editions = Some(editions);
+    }
+
+    fn video_stream(&self) -> Stream {
+        self.metadata
+            .video_stream_index
+            .and_then(|index| self.input.stream(index))
+            .expect("input has no video stream")
+    }
+
+    fn audio_stream(&self) -> Option<Stream> {
+        self.input.stream(self.metadata.audio_stream_index?)
+    }
+
+    /// The video renditions of an HLS or DASH stream, from lowest to highest bitrate. Empty
+    /// for other inputs, and streams with a single rendition.
+    pub fn variants(&self) -> Vec<Variant> {
+        find_variants(&self.input)
+    }
+
+    /// Plays `variant`'s video and audio from here on, the other variants no longer
+    /// downloaded once their current segments end. Decoders for its video come from
+    /// `variant_decoder`; its audio is encoded like the other variants'.
+    pub fn select_variant(&mut self, variant: &Variant) {
+        self.metadata.video_stream_index = Some(variant.stream_index);
+        if variant.audio_stream_index.is_some() {
+            self.metadata.audio_stream_index = variant.audio_stream_index;
+        }
+        self.metadata.width = variant.width;
+        self.metadata.height = variant.height;
+        discard_unused(&mut self.input, &self.metadata);
+    }
+
+    /// A decoder for `variant`'s video, set up like `hardware_video_decoder`'s.
+    pub fn variant_decoder(
+        &self,
+        variant: &Variant,
+        options: &Options,
+        hwdec: HwDecode,
+    ) -> decoder::Video {
+        let stream = self
+            .input
+            .stream(variant.stream_index)
+            .expect("variant stream missing");
+        stream_decoder(stream, options, hwdec)
+    }
+
+    /// The song an internet radio station says is playing, from the ICY metadata it sends
+    /// in between the audio, when that changed since the last call. libavformat copies the
+    /// updates into the input's metadata as packets are read.
+    pub fn take_stream_title(&mut self) -> Option<String> {
+        unsafe {
+            let context = self.input.as_mut_ptr();
+            let updated = ffi::AVFMT_EVENT_FLAG_METADATA_UPDATED as i32;
+            if (*context).event_flags & updated == 0 {
+                return None;
+            }
+            (*context).event_flags &= !updated;
+        }
+        self.input
+            .metadata()
+            .get("StreamTitle")
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+            .map(String::from)
+    }
+
+    /// Linear volume scale from the ReplayGain or R128 tags for `mode`, lowered where the
+    /// tagged peak would otherwise clip. `None` when the file isn't tagged.
+    pub fn replay_gain(&self, mode: ReplayGain) -> Option<f64> {
+        let (gain_key, peak_key, r128_key) = match mode {
+            ReplayGain::Off => return None,
+            ReplayGain::Track => (
+                "REPLAYGAIN_TRACK_GAIN",
+                "REPLAYGAIN_TRACK_PEAK",
+                "R128_TRACK_GAIN",
+            ),
+            ReplayGain::Album => (
+                "REPLAYGAIN_ALBUM_GAIN",
+                "REPLAYGAIN_ALBUM_PEAK",
+                "R128_ALBUM_GAIN",
+            ),
+        };
+
+        // tags may be on the container or on the audio stream
+        let tag = |key: &str| {
+            self.input
+                .metadata()
+                .get(key)
+                .map(str::to_string)
+                .or_else(|| self.audio_stream()?.metadata().get(key).map(str::to_string))
+        };
+
+        // "-6.48 dB", or Q7.8 fixed point for R128
+        let gain = tag(gain_key)
+            .and_then(|value| value.split_whitespace().next()?.parse::<f64>().ok())
+            .or_else(|| {
+                let value = tag(r128_key)?.trim().parse::<f64>().ok()?;
+                Some(value / 256_f64 + R128_TO_REPLAYGAIN)
+            });
+        let gain = match gain {
+            Some(gain) => gain,
+            None if mode == ReplayGain::Album => return self.replay_gain(ReplayGain::Track),
+            None => return None,
+        };
+
+        let scale = 10_f64.powf(gain / 20_f64);
+        let peak = tag(peak_key)
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|peak| *peak > 0_f64);
+        Some(match peak {
+            Some(peak) => scale.min(1_f64 / peak),
+            None => scale,
+        })
+    }
+
+    /// Seeks the demuxer to the keyframe at or before `position` seconds.
+    pub fn seek(&mut self, position: f64) -> Result<(), ffmpeg_next::Error> {
+        let timestamp = (position * f64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i64;
+        self.input.seek(timestamp, ..timestamp)
+    }
+
+    /// The streams played, the video one first if there's one, to remux elsewhere.
+    pub fn remux_streams(&self) -> Vec<RemuxStream> {
+        let video = self
+            .metadata
+            .video_stream_index
+            .map(|_| self.video_stream());
+        video
+            .into_iter()
+            .chain(self.audio_stream())
+            .map(|stream| RemuxStream {
+                index: stream.index(),
+                parameters: stream.parameters().clone(),
+                time_base: stream.time_base(),
+                video: stream.parameters().medium() == Type::Video,
+            })
+            .collect()
+    }
+
+    pub fn packets(&mut self) -> PacketIter {
+        self.input.packets()
+    }
+
+    /// Panics for inputs without video, see `PlaybackAssetMetadata::has_video`.
+    pub fn video_decoder(&self, options: &Options) -> decoder::Video {
+        stream_decoder(self.video_stream(), options, HwDecode::No)
+    }
+
+    /// Like `video_decoder`, decoding on the GPU with `hwdec`, e.g. `options.hwdec` where the
+    /// video sink shows its surfaces. Its frames may be surfaces on the device rather than in
+    /// memory, see `hwdec::is_hardware`.
+    pub fn hardware_video_decoder(&self, options: &Options, hwdec: HwDecode) -> decoder::Video {
+        stream_decoder(self.video_stream(), options, hwdec)
+    }
+
+    /// Inputs without audio get a decoder that's never sent anything, so the audio
+    /// pipeline stays empty.
+    pub fn audio_decoder(&self, options: &Options) -> decoder::Audio {
+        let mut context = match self.audio_stream() {
+            Some(stream) => stream.codec(),
+            None => return silent_decoder(),
+        };
+        if options.low_latency {
+            context.set_flags(codec::Flags::LOW_DELAY);
+        }
+        context.decoder().audio().unwrap()
+    }
+}
+
+/// Length of `input` in seconds, where the container says.
+fn input_duration(input: &Input) -> Option<f64> {
+    if input.duration() > 0 {
+        Some(input.duration() as f64 / f64::from(ffmpeg_next::ffi::AV_TIME_BASE))
+    } else {
+        None
+    }
+}
+
+/// A decoder for the video `stream`, threaded and with the shortcuts `options` ask for, on
+/// the hardware `hwdec` picks if any.
+fn stream_decoder(stream: Stream, options: &Options, hwdec: HwDecode) -> decoder::Video {
+    let mut context = stream.codec();
+    if options.low_latency {
+        context.set_flags(codec::Flags::LOW_DELAY);
+    }
+    // the device does the work, threads would only hold back more of its surfaces
+    let count = if hwdec::attach(&mut context, hwdec) {
+        1
+    } else {
+        options.threads.count()
+    };
+
+    // Frame threading scales best, but holds back a frame per thread and not every codec
+    // has it; slice threading adds no delay
+    let frame_threads = decoder::find(context.id()).map_or(false, |codec| {
+        codec
+            .capabilities()
+            .contains(codec::Capabilities::FRAME_THREADS)
+    });
+    let kind = if frame_threads && !options.low_latency {
+        threading::Type::Frame
+    } else {
+        threading::Type::Slice
+    };
+    context.set_threading(threading::Config {
+        kind,
+        count,
+        ..Default::default()
+    });
+
+    let mut decoder = context.decoder();
+    if options.fast {
+        // no deblocking, and frames nothing else is predicted from aren't decoded at all
+        decoder.skip_loop_filter(Discard::All);
+        decoder.skip_idct(Discard::NonReference);
+        decoder.skip_frame(Discard::NonReference);
+    }
+    decoder.video().unwrap()
+}
+
+/// The video streams of an HLS or DASH input with the bitrate of the variant each is in,
+/// from lowest to highest, or none where there's only one.
+fn find_variants(input: &Input) -> Vec<Variant> {
+    let adaptive = input
+        .format()
+        .name()
+        .split(',')
+        .any(|name| matches!(name, "hls" | "applehttp" | "dash"));
+    if !adaptive {
+        return Vec::new();
+    }
+
+    let mut variants: Vec<Variant> = input
+        .streams()
+        .filter(|stream| stream.parameters().medium() == Type::Video)
+        .filter_map(|stream| {
+            let parameters = stream.parameters();
+            let (bit_rate, width, height) = unsafe {
+                let parameters = &*parameters.as_ptr();
+                (parameters.bit_rate, parameters.width, parameters.height)
+            };
+            let bitrate = stream
+                .metadata()
+                .get("variant_bitrate")
+                .and_then(|bitrate| bitrate.parse().ok())
+                .or_else(|| Some(bit_rate as u64).filter(|bitrate| *bitrate > 0))?;
+            Some(Variant {
+                stream_index: stream.index(),
+                audio_stream_index: related_audio(input, Some(stream.index()))
+                    .as_ref()
+                    .map(Stream::index),
+                bitrate,
+                width: width as u32,
+                height: height as u32,
+            })
+        })
+        .collect();
+    variants.sort_by_key(|variant| variant.bitrate);
+    if variants.len() < 2 {
+        variants.clear();
+    }
+    variants
+}
+
+/// The audio to play with video stream `video`, as ffplay picks it: the best audio stream in
+/// the same program, which is an HLS variant's, or the best of all of them otherwise.
+fn related_audio(input: &Input, video: Option<usize>) -> Option<Stream> {
+    let related = video.map_or(-1, |index| index as i32);
+    let index = unsafe {
+        ffi::av_find_best_stream(
+            input.as_ptr() as *mut _,
+            ffi::AVMediaType::AVMEDIA_TYPE_AUDIO,
+            -1,
+            related,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if index >= 0 {
+        input.stream(index as usize)
+    } else {
+        input.streams().best(Type::Audio)
+    }
+}
+
+/// Discards every stream but the video and audio ones played.
+fn discard_unused(input: &mut Input, metadata: &PlaybackAssetMetadata) {
+    for index in 0..input.nb_streams() as usize {
+        let used = Some(index) == metadata.video_stream_index
+            || Some(index) == metadata.audio_stream_index;
+        if let Some(mut stream) = input.stream_mut(index) {
+            let discard = if used { Discard::Default } else { Discard::All };
+            unsafe {
+                (*stream.as_mut_ptr()).discard = discard.into();
+            }
+        }
+    }
+}
+
+/// A PCM decoder standing in for the audio of inputs without any.
+fn silent_decoder() -> decoder::Audio {
+    let mut context = codec::Context::new();
+    unsafe {
+        let context = context.as_mut_ptr();
+        (*context).codec_type = ffi::AVMediaType::AVMEDIA_TYPE_AUDIO;
+        (*context).sample_fmt = ffi::AVSampleFormat::AV_SAMPLE_FMT_S16;
+        (*context).sample_rate = 48_000;
+        (*context).channels = 2;
+        (*context).channel_layout = ffi::AV_CH_LAYOUT_STEREO;
+    }
+    context
+        .decoder()
+        .open_as(codec::Id::PCM_S16LE)
+        .and_then(|decoder| decoder.audio())
+        .unwrap()
+}
@@ -0,0 +1,391 @@
+use std::{collections::VecDeque, time::Duration};
+
+use ffmpeg_next::{
+    decoder,
+    frame::{Audio, Video},
+    Error,
+};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task,
+    time::{self, Instant},
+};
+
+use crate::{
+    asset::{PlaybackAsset, PlaybackAssetMetadata},
+    options::Options,
+    player::PlayerCommand,
+    timeline::{FrameTimestamps, Timeline},
+};
+
+/// A decoded frame and when it's presented, in seconds.
+pub enum AsyncFrame {
+    Video { frame: Video, time: f64 },
+    Audio { frame: Audio, time: f64 },
+}
+
+impl AsyncFrame {
+    pub fn time(&self) -> f64 {
+        match self {
+            AsyncFrame::Video { time, .. } | AsyncFrame::Audio { time, .. } => *time,
+        }
+    }
+}
+
+/// Handle for driving an `AsyncPlayer` from other tasks, like `PlayerController`.
+#[derive(Clone)]
+pub struct AsyncPlayerController {
+    sender: UnboundedSender<PlayerCommand>,
+}
+
+impl AsyncPlayerController {
+    pub fn send(&self, command: PlayerCommand) {
+        // the player may already have been dropped, nothing left to control then
+        self.sender.send(command).ok();
+    }
+
+    pub fn play(&self) {
+        self.send(PlayerCommand::Play);
+    }
+
+    pub fn pause(&self) {
+        self.send(PlayerCommand::Pause);
+    }
+
+    pub fn seek(&self, position: f64) {
+        self.send(PlayerCommand::Seek(position));
+    }
+
+    pub fn stop(&self) {
+        self.send(PlayerCommand::Stop);
+    }
+}
+
+/// Demuxer and decoders, moved onto the runtime's blocking pool for every step.
+struct Pipeline {
+    asset: PlaybackAsset,
+    metadata: PlaybackAssetMetadata,
+    /// `None` for inputs without video.
+    video_decoder: Option<decoder::Video>,
+    audio_decoder: decoder::Audio,
+    with_audio: bool,
+    video_timeline: Timeline,
+    audio_timeline: Timeline,
+    video_timestamps: FrameTimestamps,
+    audio_timestamps: FrameTimestamps,
+    /// Frames between the keyframe and the position seeked to are dropped.
+    discard_before: Option<f64>,
+    ended: bool,
+}
+
+impl Pipeline {
+    /// Reads packets until one decodes into frames, `None` once the input has ended and the
+    /// decoders are drained.
+    fn step(&mut self) -> Option<Vec<AsyncFrame>> {
+        while !self.ended {
+            match self.asset.packets().next() {
+                Some((stream, mut packet)) => {
+                    let index = stream.index();
+                    if Some(index) == self.metadata.video_stream_index() {
+                        let decoder = match &mut self.video_decoder {
+                            Some(decoder) => decoder,
+                            None => continue,
+                        };
+                        self.video_timeline.rebase(&stream, &mut packet);
+                        decoder.send_packet(&packet).ok();
+                    } else if self.with_audio && Some(index) == self.metadata.audio_stream_index() {
+                        self.audio_timeline.rebase(&stream, &mut packet);
+                        self.audio_decoder.send_packet(&packet).ok();
+                    } else {
+                        continue;
+                    }
+                }
+                None => {
+                    // the input ended: get out what the decoders held back
+                    self.ended = true;
+                    if let Some(decoder) = &mut self.video_decoder {
+                        decoder.send_eof().ok();
+                    }
+                    self.audio_decoder.send_eof().ok();
+                }
+            }
+
+            let frames = self.receive_frames();
+            if !frames.is_empty() {
+                return Some(frames);
+            }
+        }
+        None
+    }
+
+    fn receive_frames(&mut self) -> Vec<AsyncFrame> {
+        let mut frames = Vec::new();
+
+        let time_base = self.metadata.video_time_base();
+        // frames without a duration of their own last this long, in the time base
+        let frame_ticks = self
+            .metadata
+            .frame_rate()
+            .map_or(1, |fps| (1_f64 / (fps * time_base)).round() as i64);
+        if let Some(decoder) = &mut self.video_decoder {
+            let mut frame = Video::empty();
+            while decoder.receive_frame(&mut frame).is_ok() {
+                let duration = Some(frame.packet().duration)
+                    .filter(|duration| *duration > 0)
+                    .unwrap_or(frame_ticks);
+                self.video_timestamps.stamp(&mut frame, duration);
+                let time = frame.pts().unwrap_or(0) as f64 * time_base;
+                if self.discard_before.map_or(true, |target| time >= target) {
+                    frames.push(AsyncFrame::Video { frame, time });
+                }
+                frame = Video::empty();
+            }
+        }
+
+        let time_base = self.metadata.audio_time_base();
+        let mut frame = Audio::empty();
+        while self.audio_decoder.receive_frame(&mut frame).is_ok() {
+            let duration = frame.samples() as f64 / frame.rate().max(1) as f64 / time_base;
+            self.audio_timestamps
+                .stamp(&mut frame, duration.round() as i64);
+            let time = frame.pts().unwrap_or(0) as f64 * time_base;
+            if self.discard_before.map_or(true, |target| time >= target) {
+                frames.push(AsyncFrame::Audio { frame, time });
+            }
+            frame = Audio::empty();
+        }
+
+        frames
+    }
+
+    fn seek(&mut self, position: f64) {
+        if let Err(e) = self.asset.seek(position) {
+            eprintln!("failed to seek to {:.2}s: {}", position, e);
+        }
+        if let Some(decoder) = &mut self.video_decoder {
+            decoder.flush();
+        }
+        self.audio_decoder.flush();
+        self.video_timeline.reset();
+        self.audio_timeline.reset();
+        self.video_timestamps.reset();
+        self.audio_timestamps.reset();
+        self.discard_before = Some(position);
+        self.ended = false;
+    }
+}
+
+/// A player for async services: frames are taken with `next_frame` on a tokio runtime
+/// instead of going to sinks, and commands arrive over an async channel.
+///
+/// ffmpeg reads inputs with blocking I/O, so opening, demuxing and decoding run step by step
+/// on the runtime's blocking pool; no threads are kept per input, unlike `Player`.
+pub struct AsyncPlayer {
+    pipeline: Option<Pipeline>,
+    metadata: PlaybackAssetMetadata,
+    commands: UnboundedReceiver<PlayerCommand>,
+    controller: AsyncPlayerController,
+    frames: VecDeque<AsyncFrame>,
+    realtime: bool,
+    /// Where playback was at an instant, set by the first frame after opening or a seek.
+    anchor: Option<(f64, Instant)>,
+    paused: bool,
+    stopped: bool,
+}
+
+impl AsyncPlayer {
+    /// Opens `path`, which may well be a network input, without blocking the runtime.
+    pub async fn open(path: &str, options: &Options) -> Result<Self, Error> {
+        let start = options.start;
+        let path = path.to_string();
+        let options = options.clone();
+        let pipeline = task::spawn_blocking(move || {
+            let asset = PlaybackAsset::open(&path, &options)?;
+            let metadata = asset.metadata();
+            Ok::<_, Error>(Pipeline {
+                video_decoder: if metadata.has_video() {
+                    Some(asset.video_decoder(&options))
+                } else {
+                    None
+                },
+                audio_decoder: asset.audio_decoder(&options),
+                with_audio: !options.no_audio,
+                video_timeline: Timeline::new(metadata.video_time_base()),
+                audio_timeline: Timeline::new(metadata.audio_time_base()),
+                video_timestamps: FrameTimestamps::default(),
+                audio_timestamps: FrameTimestamps::default(),
+                discard_before: None,
+                ended: false,
+                asset,
+                metadata,
+            })
+        })
+        .await
+        .map_err(join_error)??;
+
+        let (sender, commands) = mpsc::unbounded_channel();
+        let mut player = AsyncPlayer {
+            metadata: pipeline.metadata,
+            pipeline: Some(pipeline),
+            commands,
+            controller: AsyncPlayerController { sender },
+            frames: VecDeque::new(),
+            realtime: true,
+            anchor: None,
+            paused: false,
+            stopped: false,
+        };
+        if let Some(start) = start {
+            player.seek(start).await?;
+        }
+        Ok(player)
+    }
+
+    pub fn metadata(&self) -> PlaybackAssetMetadata {
+        self.metadata
+    }
+
+    pub fn controller(&self) -> AsyncPlayerController {
+        self.controller.clone()
+    }
+
+    /// Hands out frames when they're due, the default, or else as fast as they decode, e.g.
+    /// to extract them.
+    pub fn set_realtime(&mut self, realtime: bool) {
+        self.realtime = realtime;
+        self.anchor = None;
+    }
+
+    /// Where playback is, in seconds.
+    pub fn position(&self) -> f64 {
+        match self.anchor {
+            Some((position, _)) if self.paused => position,
+            Some((position, at)) => position + at.elapsed().as_secs_f64(),
+            None => self.frames.front().map_or(0_f64, AsyncFrame::time),
+        }
+    }
+
+    /// The next frame, video or audio, once it's due; `None` at the end of the input or
+    /// after a stop command, and an error when decoding or seeking panicked. Commands are
+    /// handled while waiting.
+    pub async fn next_frame(&mut self) -> Result<Option<AsyncFrame>, Error> {
+        loop {
+            while let Ok(command) = self.commands.try_recv() {
+                self.handle(command).await?;
+            }
+            if self.stopped {
+                return Ok(None);
+            }
+
+            // nothing is due while paused
+            if self.paused {
+                if let Some(command) = self.commands.recv().await {
+                    self.handle(command).await?;
+                }
+                continue;
+            }
+
+            let frame_time = match self.frames.front() {
+                Some(frame) => frame.time(),
+                None => {
+                    if !self.decode().await? {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+            };
+
+            if self.realtime {
+                let due = match self.anchor {
+                    Some((position, at)) => {
+                        at + Duration::from_secs_f64((frame_time - position).max(0_f64))
+                    }
+                    None => {
+                        self.anchor = Some((frame_time, Instant::now()));
+                        Instant::now()
+                    }
+                };
+                tokio::select! {
+                    _ = time::sleep_until(due) => {}
+                    Some(command) = self.commands.recv() => {
+                        self.handle(command).await?;
+                        continue;
+                    }
+                }
+            }
+
+            return Ok(self.frames.pop_front());
+        }
+    }
+
+    async fn handle(&mut self, command: PlayerCommand) -> Result<(), Error> {
+        let command = match command {
+            PlayerCommand::TogglePause if self.paused => PlayerCommand::Play,
+            PlayerCommand::TogglePause => PlayerCommand::Pause,
+            PlayerCommand::SeekBy(offset) => PlayerCommand::Seek(self.position() + offset),
+            command => command,
+        };
+
+        match command {
+            PlayerCommand::Stop => self.stopped = true,
+            PlayerCommand::Seek(position) => self.seek(position.max(0_f64)).await?,
+            PlayerCommand::Pause if !self.paused => {
+                self.anchor = self.anchor.map(|_| (self.position(), Instant::now()));
+                self.paused = true;
+            }
+            PlayerCommand::Play if self.paused => {
+                self.paused = false;
+                self.anchor = self.anchor.map(|(position, _)| (position, Instant::now()));
+            }
+            // the rest is about outputs, which are the caller's
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Decodes the next frames on the blocking pool, false once there are none left.
+    async fn decode(&mut self) -> Result<bool, Error> {
+        let mut pipeline = match self.pipeline.take() {
+            Some(pipeline) => pipeline,
+            None => return Ok(false),
+        };
+        // a pipeline that panicked is gone, and the input ends with the error
+        let (pipeline, frames) = task::spawn_blocking(move || {
+            let frames = pipeline.step();
+            (pipeline, frames)
+        })
+        .await
+        .map_err(join_error)?;
+        self.pipeline = Some(pipeline);
+
+        match frames {
+            Some(frames) => {
+                self.frames.extend(frames);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn seek(&mut self, position: f64) -> Result<(), Error> {
+        if let Some(mut pipeline) = self.pipeline.take() {
+            let pipeline = task::spawn_blocking(move || {
+                pipeline.seek(position);
+                pipeline
+            })
+            .await
+            .map_err(join_error)?;
+            self.pipeline = Some(pipeline);
+        }
+        self.frames.clear();
+        // the first frame from the new position anchors the clock again
+        self.anchor = None;
+        Ok(())
+    }
+}
+
+/// A step on the blocking pool that panicked, or was cancelled as the runtime shut down.
+fn join_error(e: task::JoinError) -> Error {
+    eprintln!("async playback failed: {}", e);
+    Error::Bug
+}
@@ -0,0 +1,144 @@
+use ffmpeg_next::{filter, format, frame, ChannelLayout, Error, Rational};
+
+/// Runs decoded audio through an ffmpeg filtergraph, e.g. `loudnorm`.
+///
+/// The graph is built from the first frame, since the decoder's output format is only
+/// known then, and rebuilt whenever that format or the description changes, or after a
+/// seek. An empty description passes frames through.
+pub struct AudioFilter {
+    description: String,
+    time_base: Rational,
+    graph: Option<filter::Graph>,
+    input: Option<(format::Sample, u32, ChannelLayout)>,
+    failed: bool,
+}
+
+impl AudioFilter {
+    /// `time_base` is that of the frames' timestamps, in seconds.
+    pub fn new(description: &str, time_base: f64) -> Self {
+        AudioFilter {
+            description: description.to_string(),
+            time_base: Rational::from(time_base),
+            graph: None,
+            input: None,
+            failed: false,
+        }
+    }
+
+    /// Switches to another filter chain from the next frame on.
+    pub fn set_description(&mut self, description: &str) {
+        if self.description != description {
+            self.description = description.to_string();
+            self.graph = None;
+            self.failed = false;
+        }
+    }
+
+    /// Forgets the audio held by stateful filters, for after a seek.
+    pub fn reset(&mut self) {
+        self.graph = None;
+    }
+
+    /// Feeds `frame` into the graph, returning the filtered frames that are ready. Frames
+    /// pass through untouched if the graph can't be built.
+    pub fn filter(&mut self, frame: frame::Audio) -> Vec<frame::Audio> {
+        if self.failed || self.description.is_empty() || frame.rate() == 0 {
+            return vec![frame];
+        }
+
+        let layout = if frame.channel_layout().is_empty() {
+            ChannelLayout::default(frame.channels() as i32)
+        } else {
+            frame.channel_layout()
+        };
+        let input = (frame.format(), frame.rate(), layout);
+        if self.input != Some(input) {
+            self.graph = None;
+        }
+        if self.graph.is_none() {
+            match self.create_graph(input) {
+                Ok(graph) => {
+                    self.graph = Some(graph);
+                    self.input = Some(input);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "failed to create audio filter '{}': {}",
+                        self.description, e
+                    );
+                    self.failed = true;
+                    return vec![frame];
+                }
+            }
+        }
+        let graph = self.graph.as_mut().unwrap();
+
+        if graph.get("in").unwrap().source().add(&frame).is_err() {
+            return Vec::new();
+        }
+
+        self.take_filtered()
+    }
+
+    /// Signals the end of the input, returning what stateful filters still held back.
+    pub fn flush(&mut self) -> Vec<frame::Audio> {
+        let graph = match &mut self.graph {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+        if graph.get("in").unwrap().source().flush().is_err() {
+            return Vec::new();
+        }
+        let frames = self.take_filtered();
+        // an ended graph takes no more frames, the next one builds a fresh graph
+        self.graph = None;
+        frames
+    }
+
+    fn take_filtered(&mut self) -> Vec<frame::Audio> {
+        let graph = match &mut self.graph {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+
+        let mut frames = Vec::new();
+        let mut filtered = frame::Audio::empty();
+        while graph
+            .get("out")
+            .unwrap()
+            .sink()
+            .frame(&mut filtered)
+            .is_ok()
+        {
+            frames.push(filtered);
+            filtered = frame::Audio::empty();
+        }
+        frames
+    }
+
+    fn create_graph(
+        &self,
+        (format, rate, layout): (format::Sample, u32, ChannelLayout),
+    ) -> Result<filter::Graph, Error> {
+        let mut graph = filter::Graph::new();
+
+        let args = format!(
+            "time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            self.time_base.numerator(),
+            self.time_base.denominator(),
+            rate,
+            format.name(),
+            layout.bits()
+        );
+        graph.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+        graph.add(&filter::find("abuffersink").unwrap(), "out", "")?;
+
+        graph
+            .output("in", 0)?
+            .input("out", 0)?
+            .parse(&self.description)?;
+        graph.validate()?;
+
+        Ok(graph)
+    }
+}
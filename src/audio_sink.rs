@@ -0,0 +1,149 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+#[cfg(feature = "cpal")]
+mod cpal;
+#[cfg(feature = "sdl")]
+mod ring;
+#[cfg(feature = "sdl")]
+mod sdl;
+
+#[cfg(feature = "cpal")]
+pub use self::cpal::CpalAudioSink;
+#[cfg(feature = "sdl")]
+pub use self::sdl::SdlAudioSink;
+
+/// Sample rate and channel count a sink consumes. Samples are always interleaved `f32`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct AudioSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl AudioSpec {
+    /// Duration of `samples` interleaved samples, in seconds.
+    pub fn duration(&self, samples: usize) -> f64 {
+        samples as f64 / self.channels as f64 / self.sample_rate as f64
+    }
+}
+
+/// Where decoded audio ends up.
+///
+/// The player asks for `preferred_spec` once, converts every decoded frame to it and passes
+/// the interleaved samples to `write`. Embedders can hand their own implementation to
+/// `Player::set_audio_sink`.
+pub trait AudioSink {
+    fn preferred_spec(&self) -> AudioSpec;
+
+    fn write(&mut self, samples: &[f32]);
+
+    /// Seconds between a sample being written and it being heard.
+    fn latency(&self) -> f64;
+
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    /// Reopens the output as 16-bit stereo at `sample_rate` for IEC 61937 bursts of
+    /// compressed audio (`--audio-passthrough`), returning false if it can't carry them.
+    fn passthrough(&mut self, _sample_rate: u32) -> bool {
+        false
+    }
+
+    /// Plays IEC 61937 burst words untouched, once `passthrough` has succeeded.
+    fn write_bitstream(&mut self, _samples: &[i16]) {}
+}
+
+/// Discards every sample, for `--ao null` and `--no-audio`.
+pub struct NullAudioSink;
+
+impl AudioSink for NullAudioSink {
+    fn preferred_spec(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+
+    fn write(&mut self, _samples: &[f32]) {}
+
+    fn latency(&self) -> f64 {
+        0_f64
+    }
+}
+
+/// Writes everything played to a 32-bit float WAV file (`--ao wav`).
+pub struct WavAudioSink {
+    writer: BufWriter<File>,
+    spec: AudioSpec,
+    data_size: u32,
+}
+
+impl WavAudioSink {
+    pub fn create(path: &Path, spec: AudioSpec) -> io::Result<Self> {
+        let mut sink = WavAudioSink {
+            writer: BufWriter::new(File::create(path)?),
+            spec,
+            data_size: 0,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    /// RIFF header for IEEE float samples; the sizes are patched in once playback stops.
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = self.spec.channels as u32 * 4;
+
+        self.writer.write_all(b"RIFF")?;
+        self.writer
+            .write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.writer.write_all(b"WAVEfmt ")?;
+        self.writer.write_all(&16_u32.to_le_bytes())?;
+        self.writer.write_all(&3_u16.to_le_bytes())?;
+        self.writer.write_all(&self.spec.channels.to_le_bytes())?;
+        self.writer
+            .write_all(&self.spec.sample_rate.to_le_bytes())?;
+        self.writer
+            .write_all(&(self.spec.sample_rate * block_align).to_le_bytes())?;
+        self.writer.write_all(&(block_align as u16).to_le_bytes())?;
+        self.writer.write_all(&32_u16.to_le_bytes())?;
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&self.data_size.to_le_bytes())
+    }
+}
+
+impl AudioSink for WavAudioSink {
+    fn preferred_spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        for sample in samples {
+            if let Err(e) = self.writer.write_all(&sample.to_le_bytes()) {
+                eprintln!("failed to write audio: {}", e);
+                return;
+            }
+        }
+        self.data_size += (samples.len() * 4) as u32;
+    }
+
+    fn latency(&self) -> f64 {
+        0_f64
+    }
+}
+
+impl Drop for WavAudioSink {
+    fn drop(&mut self) {
+        let result = self
+            .writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.write_header())
+            .and_then(|_| self.writer.flush());
+        if let Err(e) = result {
+            eprintln!("failed to finish WAV file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use ::cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, Device, FromSample, Sample, SampleFormat, SampleRate, SizedSample, Stream,
+    StreamConfig, SupportedBufferSize, SupportedStreamConfig,
+};
+
+use super::{AudioSink, AudioSpec};
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// Sample formats we can produce, best first.
+const SAMPLE_FORMATS: [SampleFormat; 5] = [
+    SampleFormat::F32,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::U16,
+    SampleFormat::F64,
+];
+
+/// Plays through cpal (`--ao cpal`), on whatever native API the host has: ALSA, PulseAudio
+/// and JACK via ALSA, CoreAudio or WASAPI.
+pub struct CpalAudioSink {
+    device: Device,
+    stream: Stream,
+    spec: AudioSpec,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl CpalAudioSink {
+    /// Opens the output device called `device_name`, or the default one.
+    pub fn new(device_name: Option<&str>, low_latency: bool) -> Result<Self, String> {
+        let host = ::cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .output_devices()
+                .map_err(|e| e.to_string())?
+                .find(|device| device.name().map_or(false, |other| other == name))
+                .ok_or_else(|| format!("no audio output device called '{}'", name))?,
+            None => host
+                .default_output_device()
+                .ok_or_else(|| "no audio output device".to_string())?,
+        };
+
+        let supported = negotiate_config(&device)?;
+        let mut config: StreamConfig = supported.config();
+        if low_latency {
+            // a small device buffer keeps output latency down for live sources
+            if let SupportedBufferSize::Range { min, max } = supported.buffer_size() {
+                config.buffer_size = BufferSize::Fixed(512.max(*min).min(*max));
+            }
+        }
+
+        let spec = AudioSpec {
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+        };
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let paused = Arc::new(AtomicBool::new(false));
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, &queue, &paused),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, &queue, &paused),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, &queue, &paused),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, &queue, &paused),
+            SampleFormat::F64 => build_stream::<f64>(&device, &config, &queue, &paused),
+            format => return Err(format!("unsupported sample format {}", format)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(CpalAudioSink {
+            device,
+            stream,
+            spec,
+            queue,
+            paused,
+        })
+    }
+
+    /// Names of the output devices `--ao-device` accepts.
+    pub fn device_names() -> Vec<String> {
+        ::cpal::default_host()
+            .output_devices()
+            .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl AudioSink for CpalAudioSink {
+    fn preferred_spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        self.queue.lock().unwrap().extend(samples);
+    }
+
+    fn latency(&self) -> f64 {
+        let queued = self.queue.lock().unwrap().len();
+        self.spec.duration(queued)
+    }
+
+    fn pause(&mut self) {
+        // not every backend can pause a stream, so the callback also plays silence
+        self.paused.store(true, Ordering::Relaxed);
+        self.stream.pause().ok();
+    }
+
+    fn resume(&mut self) {
+        self.paused.store(false, Ordering::Relaxed);
+        self.stream.play().ok();
+    }
+
+    fn passthrough(&mut self, sample_rate: u32) -> bool {
+        // the device has to be set up for non-audio data itself, e.g. an ALSA `iec958` or
+        // `hdmi` device with AES0=0x06 picked with `--ao-device`
+        let rate = SampleRate(sample_rate);
+        let supported = match self.device.supported_output_configs() {
+            Ok(mut ranges) => ranges.any(|range| {
+                range.sample_format() == SampleFormat::I16
+                    && range.channels() == 2
+                    && range.min_sample_rate() <= rate
+                    && range.max_sample_rate() >= rate
+            }),
+            Err(_) => false,
+        };
+        if !supported {
+            return false;
+        }
+
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: rate,
+            buffer_size: BufferSize::Default,
+        };
+        let stream = match build_stream::<i16>(&self.device, &config, &self.queue, &self.paused) {
+            Ok(stream) => stream,
+            Err(_) => return false,
+        };
+        if self.paused.load(Ordering::Relaxed) {
+            stream.pause().ok();
+        } else if stream.play().is_err() {
+            return false;
+        }
+
+        self.queue.lock().unwrap().clear();
+        self.stream = stream;
+        self.spec = AudioSpec {
+            sample_rate,
+            channels: 2,
+        };
+        true
+    }
+
+    fn write_bitstream(&mut self, samples: &[i16]) {
+        // i16 -> f32 -> i16 is lossless, so the queue can carry the bursts as they are
+        self.queue
+            .lock()
+            .unwrap()
+            .extend(samples.iter().map(|&sample| f32::from_sample(sample)));
+    }
+}
+
+/// Picks the device's stereo 48kHz configuration in the best sample format we can produce,
+/// falling back to whatever the device prefers.
+fn negotiate_config(device: &Device) -> Result<SupportedStreamConfig, String> {
+    let ranges: Vec<_> = device
+        .supported_output_configs()
+        .map_err(|e| e.to_string())?
+        .collect();
+
+    let rate = SampleRate(SAMPLE_RATE);
+    for format in &SAMPLE_FORMATS {
+        for channels in &[2, 1] {
+            if let Some(range) = ranges.iter().find(|range| {
+                range.sample_format() == *format
+                    && range.channels() == *channels
+                    && range.min_sample_rate() <= rate
+                    && range.max_sample_rate() >= rate
+            }) {
+                return Ok(range.clone().with_sample_rate(rate));
+            }
+        }
+    }
+
+    // any rate and channel count works, the converter resamples and remixes to it
+    let config = device.default_output_config().map_err(|e| e.to_string())?;
+    if SAMPLE_FORMATS.contains(&config.sample_format()) {
+        return Ok(config);
+    }
+    for format in &SAMPLE_FORMATS {
+        if let Some(range) = ranges.iter().find(|range| range.sample_format() == *format) {
+            return Ok(range.clone().with_max_sample_rate());
+        }
+    }
+    Err("no supported sample format".to_string())
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    queue: &Arc<Mutex<VecDeque<f32>>>,
+    paused: &Arc<AtomicBool>,
+) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let queue = Arc::clone(queue);
+    let paused = Arc::clone(paused);
+
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                if paused.load(Ordering::Relaxed) {
+                    data.fill(T::EQUILIBRIUM);
+                    return;
+                }
+
+                // an underrun plays silence rather than blocking the device
+                let mut queue = queue.lock().unwrap();
+                for sample in data.iter_mut() {
+                    *sample = queue.pop_front().map_or(T::EQUILIBRIUM, T::from_sample);
+                }
+            },
+            |e| eprintln!("audio output error: {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
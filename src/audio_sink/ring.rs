@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Single producer, single consumer queue of samples that neither side ever locks, so the
+/// audio callback can't be held up by the main loop and vice versa.
+///
+/// Samples are stored as their bits in atomics; the counters only ever grow, and the
+/// difference between them is what's queued.
+pub struct RingBuffer {
+    samples: Box<[AtomicU32]>,
+    /// `samples.len() - 1`, the length being a power of two.
+    mask: usize,
+    written: AtomicUsize,
+    read: AtomicUsize,
+}
+
+impl RingBuffer {
+    /// Holds at least `capacity` samples.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        RingBuffer {
+            samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            mask: capacity - 1,
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Samples queued and not yet taken.
+    pub fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        self.written.load(Ordering::Acquire).wrapping_sub(read)
+    }
+
+    /// Queues as many of `samples` as there's room for, returning how many that was. Only
+    /// the producer may call this.
+    pub fn push(&self, samples: &[f32]) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        let free = self.samples.len() - written.wrapping_sub(self.read.load(Ordering::Acquire));
+        let count = samples.len().min(free);
+        for (i, sample) in samples[..count].iter().enumerate() {
+            self.samples[written.wrapping_add(i) & self.mask]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        // publishes the samples stored above to the consumer
+        self.written
+            .store(written.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Fills `out` with queued samples and the rest with silence, returning how many were
+    /// queued. Only the consumer may call this.
+    pub fn pop_into(&self, out: &mut [f32]) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let queued = self.written.load(Ordering::Acquire).wrapping_sub(read);
+        let count = out.len().min(queued);
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(
+                self.samples[read.wrapping_add(i) & self.mask].load(Ordering::Relaxed),
+            );
+        }
+        out[count..].fill(0_f32);
+        // hands the slots read above back to the producer
+        self.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ring whose counters are about to wrap around.
+    fn near_wraparound(capacity: usize) -> RingBuffer {
+        let ring = RingBuffer::new(capacity);
+        ring.written.store(usize::MAX - 2, Ordering::Relaxed);
+        ring.read.store(usize::MAX - 2, Ordering::Relaxed);
+        ring
+    }
+
+    #[test]
+    fn capacity_is_a_power_of_two() {
+        assert_eq!(RingBuffer::new(5).push(&[0_f32; 16]), 8);
+        assert_eq!(RingBuffer::new(0).push(&[0_f32; 16]), 1);
+    }
+
+    #[test]
+    fn pops_what_was_pushed() {
+        let ring = RingBuffer::new(8);
+        assert_eq!(ring.push(&[1_f32, 2_f32, 3_f32]), 3);
+        assert_eq!(ring.len(), 3);
+        let mut out = [0_f32; 3];
+        assert_eq!(ring.pop_into(&mut out), 3);
+        assert_eq!(out, [1_f32, 2_f32, 3_f32]);
+        assert_eq!(ring.len(), 0);
+    }
+
+    #[test]
+    fn pushes_what_fits() {
+        let ring = RingBuffer::new(4);
+        assert_eq!(ring.push(&[1_f32, 2_f32, 3_f32, 4_f32, 5_f32, 6_f32]), 4);
+        assert_eq!(ring.push(&[7_f32]), 0);
+
+        let mut out = [0_f32; 2];
+        assert_eq!(ring.pop_into(&mut out), 2);
+        assert_eq!(out, [1_f32, 2_f32]);
+        assert_eq!(ring.push(&[7_f32, 8_f32, 9_f32]), 2);
+
+        let mut out = [0_f32; 4];
+        assert_eq!(ring.pop_into(&mut out), 4);
+        assert_eq!(out, [3_f32, 4_f32, 7_f32, 8_f32]);
+    }
+
+    #[test]
+    fn fills_underruns_with_silence() {
+        let ring = RingBuffer::new(8);
+        ring.push(&[1_f32, 2_f32]);
+        let mut out = [9_f32; 5];
+        assert_eq!(ring.pop_into(&mut out), 2);
+        assert_eq!(out, [1_f32, 2_f32, 0_f32, 0_f32, 0_f32]);
+
+        let mut out = [9_f32; 3];
+        assert_eq!(ring.pop_into(&mut out), 0);
+        assert_eq!(out, [0_f32; 3]);
+    }
+
+    #[test]
+    fn counters_wrap_around() {
+        let ring = near_wraparound(4);
+        assert_eq!(ring.push(&[1_f32, 2_f32, 3_f32, 4_f32, 5_f32]), 4);
+        assert_eq!(ring.len(), 4);
+
+        let mut out = [0_f32; 3];
+        assert_eq!(ring.pop_into(&mut out), 3);
+        assert_eq!(out, [1_f32, 2_f32, 3_f32]);
+        assert_eq!(ring.push(&[5_f32, 6_f32, 7_f32, 8_f32]), 3);
+        assert_eq!(ring.len(), 4);
+
+        let mut out = [0_f32; 6];
+        assert_eq!(ring.pop_into(&mut out), 4);
+        assert_eq!(out, [4_f32, 5_f32, 6_f32, 7_f32, 0_f32, 0_f32]);
+        assert_eq!(ring.len(), 0);
+        assert!(ring.written.load(Ordering::Relaxed) < 8);
+    }
+}
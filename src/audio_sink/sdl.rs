@@ -0,0 +1,124 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    AudioSubsystem,
+};
+
+use super::{ring::RingBuffer, AudioSink, AudioSpec};
+
+/// Seconds of audio the ring buffer holds, far more than is ever written ahead.
+const RING_SECONDS: usize = 4;
+
+/// State shared with the audio callback; only atomics, so it never waits on the main loop.
+struct Playout {
+    ring: RingBuffer,
+    /// When the callback last ran, in nanoseconds since the sink was opened.
+    last_callback: AtomicU64,
+    opened: Instant,
+}
+
+struct Callback {
+    playout: Arc<Playout>,
+}
+
+impl AudioCallback for Callback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        // an underrun plays silence rather than holding up the device
+        self.playout.ring.pop_into(out);
+        let now = self.playout.opened.elapsed().as_nanos() as u64;
+        self.playout.last_callback.store(now, Ordering::Release);
+    }
+}
+
+/// Plays through an SDL audio callback fed from a lock-free ring buffer, so output doesn't
+/// glitch while the main loop is busy.
+pub struct SdlAudioSink {
+    audio_device: AudioDevice<Callback>,
+    playout: Arc<Playout>,
+}
+
+impl SdlAudioSink {
+    pub fn new(audio_subsystem: &AudioSubsystem, low_latency: bool) -> Self {
+        let audio_spec = AudioSpecDesired {
+            freq: Some(48000),
+            channels: Some(2),
+            // a small device buffer keeps output latency down for live sources
+            samples: if low_latency { Some(512) } else { None },
+        };
+
+        let mut playout = None;
+        let audio_device = audio_subsystem
+            .open_playback(None, &audio_spec, |spec| {
+                // SDL may not give us exactly what we asked for
+                let capacity = spec.freq as usize * spec.channels as usize * RING_SECONDS;
+                let shared = Arc::new(Playout {
+                    ring: RingBuffer::new(capacity),
+                    last_callback: AtomicU64::new(0),
+                    opened: Instant::now(),
+                });
+                playout = Some(Arc::clone(&shared));
+                Callback { playout: shared }
+            })
+            .unwrap();
+        audio_device.resume();
+
+        SdlAudioSink {
+            audio_device,
+            playout: playout.unwrap(),
+        }
+    }
+
+    /// Seconds of the last callback's samples still to come out of the device buffer.
+    fn device_buffered(&self) -> f64 {
+        let spec = self.audio_device.spec();
+        let buffer = spec.samples as f64 / spec.freq as f64;
+        let last_callback = self.playout.last_callback.load(Ordering::Acquire);
+        let now = self.playout.opened.elapsed().as_nanos() as u64;
+        let since = now.saturating_sub(last_callback);
+        (buffer - since as f64 / 1e9).max(0_f64)
+    }
+}
+
+impl AudioSink for SdlAudioSink {
+    fn preferred_spec(&self) -> AudioSpec {
+        let spec = self.audio_device.spec();
+        AudioSpec {
+            sample_rate: spec.freq as u32,
+            channels: spec.channels as u16,
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        let queued = self.playout.ring.push(samples);
+        if queued < samples.len() {
+            eprintln!(
+                "audio output overrun, dropping {} samples",
+                samples.len() - queued
+            );
+        }
+    }
+
+    fn latency(&self) -> f64 {
+        // what's waiting in the ring buffer, plus what the device has yet to play of the
+        // samples it last took
+        let queued = self.preferred_spec().duration(self.playout.ring.len());
+        queued + self.device_buffered()
+    }
+
+    fn pause(&mut self) {
+        self.audio_device.pause();
+    }
+
+    fn resume(&mut self) {
+        self.audio_device.resume();
+    }
+}
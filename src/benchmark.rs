@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use ffmpeg_next::{decoder, frame, Error};
+
+use crate::{options::Options, PlaybackAsset};
+
+struct StreamStats {
+    codec: String,
+    packets: usize,
+    frames: usize,
+    decode_time: Duration,
+}
+
+impl StreamStats {
+    fn new(codec: Option<ffmpeg_next::Codec>) -> Self {
+        StreamStats {
+            codec: codec.map_or_else(|| "unknown".to_string(), |codec| codec.name().to_string()),
+            packets: 0,
+            frames: 0,
+            decode_time: Duration::default(),
+        }
+    }
+
+    fn print(&self, kind: &str) {
+        let seconds = self.decode_time.as_secs_f64();
+        let fps = if seconds > 0_f64 {
+            self.frames as f64 / seconds
+        } else {
+            0_f64
+        };
+
+        info!(
+            "{:<6} {:<12} {:>8} packets {:>8} frames {:>9.3}s decoding {:>10.1} fps",
+            kind, self.codec, self.packets, self.frames, seconds, fps
+        );
+    }
+}
+
+/// Decodes the whole asset as fast as possible, without presentation timing or audio output,
+/// and prints throughput for each stream.
+pub fn run(asset: &mut PlaybackAsset, options: &Options) -> Result<(), Error> {
+    let metadata = asset.metadata();
+    // inputs without video only have their audio to decode
+    let mut video_decoder = if metadata.has_video() {
+        Some(asset.video_decoder(options))
+    } else {
+        None
+    };
+    let mut audio_decoder = asset.audio_decoder(options);
+
+    let mut video_stats =
+        StreamStats::new(video_decoder.as_ref().and_then(|decoder| decoder.codec()));
+    let mut audio_stats = StreamStats::new(audio_decoder.codec());
+
+    let mut video_frame = frame::Video::empty();
+    let mut audio_frame = frame::Audio::empty();
+
+    let start = Instant::now();
+
+    for (stream, packet) in asset.packets() {
+        match stream.index() {
+            idx if Some(idx) == metadata.video_stream_index() => {
+                let video_decoder = match &mut video_decoder {
+                    Some(decoder) => decoder,
+                    None => continue,
+                };
+                let started = Instant::now();
+                video_decoder.send_packet(&packet)?;
+                video_stats.frames += receive_video(video_decoder, &mut video_frame);
+                video_stats.decode_time += started.elapsed();
+                video_stats.packets += 1;
+            }
+            idx if Some(idx) == metadata.audio_stream_index() => {
+                let started = Instant::now();
+                audio_decoder.send_packet(&packet)?;
+                audio_stats.frames += receive_audio(&mut audio_decoder, &mut audio_frame);
+                audio_stats.decode_time += started.elapsed();
+                audio_stats.packets += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // Drain frames still held by the decoders
+    if let Some(video_decoder) = &mut video_decoder {
+        let started = Instant::now();
+        video_decoder.send_eof()?;
+        video_stats.frames += receive_video(video_decoder, &mut video_frame);
+        video_stats.decode_time += started.elapsed();
+    }
+
+    let started = Instant::now();
+    audio_decoder.send_eof()?;
+    audio_stats.frames += receive_audio(&mut audio_decoder, &mut audio_frame);
+    audio_stats.decode_time += started.elapsed();
+
+    let total = start.elapsed().as_secs_f64();
+
+    info!("benchmark: {}", asset.path());
+    if video_decoder.is_some() {
+        video_stats.print("video");
+    }
+    audio_stats.print("audio");
+    info!(
+        "total  {:.3}s wall clock, {:.1} video fps overall",
+        total,
+        video_stats.frames as f64 / total.max(f64::EPSILON)
+    );
+
+    Ok(())
+}
+
+fn receive_video(decoder: &mut decoder::Video, frame: &mut frame::Video) -> usize {
+    let mut count = 0;
+    while decoder.receive_frame(frame).is_ok() {
+        count += 1;
+    }
+    count
+}
+
+fn receive_audio(decoder: &mut decoder::Audio, frame: &mut frame::Audio) -> usize {
+    let mut count = 0;
+    while decoder.receive_frame(frame).is_ok() {
+        count += 1;
+    }
+    count
+}
@@ -0,0 +1,344 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::{
+    equalizer,
+    options::CompareView,
+    picture::{PictureProperty, PICTURE_STEP},
+    player::{PlayerCommand, AUDIO_DELAY_STEP, MEDIA_KEY_SKIP, VOLUME_STEP},
+};
+
+/// A key pressed with modifiers, written like `ctrl+shift+s`. Keys go by their SDL names in
+/// lowercase: `a`, `1`, `-`, `space`, `left`, `escape`, `keypad +`, `audioplay`, ... The
+/// mouse has `wheel-up`, `wheel-down`, `wheel-left`, `wheel-right`, `mouse-middle` and
+/// `mouse-right`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct KeyChord {
+    pub key: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyChord {
+    pub fn new(key: &str) -> Self {
+        KeyChord {
+            key: key.to_lowercase(),
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut chord = KeyChord::new("");
+        let mut rest = value.trim();
+
+        // modifiers come first, so `ctrl++` is ctrl and plus
+        while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" => chord.ctrl = true,
+                "alt" => chord.alt = true,
+                "shift" => chord.shift = true,
+                _ => break,
+            }
+            rest = key;
+        }
+
+        if rest.is_empty() {
+            return Err(format!("invalid key '{}'", value));
+        }
+        chord.key = rest.to_lowercase();
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "ctrl+"),
+            (self.alt, "alt+"),
+            (self.shift, "shift+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+/// The key a mouse wheel scroll by `x` and `y` stands for, going by its larger axis.
+/// Positive `y` scrolls up and positive `x` right.
+pub fn wheel_key(x: f64, y: f64) -> Option<&'static str> {
+    if x == 0_f64 && y == 0_f64 {
+        None
+    } else if y.abs() >= x.abs() {
+        Some(if y > 0_f64 { "wheel-up" } else { "wheel-down" })
+    } else {
+        Some(if x > 0_f64 {
+            "wheel-right"
+        } else {
+            "wheel-left"
+        })
+    }
+}
+
+/// Parses an action to bind a key to, e.g. `toggle-pause` or `seek-by -10`. `ignore` leaves
+/// the key unbound, which gives it to scripts and plugins.
+pub fn parse_action(value: &str) -> Result<Option<PlayerCommand>, String> {
+    let mut words = value.split_whitespace();
+    let name = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+    let invalid = || format!("invalid action '{}'", value);
+    let number = |index: usize| -> Result<f64, String> {
+        args.get(index)
+            .and_then(|arg| arg.parse().ok())
+            .ok_or_else(invalid)
+    };
+
+    let command = match name {
+        "ignore" => return Ok(None),
+        "play" => PlayerCommand::Play,
+        "pause" => PlayerCommand::Pause,
+        "toggle-pause" => PlayerCommand::TogglePause,
+        "stop" => PlayerCommand::Stop,
+        "open-last" => PlayerCommand::OpenLast,
+        "open-clipboard" => PlayerCommand::OpenClipboard,
+        "seek" => PlayerCommand::Seek(number(0)?),
+        "seek-by" => PlayerCommand::SeekBy(number(0)?),
+        "scan" => PlayerCommand::Scan(number(0)?),
+        "scan-forward" => PlayerCommand::ScanForward,
+        "scan-backward" => PlayerCommand::ScanBackward,
+        "audio-delay" => PlayerCommand::AudioDelay(number(0)?),
+        "audio-delay-by" => PlayerCommand::AudioDelayBy(number(0)?),
+        // in percent, like `--volume`
+        "volume" => PlayerCommand::Volume(number(0)? / 100_f64),
+        "volume-by" => PlayerCommand::VolumeBy(number(0)? / 100_f64),
+        "toggle-mute" => PlayerCommand::ToggleMute,
+        "toggle-controls" => PlayerCommand::ToggleControls,
+        "toggle-fullscreen" => PlayerCommand::ToggleFullscreen,
+        "toggle-ontop" => PlayerCommand::ToggleOnTop,
+        "window-scale" => PlayerCommand::WindowScale(number(0)?),
+        "mark-in" => PlayerCommand::MarkIn,
+        "mark-out" => PlayerCommand::MarkOut,
+        "export" => PlayerCommand::Export,
+        "eq-preset" => PlayerCommand::EqualizerPreset(args.first().ok_or_else(invalid)?.parse()?),
+        "cycle-equalizer-preset" => PlayerCommand::CycleEqualizerPreset,
+        // `eq-gain 1000 3`, the band by its frequency in Hz
+        "eq-gain" => PlayerCommand::EqualizerGain {
+            band: equalizer::parse_band(args.first().ok_or_else(invalid)?)?,
+            gain: number(1)?,
+        },
+        "toggle-deinterlace" => PlayerCommand::ToggleDeinterlace,
+        "toggle-autocrop" => PlayerCommand::ToggleAutocrop,
+        "toggle-spectrogram" => PlayerCommand::ToggleSpectrogram,
+        "toggle-level-meter" => PlayerCommand::ToggleLevelMeter,
+        "focus-next-tile" => PlayerCommand::FocusNextTile,
+        "toggle-tile-expanded" => PlayerCommand::ToggleTileExpanded,
+        "toggle-chapter-menu" => PlayerCommand::ToggleChapterMenu,
+        "menu-up" => PlayerCommand::MenuUp,
+        "menu-down" => PlayerCommand::MenuDown,
+        "menu-select" => PlayerCommand::MenuSelect,
+        "toggle-cast" => PlayerCommand::ToggleCast,
+        "toggle-recording" => PlayerCommand::ToggleRecording,
+        "go-live" => PlayerCommand::GoLive,
+        "aspect" => PlayerCommand::AspectMode(args.first().ok_or_else(invalid)?.parse()?),
+        "cycle-aspect" => PlayerCommand::CycleAspectMode,
+        "swap-pip" => PlayerCommand::SwapPictureInPicture,
+        "compare-view" => PlayerCommand::CompareView(match args.first() {
+            Some(&"wipe") => CompareView::Wipe,
+            Some(&"a") => CompareView::First,
+            Some(&"b") => CompareView::Second,
+            Some(&"difference") => CompareView::Difference,
+            _ => return Err(invalid()),
+        }),
+        "toggle-compare-source" => PlayerCommand::ToggleCompareSource,
+        "rotate" => PlayerCommand::Rotate,
+        "flip-horizontal" => PlayerCommand::FlipHorizontal,
+        "flip-vertical" => PlayerCommand::FlipVertical,
+        "picture-by" => {
+            let property = match args.first() {
+                Some(&"contrast") => PictureProperty::Contrast,
+                Some(&"brightness") => PictureProperty::Brightness,
+                Some(&"saturation") => PictureProperty::Saturation,
+                Some(&"gamma") => PictureProperty::Gamma,
+                _ => return Err(invalid()),
+            };
+            PlayerCommand::PictureBy(property, number(1)? as i32)
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(Some(command))
+}
+
+/// Parses `--bind` values, `key=action`.
+pub fn parse_binding(value: &str) -> Result<(KeyChord, Option<PlayerCommand>), String> {
+    let (key, action) = value
+        .rsplit_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| format!("invalid key binding '{}'", value))?;
+    Ok((key.parse()?, parse_action(action)?))
+}
+
+/// What the window outputs do on key presses.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    bindings: HashMap<KeyChord, PlayerCommand>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = [
+            ("escape", PlayerCommand::Stop),
+            ("space", PlayerCommand::TogglePause),
+            ("tab", PlayerCommand::ToggleCompareSource),
+            ("backspace", PlayerCommand::OpenLast),
+            ("ctrl+v", PlayerCommand::OpenClipboard),
+            ("left", PlayerCommand::SeekBy(-5_f64)),
+            ("right", PlayerCommand::SeekBy(5_f64)),
+            ("up", PlayerCommand::SeekBy(60_f64)),
+            ("down", PlayerCommand::SeekBy(-60_f64)),
+            // media keys: with a single file, previous restarts it and next skips ahead
+            ("audioplay", PlayerCommand::TogglePause),
+            ("mediaplay", PlayerCommand::Play),
+            ("mediapause", PlayerCommand::Pause),
+            ("audiostop", PlayerCommand::Stop),
+            ("audioprev", PlayerCommand::Seek(0_f64)),
+            ("audionext", PlayerCommand::SeekBy(MEDIA_KEY_SKIP)),
+            ("audiorewind", PlayerCommand::ScanBackward),
+            ("audiofastforward", PlayerCommand::ScanForward),
+            ("[", PlayerCommand::ScanBackward),
+            ("]", PlayerCommand::ScanForward),
+            ("-", PlayerCommand::AudioDelayBy(-AUDIO_DELAY_STEP)),
+            ("keypad -", PlayerCommand::AudioDelayBy(-AUDIO_DELAY_STEP)),
+            ("=", PlayerCommand::AudioDelayBy(AUDIO_DELAY_STEP)),
+            ("+", PlayerCommand::AudioDelayBy(AUDIO_DELAY_STEP)),
+            ("keypad +", PlayerCommand::AudioDelayBy(AUDIO_DELAY_STEP)),
+            ("wheel-up", PlayerCommand::VolumeBy(VOLUME_STEP)),
+            ("wheel-down", PlayerCommand::VolumeBy(-VOLUME_STEP)),
+            ("wheel-left", PlayerCommand::SeekBy(-5_f64)),
+            ("wheel-right", PlayerCommand::SeekBy(5_f64)),
+            ("mouse-middle", PlayerCommand::ToggleMute),
+            ("mouse-right", PlayerCommand::ToggleControls),
+            ("m", PlayerCommand::ToggleMute),
+            ("shift+m", PlayerCommand::ToggleLevelMeter),
+            ("9", PlayerCommand::VolumeBy(-VOLUME_STEP)),
+            ("0", PlayerCommand::VolumeBy(VOLUME_STEP)),
+            (
+                "1",
+                PlayerCommand::PictureBy(PictureProperty::Contrast, -PICTURE_STEP),
+            ),
+            (
+                "2",
+                PlayerCommand::PictureBy(PictureProperty::Contrast, PICTURE_STEP),
+            ),
+            (
+                "3",
+                PlayerCommand::PictureBy(PictureProperty::Brightness, -PICTURE_STEP),
+            ),
+            (
+                "4",
+                PlayerCommand::PictureBy(PictureProperty::Brightness, PICTURE_STEP),
+            ),
+            (
+                "5",
+                PlayerCommand::PictureBy(PictureProperty::Saturation, -PICTURE_STEP),
+            ),
+            (
+                "6",
+                PlayerCommand::PictureBy(PictureProperty::Saturation, PICTURE_STEP),
+            ),
+            (
+                "7",
+                PlayerCommand::PictureBy(PictureProperty::Gamma, -PICTURE_STEP),
+            ),
+            (
+                "8",
+                PlayerCommand::PictureBy(PictureProperty::Gamma, PICTURE_STEP),
+            ),
+            ("a", PlayerCommand::CycleAspectMode),
+            ("c", PlayerCommand::ToggleAutocrop),
+            ("d", PlayerCommand::ToggleDeinterlace),
+            ("g", PlayerCommand::CycleEqualizerPreset),
+            ("h", PlayerCommand::FlipHorizontal),
+            ("i", PlayerCommand::MarkIn),
+            ("o", PlayerCommand::MarkOut),
+            ("p", PlayerCommand::SwapPictureInPicture),
+            ("r", PlayerCommand::Rotate),
+            ("shift+r", PlayerCommand::ToggleRecording),
+            ("l", PlayerCommand::GoLive),
+            ("s", PlayerCommand::ToggleSpectrogram),
+            ("k", PlayerCommand::ToggleCast),
+            ("n", PlayerCommand::FocusNextTile),
+            ("z", PlayerCommand::ToggleTileExpanded),
+            ("j", PlayerCommand::ToggleChapterMenu),
+            ("v", PlayerCommand::FlipVertical),
+            ("w", PlayerCommand::CompareView(CompareView::Wipe)),
+            ("x", PlayerCommand::CompareView(CompareView::Difference)),
+            ("e", PlayerCommand::Export),
+            ("f", PlayerCommand::ToggleFullscreen),
+            ("t", PlayerCommand::ToggleOnTop),
+            ("alt+0", PlayerCommand::WindowScale(0.5)),
+            ("alt+1", PlayerCommand::WindowScale(1_f64)),
+            ("alt+2", PlayerCommand::WindowScale(2_f64)),
+        ];
+
+        KeyBindings {
+            bindings: bindings
+                .iter()
+                .map(|(key, command)| (key.parse().unwrap(), *command))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// The default bindings with `overrides` applied, `None` unbinding a key.
+    pub fn new(overrides: &[(KeyChord, Option<PlayerCommand>)]) -> Self {
+        let mut bindings = Self::default();
+        for (chord, command) in overrides {
+            match command {
+                Some(command) => bindings.bindings.insert(chord.clone(), *command),
+                None => bindings.bindings.remove(chord),
+            };
+        }
+        bindings
+    }
+
+    /// These bindings with the keys for moving through a menu on top, for while one is
+    /// shown: the arrow keys and wheel move, return picks and escape closes it.
+    pub fn menu(&self) -> Self {
+        let mut bindings = self.clone();
+        for (key, command) in [
+            ("up", PlayerCommand::MenuUp),
+            ("down", PlayerCommand::MenuDown),
+            ("wheel-up", PlayerCommand::MenuUp),
+            ("wheel-down", PlayerCommand::MenuDown),
+            ("return", PlayerCommand::MenuSelect),
+            ("keypad enter", PlayerCommand::MenuSelect),
+            ("escape", PlayerCommand::ToggleChapterMenu),
+        ] {
+            bindings.bindings.insert(KeyChord::new(key), command);
+        }
+        bindings
+    }
+
+    /// What `chord` is bound to. Without a binding of its own, a chord with shift does what
+    /// its key alone does, as shift often only picks the character (`+` is shift+`=`).
+    pub fn get(&self, chord: &KeyChord) -> Option<PlayerCommand> {
+        self.bindings.get(chord).copied().or_else(|| {
+            if !chord.shift {
+                return None;
+            }
+            let unshifted = KeyChord {
+                shift: false,
+                ..chord.clone()
+            };
+            self.bindings.get(&unshifted).copied()
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ffmpeg_next::frame;
+
+/// Shared memory cap for the buffering pipeline.
+///
+/// Encoded packets and decoded frames are accounted separately: the demuxer stops reading
+/// while the whole pipeline is over budget, and the video decoder stops decoding while the
+/// decoded frames alone are. Frames are always drained by presentation, so this can't
+/// deadlock the way a single shared limit could.
+pub struct MemoryBudget {
+    limit: Option<usize>,
+    packets: AtomicUsize,
+    frames: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: Option<usize>) -> Self {
+        MemoryBudget {
+            limit,
+            packets: AtomicUsize::new(0),
+            frames: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn add_packet(&self, size: usize) {
+        self.packets.fetch_add(size, Ordering::SeqCst);
+    }
+
+    pub fn remove_packet(&self, size: usize) {
+        self.packets.fetch_sub(size, Ordering::SeqCst);
+    }
+
+    pub fn add_frame(&self, size: usize) {
+        self.frames.fetch_add(size, Ordering::SeqCst);
+    }
+
+    pub fn remove_frame(&self, size: usize) {
+        self.frames.fetch_sub(size, Ordering::SeqCst);
+    }
+
+    pub fn used(&self) -> usize {
+        self.packets.load(Ordering::SeqCst) + self.frames.load(Ordering::SeqCst)
+    }
+
+    /// Whether the demuxer should hold off reading more packets.
+    pub fn is_full(&self) -> bool {
+        self.limit.map_or(false, |limit| self.used() >= limit)
+    }
+
+    /// Whether decoders should hold off producing more frames.
+    pub fn frames_full(&self) -> bool {
+        self.limit
+            .map_or(false, |limit| self.frames.load(Ordering::SeqCst) >= limit)
+    }
+}
+
+pub fn video_frame_size(frame: &frame::Video) -> usize {
+    (0..frame.planes())
+        .map(|plane| frame.data(plane).len())
+        .sum()
+}
+
+pub fn audio_frame_size(frame: &frame::Audio) -> usize {
+    (0..frame.planes())
+        .map(|plane| frame.data(plane).len())
+        .sum()
+}
@@ -0,0 +1,78 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use ffmpeg_next::{device, format, Dictionary, Error};
+
+use crate::options::Options;
+
+/// Input device formats taken as `format:device`.
+const DEVICE_FORMATS: &[&str] = &["v4l2", "avfoundation", "dshow", "alsa", "pulse"];
+
+/// A camera, capture card or microphone, given as `v4l2:/dev/video0`, `avfoundation:0`,
+/// `dshow:video=Integrated Camera`, `alsa:hw:1,0` or `pulse:default`, after the ffmpeg
+/// input device that reads it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureDevice {
+    pub format: String,
+    pub device: String,
+}
+
+impl CaptureDevice {
+    /// Opens the device with the size, rate and format asked for with `--capture-size`,
+    /// `--capture-fps` and `--capture-format`, where it offers them.
+    pub fn open(&self, options: &Options) -> Result<format::context::Input, Error> {
+        device::register_all();
+        let input_format = device::input::video()
+            .chain(device::input::audio())
+            .find(|format| format.name() == self.format)
+            .ok_or(Error::DemuxerNotFound)?;
+
+        let mut device_options = Dictionary::new();
+        if let Some(size) = &options.capture_size {
+            device_options.set("video_size", size);
+        }
+        if let Some(fps) = options.capture_fps {
+            device_options.set("framerate", &fps.to_string());
+        }
+        if let Some(pixel_format) = &options.capture_format {
+            // v4l2 calls both compressed and raw formats the input format; dshow wants
+            // codecs apart from pixel formats
+            let key = match (self.format.as_str(), pixel_format.as_str()) {
+                ("v4l2", _) => "input_format",
+                ("dshow", "mjpeg") | ("dshow", "h264") => "vcodec",
+                _ => "pixel_format",
+            };
+            device_options.set(key, pixel_format);
+        }
+        // frames are shown as they come, not buffered up front
+        device_options.set("fflags", "nobuffer");
+        device_options.set("probesize", "32768");
+
+        Ok(format::open_with(&Path::new(&self.device), &input_format, device_options)?.input())
+    }
+}
+
+impl FromStr for CaptureDevice {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (format, device) = value
+            .split_once(':')
+            .filter(|(format, device)| DEVICE_FORMATS.contains(format) && !device.is_empty())
+            .ok_or_else(|| {
+                format!(
+                    "invalid capture device '{}', expected e.g. v4l2:/dev/video0, avfoundation:0, dshow:video=Camera or alsa:default",
+                    value
+                )
+            })?;
+        Ok(CaptureDevice {
+            format: format.to_string(),
+            device: device.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for CaptureDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.format, self.device)
+    }
+}
@@ -0,0 +1,226 @@
+//! Casting what's playing to a Chromecast or DLNA media renderer: found over mDNS or SSDP,
+//! fed from a small HTTP server for local files, and driven by the player's transport
+//! commands while the session lasts.
+
+mod chromecast;
+mod dlna;
+mod server;
+
+use std::{
+    net::{IpAddr, UdpSocket},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use self::{chromecast::Chromecast, dlna::DlnaRenderer, server::FileServer};
+
+/// How long to look for devices before giving up.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the device's position is asked for, which also keeps the connection alive.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transport commands forwarded to the device.
+#[derive(Clone, Copy, Debug)]
+pub enum CastCommand {
+    Play,
+    Pause,
+    Seek(f64),
+    SeekBy(f64),
+}
+
+/// A device playing what was cast to it, whichever kind it is.
+trait Renderer {
+    fn play(&self) -> Result<(), String>;
+    fn pause(&self) -> Result<(), String>;
+    fn seek(&self, position: f64) -> Result<(), String>;
+    /// Where the device is, in seconds.
+    fn position(&self) -> Result<f64, String>;
+    /// Ends the session, leaving the device idle.
+    fn stop(&self);
+}
+
+enum Device {
+    Chromecast(chromecast::Device),
+    Dlna(dlna::Device),
+}
+
+impl Device {
+    fn name(&self) -> &str {
+        match self {
+            Device::Chromecast(device) => &device.name,
+            Device::Dlna(device) => &device.name,
+        }
+    }
+
+    fn address(&self) -> IpAddr {
+        match self {
+            Device::Chromecast(device) => device.address,
+            Device::Dlna(device) => device.address,
+        }
+    }
+
+    fn connect(&self, url: &str, content_type: &str) -> Result<Box<dyn Renderer>, String> {
+        Ok(match self {
+            Device::Chromecast(device) => Box::new(Chromecast::connect(device, url, content_type)?),
+            Device::Dlna(device) => Box::new(DlnaRenderer::connect(device, url, content_type)?),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CastState {
+    Discovering,
+    /// Playing on the named device.
+    Casting(String),
+    Stopped,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct CastStatus {
+    pub state: CastState,
+    /// Where the device is, in seconds.
+    pub position: f64,
+}
+
+/// A cast session run in the background: finding the device, serving the input to it and
+/// passing commands on. Dropping it ends the session.
+pub struct CastSession {
+    commands: Sender<CastCommand>,
+    status: Arc<Mutex<CastStatus>>,
+}
+
+impl CastSession {
+    /// Casts `path`, a local file or a URL the device can fetch itself, from `position` to
+    /// the device whose name contains `device`, or the first one found.
+    pub fn start(path: &str, position: f64, device: Option<&str>) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let status = Arc::new(Mutex::new(CastStatus {
+            state: CastState::Discovering,
+            position,
+        }));
+
+        thread::spawn({
+            let path = path.to_string();
+            let device = device.map(str::to_string);
+            let status = Arc::clone(&status);
+            move || {
+                let state = match run(&path, position, device.as_deref(), &receiver, &status) {
+                    Ok(()) => CastState::Stopped,
+                    Err(e) => {
+                        eprintln!("cast: {}", e);
+                        CastState::Failed(e)
+                    }
+                };
+                status.lock().unwrap().state = state;
+            }
+        });
+
+        CastSession { commands, status }
+    }
+
+    pub fn send(&self, command: CastCommand) {
+        // the session may already have ended
+        self.commands.send(command).ok();
+    }
+
+    pub fn status(&self) -> CastStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+fn run(
+    path: &str,
+    position: f64,
+    name: Option<&str>,
+    commands: &Receiver<CastCommand>,
+    status: &Mutex<CastStatus>,
+) -> Result<(), String> {
+    let device = discover(name)?;
+    info!("casting to {} at {}", device.name(), device.address());
+
+    // local files are served to the device, anything else it fetches itself
+    let server = if path.contains("://") {
+        None
+    } else {
+        let address = local_address(device.address()).map_err(|e| e.to_string())?;
+        Some(FileServer::start(Path::new(path), address).map_err(|e| e.to_string())?)
+    };
+    let url = server.as_ref().map_or(path, |server| server.url());
+
+    let session = device.connect(url, server::content_type(path))?;
+    if position > 0_f64 {
+        // some renderers only seek once they've buffered, the start is better than nothing
+        if let Err(e) = session.seek(position) {
+            eprintln!("cast: failed to seek to {:.1}s: {}", position, e);
+        }
+    }
+    status.lock().unwrap().state = CastState::Casting(device.name().to_string());
+
+    loop {
+        match commands.recv_timeout(STATUS_INTERVAL) {
+            Ok(CastCommand::Play) => session.play()?,
+            Ok(CastCommand::Pause) => session.pause()?,
+            Ok(CastCommand::Seek(position)) => session.seek(position)?,
+            Ok(CastCommand::SeekBy(offset)) => session.seek(session.position()? + offset)?,
+            Err(RecvTimeoutError::Timeout) => {}
+            // the session was dropped
+            Err(RecvTimeoutError::Disconnected) => {
+                session.stop();
+                return Ok(());
+            }
+        }
+        status.lock().unwrap().position = session.position()?;
+    }
+}
+
+/// Looks for Chromecasts and DLNA renderers at once, taking whichever matching device
+/// turns up first.
+fn discover(name: Option<&str>) -> Result<Device, String> {
+    let (found, results) = mpsc::channel();
+    thread::spawn({
+        let found = found.clone();
+        let name = name.map(str::to_string);
+        move || {
+            let device = chromecast::discover(name.as_deref(), DISCOVERY_TIMEOUT);
+            found.send(device.map(|d| d.map(Device::Chromecast))).ok();
+        }
+    });
+    thread::spawn({
+        let name = name.map(str::to_string);
+        move || {
+            let device = dlna::discover(name.as_deref(), DISCOVERY_TIMEOUT);
+            found.send(device.map(|d| d.map(Device::Dlna))).ok();
+        }
+    });
+
+    // one protocol failing, e.g. multicast being blocked for it, leaves the other
+    let mut error = None;
+    for result in results {
+        match result {
+            Ok(Some(device)) => return Ok(device),
+            Ok(None) => {}
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| "no Chromecast or DLNA renderer found".to_string()))
+}
+
+/// The address of the interface `remote` is reached through, for it to connect back to.
+fn local_address(remote: IpAddr) -> std::io::Result<IpAddr> {
+    let local = if remote.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local)?;
+    // connecting a UDP socket sends nothing, it only picks the route
+    socket.connect((remote, 9))?;
+    Ok(socket.local_addr()?.ip())
+}
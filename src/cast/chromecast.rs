@@ -0,0 +1,180 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use rust_cast::{
+    channels::{
+        media::{Media, StreamType},
+        receiver::CastDeviceApp,
+    },
+    CastDevice,
+};
+
+use super::Renderer;
+
+/// What Chromecasts announce themselves as over mDNS.
+const SERVICE: &str = "_googlecast._tcp.local.";
+
+/// The receiver every session starts out talking to.
+const RECEIVER: &str = "receiver-0";
+
+pub struct Device {
+    /// The name it was given when set up, e.g. "Living Room TV".
+    pub name: String,
+    pub address: IpAddr,
+    pub port: u16,
+}
+
+/// Looks for Chromecasts on the local network for up to `timeout`, returning the first one
+/// whose name contains `name`, any case, or the first one found at all.
+pub fn discover(name: Option<&str>, timeout: Duration) -> Result<Option<Device>, String> {
+    let mdns = ServiceDaemon::new().map_err(|e| e.to_string())?;
+    let events = mdns.browse(SERVICE).map_err(|e| e.to_string())?;
+
+    let wanted = name.map(str::to_lowercase);
+    let deadline = Instant::now() + timeout;
+    let mut found = None;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let info = match events.recv_timeout(left) {
+            Ok(ServiceEvent::ServiceResolved(info)) => info,
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+        let address = match info.get_addresses().iter().next() {
+            Some(address) => *address,
+            None => continue,
+        };
+        let device = Device {
+            name: info
+                .get_property_val_str("fn")
+                .unwrap_or_else(|| info.get_hostname())
+                .to_string(),
+            address,
+            port: info.get_port(),
+        };
+        if wanted
+            .as_ref()
+            .map_or(true, |wanted| device.name.to_lowercase().contains(wanted))
+        {
+            found = Some(device);
+            break;
+        }
+    }
+
+    mdns.shutdown().ok();
+    Ok(found)
+}
+
+/// A media session on a Chromecast's default receiver app.
+pub struct Chromecast {
+    device: CastDevice<'static>,
+    transport_id: String,
+    session_id: String,
+    media_session_id: i32,
+}
+
+impl Chromecast {
+    /// Starts the default receiver on `device` and has it play `url`.
+    pub fn connect(device: &Device, url: &str, content_type: &str) -> Result<Self, String> {
+        let error = |e: rust_cast::errors::Error| e.to_string();
+
+        // Chromecasts present self-signed certificates
+        let cast =
+            CastDevice::connect_without_host_verification(device.address.to_string(), device.port)
+                .map_err(error)?;
+        cast.connection.connect(RECEIVER).map_err(error)?;
+        cast.heartbeat.ping().map_err(error)?;
+
+        let app = cast
+            .receiver
+            .launch_app(&CastDeviceApp::DefaultMediaReceiver)
+            .map_err(error)?;
+        cast.connection
+            .connect(app.transport_id.as_str())
+            .map_err(error)?;
+
+        let status = cast
+            .media
+            .load(
+                app.transport_id.as_str(),
+                app.session_id.as_str(),
+                &Media {
+                    content_id: url.to_string(),
+                    content_type: content_type.to_string(),
+                    stream_type: StreamType::Buffered,
+                    duration: None,
+                    metadata: None,
+                },
+            )
+            .map_err(error)?;
+        let media_session_id = status
+            .entries
+            .first()
+            .map(|entry| entry.media_session_id)
+            .ok_or("the receiver didn't take the media")?;
+
+        Ok(Chromecast {
+            device: cast,
+            transport_id: app.transport_id,
+            session_id: app.session_id,
+            media_session_id,
+        })
+    }
+}
+
+impl Renderer for Chromecast {
+    fn play(&self) -> Result<(), String> {
+        self.device
+            .media
+            .play(self.transport_id.as_str(), self.media_session_id)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.device
+            .media
+            .pause(self.transport_id.as_str(), self.media_session_id)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn seek(&self, position: f64) -> Result<(), String> {
+        self.device
+            .media
+            .seek(
+                self.transport_id.as_str(),
+                self.media_session_id,
+                Some(position.max(0_f64) as f32),
+                None,
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Asking also keeps the connection alive, along with a heartbeat.
+    fn position(&self) -> Result<f64, String> {
+        self.device.heartbeat.ping().map_err(|e| e.to_string())?;
+        let status = self
+            .device
+            .media
+            .get_status(self.transport_id.as_str(), Some(self.media_session_id))
+            .map_err(|e| e.to_string())?;
+        Ok(status
+            .entries
+            .first()
+            .and_then(|entry| entry.current_time)
+            .unwrap_or(0_f32) as f64)
+    }
+
+    fn stop(&self) {
+        // it may well have gone away already
+        self.device
+            .media
+            .stop(self.transport_id.as_str(), self.media_session_id)
+            .ok();
+        self.device.receiver.stop_app(self.session_id.as_str()).ok();
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use super::Renderer;
+
+/// Where UPnP devices listen for searches.
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+
+/// The service renderers are driven through.
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+
+/// How long a renderer gets to answer a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Device {
+    /// The name it goes by on the network, e.g. "Living Room TV".
+    pub name: String,
+    pub address: IpAddr,
+    /// Where its AVTransport service takes SOAP requests.
+    control_url: String,
+}
+
+/// Looks for DLNA media renderers on the local network for up to `timeout`, returning the
+/// first one whose name contains `name`, any case, or the first one found at all.
+pub fn discover(name: Option<&str>, timeout: Duration) -> Result<Option<Device>, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+        SSDP_ADDRESS, AV_TRANSPORT
+    );
+    socket
+        .send_to(search.as_bytes(), SSDP_ADDRESS)
+        .map_err(|e| e.to_string())?;
+
+    let wanted = name.map(str::to_lowercase);
+    let deadline = Instant::now() + timeout;
+    let mut seen = Vec::new();
+    let mut buffer = [0_u8; 2048];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        socket
+            .set_read_timeout(Some(left.max(Duration::from_millis(1))))
+            .map_err(|e| e.to_string())?;
+        let length = match socket.recv_from(&mut buffer) {
+            Ok((length, _)) => length,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+
+        // devices answer once per service and interface, each is only asked once
+        let response = String::from_utf8_lossy(&buffer[..length]);
+        let location = match header(&response, "location") {
+            Some(location) if !seen.contains(&location) => location,
+            _ => continue,
+        };
+        seen.push(location.clone());
+
+        // a device that can't be described is skipped, others may still answer
+        let device = match describe(&location) {
+            Ok(Some(device)) => device,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("dlna: failed to describe {}: {}", location, e);
+                continue;
+            }
+        };
+        if wanted
+            .as_ref()
+            .map_or(true, |wanted| device.name.to_lowercase().contains(wanted))
+        {
+            return Ok(Some(device));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the device description at `location`, `None` if it has no AVTransport service.
+fn describe(location: &str) -> Result<Option<Device>, String> {
+    let (status, description) = request(location, "GET", &[], "")?;
+    if status != 200 {
+        return Err(format!("HTTP {}", status));
+    }
+
+    let control = description
+        .split("<service>")
+        .skip(1)
+        .find(|service| element(service, "serviceType").map_or(false, |t| t == AV_TRANSPORT))
+        .and_then(|service| element(service, "controlURL"));
+    let control = match control {
+        Some(control) => control,
+        None => return Ok(None),
+    };
+
+    let base = element(&description, "URLBase").unwrap_or_else(|| location.to_string());
+    let (host, _) = split_url(&base).ok_or("unsupported URL base")?;
+    let address = host
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or("the device's address didn't resolve")?
+        .ip();
+
+    Ok(Some(Device {
+        name: element(&description, "friendlyName").unwrap_or_else(|| host.clone()),
+        address,
+        control_url: resolve(&base, &control),
+    }))
+}
+
+/// A renderer playing one URL, driven over its AVTransport service.
+pub struct DlnaRenderer {
+    control_url: String,
+}
+
+impl DlnaRenderer {
+    /// Has `device` play `url`.
+    pub fn connect(device: &Device, url: &str, content_type: &str) -> Result<Self, String> {
+        let renderer = DlnaRenderer {
+            control_url: device.control_url.clone(),
+        };
+
+        // some renderers refuse media without metadata saying what it is
+        let metadata = format!(
+            "<DIDL-Lite xmlns=\"urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/\" \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+             xmlns:upnp=\"urn:schemas-upnp-org:metadata-1-0/upnp/\">\
+             <item id=\"0\" parentID=\"-1\" restricted=\"1\"><dc:title>{}</dc:title>\
+             <upnp:class>object.item.videoItem</upnp:class>\
+             <res protocolInfo=\"http-get:*:{}:*\">{}</res></item></DIDL-Lite>",
+            escape(url.rsplit('/').next().unwrap_or(url)),
+            content_type,
+            escape(url)
+        );
+        renderer.action(
+            "SetAVTransportURI",
+            &[("CurrentURI", url), ("CurrentURIMetaData", &metadata)],
+        )?;
+        renderer.play()?;
+        Ok(renderer)
+    }
+
+    /// Sends `action` with `arguments` after the instance ID, returning the response body.
+    fn action(&self, action: &str, arguments: &[(&str, &str)]) -> Result<String, String> {
+        let arguments: String = arguments
+            .iter()
+            .map(|(name, value)| format!("<{0}>{1}</{0}>", name, escape(value)))
+            .collect();
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>\
+             <u:{0} xmlns:u=\"{1}\"><InstanceID>0</InstanceID>{2}</u:{0}>\
+             </s:Body></s:Envelope>",
+            action, AV_TRANSPORT, arguments
+        );
+        let soap_action = format!("\"{}#{}\"", AV_TRANSPORT, action);
+
+        let (status, response) = request(
+            &self.control_url,
+            "POST",
+            &[
+                ("Content-Type", "text/xml; charset=\"utf-8\""),
+                ("SOAPAction", &soap_action),
+            ],
+            &body,
+        )?;
+        if status != 200 {
+            let reason = element(&response, "errorDescription").unwrap_or_default();
+            return Err(format!("{} failed with HTTP {} {}", action, status, reason));
+        }
+        Ok(response)
+    }
+}
+
+impl Renderer for DlnaRenderer {
+    fn play(&self) -> Result<(), String> {
+        self.action("Play", &[("Speed", "1")]).map(|_| ())
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.action("Pause", &[]).map(|_| ())
+    }
+
+    fn seek(&self, position: f64) -> Result<(), String> {
+        let target = format_time(position.max(0_f64));
+        self.action("Seek", &[("Unit", "REL_TIME"), ("Target", &target)])
+            .map(|_| ())
+    }
+
+    fn position(&self) -> Result<f64, String> {
+        let response = self.action("GetPositionInfo", &[])?;
+        // renderers that don't know say NOT_IMPLEMENTED
+        Ok(element(&response, "RelTime")
+            .and_then(|time| parse_time(&time))
+            .unwrap_or(0_f64))
+    }
+
+    fn stop(&self) {
+        // it may well have gone away already
+        self.action("Stop", &[]).ok();
+    }
+}
+
+/// A plain HTTP/1.0 request, so the response is neither chunked nor kept alive.
+fn request(
+    url: &str,
+    method: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<(u16, String), String> {
+    let (host, path) = split_url(url).ok_or_else(|| format!("unsupported URL '{}'", url))?;
+    let address: SocketAddr = host
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("'{}' didn't resolve", host))?;
+
+    let mut stream =
+        TcpStream::connect_timeout(&address, REQUEST_TIMEOUT).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        host,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(body.as_bytes()))
+        .map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| e.to_string())?;
+    let response = String::from_utf8_lossy(&response);
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or("malformed HTTP response")?;
+    Ok((status, body.to_string()))
+}
+
+/// `http://host:port/path` into `host:port` and `/path`.
+fn split_url(url: &str) -> Option<(String, String)> {
+    let rest = url.strip_prefix("http://")?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    Some((host, path.to_string()))
+}
+
+/// `url` relative to the description's `base`.
+fn resolve(base: &str, url: &str) -> String {
+    if url.starts_with("http://") {
+        return url.to_string();
+    }
+    let root = base
+        .strip_prefix("http://")
+        .and_then(|rest| rest.find('/').map(|index| &base[..7 + index]))
+        .unwrap_or_else(|| base.trim_end_matches('/'));
+    if url.starts_with('/') {
+        format!("{}{}", root, url)
+    } else {
+        let directory = base.rsplit_once('/').map_or(root, |(directory, _)| {
+            if directory.len() < root.len() {
+                root
+            } else {
+                directory
+            }
+        });
+        format!("{}/{}", directory, url)
+    }
+}
+
+/// The value of `name` in an SSDP response, any case.
+fn header(response: &str, name: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let (header, value) = line.split_once(':')?;
+        header
+            .trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().to_string())
+    })
+}
+
+/// The text of the first `name` element in `xml`, whatever its namespace prefix. Good
+/// enough for the flat documents renderers send, no more.
+fn element(xml: &str, name: &str) -> Option<String> {
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>')?;
+        let tag = &rest[..end];
+        let local = tag.split_whitespace().next()?;
+        let local = local.rsplit(':').next()?;
+        if local == name && !tag.ends_with('/') {
+            let content = &rest[end + 1..];
+            let close = content.find("</")?;
+            return Some(unescape(content[..close].trim()));
+        }
+        rest = &rest[end + 1..];
+    }
+    None
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Seconds as the `H:MM:SS` UPnP takes.
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// `H:MM:SS[.fraction]` into seconds.
+fn parse_time(time: &str) -> Option<f64> {
+    let mut parts = time.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600_f64 + minutes * 60_f64 + seconds)
+}
@@ -0,0 +1,188 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the accept loop looks whether the server was dropped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Serves one local file over HTTP for a cast device to fetch, with range requests so it
+/// can seek. It stops once dropped.
+pub struct FileServer {
+    url: String,
+    stopped: Arc<AtomicBool>,
+}
+
+impl FileServer {
+    /// Serves `path` on `address`, the interface the cast device reaches this machine on,
+    /// at a port picked by the system.
+    pub fn start(path: &Path, address: IpAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::new(address, 0))?;
+        listener.set_nonblocking(true)?;
+        let name = path
+            .file_name()
+            .map_or("media".into(), |name| name.to_string_lossy());
+        let url = format!(
+            "http://{}/{}",
+            listener.local_addr()?,
+            percent_encode(&name)
+        );
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let path = path.to_path_buf();
+            let stopped = Arc::clone(&stopped);
+            move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let path = path.clone();
+                            thread::spawn(move || {
+                                // a device that hangs up mid-transfer, e.g. to seek, is no error
+                                handle_connection(stream, &path).ok();
+                            });
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(POLL_INTERVAL)
+                        }
+                        Err(e) => eprintln!("cast: failed to accept a connection: {}", e),
+                    }
+                }
+            }
+        });
+
+        Ok(FileServer { url, stopped })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for FileServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// MIME type cast devices go by, from the file's extension.
+pub fn content_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match extension.as_str() {
+        "mp4" | "m4v" | "mov" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "ts" | "m2ts" => "video/mp2t",
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" => "audio/mp4",
+        "flac" => "audio/flac",
+        "ogg" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, path: &Path) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let head_only = request_line.starts_with("HEAD ");
+
+    // only the range matters of the headers
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = parse_range(value.trim());
+            }
+        }
+    }
+
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let content_type = content_type(&path.to_string_lossy());
+
+    let (status, start, end) = match range {
+        Some((start, _)) if start >= size => {
+            write!(
+                stream,
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n",
+                size
+            )?;
+            return Ok(());
+        }
+        Some((start, end)) => (
+            "206 Partial Content",
+            start,
+            end.unwrap_or(size - 1).min(size - 1),
+        ),
+        None => ("200 OK", 0, size.saturating_sub(1)),
+    };
+    let length = if size == 0 { 0 } else { end - start + 1 };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n\
+         Access-Control-Allow-Origin: *\r\nConnection: close\r\n",
+        status, content_type, length
+    )?;
+    if range.is_some() {
+        write!(
+            stream,
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start, end, size
+        )?;
+    }
+    write!(stream, "\r\n")?;
+
+    if !head_only {
+        file.seek(SeekFrom::Start(start))?;
+        io::copy(&mut file.take(length), &mut stream)?;
+    }
+    stream.flush()
+}
+
+/// `bytes=start-[end]`; suffix ranges (`bytes=-500`) and several ranges aren't used by
+/// cast devices, so they're served as the whole file.
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    if end.map_or(false, |end| end < start) {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn percent_encode(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,181 @@
+use crate::{
+    clock::format_time,
+    font,
+    ordered_chapters::ChapterMark,
+    osd::{Color, OsdTarget},
+};
+
+const SCALE: u32 = 2;
+const PADDING: u32 = 6;
+/// Entries shown at once, the list scrolling along with the selection.
+const ROWS: usize = 12;
+/// Longest label drawn, in characters, so titles can't run off the screen.
+const MAX_LABEL: usize = 48;
+
+const BACKGROUND: Color = Color::rgba(0, 0, 0, 192);
+const SELECTION: Color = Color::rgba(255, 255, 255, 64);
+const FOREGROUND: Color = Color::rgba(255, 255, 255, 255);
+
+/// What picking an entry of the menu does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MenuChoice {
+    /// Plays the edition with this index instead.
+    Edition(usize),
+    /// Seeks to this position, in seconds.
+    Chapter(f64),
+}
+
+struct Entry {
+    label: String,
+    choice: MenuChoice,
+}
+
+/// A list of the input's chapters to jump to, and of its editions to switch to when it has
+/// more than one, drawn in the middle of the OSD while shown. The arrow keys move through
+/// it then, see `KeyBindings::menu`.
+pub(crate) struct ChapterMenu {
+    visible: bool,
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl ChapterMenu {
+    pub fn new() -> Self {
+        ChapterMenu {
+            visible: false,
+            entries: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    /// Lists `chapters`, after `editions` when there's a choice of them, `edition` being
+    /// the one played.
+    pub fn set_entries(
+        &mut self,
+        editions: &[String],
+        edition: Option<usize>,
+        chapters: &[ChapterMark],
+    ) {
+        self.entries.clear();
+        if editions.len() > 1 {
+            for (index, name) in editions.iter().enumerate() {
+                let playing = if Some(index) == edition {
+                    " (PLAYING)"
+                } else {
+                    ""
+                };
+                self.entries.push(Entry {
+                    label: format!("EDITION {}: {}{}", index + 1, name, playing),
+                    choice: MenuChoice::Edition(index),
+                });
+            }
+        }
+        for (index, chapter) in chapters.iter().enumerate() {
+            let label = match &chapter.title {
+                Some(title) => format!("{} {}", format_time(chapter.position), title),
+                None => format!("{} CHAPTER {}", format_time(chapter.position), index + 1),
+            };
+            self.entries.push(Entry {
+                label,
+                choice: MenuChoice::Chapter(chapter.position),
+            });
+        }
+        self.selected = 0;
+    }
+
+    /// Whether there's nothing to choose from, so the menu isn't worth showing.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Shows the menu with the chapter playing at `position` selected, or hides it,
+    /// returning whether it's shown now.
+    pub fn toggle(&mut self, position: f64) -> bool {
+        self.visible = !self.visible && !self.entries.is_empty();
+        if self.visible {
+            self.selected = self
+                .entries
+                .iter()
+                .rposition(|entry| match entry.choice {
+                    MenuChoice::Chapter(start) => start <= position,
+                    MenuChoice::Edition(_) => false,
+                })
+                .unwrap_or(0);
+        }
+        self.visible
+    }
+
+    pub fn hide(&mut self) {
+        self.visible = false;
+    }
+
+    /// Moves the selection by `step` entries, wrapping around at either end.
+    pub fn move_by(&mut self, step: isize) {
+        let count = self.entries.len() as isize;
+        if count > 0 {
+            self.selected = (self.selected as isize + step).rem_euclid(count) as usize;
+        }
+    }
+
+    /// What the selected entry does, `None` while hidden.
+    pub fn choice(&self) -> Option<MenuChoice> {
+        if !self.visible {
+            return None;
+        }
+        self.entries.get(self.selected).map(|entry| entry.choice)
+    }
+
+    pub fn draw<T: OsdTarget>(&self, target: &mut T) {
+        if !self.visible || self.entries.is_empty() {
+            return;
+        }
+
+        // the rows around the selection, as many of them as fit
+        let first = self
+            .selected
+            .saturating_sub(ROWS / 2)
+            .min(self.entries.len().saturating_sub(ROWS));
+        let rows: Vec<(usize, String)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(ROWS)
+            .map(|(index, entry)| (index, entry.label.chars().take(MAX_LABEL).collect()))
+            .collect();
+
+        let row_height = font::text_height(SCALE) + 2 * PADDING;
+        let width = rows
+            .iter()
+            .map(|(_, label)| font::text_width(label, SCALE))
+            .max()
+            .unwrap_or(0)
+            + 2 * PADDING;
+        let height = rows.len() as u32 * row_height;
+        let (target_width, target_height) = target.size();
+        let x = (target_width as i32 - width as i32) / 2;
+        let y = (target_height as i32 - height as i32) / 2;
+        target.fill_rect(x, y, width, height, BACKGROUND);
+
+        for (row, (index, label)) in rows.iter().enumerate() {
+            let row_y = y + (row as u32 * row_height) as i32;
+            if *index == self.selected {
+                target.fill_rect(x, row_y, width, row_height, SELECTION);
+            }
+            let origin = (x + PADDING as i32, row_y + PADDING as i32);
+            font::render(label, 0, 0, SCALE, |px, py, size| {
+                target.fill_rect(
+                    origin.0 + px as i32,
+                    origin.1 + py as i32,
+                    size,
+                    size,
+                    FOREGROUND,
+                );
+            });
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Media position driven by wall-clock time, which can be re-anchored after seeks and
+/// frozen while paused.
+pub struct PlaybackClock {
+    anchor_time: Instant,
+    anchor_position: f64,
+    paused: bool,
+}
+
+impl PlaybackClock {
+    pub fn new() -> Self {
+        PlaybackClock {
+            anchor_time: Instant::now(),
+            anchor_position: 0_f64,
+            paused: false,
+        }
+    }
+
+    /// Current media position in seconds.
+    pub fn position(&self) -> f64 {
+        if self.paused {
+            self.anchor_position
+        } else {
+            self.anchor_position + self.anchor_time.elapsed().as_secs_f64()
+        }
+    }
+
+    pub fn set_position(&mut self, position: f64) {
+        self.anchor_time = Instant::now();
+        self.anchor_position = position;
+    }
+
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.anchor_position = self.position();
+            self.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.anchor_time = Instant::now();
+            self.paused = false;
+        }
+    }
+}
+
+/// Drives playback from outside instead of wall-clock time since playback started, to
+/// genlock the player to other equipment, e.g. from decoded timecode. Closures returning
+/// `Option<f64>` are external clocks too.
+pub trait ExternalClock: Send {
+    /// Media position in seconds playback should be at right now, or `None` to hold the
+    /// picture until the source has a time again.
+    fn position(&mut self) -> Option<f64>;
+}
+
+impl<F: FnMut() -> Option<f64> + Send> ExternalClock for F {
+    fn position(&mut self) -> Option<f64> {
+        self()
+    }
+}
+
+/// Follows the system's real-time clock: the position is the time since `epoch`, in seconds
+/// since 1970, looped over the duration when there is one. Machines whose clocks are kept
+/// in step by PTP or NTP show the same frame at the same time.
+pub struct SystemClock {
+    epoch: f64,
+    duration: Option<f64>,
+}
+
+impl SystemClock {
+    pub fn new(epoch: f64, duration: Option<f64>) -> Self {
+        SystemClock { epoch, duration }
+    }
+}
+
+impl ExternalClock for SystemClock {
+    fn position(&mut self) -> Option<f64> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let elapsed = now.as_secs_f64() - self.epoch;
+        if elapsed < 0_f64 {
+            return None;
+        }
+        Some(match self.duration {
+            Some(duration) if duration > 0_f64 => elapsed % duration,
+            _ => elapsed,
+        })
+    }
+}
+
+/// Formats a media position as `hh:mm:ss`.
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0_f64) as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,270 @@
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    software::scaling::{self, Flags},
+};
+
+use crate::video_sink::display_aspect;
+
+/// The inset is this fraction of the main picture's width.
+const INSET_SCALE: u32 = 4;
+
+/// Luma of the wipe line, limited range white.
+const LINE_LUMA: u8 = 235;
+
+/// Differences are multiplied by this before coloring, small ones are what matter.
+const DIFFERENCE_GAIN: u32 = 8;
+
+/// Width of the frame around the focused mosaic tile, even.
+const FOCUS_BORDER: u32 = 4;
+
+/// Combines the main input's frames with a second input's, in the YUV420P the video sinks
+/// take. Scalers are kept between frames and only rebuilt when sizes change.
+pub struct Compositor {
+    main_scaler: Option<scaling::Context>,
+    second_scaler: Option<scaling::Context>,
+    heatmap_scaler: Option<scaling::Context>,
+    tile_scalers: Vec<Option<scaling::Context>>,
+}
+
+impl Compositor {
+    pub fn new() -> Self {
+        Compositor {
+            main_scaler: None,
+            second_scaler: None,
+            heatmap_scaler: None,
+            tile_scalers: Vec::new(),
+        }
+    }
+
+    /// `main` with `inset` shrunk into its bottom right corner. Timestamps are left to the
+    /// caller.
+    pub fn picture_in_picture(&mut self, main: &Video, inset: &Video) -> Option<Video> {
+        let mut picture = scale(
+            &mut self.main_scaler,
+            main,
+            main.width() & !1,
+            main.height() & !1,
+        )?;
+        copy_properties(main, &mut picture);
+
+        let width = (picture.width() / INSET_SCALE).max(2) & !1;
+        let height =
+            ((width as f64 / display_aspect(inset)) as u32).clamp(2, picture.height()) & !1;
+        let inset = scale(&mut self.second_scaler, inset, width, height)?;
+
+        let margin = (picture.width() / 40) & !1;
+        let x = picture.width() - width - margin;
+        let y = picture.height().saturating_sub(height + margin) & !1;
+        paste(&mut picture, &inset, x, y);
+        Some(picture)
+    }
+
+    /// `frame` on its own, at the size of `like`.
+    pub fn full(&mut self, frame: &Video, like: &Video) -> Option<Video> {
+        let mut picture = scale(
+            &mut self.second_scaler,
+            frame,
+            like.width() & !1,
+            like.height() & !1,
+        )?;
+        copy_properties(like, &mut picture);
+        Some(picture)
+    }
+
+    /// `tiles` in a grid at the size of `like`, with as many columns as rows or one more,
+    /// each fit into its cell. Tiles without a frame yet stay black, and the one at `focus`
+    /// is framed in white.
+    pub fn mosaic(
+        &mut self,
+        tiles: &[Option<&Video>],
+        focus: Option<usize>,
+        like: &Video,
+    ) -> Option<Video> {
+        let (width, height) = (like.width() & !1, like.height() & !1);
+        let mut picture = Video::new(Pixel::YUV420P, width, height);
+        picture.set_color_space(like.color_space());
+        picture.set_color_range(like.color_range());
+        // limited range black
+        picture.data_mut(0).fill(16);
+        picture.data_mut(1).fill(128);
+        picture.data_mut(2).fill(128);
+
+        let columns = (tiles.len() as f64).sqrt().ceil().max(1_f64) as u32;
+        let rows = (tiles.len() as u32 + columns - 1) / columns;
+        let (cell_width, cell_height) = ((width / columns) & !1, (height / rows.max(1)) & !1);
+        self.tile_scalers.resize_with(tiles.len(), || None);
+
+        for (index, tile) in tiles.iter().enumerate() {
+            let x = (index as u32 % columns) * cell_width;
+            let y = (index as u32 / columns) * cell_height;
+
+            if let Some(tile) = tile {
+                let aspect = display_aspect(tile);
+                let (mut tile_width, mut tile_height) =
+                    (cell_width, (cell_width as f64 / aspect) as u32);
+                if tile_height > cell_height {
+                    tile_height = cell_height;
+                    tile_width = (cell_height as f64 * aspect) as u32;
+                }
+                let (tile_width, tile_height) = (tile_width.max(2) & !1, tile_height.max(2) & !1);
+                let scaled = scale(&mut self.tile_scalers[index], tile, tile_width, tile_height)?;
+                let left = x + (((cell_width - tile_width) / 2) & !1);
+                let top = y + (((cell_height - tile_height) / 2) & !1);
+                paste(&mut picture, &scaled, left, top);
+            }
+
+            if focus == Some(index) && tiles.len() > 1 {
+                frame_cell(&mut picture, x, y, cell_width, cell_height);
+            }
+        }
+        Some(picture)
+    }
+
+    /// Heatmap of the luma difference between `first` and `second`, scaled to the same
+    /// size: black where they match, through red and yellow to white where they differ most.
+    pub fn difference(&mut self, first: &Video, second: &Video) -> Option<Video> {
+        let (width, height) = (first.width() & !1, first.height() & !1);
+        let first_scaled = scale(&mut self.main_scaler, first, width, height)?;
+        let second = scale(&mut self.second_scaler, second, width, height)?;
+
+        let mut heatmap = Video::new(Pixel::RGB24, width, height);
+        let (first_stride, second_stride) = (first_scaled.stride(0), second.stride(0));
+        let heatmap_stride = heatmap.stride(0);
+        for row in 0..height as usize {
+            let a = &first_scaled.data(0)[row * first_stride..][..width as usize];
+            let b = &second.data(0)[row * second_stride..][..width as usize];
+            let line = &mut heatmap.data_mut(0)[row * heatmap_stride..][..width as usize * 3];
+            for ((a, b), pixel) in a.iter().zip(b).zip(line.chunks_exact_mut(3)) {
+                let value = (*a as i32 - *b as i32).unsigned_abs() * DIFFERENCE_GAIN;
+                let channel = |offset: u32| value.saturating_sub(offset).min(85) as u8 * 3;
+                pixel.copy_from_slice(&[channel(0), channel(85), channel(170)]);
+            }
+        }
+
+        let mut picture = scale(&mut self.heatmap_scaler, &heatmap, width, height)?;
+        // colors are swscale's own, only the shape carries over
+        copy_aspect_ratio(first, &mut picture);
+        Some(picture)
+    }
+
+    /// `first` left of a vertical line at `split` (0 to 1) of the width, `second` scaled to
+    /// the same size right of it.
+    pub fn wipe(&mut self, first: &Video, second: &Video, split: f64) -> Option<Video> {
+        let (width, height) = (first.width() & !1, first.height() & !1);
+        let mut picture = scale(&mut self.main_scaler, first, width, height)?;
+        copy_properties(first, &mut picture);
+        let second = scale(&mut self.second_scaler, second, width, height)?;
+
+        let x = ((width as f64 * split.clamp(0_f64, 1_f64)) as u32).min(width) & !1;
+        for plane in 0..3 {
+            let shift = if plane == 0 { 0 } else { 1 };
+            let (x, width) = ((x >> shift) as usize, (width >> shift) as usize);
+            let rows = (height >> shift) as usize;
+            let (source_stride, target_stride) = (second.stride(plane), picture.stride(plane));
+            for row in 0..rows {
+                let from = row * source_stride;
+                let to = row * target_stride;
+                picture.data_mut(plane)[to + x..to + width]
+                    .copy_from_slice(&second.data(plane)[from + x..from + width]);
+            }
+        }
+
+        // a two pixel line marks the split
+        let line = (x.saturating_sub(1) as usize)..((x + 1).min(width) as usize);
+        let stride = picture.stride(0);
+        for row in 0..height as usize {
+            picture.data_mut(0)[row * stride + line.start..row * stride + line.end].fill(LINE_LUMA);
+        }
+        Some(picture)
+    }
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts `frame` to `width` x `height` YUV420P, reusing the scaler while sizes stay the
+/// same.
+pub fn scale(
+    scaler: &mut Option<scaling::Context>,
+    frame: &Video,
+    width: u32,
+    height: u32,
+) -> Option<Video> {
+    let matches = scaler.as_ref().map_or(false, |scaler| {
+        let (input, output) = (scaler.input(), scaler.output());
+        input.format == frame.format()
+            && input.width == frame.width()
+            && input.height == frame.height()
+            && output.width == width
+            && output.height == height
+    });
+    if !matches {
+        *scaler = scaling::Context::get(
+            frame.format(),
+            frame.width(),
+            frame.height(),
+            Pixel::YUV420P,
+            width,
+            height,
+            Flags::BILINEAR,
+        )
+        .ok();
+    }
+
+    let mut picture = Video::empty();
+    scaler.as_mut()?.run(frame, &mut picture).ok()?;
+    Some(picture)
+}
+
+/// Carries over what the sinks need to show a scaled copy of `source` like the original.
+fn copy_properties(source: &Video, target: &mut Video) {
+    target.set_color_space(source.color_space());
+    target.set_color_range(source.color_range());
+    copy_aspect_ratio(source, target);
+}
+
+fn copy_aspect_ratio(source: &Video, target: &mut Video) {
+    unsafe {
+        (*target.as_mut_ptr()).sample_aspect_ratio = source.aspect_ratio().into();
+    }
+}
+
+/// Draws a `FOCUS_BORDER` wide white frame just inside the `width` x `height` cell at
+/// (`x`, `y`), on the luma plane.
+fn frame_cell(target: &mut Video, x: u32, y: u32, width: u32, height: u32) {
+    let stride = target.stride(0);
+    let (x, y) = (x as usize, y as usize);
+    let (width, height, border) = (width as usize, height as usize, FOCUS_BORDER as usize);
+    for row in y..y + height {
+        let line = &mut target.data_mut(0)[row * stride + x..row * stride + x + width];
+        if row < y + border || row >= y + height - border {
+            line.fill(LINE_LUMA);
+        } else {
+            line[..border].fill(LINE_LUMA);
+            line[width - border..].fill(LINE_LUMA);
+        }
+    }
+}
+
+/// Copies `source` into `target` at (`x`, `y`), both YUV420P and the position even.
+fn paste(target: &mut Video, source: &Video, x: u32, y: u32) {
+    for plane in 0..3 {
+        let shift = if plane == 0 { 0 } else { 1 };
+        let (x, y) = ((x >> shift) as usize, (y >> shift) as usize);
+        let width = (source.width() >> shift) as usize;
+        let height = (source.height() >> shift) as usize;
+
+        let source_stride = source.stride(plane);
+        let target_stride = target.stride(plane);
+        for row in 0..height {
+            let from = row * source_stride;
+            let to = (y + row) * target_stride + x;
+            let line = &source.data(plane)[from..from + width];
+            target.data_mut(plane)[to..to + width].copy_from_slice(line);
+        }
+    }
+}
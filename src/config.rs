@@ -0,0 +1,178 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use toml::{Table, Value};
+
+use crate::equalizer::Equalizer;
+
+/// Reads the TOML config file at `path` into the command line arguments it stands for, to be
+/// parsed ahead of the real ones so that those override it. Keys are option names without
+/// the dashes, and the `[keys]` table holds key bindings:
+///
+/// ```toml
+/// volume = 80
+/// cache-mem = 268435456
+/// hr-seek = true
+/// shader = ["crt.glsl", "sharpen.glsl"]
+///
+/// [keys]
+/// "ctrl+right" = "seek-by 60"
+/// ```
+///
+/// `true` turns a flag on and `false` leaves it off, arrays repeat their option, and each
+/// key binding becomes `--bind key=action`.
+pub fn load(path: &Path) -> Result<Vec<String>, String> {
+    let error = |e: &dyn ToString| format!("{}: {}", path.display(), e.to_string());
+    let text = fs::read_to_string(path).map_err(|e| error(&e))?;
+    let table = text.parse::<Table>().map_err(|e| error(&e))?;
+
+    let mut args = Vec::new();
+    for (name, value) in table {
+        match (name.as_str(), value) {
+            ("keys", Value::Table(bindings)) => {
+                for (key, action) in bindings {
+                    match action {
+                        Value::String(action) => args.push(format!("--bind={}={}", key, action)),
+                        _ => return Err(error(&format!("binding for '{}' isn't a string", key))),
+                    }
+                }
+            }
+            (_, Value::Array(values)) => {
+                for value in values {
+                    push_option(&mut args, &name, value).map_err(|e| error(&e))?;
+                }
+            }
+            (_, value) => push_option(&mut args, &name, value).map_err(|e| error(&e))?,
+        }
+    }
+    Ok(args)
+}
+
+fn push_option(args: &mut Vec<String>, name: &str, value: Value) -> Result<(), String> {
+    match value {
+        Value::Boolean(true) => args.push(format!("--{}", name)),
+        Value::Boolean(false) => {}
+        Value::String(value) => args.push(format!("--{}={}", name, value)),
+        Value::Integer(value) => args.push(format!("--{}={}", name, value)),
+        Value::Float(value) => args.push(format!("--{}={}", name, value)),
+        Value::Datetime(value) => args.push(format!("--{}={}", name, value)),
+        Value::Array(_) | Value::Table(_) => {
+            return Err(format!("unexpected value for '{}'", name));
+        }
+    }
+    Ok(())
+}
+
+/// Saves `equalizer` to the config file at `path` as its preset, or as its gains once a band
+/// has been changed by hand, creating the file if needed. Everything else in the file,
+/// comments included, is left as it is.
+pub fn save_equalizer(path: &Path, equalizer: &Equalizer) -> io::Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let text = match equalizer.preset() {
+        Some(preset) => {
+            let text = set_key(&text, "eq", Some(Value::from(preset.name())));
+            set_key(&text, "eq-gains", None)
+        }
+        None => {
+            let gains: Vec<String> = equalizer.gains().iter().map(f64::to_string).collect();
+            set_key(&text, "eq-gains", Some(Value::from(gains.join(","))))
+        }
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+/// Sets top-level `key` in the TOML `text` to `value`, or removes it for `None`, keeping the
+/// other lines. A new key goes at the end of the top-level keys, ahead of the first table.
+fn set_key(text: &str, key: &str, value: Option<Value>) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let top_level = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level].iter().position(|line| {
+        line.split_once('=').map_or(false, |(name, _)| {
+            let name = name.trim();
+            name == key || name.trim_matches('"') == key
+        })
+    });
+
+    match (existing, value) {
+        (Some(index), Some(value)) => lines[index] = format!("{} = {}", key, value),
+        (Some(index), None) => {
+            lines.remove(index);
+        }
+        (None, Some(value)) => {
+            // after the last top-level key, not among the blank lines and comments ahead
+            // of a table
+            let index = lines[..top_level]
+                .iter()
+                .rposition(|line| {
+                    let line = line.trim();
+                    !line.is_empty() && !line.starts_with('#')
+                })
+                .map_or(0, |index| index + 1);
+            lines.insert(index, format!("{} = {}", key, value));
+        }
+        (None, None) => {}
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str =
+        "# defaults\nvolume = 80\neq = \"bass\"\n\n# bindings\n[keys]\neq = \"stop\"\n";
+
+    #[test]
+    fn replaces_top_level_keys_only() {
+        let text = set_key(CONFIG, "eq", Some(Value::from("vocal")));
+
+        assert_eq!(
+            text,
+            "# defaults\nvolume = 80\neq = \"vocal\"\n\n# bindings\n[keys]\neq = \"stop\"\n"
+        );
+    }
+
+    #[test]
+    fn adds_keys_after_the_last_top_level_one() {
+        let text = set_key(CONFIG, "eq-gains", Some(Value::from("1,2")));
+
+        assert_eq!(
+            text,
+            "# defaults\nvolume = 80\neq = \"bass\"\neq-gains = \"1,2\"\n\n# bindings\n[keys]\n\
+             eq = \"stop\"\n"
+        );
+        assert_eq!(
+            set_key("", "eq", Some(Value::from("flat"))),
+            "eq = \"flat\"\n"
+        );
+    }
+
+    #[test]
+    fn removes_keys() {
+        let text = set_key(CONFIG, "eq", None);
+
+        assert_eq!(
+            text,
+            "# defaults\nvolume = 80\n\n# bindings\n[keys]\neq = \"stop\"\n"
+        );
+        assert_eq!(set_key(CONFIG, "missing", None), CONFIG);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{path::Path, str::FromStr};
+
+use ffmpeg_next::{
+    format::{self, Pixel},
+    frame,
+    media::Type,
+    software::scaling::{self, flag::Flags},
+    Error,
+};
+
+use crate::{
+    clock::format_time,
+    dump::{self, ImageFormat},
+    font,
+};
+
+const BACKGROUND: [u8; 3] = [24, 24, 24];
+const LABEL_COLOR: [u8; 3] = [255, 255, 255];
+const SPACING: u32 = 4;
+const LABEL_SCALE: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl FromStr for Grid {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid grid '{}', expected e.g. 5x5", value);
+
+        let mut parts = value.split('x');
+        let columns = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let rows = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() || columns == 0 || rows == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Grid { columns, rows })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ContactSheetSettings {
+    pub grid: Grid,
+    pub cell_width: u32,
+}
+
+/// Seeks across the whole input, grabbing one frame per grid cell, and writes the
+/// composited sheet (each cell labeled with its timestamp) to `output`.
+pub fn generate(
+    input_path: &str,
+    output: &Path,
+    settings: &ContactSheetSettings,
+) -> Result<(), Error> {
+    let mut input = format::input(&Path::new(input_path))?;
+    let (stream_index, time_base, mut decoder) = {
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or(Error::StreamNotFound)?;
+        let decoder = stream.codec().decoder().video()?;
+        (stream.index(), f64::from(stream.time_base()), decoder)
+    };
+    let duration = input.duration() as f64 / f64::from(ffmpeg_next::ffi::AV_TIME_BASE);
+
+    let Grid { columns, rows } = settings.grid;
+    let cell_width = settings.cell_width;
+    let cell_height =
+        ((decoder.height() as f64 * cell_width as f64 / decoder.width() as f64) as u32 / 2) * 2;
+
+    let sheet_width = columns * (cell_width + SPACING) + SPACING;
+    let sheet_height = rows * (cell_height + SPACING) + SPACING;
+    let mut sheet = frame::Video::new(Pixel::RGB24, sheet_width, sheet_height);
+    fill_rect(&mut sheet, 0, 0, sheet_width, sheet_height, BACKGROUND);
+
+    let mut scaler = scaling::Context::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::RGB24,
+        cell_width,
+        cell_height,
+        Flags::BILINEAR,
+    )?;
+
+    let cells = columns * rows;
+    let mut decoded = frame::Video::empty();
+    let mut scaled = frame::Video::empty();
+
+    for cell in 0..cells {
+        // Sample the middle of each equal slice of the file
+        let target = duration * (cell as f64 + 0.5) / cells as f64;
+        let position = (target * f64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i64;
+        input.seek(position, ..position)?;
+        decoder.flush();
+
+        let mut found = None;
+        for (stream, packet) in input.packets() {
+            if stream.index() != stream_index {
+                continue;
+            }
+
+            decoder.send_packet(&packet)?;
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                let seconds = decoded
+                    .timestamp()
+                    .map(|pts| pts as f64 * time_base)
+                    .unwrap_or(target);
+                if seconds >= target {
+                    found = Some(seconds);
+                    break;
+                }
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+
+        let seconds = match found {
+            Some(seconds) => seconds,
+            None => continue,
+        };
+        scaler.run(&decoded, &mut scaled)?;
+
+        let x = SPACING + (cell % columns) * (cell_width + SPACING);
+        let y = SPACING + (cell / columns) * (cell_height + SPACING);
+        blit(&scaled, &mut sheet, x, y);
+        draw_label(
+            &mut sheet,
+            x + 4,
+            y + cell_height - 4,
+            &format_time(seconds),
+        );
+
+        info!(
+            "contact sheet cell {}/{} at {}",
+            cell + 1,
+            cells,
+            format_time(seconds)
+        );
+    }
+
+    let format = ImageFormat::from_path(output).unwrap_or(ImageFormat::Png);
+    dump::save_frame(&sheet, format, output)
+}
+
+fn blit(source: &frame::Video, target: &mut frame::Video, x: u32, y: u32) {
+    let source_stride = source.stride(0);
+    let target_stride = target.stride(0);
+    let row_bytes = source.width() as usize * 3;
+
+    for row in 0..source.height() as usize {
+        let from = row * source_stride;
+        let to = (y as usize + row) * target_stride + x as usize * 3;
+        target.data_mut(0)[to..to + row_bytes]
+            .copy_from_slice(&source.data(0)[from..from + row_bytes]);
+    }
+}
+
+fn fill_rect(frame: &mut frame::Video, x: u32, y: u32, width: u32, height: u32, color: [u8; 3]) {
+    let stride = frame.stride(0);
+    let max_x = (x + width).min(frame.width());
+    let max_y = (y + height).min(frame.height());
+
+    for row in y..max_y {
+        for column in x..max_x {
+            let offset = row as usize * stride + column as usize * 3;
+            frame.data_mut(0)[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Draws `text` with its bottom-left corner at `(x, y)` on a dark backing box so it stays
+/// readable over bright frames.
+fn draw_label(frame: &mut frame::Video, x: u32, y: u32, text: &str) {
+    let top = y.saturating_sub(font::text_height(LABEL_SCALE));
+
+    fill_rect(
+        frame,
+        x.saturating_sub(LABEL_SCALE),
+        top.saturating_sub(LABEL_SCALE),
+        font::text_width(text, LABEL_SCALE) + 2 * LABEL_SCALE,
+        font::text_height(LABEL_SCALE) + 2 * LABEL_SCALE,
+        [0, 0, 0],
+    );
+
+    font::render(text, x, top, LABEL_SCALE, |x, y, size| {
+        fill_rect(frame, x, y, size, size, LABEL_COLOR)
+    });
+}
@@ -0,0 +1,100 @@
+use serde_json::{json, Value};
+
+use crate::{
+    equalizer,
+    player::{PlayerCommand, PlayerController},
+};
+
+/// Which commands a client may send.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// All of them, for clients on this machine like the IPC socket.
+    Full,
+    /// All but `load`, for the remote control, whose clients are on the network and
+    /// mustn't get the player to open local paths.
+    Remote,
+}
+
+/// Answers a JSON request like `{"command": ["seek", 10], "request_id": 1}` from the IPC
+/// socket or the remote control, with `{"error": "success", "data": ...}` echoing the
+/// `request_id` or with the error.
+///
+/// Commands are `load <path>`, `play`, `pause`, `toggle-pause`,
+/// `seek <seconds> [relative|absolute]`, `scan <speed>`, `get-position`, `get-duration`, `get-pause`,
+/// `set-volume <percent>`, `eq-preset <name>`, `eq-gain <band in Hz> <dB>` and `quit`,
+/// leaving out those `access` doesn't allow.
+pub fn respond(request: &Value, controller: &PlayerController, access: Access) -> Value {
+    let mut response = match execute(request.get("command"), controller, access) {
+        Ok(data) => json!({ "error": "success", "data": data }),
+        Err(e) => json!({ "error": e }),
+    };
+    if let Some(id) = request.get("request_id") {
+        response["request_id"] = id.clone();
+    }
+    response
+}
+
+fn execute(
+    command: Option<&Value>,
+    controller: &PlayerController,
+    access: Access,
+) -> Result<Value, String> {
+    let args = command
+        .and_then(Value::as_array)
+        .ok_or("command must be an array")?;
+    let name = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or("command must start with its name")?;
+    let number = |idx: usize| {
+        args.get(idx)
+            .and_then(Value::as_f64)
+            .ok_or_else(|| format!("{} needs a number", name))
+    };
+
+    match name {
+        "load" if access == Access::Full => {
+            let path = args
+                .get(1)
+                .and_then(Value::as_str)
+                .ok_or("load needs a path")?;
+            controller.load(path);
+        }
+        "play" => controller.play(),
+        "pause" => controller.pause(),
+        "toggle-pause" => controller.send(PlayerCommand::TogglePause),
+        "seek" => {
+            let seconds = number(1)?;
+            match args.get(2).and_then(Value::as_str).unwrap_or("relative") {
+                "relative" => controller.send(PlayerCommand::SeekBy(seconds)),
+                "absolute" => controller.seek(seconds),
+                mode => return Err(format!("unknown seek mode '{}'", mode)),
+            }
+        }
+        "scan" => controller.send(PlayerCommand::Scan(number(1)?)),
+        "get-position" => return Ok(json!(controller.status().position)),
+        "get-duration" => return Ok(json!(controller.status().duration)),
+        "get-pause" => return Ok(json!(controller.status().paused)),
+        "set-volume" => controller.send(PlayerCommand::Volume(number(1)? / 100_f64)),
+        "eq-preset" => {
+            let preset = args
+                .get(1)
+                .and_then(Value::as_str)
+                .ok_or("eq-preset needs a preset")?;
+            controller.send(PlayerCommand::EqualizerPreset(preset.parse()?));
+        }
+        "eq-gain" => {
+            let frequency = number(1)?;
+            let band = equalizer::band(frequency as u32)
+                .filter(|_| frequency.fract() == 0_f64)
+                .ok_or_else(|| format!("unknown equalizer band {}", frequency))?;
+            controller.send(PlayerCommand::EqualizerGain {
+                band,
+                gain: number(2)?,
+            });
+        }
+        "quit" => controller.stop(),
+        _ => return Err(format!("unknown command '{}'", name)),
+    }
+    Ok(Value::Null)
+}
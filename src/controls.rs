@@ -0,0 +1,394 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::format_time,
+    font,
+    osd::{Color, OsdTarget},
+    player::{MouseAction, PlayerCommand},
+};
+
+/// How long the bar stays up after the mouse last did something.
+const HIDE_AFTER: Duration = Duration::from_secs(2);
+
+/// How often dragging the seek knob while paused seeks, showing the frame under it.
+const SCRUB_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How soon after a click a second one makes it a double-click.
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(300);
+
+/// How far the mouse may move between press and release, as a fraction of the window, for
+/// it to be a click rather than a drag.
+const CLICK_SLOP: f64 = 0.01;
+
+const SCALE: u32 = 2;
+const PADDING: u32 = 8;
+const VOLUME_WIDTH: u32 = 100;
+const TRACK_HEIGHT: u32 = 4;
+const KNOB_SIZE: u32 = 12;
+
+/// The top of the volume slider, as the player's linear volume.
+const MAX_VOLUME: f64 = 2_f64;
+
+const BACKGROUND: Color = Color::rgba(0, 0, 0, 160);
+const TRACK: Color = Color::rgba(255, 255, 255, 64);
+const BUFFERED: Color = Color::rgba(255, 255, 255, 112);
+const FOREGROUND: Color = Color::rgba(255, 255, 255, 255);
+
+/// What the bar shows, as of the player's main loop's last pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ControlState {
+    pub position: f64,
+    pub duration: Option<f64>,
+    /// How far decoded video reaches, in seconds.
+    pub buffered: f64,
+    pub paused: bool,
+    pub volume: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Part {
+    PlayPause,
+    Seek,
+    Volume,
+}
+
+/// Where everything goes on a `width` x `height` target, as `(x, y, width, height)`.
+struct Layout {
+    bar: (i32, i32, u32, u32),
+    button: (i32, i32, u32, u32),
+    time: (i32, i32),
+    seek: (i32, i32, u32, u32),
+    volume: (i32, i32, u32, u32),
+}
+
+impl Layout {
+    fn new(width: u32, height: u32, time_width: u32) -> Self {
+        let bar_height = font::text_height(SCALE) + 2 * PADDING;
+        let y = height as i32 - bar_height as i32;
+        let time_x = (bar_height + PADDING) as i32;
+        let seek_x = time_x + (time_width + 2 * PADDING) as i32;
+        let volume_x = width as i32 - (VOLUME_WIDTH + PADDING) as i32;
+
+        Layout {
+            bar: (0, y, width, bar_height),
+            button: (0, y, bar_height, bar_height),
+            time: (time_x, y + PADDING as i32),
+            seek: (
+                seek_x,
+                y,
+                (volume_x - 2 * PADDING as i32 - seek_x).max(0) as u32,
+                bar_height,
+            ),
+            volume: (volume_x, y, VOLUME_WIDTH, bar_height),
+        }
+    }
+
+    fn area(&self, part: Part) -> (i32, i32, u32, u32) {
+        match part {
+            Part::PlayPause => self.button,
+            Part::Seek => self.seek,
+            Part::Volume => self.volume,
+        }
+    }
+
+    fn hit(&self, x: i32, y: i32) -> Option<Part> {
+        let inside = |(left, top, width, height): (i32, i32, u32, u32)| {
+            x >= left && x < left + width as i32 && y >= top && y < top + height as i32
+        };
+        if !inside(self.bar) {
+            None
+        } else if inside(self.button) {
+            Some(Part::PlayPause)
+        } else if inside(self.seek) {
+            Some(Part::Seek)
+        } else if inside(self.volume) {
+            Some(Part::Volume)
+        } else {
+            None
+        }
+    }
+}
+
+/// How far along `area` horizontal position `x` is, from 0 to 1.
+fn fraction(area: (i32, i32, u32, u32), x: i32) -> f64 {
+    ((x - area.0) as f64 / area.2.max(1) as f64).clamp(0_f64, 1_f64)
+}
+
+/// Auto-hiding control bar along the bottom of the OSD: a play/pause button, the time, a
+/// seek bar showing what's buffered, and a volume slider, all driven by the mouse.
+pub struct ControlBar {
+    state: ControlState,
+    last_activity: Option<Instant>,
+    dragging: Option<Part>,
+    /// Where the seek knob is being dragged to, seeking only once it's let go unless
+    /// paused.
+    seek_preview: Option<f64>,
+    last_scrub: Option<Instant>,
+    /// Shown regardless of the mouse.
+    pinned: bool,
+    was_visible: bool,
+    /// Size of the target last drawn on, which mouse positions are mapped onto.
+    size: Cell<(u32, u32)>,
+}
+
+impl Default for ControlBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControlBar {
+    pub fn new() -> Self {
+        ControlBar {
+            state: ControlState::default(),
+            last_activity: None,
+            dragging: None,
+            seek_preview: None,
+            last_scrub: None,
+            pinned: false,
+            was_visible: false,
+            size: Cell::new((0, 0)),
+        }
+    }
+
+    pub fn set_state(&mut self, state: ControlState) {
+        self.state = state;
+    }
+
+    pub fn toggle_pinned(&mut self) {
+        self.pinned = !self.pinned;
+        // let it hide right away when unpinned
+        self.last_activity = None;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.pinned
+            || self.dragging.is_some()
+            || self
+                .last_activity
+                .map_or(false, |activity| activity.elapsed() < HIDE_AFTER)
+    }
+
+    /// Hides the bar once the mouse has been left alone, returning true when it showed or
+    /// hid since the last call.
+    pub fn update(&mut self) -> bool {
+        let visible = self.is_visible();
+        let changed = visible != self.was_visible;
+        self.was_visible = visible;
+        changed
+    }
+
+    /// Takes a mouse event at `x` and `y`, fractions of the window's size, adding what it
+    /// does to `commands`. Returns whether the bar took it: presses on the bar while it's
+    /// shown, and everything while dragging something on it.
+    pub fn handle(
+        &mut self,
+        action: MouseAction,
+        x: f64,
+        y: f64,
+        commands: &mut Vec<PlayerCommand>,
+    ) -> bool {
+        let was_visible = self.is_visible();
+        self.last_activity = Some(Instant::now());
+
+        let (width, height) = self.size.get();
+        if width == 0 || height == 0 {
+            return false;
+        }
+        let (x, y) = ((x * width as f64) as i32, (y * height as f64) as i32);
+        let layout = self.layout(width, height);
+
+        match (action, self.dragging) {
+            (MouseAction::Press, _) if was_visible => match layout.hit(x, y) {
+                Some(Part::PlayPause) => {
+                    commands.push(PlayerCommand::TogglePause);
+                    true
+                }
+                Some(part) => {
+                    self.dragging = Some(part);
+                    self.drag(part, fraction(layout.area(part), x), commands);
+                    true
+                }
+                None => false,
+            },
+            (MouseAction::Move, Some(part)) => {
+                self.drag(part, fraction(layout.area(part), x), commands);
+                true
+            }
+            (MouseAction::Release, Some(part)) => {
+                if let (Part::Seek, Some(position)) = (part, self.seek_preview.take()) {
+                    commands.push(PlayerCommand::Seek(position));
+                }
+                self.dragging = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn drag(&mut self, part: Part, fraction: f64, commands: &mut Vec<PlayerCommand>) {
+        match part {
+            Part::Seek => {
+                if let Some(duration) = self.state.duration {
+                    let position = fraction * duration;
+                    self.seek_preview = Some(position);
+
+                    // while paused, scrubbing shows the frame under the knob as it moves
+                    let due = self
+                        .last_scrub
+                        .map_or(true, |scrubbed| scrubbed.elapsed() >= SCRUB_INTERVAL);
+                    if self.state.paused && due {
+                        self.last_scrub = Some(Instant::now());
+                        commands.push(PlayerCommand::Seek(position));
+                    }
+                }
+            }
+            Part::Volume => commands.push(PlayerCommand::Volume(fraction * MAX_VOLUME)),
+            Part::PlayPause => {}
+        }
+    }
+
+    fn time_text(&self) -> String {
+        let position = self.seek_preview.unwrap_or(self.state.position);
+        match self.state.duration {
+            Some(duration) => format!("{} / {}", format_time(position), format_time(duration)),
+            None => format_time(position),
+        }
+    }
+
+    fn layout(&self, width: u32, height: u32) -> Layout {
+        Layout::new(width, height, font::text_width(&self.time_text(), SCALE))
+    }
+
+    /// Height the bar takes at the bottom, for moving other elements out of its way.
+    pub fn height(&self) -> u32 {
+        font::text_height(SCALE) + 2 * PADDING
+    }
+
+    pub fn draw<T: OsdTarget>(&self, target: &mut T) {
+        let (width, height) = target.size();
+        self.size.set((width, height));
+        let layout = self.layout(width, height);
+
+        let (x, y, bar_width, bar_height) = layout.bar;
+        target.fill_rect(x, y, bar_width, bar_height, BACKGROUND);
+
+        self.draw_button(target, layout.button);
+
+        let (time_x, time_y) = layout.time;
+        font::render(&self.time_text(), 0, 0, SCALE, |px, py, size| {
+            target.fill_rect(
+                time_x + px as i32,
+                time_y + py as i32,
+                size,
+                size,
+                FOREGROUND,
+            );
+        });
+
+        let duration = self.state.duration.filter(|duration| *duration > 0_f64);
+        let position = self.seek_preview.unwrap_or(self.state.position);
+        let played = duration.map_or(0_f64, |duration| position / duration);
+        let buffered = duration.map_or(0_f64, |duration| self.state.buffered / duration);
+        draw_slider(target, layout.seek, played, Some(buffered));
+        draw_slider(target, layout.volume, self.state.volume / MAX_VOLUME, None);
+    }
+
+    /// A triangle to play while paused, two bars to pause while playing.
+    fn draw_button<T: OsdTarget>(&self, target: &mut T, area: (i32, i32, u32, u32)) {
+        let size = font::text_height(SCALE) as i32;
+        let left = area.0 + (area.2 as i32 - size) / 2;
+        let top = area.1 + (area.3 as i32 - size) / 2;
+
+        if self.state.paused {
+            for row in 0..size {
+                let length = size - (2 * row - size).abs();
+                target.fill_rect(left, top + row, length.max(1) as u32, 1, FOREGROUND);
+            }
+        } else {
+            let bar = (size / 3) as u32;
+            target.fill_rect(left, top, bar, size as u32, FOREGROUND);
+            target.fill_rect(left + size - bar as i32, top, bar, size as u32, FOREGROUND);
+        }
+    }
+}
+
+/// A track across the middle of `area`, filled up to `value` with a knob there, and up to
+/// `buffered` in a lighter colour.
+fn draw_slider<T: OsdTarget>(
+    target: &mut T,
+    area: (i32, i32, u32, u32),
+    value: f64,
+    buffered: Option<f64>,
+) {
+    let (x, y, width, height) = area;
+    let track_y = y + (height - TRACK_HEIGHT) as i32 / 2;
+    let length = |fraction: f64| (fraction.clamp(0_f64, 1_f64) * width as f64) as u32;
+
+    target.fill_rect(x, track_y, width, TRACK_HEIGHT, TRACK);
+    if let Some(buffered) = buffered {
+        target.fill_rect(x, track_y, length(buffered), TRACK_HEIGHT, BUFFERED);
+    }
+    target.fill_rect(x, track_y, length(value), TRACK_HEIGHT, FOREGROUND);
+    target.fill_rect(
+        x + length(value) as i32 - KNOB_SIZE as i32 / 2,
+        y + (height - KNOB_SIZE) as i32 / 2,
+        KNOB_SIZE,
+        KNOB_SIZE,
+        FOREGROUND,
+    );
+}
+
+/// Clicks on the picture: a double-click toggles fullscreen, and a single click pauses or
+/// resumes once it's clear no second click is coming.
+#[derive(Default)]
+pub struct ClickTracker {
+    pressed: Option<(f64, f64)>,
+    /// When a click that may still become a double-click happened.
+    pending: Option<Instant>,
+}
+
+impl ClickTracker {
+    /// Takes a mouse event the control bar didn't, adding a double-click's command to
+    /// `commands` right away.
+    pub fn handle(
+        &mut self,
+        action: MouseAction,
+        x: f64,
+        y: f64,
+        commands: &mut Vec<PlayerCommand>,
+    ) {
+        match action {
+            MouseAction::Press => self.pressed = Some((x, y)),
+            MouseAction::Release => {
+                let (pressed_x, pressed_y) = match self.pressed.take() {
+                    Some(pressed) => pressed,
+                    None => return,
+                };
+                if (x - pressed_x).abs() > CLICK_SLOP || (y - pressed_y).abs() > CLICK_SLOP {
+                    return;
+                }
+                match self.pending.take() {
+                    Some(clicked) if clicked.elapsed() < DOUBLE_CLICK_INTERVAL => {
+                        commands.push(PlayerCommand::ToggleFullscreen)
+                    }
+                    _ => self.pending = Some(Instant::now()),
+                }
+            }
+            MouseAction::Move => {}
+        }
+    }
+
+    /// Adds a single click's command to `commands` once it can't become a double-click.
+    pub fn update(&mut self, commands: &mut Vec<PlayerCommand>) {
+        if let Some(clicked) = self.pending {
+            if clicked.elapsed() >= DOUBLE_CLICK_INTERVAL {
+                self.pending = None;
+                commands.push(PlayerCommand::TogglePause);
+            }
+        }
+    }
+}
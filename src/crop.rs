@@ -0,0 +1,125 @@
+use ffmpeg_next::{format::Pixel, frame::Video};
+
+/// Luma at or below this counts as black; limited range black is 16.
+const BLACK_LIMIT: u8 = 24;
+
+/// Only every Nth frame is scanned, bars don't change from one frame to the next.
+const SAMPLE_EVERY: usize = 12;
+
+/// Rectangle of the picture to keep, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    pub fn filter_description(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+
+    fn union(&self, other: &Crop) -> Crop {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Crop {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+}
+
+/// Finds black bars around the picture by scanning the luma plane of decoded frames.
+///
+/// The detected area only ever grows, so dark scenes can't crop away picture that brighter
+/// ones showed.
+pub struct CropDetector {
+    frames: usize,
+    detected: Option<Crop>,
+}
+
+impl CropDetector {
+    pub fn new() -> Self {
+        CropDetector {
+            frames: 0,
+            detected: None,
+        }
+    }
+
+    /// Area to keep so far, `None` until a frame with any picture in it was scanned or when
+    /// there's nothing to crop.
+    pub fn crop(&self, width: u32, height: u32) -> Option<Crop> {
+        self.detected
+            .filter(|crop| crop.width < width || crop.height < height || crop.x > 0 || crop.y > 0)
+    }
+
+    pub fn sample(&mut self, frame: &Video) {
+        self.frames += 1;
+        if self.frames % SAMPLE_EVERY != 1 {
+            return;
+        }
+
+        if let Some(bounds) = picture_bounds(frame) {
+            self.detected = Some(match self.detected {
+                Some(detected) => detected.union(&bounds),
+                None => bounds,
+            });
+        }
+    }
+}
+
+impl Default for CropDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bounding box of the non-black pixels, rounded out to even numbers for the chroma planes.
+/// `None` for entirely black frames and formats without an 8-bit luma plane.
+fn picture_bounds(frame: &Video) -> Option<Crop> {
+    match frame.format() {
+        Pixel::YUV420P
+        | Pixel::YUVJ420P
+        | Pixel::YUV422P
+        | Pixel::YUVJ422P
+        | Pixel::YUV444P
+        | Pixel::YUVJ444P
+        | Pixel::NV12
+        | Pixel::NV21 => {}
+        _ => return None,
+    }
+
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let stride = frame.stride(0);
+    let luma = frame.data(0);
+    let row = |y: usize| &luma[y * stride..y * stride + width];
+
+    // every other pixel is plenty to tell picture from bar
+    let row_is_black = |y: usize| row(y).iter().step_by(2).all(|&value| value <= BLACK_LIMIT);
+    let column_is_black = |x: usize, top: usize, bottom: usize| {
+        (top..bottom).step_by(2).all(|y| row(y)[x] <= BLACK_LIMIT)
+    };
+
+    let top = (0..height).find(|&y| !row_is_black(y))?;
+    let bottom = (top..height).rev().find(|&y| !row_is_black(y))? + 1;
+    let left = (0..width).find(|&x| !column_is_black(x, top, bottom))?;
+    let right = (left..width)
+        .rev()
+        .find(|&x| !column_is_black(x, top, bottom))?
+        + 1;
+
+    let (x, y) = (left as u32 & !1, top as u32 & !1);
+    let right = ((right as u32 + 1) & !1).min(width as u32);
+    let bottom = ((bottom as u32 + 1) & !1).min(height as u32);
+    Some(Crop {
+        x,
+        y,
+        width: right - x,
+        height: bottom - y,
+    })
+}
@@ -0,0 +1,193 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ffmpeg_next::{
+    codec, encoder, error,
+    format::{self, Pixel},
+    frame,
+    media::Type,
+    software::scaling::{self, flag::Flags},
+    Error, Packet, Rational,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        extension.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    fn codec(&self) -> codec::Id {
+        match self {
+            ImageFormat::Png => codec::Id::PNG,
+            ImageFormat::Jpeg => codec::Id::MJPEG,
+        }
+    }
+
+    fn pixel_format(&self) -> Pixel {
+        match self {
+            ImageFormat::Png => Pixel::RGB24,
+            ImageFormat::Jpeg => Pixel::YUVJ420P,
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+            _ => Err(format!("unsupported image format '{}'", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DumpSettings {
+    pub directory: PathBuf,
+    pub format: ImageFormat,
+    pub every: usize,
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+/// Maps a filesystem error into the ffmpeg error type used throughout the tools.
+pub fn io_error(e: io::Error) -> Error {
+    Error::Other {
+        errno: e.raw_os_error().unwrap_or(error::EIO),
+    }
+}
+
+/// Encodes a single decoded frame as a still image and writes it to `path`.
+pub fn save_frame(frame: &frame::Video, format: ImageFormat, path: &Path) -> Result<(), Error> {
+    let data = encode_image(frame, format)?;
+    fs::write(path, data).map_err(io_error)
+}
+
+/// Encodes a single decoded frame as a still image, converting its pixel format as needed.
+pub fn encode_image(frame: &frame::Video, format: ImageFormat) -> Result<Vec<u8>, Error> {
+    let mut converted = frame::Video::empty();
+    let frame = if frame.format() == format.pixel_format() {
+        frame
+    } else {
+        let mut scaler = scaling::Context::get(
+            frame.format(),
+            frame.width(),
+            frame.height(),
+            format.pixel_format(),
+            frame.width(),
+            frame.height(),
+            Flags::BILINEAR,
+        )?;
+        scaler.run(frame, &mut converted)?;
+        &converted
+    };
+
+    let codec = encoder::find(format.codec()).ok_or(Error::EncoderNotFound)?;
+    let mut encoder = codec::context::Context::new().encoder().video()?;
+    encoder.set_width(frame.width());
+    encoder.set_height(frame.height());
+    encoder.set_format(format.pixel_format());
+    encoder.set_time_base(Rational::new(1, 25));
+    let mut encoder = encoder.open_as(codec)?;
+
+    encoder.send_frame(frame)?;
+    encoder.send_eof()?;
+
+    let mut packet = Packet::empty();
+    encoder.receive_packet(&mut packet)?;
+
+    Ok(packet.data().unwrap_or(&[]).to_vec())
+}
+
+/// Decodes the video stream without any presentation and writes every Nth frame to
+/// `<directory>/frame_000001.png`, returning the number of images written.
+pub fn dump_frames(input_path: &str, settings: &DumpSettings) -> Result<usize, Error> {
+    fs::create_dir_all(&settings.directory).map_err(io_error)?;
+
+    let mut input = format::input(&Path::new(input_path))?;
+    let (stream_index, time_base, mut decoder) = {
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or(Error::StreamNotFound)?;
+        let decoder = stream.codec().decoder().video()?;
+        (stream.index(), f64::from(stream.time_base()), decoder)
+    };
+
+    if settings.start > 0_f64 {
+        let position = (settings.start * f64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i64;
+        input.seek(position, ..position)?;
+    }
+
+    let mut decoded_count = 0;
+    let mut written_count = 0;
+    let mut decoded = frame::Video::empty();
+    let mut ended = false;
+
+    let mut receive_frames = |decoder: &mut codec::decoder::Video| -> Result<bool, Error> {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let seconds = decoded
+                .timestamp()
+                .map(|pts| pts as f64 * time_base)
+                .unwrap_or(settings.start);
+
+            if seconds < settings.start {
+                continue;
+            }
+            if settings.end.map_or(false, |end| seconds >= end) {
+                return Ok(true);
+            }
+
+            if decoded_count % settings.every.max(1) == 0 {
+                written_count += 1;
+                let path = settings.directory.join(format!(
+                    "frame_{:06}.{}",
+                    written_count,
+                    settings.format.extension()
+                ));
+                save_frame(&decoded, settings.format, &path)?;
+                info!("wrote {}", path.display());
+            }
+            decoded_count += 1;
+        }
+        Ok(false)
+    };
+
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet)?;
+        if receive_frames(&mut decoder)? {
+            ended = true;
+            break;
+        }
+    }
+
+    // Flush the frames still held by the decoder
+    if !ended {
+        decoder.send_eof()?;
+        receive_frames(&mut decoder)?;
+    }
+
+    Ok(written_count)
+}
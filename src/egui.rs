@@ -0,0 +1,263 @@
+//! Video playback inside egui apps, without an SDL window.
+
+use std::{
+    panic,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use ::egui::{
+    Color32, ColorImage, Context, Response, Sense, Slider, TextureHandle, TextureOptions, Ui, Vec2,
+    Widget,
+};
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    software::scaling::{self, Flags},
+    Error,
+};
+
+use crate::{
+    asset::{PlaybackAsset, PlaybackAssetMetadata},
+    clock::format_time,
+    options::Options,
+    player::{Player, PlayerController},
+    video_sink::VideoSink,
+};
+
+/// Newest picture and position, handed from the player thread to the UI.
+#[derive(Default)]
+struct SharedFrame {
+    image: Option<ColorImage>,
+    position: f64,
+}
+
+/// Converts frames to RGBA for the widget and wakes the UI up.
+struct EguiVideoSink {
+    context: Context,
+    shared: Arc<Mutex<SharedFrame>>,
+    scaler: Option<scaling::Context>,
+}
+
+impl VideoSink for EguiVideoSink {
+    fn configure(&mut self, _metadata: &PlaybackAssetMetadata) {}
+
+    fn submit(&mut self, frame: &Video, pts: f64) {
+        let (width, height) = (frame.width(), frame.height());
+
+        let matches = self.scaler.as_ref().map_or(false, |scaler| {
+            let input = scaler.input();
+            input.format == frame.format() && input.width == width && input.height == height
+        });
+        if !matches {
+            self.scaler = scaling::Context::get(
+                frame.format(),
+                width,
+                height,
+                Pixel::RGBA,
+                width,
+                height,
+                Flags::BILINEAR,
+            )
+            .ok();
+        }
+        let scaler = match &mut self.scaler {
+            Some(scaler) => scaler,
+            None => return,
+        };
+
+        let mut rgba = Video::empty();
+        if scaler.run(frame, &mut rgba).is_err() {
+            return;
+        }
+
+        // drop the row padding egui doesn't expect
+        let row = width as usize * 4;
+        let pixels: Vec<u8> = rgba
+            .data(0)
+            .chunks(rgba.stride(0))
+            .take(height as usize)
+            .flat_map(|line| line[..row].iter().copied())
+            .collect();
+        let image = ColorImage::from_rgba_unmultiplied([width as usize, height as usize], &pixels);
+
+        {
+            let mut shared = self.shared.lock().unwrap();
+            shared.image = Some(image);
+            shared.position = pts;
+        }
+        self.context.request_repaint();
+    }
+}
+
+/// Plays a file inside an egui layout, with a play/pause button and a seek bar.
+///
+/// Decoding and timing run on a background thread; audio still goes wherever
+/// `options.audio_output` says. Playback stops when the widget is dropped.
+pub struct VideoWidget {
+    controller: PlayerController,
+    shared: Arc<Mutex<SharedFrame>>,
+    metadata: PlaybackAssetMetadata,
+    texture: Option<TextureHandle>,
+    scrubbing: Option<f64>,
+}
+
+impl VideoWidget {
+    /// Starts playing `path`, failing when it can't be opened. A panic on the player thread
+    /// before playback starts is passed on to the caller.
+    pub fn new(context: &Context, path: &str, options: Options) -> Result<Self, Error> {
+        let shared = Arc::new(Mutex::new(SharedFrame::default()));
+        let (sender, receiver) = mpsc::channel();
+
+        let player_thread = thread::spawn({
+            let context = context.clone();
+            let shared = Arc::clone(&shared);
+            let path = path.to_string();
+
+            move || {
+                let asset = match PlaybackAsset::open(&path, &options) {
+                    Ok(asset) => asset,
+                    Err(e) => {
+                        sender.send(Err(e)).ok();
+                        return;
+                    }
+                };
+                let mut player = Player::new(options);
+                player.set_video_sink(Box::new(EguiVideoSink {
+                    context,
+                    shared,
+                    scaler: None,
+                }));
+
+                sender
+                    .send(Ok((player.controller(), asset.metadata())))
+                    .ok();
+                player.play(asset);
+            }
+        });
+        let (controller, metadata) = match receiver.recv() {
+            Ok(result) => result?,
+            // the thread only goes away without a word if it panicked
+            Err(_) => match player_thread.join() {
+                Err(panic) => panic::resume_unwind(panic),
+                Ok(()) => unreachable!("the player thread ended without answering"),
+            },
+        };
+
+        Ok(VideoWidget {
+            controller,
+            shared,
+            metadata,
+            texture: None,
+            scrubbing: None,
+        })
+    }
+
+    pub fn play(&self) {
+        self.controller.play();
+    }
+
+    pub fn pause(&self) {
+        self.controller.pause();
+    }
+
+    /// Whether the player is paused, however it got there: through the widget, at the end
+    /// of the file or by another controller.
+    pub fn is_paused(&self) -> bool {
+        self.controller.status().paused
+    }
+
+    pub fn seek(&self, position: f64) {
+        self.controller.seek(position);
+    }
+
+    /// Position of the frame on screen, in seconds.
+    pub fn position(&self) -> f64 {
+        self.shared.lock().unwrap().position
+    }
+
+    pub fn duration(&self) -> Option<f64> {
+        self.metadata.duration()
+    }
+
+    fn update_texture(&mut self, context: &Context) {
+        let image = match self.shared.lock().unwrap().image.take() {
+            Some(image) => image,
+            None => return,
+        };
+
+        match &mut self.texture {
+            Some(texture) => texture.set(image, TextureOptions::LINEAR),
+            None => {
+                self.texture = Some(context.load_texture("video", image, TextureOptions::LINEAR))
+            }
+        }
+    }
+
+    fn controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let paused = self.is_paused();
+            if ui.button(if paused { "Play" } else { "Pause" }).clicked() {
+                if paused {
+                    self.play();
+                } else {
+                    self.pause();
+                }
+            }
+
+            let mut position = self.scrubbing.unwrap_or_else(|| self.position());
+            if let Some(duration) = self.duration() {
+                let slider = ui.add(Slider::new(&mut position, 0_f64..=duration).show_value(false));
+
+                // only seek once the handle is let go, not for every step of a drag
+                if slider.changed() {
+                    self.scrubbing = Some(position);
+                }
+                if !slider.dragged() {
+                    if let Some(position) = self.scrubbing.take() {
+                        self.seek(position);
+                    }
+                }
+            }
+
+            ui.label(format!(
+                "{} / {}",
+                format_time(position),
+                self.duration()
+                    .map_or_else(|| "--:--:--".to_string(), format_time)
+            ));
+        });
+    }
+}
+
+impl Widget for &mut VideoWidget {
+    fn ui(self, ui: &mut Ui) -> Response {
+        self.update_texture(ui.ctx());
+
+        ui.vertical(|ui| {
+            // fit the available width, keeping the video's aspect ratio
+            let aspect = self.metadata.height() as f32 / self.metadata.width().max(1) as f32;
+            let width = ui.available_width();
+            let size = Vec2::new(width, width * aspect);
+
+            let response = match &self.texture {
+                Some(texture) => ui.image(texture.id(), size),
+                None => {
+                    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+                    ui.painter().rect_filled(rect, 0_f32, Color32::BLACK);
+                    response
+                }
+            };
+
+            self.controls(ui);
+            response
+        })
+        .inner
+    }
+}
+
+impl Drop for VideoWidget {
+    fn drop(&mut self) {
+        self.controller.stop();
+    }
+}
@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+/// Centre frequencies of the equalizer's octave bands, in Hz.
+pub const BANDS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000];
+
+/// Gains are kept within this many dB either way.
+pub const MAX_GAIN: f64 = 12_f64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EqualizerPreset {
+    Flat,
+    Bass,
+    Treble,
+    Vocal,
+    /// Boosted lows and highs, for listening at low volume.
+    Loudness,
+}
+
+impl EqualizerPreset {
+    const ALL: [EqualizerPreset; 5] = [
+        EqualizerPreset::Flat,
+        EqualizerPreset::Bass,
+        EqualizerPreset::Treble,
+        EqualizerPreset::Vocal,
+        EqualizerPreset::Loudness,
+    ];
+
+    pub fn gains(&self) -> [f64; 10] {
+        match self {
+            EqualizerPreset::Flat => [0_f64; 10],
+            EqualizerPreset::Bass => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            EqualizerPreset::Treble => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0],
+            EqualizerPreset::Vocal => [-2.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0],
+            EqualizerPreset::Loudness => [6.0, 4.0, 2.0, 0.0, -1.0, -1.0, 0.0, 2.0, 4.0, 5.0],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EqualizerPreset::Flat => "flat",
+            EqualizerPreset::Bass => "bass",
+            EqualizerPreset::Treble => "treble",
+            EqualizerPreset::Vocal => "vocal",
+            EqualizerPreset::Loudness => "loudness",
+        }
+    }
+
+    /// The preset after this one, wrapping around.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|preset| preset == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl FromStr for EqualizerPreset {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|preset| preset.name() == value)
+            .copied()
+            .ok_or_else(|| format!("unknown equalizer preset '{}'", value))
+    }
+}
+
+/// Ten-band graphic equalizer, applied with ffmpeg's `equalizer` filter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Equalizer {
+    gains: [f64; 10],
+    preset: Option<EqualizerPreset>,
+}
+
+impl Equalizer {
+    pub fn new(preset: EqualizerPreset) -> Self {
+        Equalizer {
+            gains: preset.gains(),
+            preset: Some(preset),
+        }
+    }
+
+    /// Band gains in dB, lowest band first.
+    pub fn gains(&self) -> [f64; 10] {
+        self.gains
+    }
+
+    /// The preset in use, `None` once a band has been changed by hand.
+    pub fn preset(&self) -> Option<EqualizerPreset> {
+        self.preset
+    }
+
+    pub fn set_preset(&mut self, preset: EqualizerPreset) {
+        self.gains = preset.gains();
+        self.preset = Some(preset);
+    }
+
+    pub fn set_gains(&mut self, gains: [f64; 10]) {
+        for (band, gain) in gains.iter().enumerate() {
+            self.set_gain(band, *gain);
+        }
+    }
+
+    pub fn set_gain(&mut self, band: usize, gain: f64) {
+        if let Some(slot) = self.gains.get_mut(band) {
+            *slot = gain.clamp(-MAX_GAIN, MAX_GAIN);
+            self.preset = None;
+        }
+    }
+
+    /// Filter description for the current gains, `None` when every band is flat.
+    pub fn filter_description(&self) -> Option<String> {
+        let filters: Vec<String> = BANDS
+            .iter()
+            .zip(&self.gains)
+            .filter(|(_, gain)| **gain != 0_f64)
+            .map(|(frequency, gain)| format!("equalizer=f={}:t=o:w=1:g={:.1}", frequency, gain))
+            .collect();
+
+        if filters.is_empty() {
+            None
+        } else {
+            Some(filters.join(","))
+        }
+    }
+}
+
+impl Default for Equalizer {
+    fn default() -> Self {
+        Self::new(EqualizerPreset::Flat)
+    }
+}
+
+/// The index of the band centred on `frequency` Hz, if it's one of `BANDS`.
+pub fn band(frequency: u32) -> Option<usize> {
+    BANDS.iter().position(|band| *band == frequency)
+}
+
+/// Parses a band given by its centre frequency in Hz, e.g. `1000`, into its index.
+pub fn parse_band(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .and_then(band)
+        .ok_or_else(|| format!("unknown equalizer band '{}'", value))
+}
+
+/// Parses gains for every band as comma-separated dB, e.g. `3,2,0,0,0,0,0,1,2,3`.
+pub fn parse_gains(value: &str) -> Result<[f64; 10], String> {
+    let invalid = || format!("invalid equalizer gains '{}'", value);
+
+    let gains = value
+        .split(',')
+        .map(|gain| gain.trim().parse::<f64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if gains.len() != BANDS.len() {
+        return Err(invalid());
+    }
+
+    let mut result = [0_f64; 10];
+    result.copy_from_slice(&gains);
+    Ok(result)
+}
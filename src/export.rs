@@ -0,0 +1,252 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ffmpeg_next::{
+    codec, encoder, filter,
+    format::{self, Pixel},
+    frame,
+    media::Type,
+    Dictionary, Error, Packet, Rational,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Gif,
+    WebP,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        extension.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Gif => "gif",
+            ExportFormat::WebP => "webp",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "gif" => Ok(ExportFormat::Gif),
+            "webp" => Ok(ExportFormat::WebP),
+            _ => Err(format!("unsupported export format '{}'", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportSettings {
+    pub output: PathBuf,
+    pub format: ExportFormat,
+    pub start: f64,
+    pub end: f64,
+    pub fps: u32,
+    pub width: Option<u32>,
+}
+
+impl ExportSettings {
+    /// Builds a file name like `teaser_12.50-15.00.gif` in the current directory.
+    pub fn default_output(input: &str, format: ExportFormat, start: f64, end: f64) -> PathBuf {
+        let stem = Path::new(input)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("export");
+
+        PathBuf::from(format!(
+            "{}_{:.2}-{:.2}.{}",
+            stem,
+            start,
+            end,
+            format.extension()
+        ))
+    }
+}
+
+/// Converts the `[start, end)` range of the input into an animated GIF or WebP.
+pub fn export_range(input_path: &str, settings: &ExportSettings) -> Result<(), Error> {
+    // there's no time base for 0 fps, nor a picture 0 pixels wide
+    if settings.fps == 0 || settings.width == Some(0) {
+        return Err(Error::InvalidData);
+    }
+
+    let mut input = format::input(&Path::new(input_path))?;
+
+    let (stream_index, stream_time_base, mut decoder) = {
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or(Error::StreamNotFound)?;
+        let decoder = stream.codec().decoder().video()?;
+        (stream.index(), stream.time_base(), decoder)
+    };
+    let time_base = f64::from(stream_time_base);
+
+    // Output dimensions, keeping the aspect ratio and an even height
+    let width = settings
+        .width
+        .unwrap_or(decoder.width())
+        .min(decoder.width());
+    let height = ((decoder.height() as f64 * width as f64 / decoder.width() as f64) as u32 / 2) * 2;
+
+    let mut graph = create_filter_graph(&decoder, stream_time_base, settings, width, height)?;
+
+    // Encoder + muxer
+    let mut output = format::output(&settings.output)?;
+    let encoder_time_base = Rational::new(1, settings.fps as i32);
+    let mut encoder = {
+        let codec = match settings.format {
+            ExportFormat::Gif => encoder::find(codec::Id::GIF),
+            ExportFormat::WebP => encoder::find_by_name("libwebp_anim"),
+        }
+        .ok_or(Error::EncoderNotFound)?;
+
+        let mut stream = output.add_stream(codec)?;
+        let mut encoder = stream.codec().encoder().video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_format(output_pixel_format(settings.format));
+        encoder.set_time_base(encoder_time_base);
+
+        let encoder = encoder.open_as(codec)?;
+        stream.set_parameters(&encoder);
+        encoder
+    };
+
+    let mut muxer_options = Dictionary::new();
+    muxer_options.set("loop", "0");
+    output.write_header_with(muxer_options)?;
+    let output_time_base = output.stream(0).unwrap().time_base();
+
+    let write_packets =
+        |encoder: &mut encoder::Video, output: &mut format::context::Output| -> Result<(), Error> {
+            let mut packet = Packet::empty();
+            while encoder.receive_packet(&mut packet).is_ok() {
+                packet.set_stream(0);
+                packet.rescale_ts(encoder_time_base, output_time_base);
+                packet.write_interleaved(output)?;
+            }
+            Ok(())
+        };
+
+    let drain_filter = |graph: &mut filter::Graph,
+                        encoder: &mut encoder::Video,
+                        output: &mut format::context::Output|
+     -> Result<(), Error> {
+        let mut filtered = frame::Video::empty();
+        while graph
+            .get("out")
+            .unwrap()
+            .sink()
+            .frame(&mut filtered)
+            .is_ok()
+        {
+            encoder.send_frame(&filtered)?;
+            write_packets(encoder, output)?;
+        }
+        Ok(())
+    };
+
+    // Seek to the keyframe before the range and decode from there
+    let position = (settings.start * f64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i64;
+    input.seek(position, ..position)?;
+
+    let mut decoded = frame::Video::empty();
+    'demux: for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let pts = match decoded.timestamp().or_else(|| decoded.pts()) {
+                Some(pts) => pts,
+                None => continue,
+            };
+            let seconds = pts as f64 * time_base;
+
+            if seconds < settings.start {
+                continue;
+            }
+            if seconds >= settings.end {
+                break 'demux;
+            }
+
+            decoded.set_pts(Some(pts));
+            graph.get("in").unwrap().source().add(&decoded)?;
+            drain_filter(&mut graph, &mut encoder, &mut output)?;
+        }
+    }
+
+    // Flush the filter graph (palettegen only emits once it has seen every frame) and encoder
+    graph.get("in").unwrap().source().flush()?;
+    drain_filter(&mut graph, &mut encoder, &mut output)?;
+    encoder.send_eof()?;
+    write_packets(&mut encoder, &mut output)?;
+
+    output.write_trailer()?;
+
+    Ok(())
+}
+
+fn output_pixel_format(format: ExportFormat) -> Pixel {
+    match format {
+        ExportFormat::Gif => Pixel::PAL8,
+        ExportFormat::WebP => Pixel::YUV420P,
+    }
+}
+
+fn create_filter_graph(
+    decoder: &codec::decoder::Video,
+    time_base: Rational,
+    settings: &ExportSettings,
+    width: u32,
+    height: u32,
+) -> Result<filter::Graph, Error> {
+    let mut graph = filter::Graph::new();
+
+    let aspect = decoder.aspect_ratio();
+    let args = format!(
+        "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+        decoder.width(),
+        decoder.height(),
+        ffmpeg_next::ffi::AVPixelFormat::from(decoder.format()) as i32,
+        time_base.numerator(),
+        time_base.denominator(),
+        aspect.numerator().max(1),
+        aspect.denominator().max(1),
+    );
+
+    graph.add(&filter::find("buffer").unwrap(), "in", &args)?;
+    graph.add(&filter::find("buffersink").unwrap(), "out", "")?;
+    graph
+        .get("out")
+        .unwrap()
+        .set_pixel_format(output_pixel_format(settings.format));
+
+    let scale = format!(
+        "fps={},scale={}:{}:flags=lanczos",
+        settings.fps, width, height
+    );
+    let spec = match settings.format {
+        // Generate an optimized palette from the whole range, then map every frame onto it
+        ExportFormat::Gif => format!(
+            "{},split[a][b];[a]palettegen=stats_mode=diff[p];[b][p]paletteuse=dither=bayer:diff_mode=rectangle",
+            scale
+        ),
+        ExportFormat::WebP => format!("{},format=yuv420p", scale),
+    };
+
+    graph.output("in", 0)?.input("out", 0)?.parse(&spec)?;
+    graph.validate()?;
+
+    Ok(graph)
+}
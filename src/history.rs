@@ -0,0 +1,137 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::options::config_dir;
+
+/// Kept in `config_dir()`, one tab-separated entry per line, oldest first.
+const HISTORY_FILE: &str = "history.tsv";
+
+/// Entries beyond this many are forgotten, oldest first.
+const MAX_ENTRIES: usize = 200;
+
+/// A file played earlier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub path: String,
+    /// When playback started, in seconds since 1970.
+    pub opened: u64,
+    /// How much of it was played, from 0 to 1.
+    pub completion: f64,
+}
+
+impl HistoryEntry {
+    /// The entry as listed by `--history`, e.g. `2024-05-01 21:14   87%  movie.mkv`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}  {:>3.0}%  {}",
+            format_date(self.opened),
+            self.completion * 100_f64,
+            self.path
+        )
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(HISTORY_FILE))
+}
+
+/// Played files, most recent first; a file played again only shows up once.
+pub fn load() -> Vec<HistoryEntry> {
+    let text = match history_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(text) => text,
+        None => return Vec::new(),
+    };
+
+    let mut entries: Vec<HistoryEntry> = text
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some(HistoryEntry {
+                opened: fields.next()?.parse().ok()?,
+                completion: fields.next()?.parse().ok()?,
+                path: fields.next()?.to_string(),
+            })
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    entries.retain(|entry| seen.insert(entry.path.clone()));
+    entries
+}
+
+/// The file played last, e.g. to pick up where it was left with `--last`.
+pub fn most_recent() -> Option<HistoryEntry> {
+    load().into_iter().next()
+}
+
+/// Adds `path` as played from `opened` on, `completion` of the way through.
+pub fn record(path: &str, opened: SystemTime, completion: f64) -> io::Result<()> {
+    let history = match history_path() {
+        Some(history) => history,
+        None => return Ok(()),
+    };
+    if let Some(dir) = history.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut entries = load();
+    entries.retain(|entry| entry.path != path);
+    entries.insert(
+        0,
+        HistoryEntry {
+            path: path.to_string(),
+            opened: opened
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            completion: completion.clamp(0_f64, 1_f64),
+        },
+    );
+    entries.truncate(MAX_ENTRIES);
+
+    let mut file = fs::File::create(&history)?;
+    for entry in entries.iter().rev() {
+        // paths are the last field, so they may have tabs in them
+        writeln!(
+            file,
+            "{}\t{:.3}\t{}",
+            entry.opened, entry.completion, entry.path
+        )?;
+    }
+    Ok(())
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC, for `seconds` since 1970.
+fn format_date(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let minutes = seconds % 86_400 / 60;
+
+    // civil-from-days, counting in 400 year eras starting on March 1st
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
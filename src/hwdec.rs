@@ -0,0 +1,113 @@
+use std::ptr;
+
+use ffmpeg_next::{codec, decoder, ffi, format::Pixel, frame::Video, Error};
+
+use crate::options::HwDecode;
+
+/// `AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX`, which the bindings only have in an anonymous
+/// enum: the decoder decodes on a device it's given, allocating the surfaces itself.
+const HW_DEVICE_CTX: i32 = 0x01;
+
+/// Surfaces allocated beyond what decoding needs, so frames queued ahead of presentation on
+/// the GPU don't starve the decoder of them.
+const EXTRA_HW_FRAMES: i32 = 32;
+
+/// The device type `hwdec` decodes on, if any on this platform.
+fn device_type(hwdec: HwDecode) -> Option<ffi::AVHWDeviceType> {
+    match hwdec {
+        HwDecode::No => None,
+        HwDecode::Auto if cfg!(target_os = "linux") => {
+            Some(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI)
+        }
+        HwDecode::Auto => None,
+        HwDecode::Vaapi => Some(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI),
+    }
+}
+
+/// The surface format VAAPI decodes into, whatever the bindings call it.
+pub fn vaapi_format() -> Pixel {
+    Pixel::from(ffi::AVPixelFormat::AV_PIX_FMT_VAAPI)
+}
+
+/// The format of the surfaces `hwdec` decodes into, for checking a video sink shows them,
+/// see `VideoSink::hardware_formats`.
+pub fn surface_format(hwdec: HwDecode) -> Option<Pixel> {
+    device_type(hwdec).map(|_| vaapi_format())
+}
+
+/// Whether `codec` decodes on devices of type `kind`.
+fn supports(codec: &codec::Codec, kind: ffi::AVHWDeviceType) -> bool {
+    (0..)
+        .map(|index| unsafe { ffi::avcodec_get_hw_config(codec.as_ptr(), index) })
+        .take_while(|config| !config.is_null())
+        .any(|config| unsafe {
+            (*config).device_type == kind && (*config).methods & HW_DEVICE_CTX != 0
+        })
+}
+
+/// Sets `context` up to decode on the device `hwdec` asks for if its codec supports it and
+/// it opens, before the decoder is opened. libavcodec's own format negotiation picks the
+/// device's surfaces then, and falls back to software for streams the device can't decode,
+/// e.g. an unsupported profile. Returns whether a device was attached.
+pub fn attach(context: &mut codec::Context, hwdec: HwDecode) -> bool {
+    let codec = match decoder::find(context.id()) {
+        Some(codec) => codec,
+        None => return false,
+    };
+
+    let kind = match device_type(hwdec) {
+        Some(kind) => kind,
+        None => return false,
+    };
+    if !supports(&codec, kind) {
+        info!(
+            "no hardware decoder for {}, decoding in software",
+            codec.name()
+        );
+        return false;
+    }
+
+    let mut device = ptr::null_mut();
+    let result =
+        unsafe { ffi::av_hwdevice_ctx_create(&mut device, kind, ptr::null(), ptr::null_mut(), 0) };
+    if result < 0 {
+        eprintln!(
+            "failed to open hardware decoder {:?}: {}",
+            kind,
+            Error::from(result)
+        );
+        return false;
+    }
+
+    unsafe {
+        let context = context.as_mut_ptr();
+        // the context keeps a reference of its own
+        (*context).hw_device_ctx = ffi::av_buffer_ref(device);
+        (*context).extra_hw_frames = EXTRA_HW_FRAMES;
+        ffi::av_buffer_unref(&mut device);
+    }
+    info!("decoding {} on {:?}", codec.name(), kind);
+    true
+}
+
+/// Whether `frame` is a surface on the device it was decoded on rather than in memory.
+pub fn is_hardware(frame: &Video) -> bool {
+    unsafe { !(*frame.as_ptr()).hw_frames_ctx.is_null() }
+}
+
+/// Copies a hardware `frame` to system memory, in the format the device prefers, e.g. NV12
+/// or P010, with its timestamps and colour properties.
+pub fn download(frame: &Video) -> Result<Video, Error> {
+    let mut downloaded = Video::empty();
+    unsafe {
+        let result = ffi::av_hwframe_transfer_data(downloaded.as_mut_ptr(), frame.as_ptr(), 0);
+        if result < 0 {
+            return Err(Error::from(result));
+        }
+        let result = ffi::av_frame_copy_props(downloaded.as_mut_ptr(), frame.as_ptr());
+        if result < 0 {
+            return Err(Error::from(result));
+        }
+    }
+    Ok(downloaded)
+}
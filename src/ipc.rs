@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    control,
+    player::{PlayerController, PlayerStatus},
+};
+
+/// How often the player's status is checked for changes to report as events.
+const EVENT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Serves mpv-style JSON IPC on a Unix socket at `path`, in the background.
+///
+/// Each line a client writes is a request like `{"command": ["seek", 10], "request_id": 1}`
+/// and gets a line back like `{"error": "success", "data": null, "request_id": 1}`, see
+/// `control::respond` for the commands.
+///
+/// Every client also gets events as lines like `{"event": "pause"}`: `start-file`,
+/// `end-file`, `pause`, `unpause` and `seek`.
+pub fn serve(path: &Path, controller: PlayerController) -> io::Result<()> {
+    // a socket left behind by an earlier run would make binding fail
+    if fs::metadata(path).map_or(false, |metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let clients: Arc<Mutex<Vec<UnixStream>>> = Arc::default();
+
+    thread::spawn({
+        let controller = controller.clone();
+        let clients = Arc::clone(&clients);
+        move || send_events(&controller, &clients)
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("ipc: failed to accept a client: {}", e);
+                    continue;
+                }
+            };
+            if let Ok(events) = stream.try_clone() {
+                clients.lock().unwrap().push(events);
+            }
+
+            let controller = controller.clone();
+            thread::spawn(move || handle_client(stream, &controller));
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_client(stream: UnixStream, controller: &PlayerController) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => control::respond(&request, controller, control::Access::Full),
+            Err(e) => json!({ "error": format!("invalid request: {}", e) }),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+/// Reports changes in the player's status to every client, dropping those that went away.
+fn send_events(controller: &PlayerController, clients: &Mutex<Vec<UnixStream>>) {
+    let mut last = PlayerStatus::default();
+    loop {
+        thread::sleep(EVENT_INTERVAL);
+        let status = controller.status();
+
+        let mut events = Vec::new();
+        if status.path != last.path {
+            if last.path.is_some() {
+                events.push(json!({ "event": "end-file" }));
+            }
+            if let Some(path) = &status.path {
+                events.push(json!({ "event": "start-file", "path": path }));
+            }
+        } else if status.path.is_some() {
+            if status.paused != last.paused {
+                let event = if status.paused { "pause" } else { "unpause" };
+                events.push(json!({ "event": event }));
+            }
+            if status.seeks != last.seeks {
+                events.push(json!({ "event": "seek" }));
+            }
+        }
+        last = status;
+
+        if events.is_empty() {
+            continue;
+        }
+        clients.lock().unwrap().retain(|client| {
+            let mut client = client;
+            events
+                .iter()
+                .all(|event| writeln!(client, "{}", event).is_ok())
+        });
+    }
+}
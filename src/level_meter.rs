@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    audio_sink::AudioSpec,
+    osd::{Color, OsdTarget},
+};
+
+/// Levels below this many dB under full scale are drawn as silence.
+const FLOOR_DB: f32 = -60_f32;
+/// Above these the bars turn yellow, then red.
+const WARNING_DB: f32 = -18_f32;
+const CLIPPING_DB: f32 = -3_f32;
+/// How fast the bars fall, in dB per second, and how long peaks are held.
+const FALL_RATE: f32 = 24_f32;
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+
+const BAR_WIDTH: u32 = 10;
+const BAR_HEIGHT: u32 = 160;
+const GAP: u32 = 4;
+const MARGIN: i32 = 16;
+const BACKGROUND: Color = Color::rgba(0, 0, 0, 160);
+
+struct Channel {
+    /// Peak level in dB when last updated, falling at `FALL_RATE` from then on, also once
+    /// the audio stops coming.
+    level: f32,
+    updated: Instant,
+    /// Highest level lately, in dB, and when it was reached.
+    peak: f32,
+    peak_at: Instant,
+}
+
+impl Channel {
+    fn level_at(&self, now: Instant) -> f32 {
+        self.level - FALL_RATE * now.duration_since(self.updated).as_secs_f32()
+    }
+}
+
+/// A peak meter per channel of the audio being played, drawn on the left edge of the OSD
+/// while shown: for checking the level of a feed, and that it's there at all.
+///
+/// Like the spectrogram, it takes the audio as it's written to the sink, after the volume.
+pub(crate) struct LevelMeter {
+    visible: bool,
+    channels: Vec<Channel>,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        LevelMeter {
+            visible: false,
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Shows or hides the meter, returning whether it's shown now.
+    pub fn toggle(&mut self) -> bool {
+        self.set_visible(!self.visible);
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.channels.clear();
+    }
+
+    /// Adds interleaved `samples` in `spec`, nothing while hidden.
+    pub fn push(&mut self, samples: &[f32], spec: AudioSpec) {
+        if !self.visible || spec.channels == 0 {
+            return;
+        }
+
+        let count = spec.channels as usize;
+        let now = Instant::now();
+        if self.channels.len() != count {
+            self.channels = (0..count)
+                .map(|_| Channel {
+                    level: FLOOR_DB,
+                    updated: now,
+                    peak: FLOOR_DB,
+                    peak_at: now,
+                })
+                .collect();
+        }
+
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            let peak = samples
+                .iter()
+                .skip(index)
+                .step_by(count)
+                .fold(0_f32, |peak, sample| peak.max(sample.abs()));
+            let db = (20_f32 * peak.max(1e-9).log10()).max(FLOOR_DB);
+
+            channel.level = db.max(channel.level_at(now));
+            channel.updated = now;
+            if db >= channel.peak || now.duration_since(channel.peak_at) > PEAK_HOLD {
+                channel.peak = db;
+                channel.peak_at = now;
+            }
+        }
+    }
+
+    /// `bottom` is how much of the bottom of `target` is taken, by the control bar.
+    pub fn draw<T: OsdTarget>(&self, target: &mut T, bottom: i32) {
+        if !self.visible || self.channels.is_empty() {
+            return;
+        }
+
+        let (_, height) = target.size();
+        let count = self.channels.len() as u32;
+        let width = count * BAR_WIDTH + (count + 1) * GAP;
+        // centered in what the control bar leaves
+        let y = (height as i32 - bottom - (BAR_HEIGHT + 2 * GAP) as i32) / 2;
+        target.fill_rect(MARGIN, y, width, BAR_HEIGHT + 2 * GAP, BACKGROUND);
+
+        let scale = |db: f32| ((db - FLOOR_DB) / -FLOOR_DB).clamp(0_f32, 1_f32);
+        let bottom_y = y + (GAP + BAR_HEIGHT) as i32;
+        let now = Instant::now();
+        for (index, channel) in self.channels.iter().enumerate() {
+            let x = MARGIN + (GAP + index as u32 * (BAR_WIDTH + GAP)) as i32;
+
+            // drawn in three runs, so the colors stay where the levels are
+            let mut from = 0_f32;
+            for (to, color) in [
+                (scale(WARNING_DB), Color::rgba(64, 208, 64, 224)),
+                (scale(CLIPPING_DB), Color::rgba(232, 200, 48, 224)),
+                (1_f32, Color::rgba(232, 48, 48, 224)),
+            ] {
+                let top = scale(channel.level_at(now)).min(to);
+                if top > from {
+                    let run_y = bottom_y - (top * BAR_HEIGHT as f32) as i32;
+                    let run_height = ((top - from) * BAR_HEIGHT as f32).ceil() as u32;
+                    target.fill_rect(x, run_y, BAR_WIDTH, run_height, color);
+                }
+                from = to;
+            }
+
+            if channel.peak > FLOOR_DB && now.duration_since(channel.peak_at) <= PEAK_HOLD {
+                let peak_y = bottom_y - (scale(channel.peak) * BAR_HEIGHT as f32) as i32;
+                target.fill_rect(x, peak_y, BAR_WIDTH, 2, Color::rgba(255, 255, 255, 224));
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// first, so every module below can use its macros
+#[macro_use]
+pub mod output;
+
+mod abr;
+mod audio_filter;
+mod budget;
+mod capture;
+#[cfg(feature = "cast")]
+mod cast;
+mod chapter_menu;
+mod clock;
+mod compose;
+mod config;
+#[cfg(any(all(unix, feature = "ipc"), feature = "remote"))]
+mod control;
+mod controls;
+mod crop;
+mod font;
+mod hwdec;
+mod level_meter;
+mod monitor;
+mod mosaic;
+#[cfg(feature = "now-playing")]
+mod now_playing;
+mod ordered_chapters;
+mod pacing;
+mod playback;
+mod remux;
+#[cfg(feature = "scripting")]
+mod script;
+mod secondary;
+mod spdif;
+mod spectrogram;
+mod sync;
+mod timeline;
+mod timeshift;
+mod video_filter;
+mod visualizer;
+
+pub mod ambilight;
+pub mod asset;
+#[cfg(feature = "tokio")]
+pub mod async_player;
+pub mod audio_sink;
+pub mod benchmark;
+pub mod bindings;
+pub mod contact_sheet;
+pub mod dump;
+#[cfg(feature = "egui")]
+pub mod egui;
+pub mod equalizer;
+pub mod export;
+pub mod history;
+#[cfg(all(unix, feature = "ipc"))]
+pub mod ipc;
+pub mod metrics;
+pub mod options;
+pub mod osd;
+pub mod picture;
+pub mod player;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod progress;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod video_sink;
+
+#[cfg(feature = "tokio")]
+pub use crate::async_player::{AsyncPlayer, AsyncPlayerController};
+pub use crate::{
+    asset::{PlaybackAsset, PlaybackAssetMetadata},
+    audio_sink::AudioSink,
+    clock::ExternalClock,
+    options::Options,
+    player::{
+        PlaybackOutcome, PlaybackState, Player, PlayerCommand, PlayerController, PlayerEvent,
+        PlayerStatus,
+    },
+    video_sink::VideoSink,
+};
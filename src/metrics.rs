@@ -0,0 +1,384 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ffmpeg_next::{decoder, format, frame::Video, media::Type, Error, Packet};
+
+use crate::{compose, dump::io_error};
+
+/// PSNR of identical frames is infinite; this stands in for it so averages and exports
+/// stay numbers.
+const MAX_PSNR: f64 = 100_f64;
+
+/// SSIM is computed over windows of this size, overlapping by half.
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+
+/// Stabilizing constants from the SSIM paper, for 8-bit samples.
+const SSIM_C1: f64 = (0.01 * 255_f64) * (0.01 * 255_f64);
+const SSIM_C2: f64 = (0.03 * 255_f64) * (0.03 * 255_f64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Psnr,
+    Ssim,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Psnr => "psnr",
+            Metric::Ssim => "ssim",
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "psnr" => Ok(Metric::Psnr),
+            "ssim" => Ok(Metric::Ssim),
+            _ => Err(format!("unsupported metric '{}'", value)),
+        }
+    }
+}
+
+/// Parses a comma separated list like `psnr,ssim`.
+pub fn parse_metrics(value: &str) -> Result<Vec<Metric>, String> {
+    let mut metrics = Vec::new();
+    for metric in value.split(',') {
+        let metric = metric.parse()?;
+        if !metrics.contains(&metric) {
+            metrics.push(metric);
+        }
+    }
+    Ok(metrics)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricsFormat {
+    Csv,
+    Json,
+}
+
+impl MetricsFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "csv" => Ok(MetricsFormat::Csv),
+            "json" => Ok(MetricsFormat::Json),
+            _ => Err(format!("unsupported metrics format '{}'", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsSettings {
+    pub metrics: Vec<Metric>,
+    /// CSV or JSON, going by the extension; CSV when it's neither.
+    pub output: Option<PathBuf>,
+}
+
+/// Scores of one frame of the distorted input against the reference, in the order of
+/// `MetricsSettings::metrics`.
+#[derive(Clone, Debug)]
+pub struct FrameMetrics {
+    pub frame: usize,
+    pub time: f64,
+    pub values: Vec<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsReport {
+    pub metrics: Vec<Metric>,
+    pub frames: Vec<FrameMetrics>,
+}
+
+impl MetricsReport {
+    /// Aggregate of each metric over all frames. PSNR is taken from the mean squared error
+    /// of the whole run rather than averaged, the way ffmpeg's `psnr` filter reports it.
+    pub fn average(&self) -> Vec<f64> {
+        let count = self.frames.len().max(1) as f64;
+        self.metrics
+            .iter()
+            .enumerate()
+            .map(|(idx, metric)| {
+                let values = self.frames.iter().map(|frame| frame.values[idx]);
+                match metric {
+                    Metric::Psnr => psnr(values.map(mse_from_psnr).sum::<f64>() / count),
+                    Metric::Ssim => values.sum::<f64>() / count,
+                }
+            })
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frame,time");
+        for metric in &self.metrics {
+            csv.push(',');
+            csv.push_str(metric.name());
+        }
+        csv.push('\n');
+
+        for frame in &self.frames {
+            let _ = write!(csv, "{},{:.3}", frame.frame, frame.time);
+            for value in &frame.values {
+                let _ = write!(csv, ",{:.6}", value);
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        let fields = |values: &[f64]| {
+            self.metrics
+                .iter()
+                .zip(values)
+                .map(|(metric, value)| format!("\"{}\": {:.6}", metric.name(), value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                format!(
+                    "    {{\"frame\": {}, \"time\": {:.3}, {}}}",
+                    frame.frame,
+                    frame.time,
+                    fields(&frame.values)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+
+        format!(
+            "{{\n  \"average\": {{{}}},\n  \"frames\": [\n{}\n  ]\n}}\n",
+            fields(&self.average()),
+            frames
+        )
+    }
+}
+
+/// Decodes `reference` and `distorted` side by side, frame for frame, and scores each of
+/// the distorted frames against the reference one. The distorted video is scaled to the
+/// reference's size first; both metrics look at luma only.
+///
+/// Per-frame scores are printed as they come, and the whole report is written to
+/// `settings.output` when there is one.
+pub fn compare(
+    reference: &str,
+    distorted: &str,
+    settings: &MetricsSettings,
+) -> Result<MetricsReport, Error> {
+    let mut reference = FrameReader::open(reference)?;
+    let mut distorted = FrameReader::open(distorted)?;
+    let (mut reference_scaler, mut distorted_scaler) = (None, None);
+
+    let mut report = MetricsReport {
+        metrics: settings.metrics.clone(),
+        frames: Vec::new(),
+    };
+
+    while let (Some(first), Some(second)) = (reference.next_frame()?, distorted.next_frame()?) {
+        let (width, height) = (first.width() & !1, first.height() & !1);
+        let first_luma = compose::scale(&mut reference_scaler, &first, width, height)
+            .ok_or(Error::InvalidData)?;
+        let second_luma = compose::scale(&mut distorted_scaler, &second, width, height)
+            .ok_or(Error::InvalidData)?;
+
+        let values = settings
+            .metrics
+            .iter()
+            .map(|metric| match metric {
+                Metric::Psnr => psnr(mean_squared_error(&first_luma, &second_luma)),
+                Metric::Ssim => ssim(&first_luma, &second_luma),
+            })
+            .collect::<Vec<_>>();
+
+        let frame = FrameMetrics {
+            frame: report.frames.len(),
+            time: first.timestamp().unwrap_or(0) as f64 * reference.time_base,
+            values,
+        };
+        info!(
+            "{}",
+            describe(frame.frame, &settings.metrics, &frame.values)
+        );
+        report.frames.push(frame);
+    }
+
+    info!("{}", describe_average(&report));
+
+    if let Some(output) = &settings.output {
+        let contents = match MetricsFormat::from_path(output).unwrap_or(MetricsFormat::Csv) {
+            MetricsFormat::Csv => report.to_csv(),
+            MetricsFormat::Json => report.to_json(),
+        };
+        fs::write(output, contents).map_err(io_error)?;
+    }
+
+    Ok(report)
+}
+
+fn describe(frame: usize, metrics: &[Metric], values: &[f64]) -> String {
+    let mut line = format!("frame {:>6}", frame);
+    for (metric, value) in metrics.iter().zip(values) {
+        let _ = write!(line, "  {} {:>9.4}", metric.name(), value);
+    }
+    line
+}
+
+fn describe_average(report: &MetricsReport) -> String {
+    let mut line = format!("average over {} frames", report.frames.len());
+    for (metric, value) in report.metrics.iter().zip(report.average()) {
+        let _ = write!(line, "  {} {:.4}", metric.name(), value);
+    }
+    line
+}
+
+/// The best video stream of a file, decoded a frame at a time.
+struct FrameReader {
+    input: format::context::Input,
+    stream_index: usize,
+    time_base: f64,
+    decoder: decoder::Video,
+    ended: bool,
+}
+
+impl FrameReader {
+    fn open(path: &str) -> Result<Self, Error> {
+        let input = format::input(&Path::new(path))?;
+        let (stream_index, time_base, decoder) = {
+            let stream = input
+                .streams()
+                .best(Type::Video)
+                .ok_or(Error::StreamNotFound)?;
+            let decoder = stream.codec().decoder().video()?;
+            (stream.index(), f64::from(stream.time_base()), decoder)
+        };
+
+        Ok(FrameReader {
+            input,
+            stream_index,
+            time_base,
+            decoder,
+            ended: false,
+        })
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Video>, Error> {
+        let mut frame = Video::empty();
+        loop {
+            if self.decoder.receive_frame(&mut frame).is_ok() {
+                return Ok(Some(frame));
+            }
+            if self.ended {
+                return Ok(None);
+            }
+
+            let mut packet = Packet::empty();
+            match packet.read(&mut self.input) {
+                Ok(()) if packet.stream() == self.stream_index => {
+                    self.decoder.send_packet(&packet)?
+                }
+                Ok(()) => {}
+                Err(Error::Eof) => {
+                    self.decoder.send_eof()?;
+                    self.ended = true;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Luma rows of a YUV420P frame, without the stride padding.
+fn luma_rows(frame: &Video) -> impl Iterator<Item = &[u8]> {
+    let (width, stride) = (frame.width() as usize, frame.stride(0));
+    frame
+        .data(0)
+        .chunks(stride)
+        .take(frame.height() as usize)
+        .map(move |row| &row[..width])
+}
+
+fn mean_squared_error(first: &Video, second: &Video) -> f64 {
+    let total: u64 = luma_rows(first)
+        .zip(luma_rows(second))
+        .flat_map(|(a, b)| a.iter().zip(b))
+        .map(|(a, b)| {
+            let difference = (*a as i64 - *b as i64).unsigned_abs();
+            difference * difference
+        })
+        .sum();
+    let pixels = (first.width() as u64 * first.height() as u64).max(1);
+    total as f64 / pixels as f64
+}
+
+fn psnr(mse: f64) -> f64 {
+    if mse <= 0_f64 {
+        return MAX_PSNR;
+    }
+    (10_f64 * (255_f64 * 255_f64 / mse).log10()).min(MAX_PSNR)
+}
+
+fn mse_from_psnr(psnr: f64) -> f64 {
+    255_f64 * 255_f64 / 10_f64.powf(psnr / 10_f64)
+}
+
+/// Mean SSIM over all windows of the luma plane.
+fn ssim(first: &Video, second: &Video) -> f64 {
+    let a = luma_rows(first).collect::<Vec<_>>();
+    let b = luma_rows(second).collect::<Vec<_>>();
+    let (width, height) = (first.width() as usize, first.height() as usize);
+    if width < SSIM_WINDOW || height < SSIM_WINDOW {
+        return 1_f64;
+    }
+
+    let mut total = 0_f64;
+    let mut windows = 0;
+    for y in (0..=height - SSIM_WINDOW).step_by(SSIM_STEP) {
+        for x in (0..=width - SSIM_WINDOW).step_by(SSIM_STEP) {
+            let (mut sum_a, mut sum_b) = (0_f64, 0_f64);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0_f64, 0_f64, 0_f64);
+            for row in y..y + SSIM_WINDOW {
+                for column in x..x + SSIM_WINDOW {
+                    let (a, b) = (a[row][column] as f64, b[row][column] as f64);
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+
+            let count = (SSIM_WINDOW * SSIM_WINDOW) as f64;
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            total += ((2_f64 * mean_a * mean_b + SSIM_C1) * (2_f64 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1)
+                    * (variance_a + variance_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
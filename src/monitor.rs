@@ -0,0 +1,92 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use ffmpeg_next::{frame::Audio, media, Error};
+
+use crate::{capture::CaptureDevice, options::Options};
+
+struct Shared {
+    frames: VecDeque<Audio>,
+    stopped: bool,
+}
+
+/// A microphone or line input played in place of the input's own audio, to check a feed
+/// alongside the picture (`--audio-input`).
+///
+/// Its frames aren't scheduled against the clock, the device's timestamps having nothing
+/// to do with the input's: they're handed out as they're captured, and the oldest dropped
+/// once more than the monitoring latency is waiting.
+pub struct AudioMonitor {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl AudioMonitor {
+    /// Opens `device` and starts decoding its audio.
+    pub fn open(device: &CaptureDevice, options: &Options) -> Result<Self, Error> {
+        let mut input = device.open(options)?;
+        let stream = input
+            .streams()
+            .best(media::Type::Audio)
+            .ok_or(Error::StreamNotFound)?;
+        let index = stream.index();
+        let mut decoder = stream.codec().decoder().audio()?;
+
+        let shared = Arc::new(Mutex::new(Shared {
+            frames: VecDeque::new(),
+            stopped: false,
+        }));
+
+        thread::spawn({
+            let shared = Arc::clone(&shared);
+            let device = device.to_string();
+            move || {
+                for (stream, packet) in input.packets() {
+                    if shared.lock().unwrap().stopped {
+                        return;
+                    }
+                    if stream.index() != index || decoder.send_packet(&packet).is_err() {
+                        continue;
+                    }
+                    let mut frame = Audio::empty();
+                    while decoder.receive_frame(&mut frame).is_ok() {
+                        shared.lock().unwrap().frames.push_back(frame);
+                        frame = Audio::empty();
+                    }
+                }
+                eprintln!("audio input {} ended", device);
+            }
+        });
+
+        Ok(AudioMonitor { shared })
+    }
+
+    /// The frames captured since the last call, the newest `latency` seconds of them.
+    pub fn take(&mut self, latency: f64) -> Vec<Audio> {
+        let mut shared = self.shared.lock().unwrap();
+        let mut queued: f64 = shared.frames.iter().map(duration).sum();
+        // the newest frame is kept, however long it is
+        while queued > latency && shared.frames.len() > 1 {
+            if let Some(frame) = shared.frames.pop_front() {
+                queued -= duration(&frame);
+            }
+        }
+        shared.frames.drain(..).collect()
+    }
+}
+
+impl Drop for AudioMonitor {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().stopped = true;
+    }
+}
+
+fn duration(frame: &Audio) -> f64 {
+    if frame.rate() > 0 {
+        frame.samples() as f64 / frame.rate() as f64
+    } else {
+        0_f64
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ffmpeg_next::{
+    decoder,
+    frame::{Audio, Video},
+    Packet,
+};
+
+use crate::{asset::PlaybackAsset, compose::Compositor, options::Options};
+
+/// A tile falling this far behind its own pace, or jumping this far ahead, e.g. for a live
+/// input that stalled, starts over from where it is.
+const MAX_DRIFT: f64 = 1_f64;
+
+/// Seconds of the focused tile's audio kept waiting to be played at most.
+const AUDIO_LATENCY: f64 = 0.2;
+
+struct Shared {
+    /// The newest frame due, shown until the next one is.
+    video: Option<Video>,
+    /// A frame came since the mosaic was last composed.
+    updated: bool,
+    /// Audio is only kept for the focused tile.
+    with_audio: bool,
+    audio: VecDeque<Audio>,
+    stopped: bool,
+}
+
+/// One input tiled next to the main one, decoded on a thread of its own and paced by its
+/// own timestamps, so live inputs that aren't in step with each other all show what's
+/// newest.
+struct Tile {
+    path: String,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Tile {
+    fn open(path: &str, options: &Options) -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            video: None,
+            updated: false,
+            with_audio: false,
+            audio: VecDeque::new(),
+            stopped: false,
+        }));
+
+        thread::spawn({
+            let path = path.to_string();
+            let options = options.clone();
+            let shared = Arc::clone(&shared);
+            move || match PlaybackAsset::open(&path, &options) {
+                // an input that fails to open stays a black tile
+                Ok(asset) => run(asset, &options, &shared),
+                Err(e) => eprintln!("failed to open {}: {}", path, e),
+            }
+        });
+
+        Tile {
+            path: path.to_string(),
+            shared,
+        }
+    }
+}
+
+impl Drop for Tile {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().stopped = true;
+    }
+}
+
+/// More inputs tiled in a grid with the main one, like a small NVR: one of them is focused,
+/// its audio being the one played, and can be expanded to fill the picture.
+///
+/// The main input is tile 0 and keeps to the player's clock; the others each go at their
+/// own pace, seeks and pauses leaving them be.
+pub struct Mosaic {
+    tiles: Vec<Tile>,
+    focus: usize,
+    expanded: bool,
+}
+
+impl Mosaic {
+    /// Starts decoding `paths`, tiled after the main input in that order.
+    pub fn open(paths: &[String], options: &Options) -> Self {
+        Mosaic {
+            tiles: paths.iter().map(|path| Tile::open(path, options)).collect(),
+            focus: 0,
+            expanded: false,
+        }
+    }
+
+    /// Tiles, the main input's included.
+    pub fn count(&self) -> usize {
+        self.tiles.len() + 1
+    }
+
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    /// The input of tile `index`, `None` for the main input.
+    pub fn path(&self, index: usize) -> Option<&str> {
+        index
+            .checked_sub(1)
+            .and_then(|index| self.tiles.get(index))
+            .map(|tile| tile.path.as_str())
+    }
+
+    /// Moves the focus, and the audio with it, to the next tile, returning which it is.
+    pub fn focus_next(&mut self) -> usize {
+        self.focus = (self.focus + 1) % self.count();
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let mut shared = tile.shared.lock().unwrap();
+            shared.with_audio = index + 1 == self.focus;
+            shared.audio.clear();
+        }
+        self.focus
+    }
+
+    /// Shows the focused tile alone or all of them, returning whether it's alone now.
+    pub fn toggle_expanded(&mut self) -> bool {
+        self.expanded = !self.expanded;
+        self.expanded
+    }
+
+    /// Whether any tile has a frame that hasn't been composed yet, clearing it.
+    pub fn take_updated(&mut self) -> bool {
+        self.tiles.iter().fold(false, |updated, tile| {
+            let mut shared = tile.shared.lock().unwrap();
+            let tile_updated = shared.updated;
+            shared.updated = false;
+            updated || tile_updated
+        })
+    }
+
+    /// The main input's `frame` tiled with the others' newest frames, `None` to show
+    /// `frame` as it is.
+    pub fn compose(&mut self, compositor: &mut Compositor, frame: &Video) -> Option<Video> {
+        if self.expanded && self.focus == 0 {
+            return None;
+        }
+
+        let tiles: Vec<_> = self
+            .tiles
+            .iter()
+            .map(|tile| tile.shared.lock().unwrap())
+            .collect();
+        let mut picture = if self.expanded {
+            let tile = tiles[self.focus - 1].video.as_ref();
+            compositor.mosaic(&[tile], None, frame)
+        } else {
+            let mut frames = vec![Some(frame)];
+            frames.extend(tiles.iter().map(|shared| shared.video.as_ref()));
+            compositor.mosaic(&frames, Some(self.focus), frame)
+        }?;
+        for mut shared in tiles {
+            shared.updated = false;
+        }
+        picture.set_pts(frame.pts());
+        Some(picture)
+    }
+
+    /// The focused tile's audio captured since the last call, nothing while it's the main
+    /// input.
+    pub fn take_audio(&mut self) -> Vec<Audio> {
+        let tile = match self
+            .focus
+            .checked_sub(1)
+            .and_then(|index| self.tiles.get(index))
+        {
+            Some(tile) => tile,
+            None => return Vec::new(),
+        };
+        let mut shared = tile.shared.lock().unwrap();
+        let mut queued: f64 = shared.audio.iter().map(audio_duration).sum();
+        while queued > AUDIO_LATENCY && shared.audio.len() > 1 {
+            if let Some(frame) = shared.audio.pop_front() {
+                queued -= audio_duration(&frame);
+            }
+        }
+        shared.audio.drain(..).collect()
+    }
+}
+
+fn run(mut asset: PlaybackAsset, options: &Options, shared: &Mutex<Shared>) {
+    let metadata = asset.metadata();
+    let mut video_decoder = if metadata.has_video() {
+        Some(asset.video_decoder(options))
+    } else {
+        None
+    };
+    let mut audio_decoder = asset.audio_decoder(options);
+    // the timestamp, in seconds, due at the instant
+    let mut anchor: Option<(f64, Instant)> = None;
+
+    for (stream, packet) in asset.packets() {
+        if shared.lock().unwrap().stopped {
+            return;
+        }
+
+        if Some(stream.index()) == metadata.video_stream_index() {
+            let decoder = match &mut video_decoder {
+                Some(decoder) => decoder,
+                None => continue,
+            };
+            for frame in decode_video(decoder, &packet) {
+                if let Some(pts) = frame.pts() {
+                    pace(&mut anchor, pts as f64 * metadata.video_time_base());
+                }
+                let mut shared = shared.lock().unwrap();
+                shared.video = Some(frame);
+                shared.updated = true;
+            }
+        } else if Some(stream.index()) == metadata.audio_stream_index() {
+            for frame in decode_audio(&mut audio_decoder, &packet) {
+                // without video, the audio sets the pace
+                if let (false, Some(pts)) = (metadata.has_video(), frame.pts()) {
+                    pace(&mut anchor, pts as f64 * metadata.audio_time_base());
+                }
+                let mut shared = shared.lock().unwrap();
+                if shared.with_audio {
+                    shared.audio.push_back(frame);
+                }
+            }
+        }
+    }
+    // the last frame stays up once the input ends
+}
+
+/// Waits until `time` is due going by `anchor`, starting over from `time` when it's too far
+/// off.
+fn pace(anchor: &mut Option<(f64, Instant)>, time: f64) {
+    let now = Instant::now();
+    let (start, at) = *anchor.get_or_insert((time, now));
+    let wait = time - start - now.duration_since(at).as_secs_f64();
+    if wait.abs() > MAX_DRIFT {
+        *anchor = Some((time, now));
+    } else if wait > 0_f64 {
+        thread::sleep(Duration::from_secs_f64(wait));
+    }
+}
+
+fn audio_duration(frame: &Audio) -> f64 {
+    if frame.rate() > 0 {
+        frame.samples() as f64 / frame.rate() as f64
+    } else {
+        0_f64
+    }
+}
+
+fn decode_video(decoder: &mut decoder::Video, packet: &Packet) -> Vec<Video> {
+    let mut frames = Vec::new();
+    if decoder.send_packet(packet).is_ok() {
+        let mut frame = Video::empty();
+        while decoder.receive_frame(&mut frame).is_ok() {
+            frames.push(frame);
+            frame = Video::empty();
+        }
+    }
+    frames
+}
+
+fn decode_audio(decoder: &mut decoder::Audio, packet: &Packet) -> Vec<Audio> {
+    let mut frames = Vec::new();
+    if decoder.send_packet(packet).is_ok() {
+        let mut frame = Audio::empty();
+        while decoder.receive_frame(&mut frame).is_ok() {
+            frames.push(frame);
+            frame = Audio::empty();
+        }
+    }
+    frames
+}
@@ -0,0 +1,126 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use souvlaki::{
+    MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig,
+    SeekDirection,
+};
+
+use crate::player::{PlayerCommand, PlayerController};
+
+/// How far the seek buttons of the system's controls skip, in seconds.
+const SEEK_STEP: f64 = 10_f64;
+
+/// The system shows a moving position on its own, it only needs correcting now and then.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Registers the player as the system's now-playing app: on macOS the Control Center and
+/// Touch Bar show the title and position, and their transport controls drive the player.
+pub struct NowPlaying {
+    controls: MediaControls,
+    last_update: Option<(Instant, bool)>,
+}
+
+impl NowPlaying {
+    /// `None` when the platform's media controls couldn't be set up.
+    pub fn new(controller: PlayerController, path: &str, duration: Option<f64>) -> Option<Self> {
+        let config = PlatformConfig {
+            dbus_name: "video_player",
+            display_name: "Video Player",
+            hwnd: None,
+        };
+        let mut controls = match MediaControls::new(config) {
+            Ok(controls) => controls,
+            Err(e) => {
+                eprintln!("failed to register media controls: {:?}", e);
+                return None;
+            }
+        };
+
+        let attached = controls.attach(move |event| {
+            if let Some(command) = command_for(event) {
+                controller.send(command);
+            }
+        });
+        if let Err(e) = attached {
+            eprintln!("failed to attach media controls: {:?}", e);
+            return None;
+        }
+
+        let title = Path::new(path).file_stem().map_or_else(
+            || path.to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        controls
+            .set_metadata(MediaMetadata {
+                title: Some(&title),
+                duration: duration.map(Duration::from_secs_f64),
+                ..Default::default()
+            })
+            .ok();
+
+        Some(NowPlaying {
+            controls,
+            last_update: None,
+        })
+    }
+
+    /// Reports the playback state when it changed, and the position every few seconds.
+    pub fn update(&mut self, position: f64, paused: bool) {
+        let due = self.last_update.map_or(true, |(updated, was_paused)| {
+            was_paused != paused || updated.elapsed() >= UPDATE_INTERVAL
+        });
+        if !due {
+            return;
+        }
+
+        let progress = Some(MediaPosition(Duration::from_secs_f64(position.max(0_f64))));
+        let playback = if paused {
+            MediaPlayback::Paused { progress }
+        } else {
+            MediaPlayback::Playing { progress }
+        };
+        self.controls.set_playback(playback).ok();
+        self.last_update = Some((Instant::now(), paused));
+    }
+
+    /// Replaces the title the system shows, e.g. with the song an internet radio station is
+    /// playing.
+    pub fn set_title(&mut self, title: &str) {
+        self.controls
+            .set_metadata(MediaMetadata {
+                title: Some(title),
+                ..Default::default()
+            })
+            .ok();
+    }
+
+    /// Makes the next `update` report right away, e.g. after a seek.
+    pub fn invalidate(&mut self) {
+        self.last_update = None;
+    }
+}
+
+fn command_for(event: MediaControlEvent) -> Option<PlayerCommand> {
+    let step = |direction| match direction {
+        SeekDirection::Forward => SEEK_STEP,
+        SeekDirection::Backward => -SEEK_STEP,
+    };
+
+    match event {
+        MediaControlEvent::Play => Some(PlayerCommand::Play),
+        MediaControlEvent::Pause => Some(PlayerCommand::Pause),
+        MediaControlEvent::Toggle => Some(PlayerCommand::TogglePause),
+        MediaControlEvent::Stop | MediaControlEvent::Quit => Some(PlayerCommand::Stop),
+        MediaControlEvent::Seek(direction) => Some(PlayerCommand::SeekBy(step(direction))),
+        MediaControlEvent::SeekBy(direction, amount) => Some(PlayerCommand::SeekBy(
+            step(direction).signum() * amount.as_secs_f64(),
+        )),
+        MediaControlEvent::SetPosition(MediaPosition(position)) => {
+            Some(PlayerCommand::Seek(position.as_secs_f64()))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,1012 @@
+use std::{env, path::PathBuf, str::FromStr};
+
+use crate::{
+    ambilight::{AmbilightTarget, LedLayout},
+    bindings::{self, KeyChord},
+    capture::CaptureDevice,
+    config,
+    contact_sheet::Grid,
+    dump::ImageFormat,
+    equalizer::{self, EqualizerPreset},
+    export::ExportFormat,
+    metrics::{self, Metric},
+    player::PlayerCommand,
+    progress::ProgressFormat,
+};
+
+const DEFAULT_INPUT: &str = "resources/tears-of-steel_teaser.mp4";
+
+/// Read from `config_dir()` at startup.
+const CONFIG_FILE: &str = "config.toml";
+
+/// The first windowed output this build has.
+#[cfg(feature = "sdl")]
+const DEFAULT_VIDEO_OUTPUT: VideoOutput = VideoOutput::Sdl;
+#[cfg(all(not(feature = "sdl"), feature = "winit"))]
+const DEFAULT_VIDEO_OUTPUT: VideoOutput = VideoOutput::Winit;
+#[cfg(not(any(feature = "sdl", feature = "winit")))]
+const DEFAULT_VIDEO_OUTPUT: VideoOutput = VideoOutput::Null;
+
+#[cfg(feature = "sdl")]
+const DEFAULT_AUDIO_OUTPUT: AudioOutput = AudioOutput::Sdl;
+#[cfg(all(not(feature = "sdl"), feature = "cpal"))]
+const DEFAULT_AUDIO_OUTPUT: AudioOutput = AudioOutput::Cpal;
+#[cfg(not(any(feature = "sdl", feature = "cpal")))]
+const DEFAULT_AUDIO_OUTPUT: AudioOutput = AudioOutput::Null;
+
+/// Where decoded video goes: an SDL window, an OpenGL window with shader-based colour
+/// conversion, a winit window without SDL, inline terminal graphics, coloured terminal text,
+/// or nowhere for headless runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VideoOutput {
+    #[cfg(feature = "sdl")]
+    Sdl,
+    #[cfg(feature = "sdl")]
+    Gl,
+    #[cfg(feature = "winit")]
+    Winit,
+    Terminal,
+    Tct,
+    Null,
+}
+
+impl FromStr for VideoOutput {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            #[cfg(feature = "sdl")]
+            "sdl" => Ok(VideoOutput::Sdl),
+            #[cfg(feature = "sdl")]
+            "gl" => Ok(VideoOutput::Gl),
+            #[cfg(feature = "winit")]
+            "winit" => Ok(VideoOutput::Winit),
+            "terminal" => Ok(VideoOutput::Terminal),
+            "tct" => Ok(VideoOutput::Tct),
+            "null" => Ok(VideoOutput::Null),
+            _ => Err(format!("unknown video output '{}'", value)),
+        }
+    }
+}
+
+/// Where decoded audio goes: an SDL or cpal audio device, a WAV file, or discarded on
+/// schedule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioOutput {
+    #[cfg(feature = "sdl")]
+    Sdl,
+    #[cfg(feature = "cpal")]
+    Cpal,
+    Wav,
+    Null,
+}
+
+impl FromStr for AudioOutput {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            #[cfg(feature = "sdl")]
+            "sdl" => Ok(AudioOutput::Sdl),
+            #[cfg(feature = "cpal")]
+            "cpal" => Ok(AudioOutput::Cpal),
+            "wav" => Ok(AudioOutput::Wav),
+            "null" => Ok(AudioOutput::Null),
+            _ => Err(format!("unknown audio output '{}'", value)),
+        }
+    }
+}
+
+/// Which ReplayGain tags set the playback volume.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayGain {
+    Track,
+    /// Album gain, falling back to track gain for files without it.
+    Album,
+    Off,
+}
+
+impl FromStr for ReplayGain {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "track" => Ok(ReplayGain::Track),
+            "album" => Ok(ReplayGain::Album),
+            "off" => Ok(ReplayGain::Off),
+            _ => Err(format!("unknown replaygain mode '{}'", value)),
+        }
+    }
+}
+
+/// How the picture fits the window: its own display aspect ratio, a forced 4:3 or 16:9, or
+/// stretched over the whole window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AspectMode {
+    Source,
+    Ratio4x3,
+    Ratio16x9,
+    Fill,
+}
+
+impl AspectMode {
+    /// The mode the aspect key switches to next.
+    pub fn next(&self) -> Self {
+        match self {
+            AspectMode::Source => AspectMode::Ratio4x3,
+            AspectMode::Ratio4x3 => AspectMode::Ratio16x9,
+            AspectMode::Ratio16x9 => AspectMode::Fill,
+            AspectMode::Fill => AspectMode::Source,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AspectMode::Source => "source",
+            AspectMode::Ratio4x3 => "4:3",
+            AspectMode::Ratio16x9 => "16:9",
+            AspectMode::Fill => "fill",
+        }
+    }
+}
+
+impl FromStr for AspectMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "source" => Ok(AspectMode::Source),
+            "4:3" => Ok(AspectMode::Ratio4x3),
+            "16:9" => Ok(AspectMode::Ratio16x9),
+            "fill" => Ok(AspectMode::Fill),
+            _ => Err(format!("unknown aspect mode '{}'", value)),
+        }
+    }
+}
+
+/// How the picture maps to the window: as is, or as the inside of a sphere for 360° video.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Flat,
+    Equirect,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "flat" => Ok(Projection::Flat),
+            "equirect" => Ok(Projection::Equirect),
+            _ => Err(format!("unknown projection '{}'", value)),
+        }
+    }
+}
+
+/// How 3D video is turned into something a normal display can show: the left eye of a
+/// side-by-side or top-bottom picture, a red/cyan anaglyph, or the left eye of whichever
+/// layout the picture's shape suggests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoMode {
+    SideBySide,
+    TopBottom,
+    Anaglyph,
+    LeftOnly,
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "sbs" => Ok(StereoMode::SideBySide),
+            "tb" => Ok(StereoMode::TopBottom),
+            "anaglyph" => Ok(StereoMode::Anaglyph),
+            "left-only" => Ok(StereoMode::LeftOnly),
+            _ => Err(format!("unknown stereo mode '{}'", value)),
+        }
+    }
+}
+
+/// What `--compare` shows: a wipe between the two inputs, one of them full-frame, or a
+/// heatmap of where they differ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareView {
+    Wipe,
+    First,
+    Second,
+    Difference,
+}
+
+impl CompareView {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompareView::Wipe => "wipe",
+            CompareView::First => "a",
+            CompareView::Second => "b",
+            CompareView::Difference => "difference",
+        }
+    }
+}
+
+/// How many threads the video decoder uses: one per core, or a fixed number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threads {
+    Auto,
+    Count(usize),
+}
+
+impl Threads {
+    /// `thread_count` as libavcodec takes it, 0 picking one per core.
+    pub fn count(&self) -> usize {
+        match self {
+            Threads::Auto => 0,
+            Threads::Count(count) => *count,
+        }
+    }
+}
+
+impl FromStr for Threads {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Threads::Auto),
+            _ => match value.parse() {
+                Ok(count) if count > 0 => Ok(Threads::Count(count)),
+                _ => Err(format!("invalid thread count '{}'", value)),
+            },
+        }
+    }
+}
+
+/// Which hardware decoder `--hwdec` asks for: none, the usual one for the platform, or a
+/// given API. Streams it can't decode are decoded in software.
+///
+/// Only APIs whose surfaces a video output shows without copying them back to memory are
+/// offered, which so far is VAAPI, shown by `--vo gl` on Wayland. `auto` decodes in software
+/// elsewhere, as does any API with an output that can't show its surfaces. Frames are
+/// still copied while filters that need them in memory, like `--autocrop`, are on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HwDecode {
+    No,
+    Auto,
+    Vaapi,
+}
+
+impl FromStr for HwDecode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "no" => Ok(HwDecode::No),
+            "auto" => Ok(HwDecode::Auto),
+            "vaapi" => Ok(HwDecode::Vaapi),
+            _ => Err(format!("unknown hardware decoder '{}'", value)),
+        }
+    }
+}
+
+/// What drives the playback position: the player's own clock, or the system's real-time
+/// clock for playing in step with other machines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockSource {
+    Internal,
+    System,
+}
+
+impl FromStr for ClockSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "internal" => Ok(ClockSource::Internal),
+            "system" => Ok(ClockSource::System),
+            _ => Err(format!("unknown clock source '{}'", value)),
+        }
+    }
+}
+
+/// When the screensaver and system sleep are held off: always, even while paused, while
+/// anything plays, only while video plays rather than music with cover art, or never.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopScreensaver {
+    Always,
+    Playing,
+    Video,
+    No,
+}
+
+impl FromStr for StopScreensaver {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "always" => Ok(StopScreensaver::Always),
+            "playing" => Ok(StopScreensaver::Playing),
+            "video" => Ok(StopScreensaver::Video),
+            "no" => Ok(StopScreensaver::No),
+            _ => Err(format!("unknown screensaver policy '{}'", value)),
+        }
+    }
+}
+
+/// Whether playback pauses on the last frame at the end instead of closing: at the end of
+/// the last input, at the end of every input even with others queued, or never.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeepOpen {
+    Yes,
+    Always,
+    No,
+}
+
+impl FromStr for KeepOpen {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "yes" => Ok(KeepOpen::Yes),
+            "always" => Ok(KeepOpen::Always),
+            "no" => Ok(KeepOpen::No),
+            _ => Err(format!("unknown keep-open policy '{}'", value)),
+        }
+    }
+}
+
+/// What's shown for inputs without video: FFT bars, the waveform, or a black picture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VisualizerStyle {
+    Bars,
+    Wave,
+    Off,
+}
+
+impl FromStr for VisualizerStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "bars" => Ok(VisualizerStyle::Bars),
+            "wave" => Ok(VisualizerStyle::Wave),
+            "off" => Ok(VisualizerStyle::Off),
+            _ => Err(format!("unknown visualizer '{}'", value)),
+        }
+    }
+}
+
+/// Which variant of an adaptive HLS or DASH stream plays: switched with the measured
+/// download throughput, the highest or lowest bitrate, or the one closest to a bitrate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HlsBitrate {
+    Auto,
+    Best,
+    Worst,
+    /// Bits per second; the highest variant at or below it, the lowest when none is.
+    Pinned(u64),
+}
+
+impl FromStr for HlsBitrate {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(HlsBitrate::Auto),
+            "best" => Ok(HlsBitrate::Best),
+            "worst" => Ok(HlsBitrate::Worst),
+            _ => value.parse().map(HlsBitrate::Pinned).map_err(|_| {
+                format!(
+                    "invalid bitrate '{}', expected auto, best, worst or bits per second",
+                    value
+                )
+            }),
+        }
+    }
+}
+
+/// Whether video is deinterlaced: always, never, or once the decoder flags frames as
+/// interlaced, and then only those frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Deinterlace {
+    Auto,
+    On,
+    Off,
+}
+
+impl FromStr for Deinterlace {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Deinterlace::Auto),
+            "on" => Ok(Deinterlace::On),
+            "off" => Ok(Deinterlace::Off),
+            _ => Err(format!("unknown deinterlace mode '{}'", value)),
+        }
+    }
+}
+
+/// Loudness normalizer for `--loudnorm`: EBU R128 `loudnorm`, or `dynaudnorm`, which
+/// evens out quiet and loud passages more aggressively.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoudnessNormalizer {
+    Loudnorm,
+    Dynaudnorm,
+}
+
+impl FromStr for LoudnessNormalizer {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "loudnorm" => Ok(LoudnessNormalizer::Loudnorm),
+            "dynaudnorm" => Ok(LoudnessNormalizer::Dynaudnorm),
+            _ => Err(format!("unknown loudness normalizer '{}'", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub input: String,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// Stop playback after presenting this many video frames, for automated runs.
+    pub frames: Option<usize>,
+    /// Stop playback once it reaches this position, in seconds.
+    pub until: Option<f64>,
+    pub hr_seek: bool,
+    pub prebuffer_frames: usize,
+    pub prebuffer_audio_ms: u64,
+    pub cache_mem: Option<usize>,
+    pub low_latency: bool,
+    pub threads: Threads,
+    /// Trades picture quality for decoding speed, for machines that drop frames otherwise.
+    pub fast: bool,
+    pub hwdec: HwDecode,
+    pub benchmark: bool,
+    /// Print nothing to stdout, for scripts.
+    pub quiet: bool,
+    /// Write progress records while playing, to stdout or `progress_fd`.
+    pub progress: Option<ProgressFormat>,
+    pub progress_fd: Option<i32>,
+    pub video_output: VideoOutput,
+    pub video_filter: Option<String>,
+    pub shaders: Vec<PathBuf>,
+    /// Rhai scripts hooked into playback.
+    pub scripts: Vec<PathBuf>,
+    /// Directory native plugins are loaded from, `plugins` in `config_dir()` by default.
+    pub plugins_dir: Option<PathBuf>,
+    /// Second input shown in a corner of the main one.
+    pub pip: Option<String>,
+    /// Second input compared against the main one, split-screen.
+    pub compare: Option<String>,
+    /// Scores the `--compare` inputs against each other instead of playing them.
+    pub metrics: Option<Vec<Metric>>,
+    pub metrics_output: Option<PathBuf>,
+    /// Play the second input's audio instead of the main one's.
+    pub pip_audio: bool,
+    /// More inputs tiled in a grid with the main one, e.g. cameras watched at once.
+    pub mosaic: Vec<String>,
+    /// `None` follows the stream's spherical metadata.
+    pub projection: Option<Projection>,
+    pub aspect: AspectMode,
+    /// Keep the window above other applications'.
+    pub on_top: bool,
+    /// Open the window at this multiple of the video's size in pixels, instead of fitting
+    /// it to the screen.
+    pub window_scale: Option<f64>,
+    pub stop_screensaver: StopScreensaver,
+    pub visualizer: VisualizerStyle,
+    /// LED controller the colors along the picture's edges are sent to.
+    pub ambilight: Option<AmbilightTarget>,
+    pub ambilight_leds: LedLayout,
+    /// Pause on the last frame at the end instead of closing, until told what to do next.
+    pub keep_open: KeepOpen,
+    pub deinterlace: Deinterlace,
+    pub autocrop: bool,
+    pub stereo: Option<StereoMode>,
+    pub interpolate: bool,
+    pub interpolate_fps: Option<u32>,
+    /// Address to lead a watch party from, e.g. `0.0.0.0:7788`.
+    pub sync_master: Option<String>,
+    /// Address of the master to follow.
+    pub sync_join: Option<String>,
+    pub clock: ClockSource,
+    /// Time in seconds since 1970 at which `--clock system` is at position 0.
+    pub clock_epoch: f64,
+    pub terminal_width: u32,
+    pub terminal_columns: Option<u32>,
+    pub audio_output: AudioOutput,
+    pub audio_file: PathBuf,
+    pub audio_device: Option<String>,
+    pub list_audio_devices: bool,
+    /// List the files played before instead of playing.
+    pub list_history: bool,
+    /// Play the most recently played file.
+    pub last: bool,
+    /// Unix socket to take JSON IPC commands on.
+    pub ipc_socket: Option<PathBuf>,
+    /// Address to serve the web remote control on, e.g. `0.0.0.0:8080`.
+    pub remote: Option<String>,
+    /// Where the packets played are remuxed to as well: an `rtmp://` URL, or
+    /// `http://0.0.0.0:PORT` to serve them to a client as MPEG-TS.
+    pub restream: Option<String>,
+    /// Size asked of a capture device, e.g. `1280x720`.
+    pub capture_size: Option<String>,
+    pub capture_fps: Option<f64>,
+    /// Pixel or compressed format asked of a capture device, e.g. `mjpeg` or `yuyv422`.
+    pub capture_format: Option<String>,
+    /// Microphone or line input played instead of the input's audio, e.g. `alsa:default`.
+    pub audio_input: Option<String>,
+    /// Seconds live audio may trail what's captured or received before it's dropped to catch
+    /// up, set in milliseconds with `--monitor-latency`. Low latency playback keeps to a
+    /// tenth of a second otherwise.
+    pub monitor_latency: Option<f64>,
+    /// Variant of an adaptive stream played, set with `--hls-bitrate`.
+    pub hls_bitrate: HlsBitrate,
+    /// Seconds of a live stream kept on disk to pause and seek back in, set in minutes with
+    /// `--timeshift`.
+    pub timeshift: Option<f64>,
+    /// File the packets played are recorded to from the start, remuxed.
+    pub record: Option<PathBuf>,
+    /// Chromecast or DLNA renderer to cast to, by part of its name; the first one found
+    /// otherwise.
+    pub cast_device: Option<String>,
+    /// Linear, 1 being the file's own level.
+    pub volume: f64,
+    pub no_audio: bool,
+    pub audio_passthrough: bool,
+    pub audio_delay: f64,
+    pub replaygain: ReplayGain,
+    pub audio_filter: Option<String>,
+    pub loudnorm: Option<LoudnessNormalizer>,
+    pub equalizer_preset: EqualizerPreset,
+    pub equalizer_gains: Option<[f64; 10]>,
+    pub export: Option<PathBuf>,
+    pub export_format: ExportFormat,
+    pub export_fps: u32,
+    pub export_width: Option<u32>,
+    pub dump_frames: Option<PathBuf>,
+    pub dump_every: usize,
+    pub dump_format: ImageFormat,
+    pub contact_sheet: Option<PathBuf>,
+    pub grid: Grid,
+    pub cell_width: u32,
+    /// Changes to the default key bindings, from `--bind` and the config file's `[keys]`,
+    /// `None` unbinding the key.
+    pub bindings: Vec<(KeyChord, Option<PlayerCommand>)>,
+    /// Config file that settings changed during playback, like the equalizer's, are saved
+    /// to, whether it exists yet or not. `None` leaves them unsaved, as with `--no-config`.
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            input: DEFAULT_INPUT.to_string(),
+            start: None,
+            end: None,
+            hr_seek: false,
+            prebuffer_frames: 8,
+            prebuffer_audio_ms: 200,
+            cache_mem: None,
+            low_latency: false,
+            threads: Threads::Auto,
+            fast: false,
+            hwdec: HwDecode::No,
+            benchmark: false,
+            quiet: false,
+            progress: None,
+            progress_fd: None,
+            video_output: DEFAULT_VIDEO_OUTPUT,
+            video_filter: None,
+            shaders: Vec::new(),
+            scripts: Vec::new(),
+            plugins_dir: None,
+            pip: None,
+            compare: None,
+            metrics: None,
+            metrics_output: None,
+            pip_audio: false,
+            mosaic: Vec::new(),
+            projection: None,
+            aspect: AspectMode::Source,
+            on_top: false,
+            window_scale: None,
+            stop_screensaver: StopScreensaver::Video,
+            visualizer: VisualizerStyle::Bars,
+            ambilight: None,
+            ambilight_leds: LedLayout {
+                horizontal: 32,
+                vertical: 18,
+            },
+            keep_open: KeepOpen::Yes,
+            deinterlace: Deinterlace::Auto,
+            autocrop: false,
+            stereo: None,
+            interpolate: false,
+            interpolate_fps: None,
+            sync_master: None,
+            sync_join: None,
+            clock: ClockSource::Internal,
+            clock_epoch: 0_f64,
+            terminal_width: 480,
+            terminal_columns: None,
+            audio_output: DEFAULT_AUDIO_OUTPUT,
+            audio_file: PathBuf::from("audio.wav"),
+            audio_device: None,
+            list_audio_devices: false,
+            list_history: false,
+            last: false,
+            ipc_socket: None,
+            remote: None,
+            restream: None,
+            record: None,
+            timeshift: None,
+            capture_size: None,
+            capture_fps: None,
+            capture_format: None,
+            audio_input: None,
+            monitor_latency: None,
+            hls_bitrate: HlsBitrate::Auto,
+            cast_device: None,
+            volume: 1_f64,
+            no_audio: false,
+            audio_passthrough: false,
+            audio_delay: 0_f64,
+            replaygain: ReplayGain::Off,
+            audio_filter: None,
+            loudnorm: None,
+            equalizer_preset: EqualizerPreset::Flat,
+            equalizer_gains: None,
+            export: None,
+            export_format: ExportFormat::Gif,
+            export_fps: 15,
+            export_width: Some(480),
+            dump_frames: None,
+            dump_every: 1,
+            dump_format: ImageFormat::Png,
+            contact_sheet: None,
+            grid: Grid {
+                columns: 4,
+                rows: 4,
+            },
+            cell_width: 320,
+            bindings: Vec::new(),
+            config_file: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line on top of the defaults from the config file, `config.toml`
+    /// in `config_dir()` unless `--config` names another one or `--no-config` is given.
+    pub fn from_args() -> Result<Self, String> {
+        let args: Vec<String> = env::args().skip(1).collect();
+
+        let named = config_arg(&args);
+        let path = if args.iter().any(|arg| arg == "--no-config") {
+            None
+        } else {
+            named
+                .clone()
+                .or_else(|| config_dir().map(|dir| dir.join(CONFIG_FILE)))
+        };
+        let defaults = match &path {
+            // only the default one may not be there yet
+            Some(path) if named.is_some() || path.exists() => config::load(path)?,
+            _ => Vec::new(),
+        };
+
+        let mut options = Self::parse(defaults.into_iter().chain(args))?;
+        options.config_file = path;
+        Ok(options)
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.input = arg;
+                continue;
+            }
+
+            // Accept both `--name value` and `--name=value`
+            let (name, inline_value) = match arg.find('=') {
+                Some(idx) => (&arg[..idx], Some(arg[idx + 1..].to_string())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match name {
+                "--hr-seek" => options.hr_seek = true,
+                "--prebuffer-frames" => options.prebuffer_frames = parse_value(name, &value()?)?,
+                "--prebuffer-audio" => options.prebuffer_audio_ms = parse_value(name, &value()?)?,
+                "--cache-mem" => options.cache_mem = Some(parse_value(name, &value()?)?),
+                "--low-latency" => options.low_latency = true,
+                "--threads" => options.threads = value()?.parse()?,
+                "--fast" => options.fast = true,
+                "--hwdec" => options.hwdec = value()?.parse()?,
+                "--benchmark" => options.benchmark = true,
+                "--quiet" => options.quiet = true,
+                "--progress" => options.progress = Some(value()?.parse()?),
+                "--progress-fd" => options.progress_fd = Some(parse_value(name, &value()?)?),
+                "--vo" => options.video_output = value()?.parse()?,
+                "--vf" => options.video_filter = Some(value()?),
+                "--shader" => options.shaders.push(PathBuf::from(value()?)),
+                #[cfg(feature = "scripting")]
+                "--script" => options.scripts.push(PathBuf::from(value()?)),
+                #[cfg(feature = "plugins")]
+                "--plugins-dir" => options.plugins_dir = Some(PathBuf::from(value()?)),
+                "--pip" => options.pip = Some(value()?),
+                "--pip-audio" => options.pip_audio = true,
+                // `cam1 --mosaic cam2 --mosaic cam3`, the main input being the first tile
+                "--mosaic" => options.mosaic.push(value()?),
+                // `--compare a.mp4 b.mp4`, the first one being the main input
+                "--compare" => {
+                    options.input = value()?;
+                    options.compare = Some(
+                        args.next()
+                            .ok_or_else(|| format!("missing second input for {}", name))?,
+                    );
+                }
+                "--metrics" => options.metrics = Some(metrics::parse_metrics(&value()?)?),
+                "--metrics-output" => options.metrics_output = Some(PathBuf::from(value()?)),
+                "--projection" => options.projection = Some(value()?.parse()?),
+                "--aspect" => options.aspect = value()?.parse()?,
+                "--ontop" => options.on_top = true,
+                "--stop-screensaver" => options.stop_screensaver = value()?.parse()?,
+                "--visualizer" => options.visualizer = value()?.parse()?,
+                "--ambilight" => options.ambilight = Some(value()?.parse()?),
+                "--ambilight-leds" => options.ambilight_leds = value()?.parse()?,
+                "--window-scale" => {
+                    let scale: f64 = parse_value(name, &value()?)?;
+                    if scale <= 0_f64 {
+                        return Err(format!("invalid value '{}' for {}", scale, name));
+                    }
+                    options.window_scale = Some(scale);
+                }
+                // `--keep-open` alone, or with `yes`, `always` or `no`; the next argument is
+                // only taken as its value if it's one of those rather than an input
+                "--keep-open" => {
+                    let has_value = inline_value.is_some()
+                        || args
+                            .peek()
+                            .map_or(false, |next| next.parse::<KeepOpen>().is_ok());
+                    options.keep_open = if has_value {
+                        value()?.parse()?
+                    } else {
+                        KeepOpen::Yes
+                    };
+                }
+                "--deinterlace" => options.deinterlace = value()?.parse()?,
+                "--autocrop" => options.autocrop = true,
+                "--stereo" => options.stereo = Some(value()?.parse()?),
+                "--interpolate" => options.interpolate = true,
+                "--interpolate-fps" => {
+                    options.interpolate_fps = Some(parse_value(name, &value()?)?)
+                }
+                "--sync-master" => options.sync_master = Some(value()?),
+                "--sync-join" => options.sync_join = Some(value()?),
+                "--clock" => options.clock = value()?.parse()?,
+                "--clock-epoch" => options.clock_epoch = parse_value(name, &value()?)?,
+                "--term-width" => options.terminal_width = parse_value(name, &value()?)?,
+                "--term-columns" => options.terminal_columns = Some(parse_value(name, &value()?)?),
+                "--ao" => options.audio_output = value()?.parse()?,
+                "--ao-file" => options.audio_file = PathBuf::from(value()?),
+                "--ao-device" => options.audio_device = Some(value()?),
+                #[cfg(feature = "cpal")]
+                "--list-audio-devices" => options.list_audio_devices = true,
+                #[cfg(all(unix, feature = "ipc"))]
+                "--ipc-socket" => options.ipc_socket = Some(PathBuf::from(value()?)),
+                #[cfg(feature = "remote")]
+                "--remote" => options.remote = Some(value()?),
+                "--restream" => options.restream = Some(value()?),
+                "--record" => options.record = Some(PathBuf::from(value()?)),
+                // `--capture v4l2:/dev/video0`, monitored with as little delay as it takes
+                "--capture" => {
+                    let device: CaptureDevice = value()?.parse()?;
+                    options.input = device.to_string();
+                    options.low_latency = true;
+                }
+                "--capture-size" => options.capture_size = Some(value()?),
+                "--capture-fps" => options.capture_fps = Some(parse_value(name, &value()?)?),
+                "--capture-format" => options.capture_format = Some(value()?),
+                "--audio-input" => {
+                    let device: CaptureDevice = value()?.parse()?;
+                    options.audio_input = Some(device.to_string());
+                }
+                "--monitor-latency" => {
+                    options.monitor_latency = Some(parse_value::<f64>(name, &value()?)? / 1000_f64)
+                }
+                "--hls-bitrate" => options.hls_bitrate = value()?.parse()?,
+                "--timeshift" => {
+                    options.timeshift = Some(parse_value::<f64>(name, &value()?)? * 60_f64)
+                }
+                #[cfg(feature = "cast")]
+                "--cast-device" => options.cast_device = Some(value()?),
+                "--volume" => options.volume = parse_value::<f64>(name, &value()?)? / 100_f64,
+                "--history" => options.list_history = true,
+                "--last" => options.last = true,
+                "--no-audio" => options.no_audio = true,
+                "--audio-passthrough" => options.audio_passthrough = true,
+                "--audio-delay" => options.audio_delay = parse_value(name, &value()?)?,
+                "--af" => options.audio_filter = Some(value()?),
+                "--eq" => options.equalizer_preset = value()?.parse()?,
+                "--eq-gains" => options.equalizer_gains = Some(equalizer::parse_gains(&value()?)?),
+                "--replaygain" => options.replaygain = value()?.parse()?,
+                // `--loudnorm` alone, or `--loudnorm=dynaudnorm`
+                "--loudnorm" => {
+                    options.loudnorm = Some(match &inline_value {
+                        Some(normalizer) => normalizer.parse()?,
+                        None => LoudnessNormalizer::Loudnorm,
+                    })
+                }
+                "--start" => options.start = Some(parse_time(&value()?)?),
+                "--end" => options.end = Some(parse_time(&value()?)?),
+                "--frames" => options.frames = Some(parse_value(name, &value()?)?),
+                "--until" => options.until = Some(parse_time(&value()?)?),
+                "--export" => options.export = Some(PathBuf::from(value()?)),
+                "--export-format" => options.export_format = parse_value(name, &value()?)?,
+                "--export-fps" => {
+                    let fps: u32 = parse_value(name, &value()?)?;
+                    if fps == 0 {
+                        return Err(format!("invalid value '{}' for {}", fps, name));
+                    }
+                    options.export_fps = fps;
+                }
+                "--export-width" => {
+                    options.export_width = match value()?.as_str() {
+                        "source" => None,
+                        width => match parse_value(name, width)? {
+                            0 => return Err(format!("invalid value '{}' for {}", width, name)),
+                            width => Some(width),
+                        },
+                    }
+                }
+                "--dump-frames" => options.dump_frames = Some(PathBuf::from(value()?)),
+                "--every" => options.dump_every = parse_value(name, &value()?)?,
+                "--dump-format" => options.dump_format = parse_value(name, &value()?)?,
+                "--contact-sheet" => options.contact_sheet = Some(PathBuf::from(value()?)),
+                "--grid" => options.grid = value()?.parse()?,
+                "--cell-width" => options.cell_width = parse_value(name, &value()?)?,
+                // `--bind ctrl+right="seek-by 60"`
+                "--bind" => options.bindings.push(bindings::parse_binding(&value()?)?),
+                // already read by `from_args`
+                "--config" => {
+                    value()?;
+                }
+                "--no-config" => {}
+                _ => return Err(format!("unknown option {}", name)),
+            }
+        }
+
+        if options.sync_master.is_some() && options.sync_join.is_some() {
+            return Err("--sync-master and --sync-join can't be used together".to_string());
+        }
+        if !options.mosaic.is_empty() && (options.pip.is_some() || options.compare.is_some()) {
+            return Err("--mosaic can't be used with --pip or --compare".to_string());
+        }
+        if options.audio_input.is_some() && options.audio_passthrough {
+            return Err("--audio-input and --audio-passthrough can't be used together".to_string());
+        }
+
+        if options.no_audio {
+            // there won't be any decoded audio to wait for
+            options.prebuffer_audio_ms = 0;
+        }
+
+        if options.low_latency {
+            // start presenting as soon as the first frame is decoded
+            options.prebuffer_frames = 1;
+            options.prebuffer_audio_ms = 0;
+        }
+
+        Ok(options)
+    }
+}
+
+/// The path given with `--config`, either as `--config path` or `--config=path`.
+fn config_arg(args: &[String]) -> Option<PathBuf> {
+    args.iter().enumerate().find_map(|(index, arg)| {
+        if arg == "--config" {
+            args.get(index + 1).map(PathBuf::from)
+        } else {
+            arg.strip_prefix("--config=").map(PathBuf::from)
+        }
+    })
+}
+
+/// Where the player's own files live: `$XDG_CONFIG_HOME/video-player-rs`, falling back to
+/// `~/.config/video-player-rs`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("video-player-rs"))
+}
+
+pub fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+/// Parses a time given either in seconds (`90.5`) or as `[hh:]mm:ss[.fff]`.
+pub fn parse_time(value: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid time '{}'", value);
+
+    value.split(':').try_fold(0_f64, |total, part| {
+        let part = part.parse::<f64>().map_err(|_| invalid())?;
+        if part < 0_f64 {
+            return Err(invalid());
+        }
+        Ok(total * 60_f64 + part)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn takes_keep_open_values_in_either_form() {
+        assert_eq!(
+            parse(&["--keep-open", "in.mp4"]).unwrap().keep_open,
+            KeepOpen::Yes
+        );
+        assert_eq!(
+            parse(&["--keep-open=no", "in.mp4"]).unwrap().keep_open,
+            KeepOpen::No
+        );
+
+        let options = parse(&["--keep-open", "always", "in.mp4"]).unwrap();
+        assert_eq!(options.keep_open, KeepOpen::Always);
+        assert_eq!(options.input, "in.mp4");
+
+        let options = parse(&["--keep-open", "no", "in.mp4"]).unwrap();
+        assert_eq!(options.keep_open, KeepOpen::No);
+        assert_eq!(options.input, "in.mp4");
+
+        // anything else is the input
+        let options = parse(&["--keep-open", "nope.mp4"]).unwrap();
+        assert_eq!(options.keep_open, KeepOpen::Yes);
+        assert_eq!(options.input, "nope.mp4");
+
+        assert!(parse(&["--keep-open=maybe"]).is_err());
+    }
+
+    #[test]
+    fn takes_export_frame_rates_and_widths() {
+        let options = parse(&["--export-fps", "10", "--export-width=320", "in.mp4"]).unwrap();
+
+        assert_eq!(options.export_fps, 10);
+        assert_eq!(options.export_width, Some(320));
+        assert_eq!(options.input, "in.mp4");
+        assert_eq!(
+            parse(&["--export-width", "source"]).unwrap().export_width,
+            None
+        );
+    }
+
+    #[test]
+    fn refuses_export_frame_rates_and_widths_of_zero() {
+        assert!(parse(&["--export-fps", "0"]).is_err());
+        assert!(parse(&["--export-fps=-5"]).is_err());
+        assert!(parse(&["--export-width", "0"]).is_err());
+        assert!(parse(&["--export-width=-320"]).is_err());
+    }
+}
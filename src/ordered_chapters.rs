@@ -0,0 +1,1080 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    iter,
+    path::{Path, PathBuf},
+};
+
+use ffmpeg_next::{codec::packet::Mut, ffi};
+
+use crate::{
+    asset::{PlaybackAsset, PlaybackAssetMetadata},
+    options::Options,
+    timeshift::Next,
+};
+
+// EBML IDs from the Matroska specification, of the elements read
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_A966;
+const SEGMENT_UID: u32 = 0x73A4;
+const TIMESTAMP_SCALE: u32 = 0x002A_D7B1;
+const DURATION: u32 = 0x4489;
+const CHAPTERS: u32 = 0x1043_A770;
+const EDITION_ENTRY: u32 = 0x45B9;
+const EDITION_FLAG_DEFAULT: u32 = 0x45DB;
+const EDITION_FLAG_ORDERED: u32 = 0x45DD;
+const EDITION_DISPLAY: u32 = 0x4520;
+const EDITION_STRING: u32 = 0x4521;
+const CHAPTER_ATOM: u32 = 0xB6;
+const CHAPTER_TIME_START: u32 = 0x91;
+const CHAPTER_TIME_END: u32 = 0x92;
+const CHAPTER_FLAG_HIDDEN: u32 = 0x98;
+const CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+const CHAPTER_SEGMENT_UID: u32 = 0x6E67;
+const CHAPTER_DISPLAY: u32 = 0x80;
+const CHAP_STRING: u32 = 0x85;
+
+/// Elements read whole are the segment info and the chapters, which are small; anything
+/// claiming to be bigger is broken.
+const MAX_ELEMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Files next to an ordered one that may be the segments it links to.
+const SEGMENT_EXTENSIONS: [&str; 3] = ["mkv", "mka", "mks"];
+
+/// Chapters closer than this many seconds follow on from each other, and play on without
+/// seeking.
+const CONTIGUOUS: f64 = 0.001;
+
+#[derive(Clone, Default)]
+struct Segment {
+    uid: Option<Vec<u8>>,
+    /// In seconds.
+    duration: Option<f64>,
+    editions: Vec<Edition>,
+}
+
+#[derive(Clone, Default)]
+struct Edition {
+    title: Option<String>,
+    default: bool,
+    ordered: bool,
+    chapters: Vec<Chapter>,
+}
+
+#[derive(Clone)]
+struct Chapter {
+    /// In seconds, into the segment the chapter plays.
+    start: f64,
+    end: Option<f64>,
+    enabled: bool,
+    /// Played, but not listed.
+    hidden: bool,
+    /// The linked segment played, the file's own when `None`.
+    segment: Option<Vec<u8>>,
+    title: Option<String>,
+}
+
+/// A stretch of a file the ordered edition plays.
+#[derive(Clone)]
+struct Piece {
+    /// Index into `OrderedChapters::files`.
+    file: usize,
+    /// Where it starts and ends in the file, in seconds.
+    start: f64,
+    end: f64,
+    /// Where it starts on the edition's timeline.
+    at: f64,
+    /// Of the chapter played, listed unless it's hidden.
+    title: Option<String>,
+    hidden: bool,
+}
+
+/// A chapter to jump to, by where it starts on the timeline played, in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChapterMark {
+    pub position: f64,
+    pub title: Option<String>,
+}
+
+/// The editions of a Matroska file: alternative chapter lists, e.g. a theatrical cut and an
+/// extended one, each either marking chapters in the file or ordered to play them in an
+/// order of their own, see `OrderedChapters`.
+#[derive(Clone)]
+pub struct Editions {
+    path: PathBuf,
+    segment: Segment,
+    selected: usize,
+    /// The selected edition's, when it's ordered.
+    timeline: Option<OrderedChapters>,
+}
+
+impl Editions {
+    /// The editions of the Matroska file at `path`, with the default one selected. `None`
+    /// for files without chapters.
+    pub fn read(path: &Path) -> Option<Self> {
+        let segment = match read_segment(path, true) {
+            Ok(segment) => segment,
+            Err(e) => {
+                eprintln!("failed to read the chapters of {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if segment.editions.is_empty() {
+            return None;
+        }
+        let selected = segment
+            .editions
+            .iter()
+            .position(|edition| edition.default)
+            .unwrap_or(0);
+        let mut editions = Editions {
+            path: path.to_path_buf(),
+            segment,
+            selected,
+            timeline: None,
+        };
+        editions.select(selected);
+        Some(editions)
+    }
+
+    /// Titles of the editions, or their numbers where they have none.
+    pub fn names(&self) -> Vec<String> {
+        self.segment
+            .editions
+            .iter()
+            .enumerate()
+            .map(|(index, edition)| match &edition.title {
+                Some(title) => title.clone(),
+                None => format!("Edition {}", index + 1),
+            })
+            .collect()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Plays edition `index` from now on, returning false when there's no such edition.
+    pub fn select(&mut self, index: usize) -> bool {
+        let edition = match self.segment.editions.get(index) {
+            Some(edition) => edition,
+            None => return false,
+        };
+        self.selected = index;
+        self.timeline = if edition.ordered {
+            OrderedChapters::new(&self.path, &self.segment, edition)
+        } else {
+            None
+        };
+        true
+    }
+
+    /// Selects edition `index` while `position` seconds into the one played, returning
+    /// where the new one plays the same part of the same file, or its start when it
+    /// doesn't. `None` when there's no such edition.
+    pub fn switch(&mut self, index: usize, position: f64) -> Option<f64> {
+        let (file, time) = match &self.timeline {
+            Some(timeline) => timeline.locate(position),
+            None => (self.path.clone(), position),
+        };
+        if !self.select(index) {
+            return None;
+        }
+        let position = match &self.timeline {
+            Some(timeline) => timeline.find(&file, time),
+            None if file == self.path => Some(time),
+            None => None,
+        };
+        Some(position.unwrap_or(0_f64))
+    }
+
+    /// The selected edition's timeline, `None` when it isn't ordered.
+    pub fn timeline(&self) -> Option<&OrderedChapters> {
+        self.timeline.as_ref()
+    }
+
+    /// Length of the selected edition, which is the file's unless it's ordered.
+    pub fn duration(&self) -> Option<f64> {
+        match &self.timeline {
+            Some(timeline) => Some(timeline.duration()),
+            None => self.segment.duration,
+        }
+    }
+
+    /// The selected edition's chapters, on its timeline when it's ordered.
+    pub fn chapters(&self) -> Vec<ChapterMark> {
+        if let Some(timeline) = &self.timeline {
+            return timeline.chapters();
+        }
+        self.segment.editions[self.selected]
+            .chapters
+            .iter()
+            .filter(|chapter| chapter.enabled && !chapter.hidden)
+            .map(|chapter| ChapterMark {
+                position: chapter.start,
+                title: chapter.title.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The timeline of a Matroska file's ordered edition, as anime releases use: its chapters
+/// played one after the other, each a part of the file itself or of a linked segment, a
+/// file in the same directory with e.g. the opening a series' episodes share.
+#[derive(Clone)]
+pub struct OrderedChapters {
+    /// The file itself first, then the linked segments played.
+    files: Vec<PathBuf>,
+    pieces: Vec<Piece>,
+}
+
+impl OrderedChapters {
+    /// The timeline of `edition`, an ordered one of `segment`, the file at `path`. `None`
+    /// when none of its chapters can be played.
+    fn new(path: &Path, segment: &Segment, edition: &Edition) -> Option<Self> {
+        let mut files = vec![path.to_path_buf()];
+        let mut durations = vec![segment.duration];
+        // the directory is only looked through once a chapter links elsewhere
+        let mut linked: Option<Vec<(PathBuf, Segment)>> = None;
+        let mut pieces = Vec::new();
+        let mut at = 0_f64;
+
+        for chapter in edition.chapters.iter().filter(|chapter| chapter.enabled) {
+            let file = match &chapter.segment {
+                Some(uid) if Some(uid) != segment.uid.as_ref() => {
+                    let linked = linked.get_or_insert_with(|| find_segments(path));
+                    let found = linked
+                        .iter()
+                        .find(|(_, linked)| linked.uid.as_ref() == Some(uid));
+                    let (linked_path, linked) = match found {
+                        Some(found) => found,
+                        None => {
+                            eprintln!(
+                                "linked segment {} of {} not found, skipping its chapter",
+                                hex(uid),
+                                path.display()
+                            );
+                            continue;
+                        }
+                    };
+                    match files.iter().position(|file| file == linked_path) {
+                        Some(file) => file,
+                        None => {
+                            files.push(linked_path.clone());
+                            durations.push(linked.duration);
+                            files.len() - 1
+                        }
+                    }
+                }
+                _ => 0,
+            };
+
+            // ordered chapters should all have an end, the segment's own is the fallback
+            let end = match chapter.end.or(durations[file]) {
+                Some(end) if end > chapter.start => end,
+                _ => continue,
+            };
+            info!(
+                "ordered chapter {} plays {:.2}s to {:.2}s of {}",
+                chapter.title.as_deref().unwrap_or("without a title"),
+                chapter.start,
+                end,
+                files[file].display()
+            );
+            pieces.push(Piece {
+                file,
+                start: chapter.start,
+                end,
+                at,
+                title: chapter.title.clone(),
+                hidden: chapter.hidden,
+            });
+            at += end - chapter.start;
+        }
+
+        if pieces.is_empty() {
+            return None;
+        }
+        Some(OrderedChapters { files, pieces })
+    }
+
+    /// Length of the timeline, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.pieces
+            .last()
+            .map_or(0_f64, |piece| piece.at + piece.end - piece.start)
+    }
+
+    /// The file played `position` seconds into the timeline, and how many seconds into it.
+    fn locate(&self, position: f64) -> (PathBuf, f64) {
+        let piece = self
+            .pieces
+            .iter()
+            .rev()
+            .find(|piece| piece.at <= position)
+            .unwrap_or(&self.pieces[0]);
+        let time = (piece.start + position - piece.at).clamp(piece.start, piece.end);
+        (self.files[piece.file].clone(), time)
+    }
+
+    /// Where on the timeline `time` seconds into `file` are played, if they are.
+    fn find(&self, file: &Path, time: f64) -> Option<f64> {
+        self.pieces
+            .iter()
+            .find(|piece| self.files[piece.file] == file && piece.start <= time && time < piece.end)
+            .map(|piece| piece.at + time - piece.start)
+    }
+
+    /// The chapters played, by where they start on the timeline.
+    pub fn chapters(&self) -> Vec<ChapterMark> {
+        self.pieces
+            .iter()
+            .filter(|piece| !piece.hidden)
+            .map(|piece| ChapterMark {
+                position: piece.at,
+                title: piece.title.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Reads the packets of an `OrderedChapters` timeline, with the timestamps and stream
+/// indices of the file itself, so the linked segments play as a part of it.
+///
+/// The file's own decoders decode the linked segments too, which works as they're encoded
+/// alike to be linked at all. Video packets around a chapter are decoded, for the frames
+/// in it to depend on, but not shown; audio packets are dropped.
+pub struct OrderedSource {
+    chapters: OrderedChapters,
+    options: Options,
+    /// By file, each opened once it's first played.
+    assets: Vec<Option<PlaybackAsset>>,
+    metadata: PlaybackAssetMetadata,
+    piece: usize,
+}
+
+impl OrderedSource {
+    /// Plays `chapters` of `asset`, the file they were read from.
+    pub fn new(asset: PlaybackAsset, chapters: OrderedChapters, options: &Options) -> Self {
+        let metadata = asset.metadata();
+        let mut assets: Vec<_> = chapters.files.iter().map(|_| None).collect();
+        assets[0] = Some(asset);
+        let mut source = OrderedSource {
+            chapters,
+            options: options.clone(),
+            assets,
+            metadata,
+            piece: 0,
+        };
+        source.seek(0_f64);
+        source
+    }
+
+    /// The file itself, to play another of its editions.
+    pub fn into_asset(mut self) -> PlaybackAsset {
+        self.assets
+            .swap_remove(0)
+            .expect("the file itself is always open")
+    }
+
+    /// Carries on from the chapter `position` falls in.
+    pub fn seek(&mut self, position: f64) {
+        let piece = self
+            .chapters
+            .pieces
+            .iter()
+            .rposition(|piece| piece.at <= position)
+            .unwrap_or(0);
+        let offset = position - self.chapters.pieces[piece].at;
+        self.enter(piece, offset.max(0_f64));
+    }
+
+    /// Moves on to `piece`, `offset` seconds into it.
+    fn enter(&mut self, piece: usize, offset: f64) {
+        self.piece = piece;
+        let (file, position) = match self.chapters.pieces.get(piece) {
+            Some(piece) => (piece.file, piece.start + offset),
+            None => return,
+        };
+
+        if self.assets[file].is_none() {
+            let path = &self.chapters.files[file];
+            match PlaybackAsset::open(&path.to_string_lossy(), &self.options) {
+                Ok(asset) => self.assets[file] = Some(asset),
+                Err(e) => {
+                    eprintln!("failed to open linked segment {}: {}", path.display(), e);
+                    return;
+                }
+            }
+        }
+        if let Some(asset) = &mut self.assets[file] {
+            if let Err(e) = asset.seek(position) {
+                eprintln!("failed to seek to {:.2}s: {}", position, e);
+            }
+        }
+    }
+
+    pub fn next(&mut self) -> Next {
+        'packets: loop {
+            let file = match self.chapters.pieces.get(self.piece) {
+                Some(piece) => piece.file,
+                None => return Next::End,
+            };
+            // a linked segment that failed to open is skipped
+            let asset = match &mut self.assets[file] {
+                Some(asset) => asset,
+                None => {
+                    self.enter(self.piece + 1, 0_f64);
+                    continue;
+                }
+            };
+            let metadata = asset.metadata();
+            let (index, mut packet) = match asset.packets().next() {
+                Some((stream, packet)) => (stream.index(), packet),
+                None => {
+                    self.enter(self.piece + 1, 0_f64);
+                    continue;
+                }
+            };
+
+            // streams are matched up with the file's own by kind
+            let (main_index, time_base) = if Some(index) == metadata.video_stream_index() {
+                (
+                    self.metadata.video_stream_index(),
+                    metadata.video_time_base(),
+                )
+            } else if Some(index) == metadata.audio_stream_index() {
+                (
+                    self.metadata.audio_stream_index(),
+                    metadata.audio_time_base(),
+                )
+            } else {
+                continue;
+            };
+            let main_index = match main_index {
+                Some(main_index) => main_index,
+                None => continue,
+            };
+            let video = Some(main_index) == self.metadata.video_stream_index();
+            let main_time_base = if video {
+                self.metadata.video_time_base()
+            } else {
+                self.metadata.audio_time_base()
+            };
+            let seconds = |timestamp: i64| timestamp as f64 * time_base;
+
+            // the video's decode timestamps, or the audio's without video, say when the
+            // chapter is over
+            let clock = video || !self.metadata.has_video();
+            let dts = packet.dts().or_else(|| packet.pts()).map(seconds);
+            loop {
+                let piece = &self.chapters.pieces[self.piece];
+                if !clock || dts.map_or(true, |dts| dts < piece.end) {
+                    break;
+                }
+                let contiguous = self
+                    .chapters
+                    .pieces
+                    .get(self.piece + 1)
+                    .map_or(false, |next| {
+                        next.file == file && (next.start - piece.end).abs() < CONTIGUOUS
+                    });
+                if contiguous {
+                    self.piece += 1;
+                } else {
+                    self.enter(self.piece + 1, 0_f64);
+                    continue 'packets;
+                }
+            }
+
+            let piece = &self.chapters.pieces[self.piece];
+            let outside = packet
+                .pts()
+                .map(seconds)
+                .map_or(false, |pts| pts < piece.start || pts >= piece.end);
+            if outside {
+                if !video {
+                    continue;
+                }
+                unsafe {
+                    (*packet.as_mut_ptr()).flags |= ffi::AV_PKT_FLAG_DISCARD as i32;
+                }
+            }
+
+            let (start, at) = (piece.start, piece.at);
+            let rebase = |timestamp: i64| {
+                ((seconds(timestamp) - start + at) / main_time_base).round() as i64
+            };
+            packet.set_pts(packet.pts().map(rebase));
+            packet.set_dts(packet.dts().map(rebase));
+            packet.set_duration((packet.duration() as f64 * time_base / main_time_base) as i64);
+            packet.set_stream(main_index);
+            return Next::Packet(main_index, packet);
+        }
+    }
+}
+
+/// The segment info of the Matroska file at `path`, and its chapters `with_chapters`.
+///
+/// The segment's children are walked from one to the next, clusters and all, until what's
+/// asked for was read.
+fn read_segment(path: &Path, with_chapters: bool) -> io::Result<Segment> {
+    let mut file = File::open(path)?;
+    let length = file.seek(SeekFrom::End(0))?;
+    let mut segment = Segment::default();
+    let (mut info, mut chapters) = (false, !with_chapters);
+    let mut position = 0;
+
+    while position < length && !(info && chapters) {
+        file.seek(SeekFrom::Start(position))?;
+        let mut header = Vec::with_capacity(12);
+        (&mut file).take(12).read_to_end(&mut header)?;
+        let (id, id_length) = vint(&header, true).ok_or_else(invalid)?;
+        let (size, size_length) = vint(&header[id_length..], false).ok_or_else(invalid)?;
+        let body = position + (id_length + size_length) as u64;
+        let size = Some(size).filter(|size| !is_unknown(*size, size_length));
+
+        match id as u32 {
+            // the EBML header comes first, then the segment with everything else in it
+            SEGMENT => {
+                position = body;
+                continue;
+            }
+            INFO | CHAPTERS => {
+                let size = size
+                    .filter(|size| *size <= MAX_ELEMENT_SIZE)
+                    .ok_or_else(invalid)?;
+                let mut data = vec![0; size as usize];
+                file.seek(SeekFrom::Start(body))?;
+                file.read_exact(&mut data)?;
+                if id as u32 == INFO {
+                    read_info(&data, &mut segment);
+                    info = true;
+                } else {
+                    segment.editions = read_editions(&data);
+                    chapters = true;
+                }
+            }
+            _ => {}
+        }
+
+        // a cluster of unknown size, as live muxers write, can only be read through
+        position = match size {
+            Some(size) => body + size,
+            None => break,
+        };
+    }
+    Ok(segment)
+}
+
+/// The segments in the Matroska files next to `path`, to find the ones it links to.
+fn find_segments(path: &Path) -> Vec<(PathBuf, Segment)> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("failed to look for linked segments: {}", e);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|candidate| {
+            candidate.file_name() != path.file_name()
+                && candidate
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .map_or(false, |extension| {
+                        SEGMENT_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                    })
+        })
+        .filter_map(|candidate| {
+            let segment = read_segment(&candidate, false).ok()?;
+            Some((candidate, segment))
+        })
+        .collect()
+}
+
+fn read_info(data: &[u8], segment: &mut Segment) {
+    // nanoseconds per tick of the duration
+    let mut scale = 1_000_000;
+    let mut duration = None;
+    for (id, body) in children(data) {
+        match id {
+            SEGMENT_UID => segment.uid = Some(body.to_vec()),
+            TIMESTAMP_SCALE => scale = uint(body),
+            DURATION => duration = float(body),
+            _ => {}
+        }
+    }
+    segment.duration = duration.map(|duration| duration * scale as f64 / 1e9);
+}
+
+fn read_editions(data: &[u8]) -> Vec<Edition> {
+    children(data)
+        .filter(|(id, _)| *id == EDITION_ENTRY)
+        .map(|(_, data)| {
+            let mut edition = Edition::default();
+            for (id, body) in children(data) {
+                match id {
+                    EDITION_FLAG_DEFAULT => edition.default = uint(body) != 0,
+                    EDITION_FLAG_ORDERED => edition.ordered = uint(body) != 0,
+                    EDITION_DISPLAY if edition.title.is_none() => {
+                        edition.title = children(body)
+                            .find(|(id, _)| *id == EDITION_STRING)
+                            .map(|(_, title)| string(title));
+                    }
+                    // nested chapters don't take part in ordered playback
+                    CHAPTER_ATOM => edition.chapters.push(read_chapter(body)),
+                    _ => {}
+                }
+            }
+            edition
+        })
+        .collect()
+}
+
+fn read_chapter(data: &[u8]) -> Chapter {
+    let mut chapter = Chapter {
+        start: 0_f64,
+        end: None,
+        enabled: true,
+        hidden: false,
+        segment: None,
+        title: None,
+    };
+    // times are in nanoseconds, whatever the timestamp scale
+    for (id, body) in children(data) {
+        match id {
+            CHAPTER_TIME_START => chapter.start = uint(body) as f64 / 1e9,
+            CHAPTER_TIME_END => chapter.end = Some(uint(body) as f64 / 1e9),
+            CHAPTER_FLAG_ENABLED => chapter.enabled = uint(body) != 0,
+            CHAPTER_FLAG_HIDDEN => chapter.hidden = uint(body) != 0,
+            CHAPTER_SEGMENT_UID => chapter.segment = Some(body.to_vec()),
+            CHAPTER_DISPLAY if chapter.title.is_none() => {
+                chapter.title = children(body)
+                    .find(|(id, _)| *id == CHAP_STRING)
+                    .map(|(_, title)| string(title));
+            }
+            _ => {}
+        }
+    }
+    chapter
+}
+
+/// The elements in a master element's `data`, by ID.
+fn children(mut data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    iter::from_fn(move || {
+        let (id, id_length) = vint(data, true)?;
+        let (size, size_length) = vint(&data[id_length..], false)?;
+        let start = id_length + size_length;
+        // an unknown or overlong size takes the rest
+        let end = start.saturating_add(size as usize).min(data.len());
+        let body = &data[start..end];
+        data = &data[end..];
+        Some((id as u32, body))
+    })
+}
+
+/// A variable length integer at the start of `data`, and its length. IDs keep the length
+/// marker bit, sizes don't.
+fn vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let length = first.leading_zeros() as usize + 1;
+    if length > 8 || data.len() < length {
+        return None;
+    }
+    let first = if keep_marker {
+        u64::from(first)
+    } else {
+        u64::from(first) & (0xFF >> length)
+    };
+    let value = data[1..length]
+        .iter()
+        .fold(first, |value, byte| value << 8 | u64::from(*byte));
+    Some((value, length))
+}
+
+/// Whether a size is all ones, for a master element whose end isn't known.
+fn is_unknown(size: u64, length: usize) -> bool {
+    size == (1 << (7 * length)) - 1
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0, |value, byte| value << 8 | u64::from(*byte))
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64),
+        8 => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(data);
+            Some(f64::from_be_bytes(bytes))
+        }
+        _ => None,
+    }
+}
+
+fn string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "broken Matroska element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EBML: u32 = 0x1A45_DFA3;
+
+    fn size(size: u64) -> Vec<u8> {
+        if size < 0x7F {
+            vec![0x80 | size as u8]
+        } else {
+            let mut bytes = vec![0x01];
+            bytes.extend_from_slice(&size.to_be_bytes()[1..]);
+            bytes
+        }
+    }
+
+    fn id(id: u32) -> Vec<u8> {
+        id.to_be_bytes()
+            .iter()
+            .copied()
+            .skip_while(|byte| *byte == 0)
+            .collect()
+    }
+
+    fn element(element_id: u32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = id(element_id);
+        bytes.extend(size(body.len() as u64));
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn uint_element(element_id: u32, value: u64) -> Vec<u8> {
+        element(element_id, &value.to_be_bytes())
+    }
+
+    fn chapter(start: f64, end: Option<f64>, extra: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = uint_element(CHAPTER_TIME_START, (start * 1e9) as u64);
+        if let Some(end) = end {
+            body.extend(uint_element(CHAPTER_TIME_END, (end * 1e9) as u64));
+        }
+        body.extend(extra.concat());
+        element(CHAPTER_ATOM, &body)
+    }
+
+    fn edition(flags: &[Vec<u8>], chapters: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = flags.concat();
+        body.extend(chapters.concat());
+        element(EDITION_ENTRY, &body)
+    }
+
+    /// A Matroska file of an EBML header and a segment of unknown size with `children`.
+    fn matroska(children: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = element(EBML, &[]);
+        bytes.extend(id(SEGMENT));
+        bytes.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        bytes.extend(children.concat());
+        bytes
+    }
+
+    fn info(uid: &[u8], duration: f64) -> Vec<u8> {
+        let mut body = element(SEGMENT_UID, uid);
+        body.extend(uint_element(TIMESTAMP_SCALE, 1_000_000));
+        body.extend(element(DURATION, &(duration * 1000_f64).to_be_bytes()));
+        element(INFO, &body)
+    }
+
+    /// Writes `bytes` to a file of its own directory, so no other test's files look like
+    /// linked segments.
+    fn write(test: &str, bytes: &[u8]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "video-player-ordered-chapters-{}-{}",
+            std::process::id(),
+            test
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("file.mkv");
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn vint_lengths_and_markers() {
+        assert_eq!(vint(&[0x81], false), Some((1, 1)));
+        assert_eq!(vint(&[0x81], true), Some((0x81, 1)));
+        assert_eq!(vint(&[0x40, 0x02], false), Some((2, 2)));
+        assert_eq!(vint(&[0x40, 0x02], true), Some((0x4002, 2)));
+        assert_eq!(
+            vint(&[0x1A, 0x45, 0xDF, 0xA3], true),
+            Some((0x1A45_DFA3, 4))
+        );
+        assert_eq!(
+            vint(&[0x01, 0, 0, 0, 0, 0, 0x01, 0x00], false),
+            Some((0x100, 8))
+        );
+        // only the bytes of its own length are read
+        assert_eq!(vint(&[0x82, 0xFF], false), Some((2, 1)));
+    }
+
+    #[test]
+    fn vint_rejects_truncated_and_overlong() {
+        assert_eq!(vint(&[], false), None);
+        assert_eq!(vint(&[0x40], false), None);
+        assert_eq!(vint(&[0x01, 0, 0], false), None);
+        // no marker bit in the first byte would make it 9 bytes or longer
+        assert_eq!(vint(&[0x00, 0, 0, 0, 0, 0, 0, 0, 0x01], false), None);
+    }
+
+    #[test]
+    fn unknown_sizes() {
+        assert!(is_unknown(0x7F, 1));
+        assert!(is_unknown(0x3FFF, 2));
+        assert!(is_unknown((1 << 56) - 1, 8));
+        assert!(!is_unknown(0x7E, 1));
+        assert!(!is_unknown(0x7F, 2));
+
+        // an element of unknown size takes the rest of its parent
+        let data = [0xB6, 0xFF, 0x91, 0x81, 0x05];
+        let elements: Vec<_> = children(&data).collect();
+        assert_eq!(elements, vec![(CHAPTER_ATOM, &data[2..])]);
+    }
+
+    #[test]
+    fn children_are_cut_short() {
+        let mut data = uint_element(CHAPTER_TIME_START, 1);
+        // claims 16 bytes, has 2
+        data.extend_from_slice(&[0x92, 0x90, 0x01, 0x02]);
+        let elements: Vec<_> = children(&data).collect();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].0, CHAPTER_TIME_START);
+        assert_eq!(uint(elements[0].1), 1);
+        assert_eq!(elements[1], (CHAPTER_TIME_END, &[0x01, 0x02][..]));
+
+        // a header cut off ends the list
+        assert_eq!(children(&[0x45, 0xB9]).count(), 0);
+        assert_eq!(children(&[0x45]).count(), 0);
+    }
+
+    #[test]
+    fn reads_segment_info() {
+        let path = write("info", &matroska(&[info(&[1, 2, 3, 4], 90_f64)]));
+        let segment = read_segment(&path, false).unwrap();
+        assert_eq!(segment.uid, Some(vec![1, 2, 3, 4]));
+        assert_eq!(segment.duration, Some(90_f64));
+        assert!(segment.editions.is_empty());
+    }
+
+    #[test]
+    fn rejects_broken_elements() {
+        // the info claims more bytes than the file has
+        let mut truncated = matroska(&[]);
+        truncated.extend(id(INFO));
+        truncated.extend(size(64));
+        truncated.extend_from_slice(&[0; 8]);
+        let path = write("truncated", &truncated);
+        assert!(read_segment(&path, false).is_err());
+
+        let mut overlong = matroska(&[]);
+        overlong.extend(id(INFO));
+        overlong.extend(size(MAX_ELEMENT_SIZE + 1));
+        let path = write("overlong", &overlong);
+        assert!(read_segment(&path, false).is_err());
+
+        // the info has to say where it ends to be read whole
+        let mut unknown = matroska(&[]);
+        unknown.extend(id(INFO));
+        unknown.push(0xFF);
+        let path = write("unknown", &unknown);
+        assert!(read_segment(&path, false).is_err());
+    }
+
+    #[test]
+    fn default_and_ordered_editions() {
+        let title = element(EDITION_DISPLAY, &element(EDITION_STRING, b"Extended"));
+        let chapters = element(
+            CHAPTERS,
+            &[
+                edition(&[], &[chapter(0_f64, Some(5_f64), &[])]),
+                edition(
+                    &[
+                        uint_element(EDITION_FLAG_DEFAULT, 1),
+                        uint_element(EDITION_FLAG_ORDERED, 1),
+                        title,
+                    ],
+                    &[
+                        chapter(10_f64, Some(20_f64), &[]),
+                        chapter(0_f64, Some(10_f64), &[]),
+                    ],
+                ),
+            ]
+            .concat(),
+        );
+        let path = write("editions", &matroska(&[info(&[1], 30_f64), chapters]));
+
+        let mut editions = Editions::read(&path).unwrap();
+        assert_eq!(editions.names(), vec!["Edition 1", "Extended"]);
+        assert_eq!(editions.selected(), 1);
+        let timeline = editions.timeline().unwrap();
+        assert_eq!(timeline.duration(), 20_f64);
+        assert_eq!(editions.duration(), Some(20_f64));
+        let positions: Vec<_> = timeline
+            .chapters()
+            .iter()
+            .map(|chapter| chapter.position)
+            .collect();
+        assert_eq!(positions, vec![0_f64, 10_f64]);
+
+        assert!(editions.select(0));
+        assert!(editions.timeline().is_none());
+        assert_eq!(
+            editions.chapters(),
+            vec![ChapterMark {
+                position: 0_f64,
+                title: None,
+            }]
+        );
+        assert!(!editions.select(2));
+        assert_eq!(editions.selected(), 0);
+    }
+
+    #[test]
+    fn switching_editions_keeps_the_place() {
+        let chapters = element(
+            CHAPTERS,
+            &[
+                edition(&[], &[chapter(0_f64, Some(30_f64), &[])]),
+                edition(
+                    &[uint_element(EDITION_FLAG_ORDERED, 1)],
+                    &[
+                        chapter(10_f64, Some(20_f64), &[]),
+                        chapter(0_f64, Some(10_f64), &[]),
+                    ],
+                ),
+            ]
+            .concat(),
+        );
+        let path = write("switch", &matroska(&[info(&[1], 30_f64), chapters]));
+        let mut editions = Editions::read(&path).unwrap();
+        assert_eq!(editions.duration(), Some(30_f64));
+
+        // 3s into the file is 3s into its second chapter on the ordered timeline
+        assert_eq!(editions.switch(1, 3_f64), Some(13_f64));
+        assert_eq!(editions.selected(), 1);
+        assert_eq!(editions.duration(), Some(20_f64));
+        assert_eq!(editions.switch(0, 5_f64), Some(15_f64));
+
+        // the ordered edition doesn't play the end of the file
+        assert_eq!(editions.switch(1, 25_f64), Some(0_f64));
+        assert_eq!(editions.switch(2, 5_f64), None);
+        assert_eq!(editions.selected(), 1);
+    }
+
+    #[test]
+    fn first_edition_without_a_default() {
+        let chapters = element(
+            CHAPTERS,
+            &[
+                edition(&[], &[chapter(0_f64, None, &[])]),
+                edition(&[uint_element(EDITION_FLAG_DEFAULT, 0)], &[]),
+            ]
+            .concat(),
+        );
+        let path = write("no-default", &matroska(&[info(&[1], 30_f64), chapters]));
+        assert_eq!(Editions::read(&path).unwrap().selected(), 0);
+
+        let path = write("no-chapters", &matroska(&[info(&[1], 30_f64)]));
+        assert!(Editions::read(&path).is_none());
+    }
+
+    #[test]
+    fn ordered_timeline_skips_what_cant_play() {
+        let missing = element(CHAPTER_SEGMENT_UID, &[9, 9, 9, 9]);
+        let own = element(CHAPTER_SEGMENT_UID, &[1, 2, 3, 4]);
+        let hidden = uint_element(CHAPTER_FLAG_HIDDEN, 1);
+        let disabled = uint_element(CHAPTER_FLAG_ENABLED, 0);
+        let named = element(CHAPTER_DISPLAY, &element(CHAP_STRING, b"Part B"));
+        let chapters = element(
+            CHAPTERS,
+            &edition(
+                &[uint_element(EDITION_FLAG_ORDERED, 1)],
+                &[
+                    chapter(0_f64, Some(2_f64), &[]),
+                    // a linked segment that isn't next to the file
+                    chapter(0_f64, Some(30_f64), &[missing]),
+                    chapter(5_f64, Some(5_f64), &[]),
+                    chapter(8_f64, Some(6_f64), &[]),
+                    chapter(2_f64, Some(3_f64), &[disabled]),
+                    // the file's own segment, linked to by its UID
+                    chapter(3_f64, Some(4_f64), &[own, hidden]),
+                    // without an end, up to the end of the segment
+                    chapter(40_f64, None, &[named]),
+                ],
+            ),
+        );
+        let path = write(
+            "ordered",
+            &matroska(&[info(&[1, 2, 3, 4], 50_f64), chapters]),
+        );
+
+        let editions = Editions::read(&path).unwrap();
+        let timeline = editions.timeline().unwrap();
+        assert_eq!(timeline.files, vec![path]);
+        let pieces: Vec<_> = timeline
+            .pieces
+            .iter()
+            .map(|piece| (piece.file, piece.start, piece.end, piece.at))
+            .collect();
+        assert_eq!(
+            pieces,
+            vec![
+                (0, 0_f64, 2_f64, 0_f64),
+                (0, 3_f64, 4_f64, 2_f64),
+                (0, 40_f64, 50_f64, 3_f64),
+            ]
+        );
+        assert_eq!(timeline.duration(), 13_f64);
+        assert_eq!(
+            timeline.chapters(),
+            vec![
+                ChapterMark {
+                    position: 0_f64,
+                    title: None,
+                },
+                ChapterMark {
+                    position: 3_f64,
+                    title: Some("Part B".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ordered_timeline_of_nothing_playable() {
+        let chapters = element(
+            CHAPTERS,
+            &edition(
+                &[uint_element(EDITION_FLAG_ORDERED, 1)],
+                &[chapter(5_f64, Some(1_f64), &[])],
+            ),
+        );
+        let path = write("unplayable", &matroska(&[info(&[1], 30_f64), chapters]));
+        assert!(Editions::read(&path).unwrap().timeline().is_none());
+    }
+}
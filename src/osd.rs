@@ -0,0 +1,320 @@
+use std::{
+    f64::consts::PI,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "sdl")]
+use sdl2::{
+    rect::Rect,
+    render::{BlendMode, Canvas},
+    video::Window,
+};
+
+use crate::{
+    chapter_menu::ChapterMenu, controls::ControlBar, font, level_meter::LevelMeter,
+    spectrogram::Spectrogram,
+};
+
+const SCALE: u32 = 3;
+const MARGIN: i32 = 16;
+const PADDING: u32 = 6;
+
+/// Non-premultiplied RGBA colour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color { r, g, b, a }
+    }
+}
+
+/// Something the OSD can be drawn onto: an SDL canvas, or an RGBA overlay for outputs that
+/// composite it themselves.
+pub trait OsdTarget {
+    fn size(&self) -> (u32, u32);
+
+    /// Blends `color` over the given rectangle.
+    fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color);
+}
+
+#[cfg(feature = "sdl")]
+impl OsdTarget for Canvas<Window> {
+    fn size(&self) -> (u32, u32) {
+        self.output_size().unwrap_or((0, 0))
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        self.set_blend_mode(BlendMode::Blend);
+        self.set_draw_color(sdl2::pixels::Color::RGBA(
+            color.r, color.g, color.b, color.a,
+        ));
+        Canvas::fill_rect(self, Rect::new(x, y, width, height)).ok();
+    }
+}
+
+/// Transparent RGBA pixels (non-premultiplied, tightly packed) the OSD can be drawn into.
+pub struct OsdImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl OsdImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        OsdImage {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|value| *value = 0);
+    }
+}
+
+impl OsdTarget for OsdImage {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        let left = x.max(0) as u32;
+        let top = y.max(0) as u32;
+        let right = ((x + width as i32).max(0) as u32).min(self.width);
+        let bottom = ((y + height as i32).max(0) as u32).min(self.height);
+        let alpha = color.a as f32 / 255_f32;
+
+        for row in top..bottom {
+            for column in left..right {
+                let offset = ((row * self.width + column) * 4) as usize;
+                let pixel = &mut self.pixels[offset..offset + 4];
+
+                // source-over onto a possibly transparent destination
+                let below = pixel[3] as f32 / 255_f32;
+                let out = alpha + below * (1_f32 - alpha);
+                if out > 0_f32 {
+                    for (channel, source) in [color.r, color.g, color.b].iter().enumerate() {
+                        pixel[channel] = ((*source as f32 * alpha
+                            + pixel[channel] as f32 * below * (1_f32 - alpha))
+                            / out) as u8;
+                    }
+                }
+                pixel[3] = (out * 255_f32) as u8;
+            }
+        }
+    }
+}
+
+/// On-screen display drawn over the video: a transient message line plus a persistent
+/// status line (e.g. buffering progress) with an optional spinner, and lines kept in the
+/// top right corner (e.g. from plugins) above an indicator (e.g. the adaptive stream variant
+/// playing), over a control bar shown while the mouse is used, a spectrogram and level meter
+/// of the audio when toggled on, and a menu of the chapters when opened.
+pub struct Osd {
+    message: Option<(String, Instant)>,
+    status: Option<String>,
+    spinner: bool,
+    overlay: Vec<String>,
+    indicator: Option<String>,
+    controls: ControlBar,
+    spectrogram: Spectrogram,
+    level_meter: LevelMeter,
+    chapter_menu: ChapterMenu,
+    created: Instant,
+}
+
+impl Default for Osd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Osd {
+            message: None,
+            status: None,
+            spinner: false,
+            overlay: Vec::new(),
+            indicator: None,
+            controls: ControlBar::new(),
+            spectrogram: Spectrogram::new(),
+            level_meter: LevelMeter::new(),
+            chapter_menu: ChapterMenu::new(),
+            created: Instant::now(),
+        }
+    }
+
+    pub fn show_message(&mut self, text: &str, duration: Duration) {
+        self.message = Some((text.to_string(), Instant::now() + duration));
+    }
+
+    pub fn set_status(&mut self, status: Option<String>, spinner: bool) {
+        self.status = status;
+        self.spinner = spinner;
+    }
+
+    /// Replaces the top right lines, returning true when they changed.
+    pub fn set_overlay(&mut self, lines: Vec<String>) -> bool {
+        if lines == self.overlay {
+            return false;
+        }
+        self.overlay = lines;
+        true
+    }
+
+    /// Replaces the line under the top right ones, returning true when it changed.
+    pub fn set_indicator(&mut self, indicator: Option<String>) -> bool {
+        if indicator == self.indicator {
+            return false;
+        }
+        self.indicator = indicator;
+        true
+    }
+
+    pub(crate) fn controls_mut(&mut self) -> &mut ControlBar {
+        &mut self.controls
+    }
+
+    pub(crate) fn spectrogram_mut(&mut self) -> &mut Spectrogram {
+        &mut self.spectrogram
+    }
+
+    pub(crate) fn level_meter_mut(&mut self) -> &mut LevelMeter {
+        &mut self.level_meter
+    }
+
+    pub(crate) fn chapter_menu_mut(&mut self) -> &mut ChapterMenu {
+        &mut self.chapter_menu
+    }
+
+    /// Whether anything is on screen (animated elements need a redraw every tick).
+    pub fn is_visible(&self) -> bool {
+        self.status.is_some()
+            || self.message.is_some()
+            || self.controls.is_visible()
+            || self.spectrogram.is_visible()
+            || self.level_meter.is_visible()
+            || self.chapter_menu.is_visible()
+    }
+
+    /// Drops expired messages and hides idle controls, returning true when the display
+    /// changed.
+    pub fn update(&mut self) -> bool {
+        let controls_changed = self.controls.update();
+        match &self.message {
+            Some((_, expires)) if Instant::now() >= *expires => {
+                self.message = None;
+                true
+            }
+            _ => controls_changed,
+        }
+    }
+
+    pub fn draw<T: OsdTarget>(&self, target: &mut T) {
+        if let Some((message, _)) = &self.message {
+            draw_text_box(target, MARGIN, MARGIN, message);
+        }
+
+        // the status line moves up out of the control bar's way
+        let bottom = if self.controls.is_visible() {
+            self.controls.height() as i32
+        } else {
+            0
+        };
+
+        if let Some(status) = &self.status {
+            let (_, height) = target.size();
+            let y = height as i32
+                - bottom
+                - MARGIN
+                - font::text_height(SCALE) as i32
+                - 2 * PADDING as i32;
+            let mut x = MARGIN;
+
+            if self.spinner {
+                self.draw_spinner(target, x, y);
+                x += (font::text_height(SCALE) + 3 * PADDING) as i32;
+            }
+            draw_text_box(target, x, y, status);
+        }
+
+        let (width, _) = target.size();
+        let line_height = (font::text_height(SCALE) + 3 * PADDING) as i32;
+        for (index, line) in self.overlay.iter().chain(&self.indicator).enumerate() {
+            let x = width as i32 - MARGIN - (font::text_width(line, SCALE) + 2 * PADDING) as i32;
+            draw_text_box(target, x, MARGIN + index as i32 * line_height, line);
+        }
+
+        self.spectrogram.draw(target, bottom);
+        self.level_meter.draw(target, bottom);
+        self.chapter_menu.draw(target);
+
+        if self.controls.is_visible() {
+            self.controls.draw(target);
+        }
+    }
+
+    /// Eight dots in a circle with a highlight that goes round a few times per second.
+    fn draw_spinner<T: OsdTarget>(&self, target: &mut T, x: i32, y: i32) {
+        let size = font::text_height(SCALE) + 2 * PADDING;
+        let radius = size as f64 / 2_f64 - SCALE as f64;
+        let center = (
+            x as f64 + size as f64 / 2_f64,
+            y as f64 + size as f64 / 2_f64,
+        );
+        let active = (self.created.elapsed().as_millis() / 100 % 8) as usize;
+
+        for dot in 0..8 {
+            let angle = dot as f64 * PI / 4_f64;
+            let alpha = if dot == active { 255 } else { 96 };
+            target.fill_rect(
+                (center.0 + radius * angle.cos()) as i32 - SCALE as i32 / 2,
+                (center.1 + radius * angle.sin()) as i32 - SCALE as i32 / 2,
+                SCALE,
+                SCALE,
+                Color::rgba(255, 255, 255, alpha),
+            );
+        }
+    }
+}
+
+fn draw_text_box<T: OsdTarget>(target: &mut T, x: i32, y: i32, text: &str) {
+    target.fill_rect(
+        x,
+        y,
+        font::text_width(text, SCALE) + 2 * PADDING,
+        font::text_height(SCALE) + 2 * PADDING,
+        Color::rgba(0, 0, 0, 160),
+    );
+
+    let origin = (x + PADDING as i32, y + PADDING as i32);
+    font::render(text, 0, 0, SCALE, |px, py, size| {
+        target.fill_rect(
+            origin.0 + px as i32,
+            origin.1 + py as i32,
+            size,
+            size,
+            Color::rgba(255, 255, 255, 255),
+        );
+    });
+}
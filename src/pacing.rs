@@ -0,0 +1,52 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Sleeps are cut short by this much and the rest is waited out yielding, as they can
+/// overshoot by about a millisecond.
+const SPIN: Duration = Duration::from_millis(1);
+
+/// Decides when the main loop wakes up next, instead of polling every millisecond.
+///
+/// Frames are shown on the first predicted vblank at or after they're due, so they go out
+/// at a steady cadence of whole refresh intervals. Presenting doesn't wait on vsync, so the
+/// phase of the display's vblanks isn't known; the grid starts when the scheduler is made.
+pub struct FrameScheduler {
+    refresh_interval: Option<Duration>,
+    epoch: Instant,
+}
+
+impl FrameScheduler {
+    /// `refresh_rate` in Hz, `None` presenting frames as soon as they're due.
+    pub fn new(refresh_rate: Option<u32>) -> Self {
+        FrameScheduler {
+            refresh_interval: refresh_rate
+                .filter(|rate| *rate > 0)
+                .map(|rate| Duration::from_secs_f64(1_f64 / rate as f64)),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// The first vblank at or after `instant`.
+    pub fn next_vblank(&self, instant: Instant) -> Instant {
+        let interval = match self.refresh_interval {
+            Some(interval) => interval.as_nanos(),
+            None => return instant,
+        };
+        let elapsed = instant.saturating_duration_since(self.epoch).as_nanos();
+        let vblanks = (elapsed + interval - 1) / interval;
+        self.epoch + Duration::from_nanos((vblanks * interval) as u64)
+    }
+
+    /// Blocks until `deadline`, returning right away if it has passed.
+    pub fn sleep_until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if deadline > now + SPIN {
+            thread::sleep(deadline - now - SPIN);
+        }
+        while Instant::now() < deadline {
+            thread::yield_now();
+        }
+    }
+}
@@ -0,0 +1,72 @@
+/// Settings of the picture equalizer, each from -100 to 100 with 0 leaving the picture as is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PictureProperty {
+    Contrast,
+    Brightness,
+    Saturation,
+    Gamma,
+}
+
+impl PictureProperty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PictureProperty::Contrast => "contrast",
+            PictureProperty::Brightness => "brightness",
+            PictureProperty::Saturation => "saturation",
+            PictureProperty::Gamma => "gamma",
+        }
+    }
+}
+
+/// How far the picture keys move a property.
+pub const PICTURE_STEP: i32 = 5;
+
+/// Brightness, contrast, saturation and gamma, applied with ffmpeg's `eq` filter.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PictureEqualizer {
+    contrast: i32,
+    brightness: i32,
+    saturation: i32,
+    gamma: i32,
+}
+
+impl PictureEqualizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, property: PictureProperty) -> i32 {
+        match property {
+            PictureProperty::Contrast => self.contrast,
+            PictureProperty::Brightness => self.brightness,
+            PictureProperty::Saturation => self.saturation,
+            PictureProperty::Gamma => self.gamma,
+        }
+    }
+
+    pub fn set(&mut self, property: PictureProperty, value: i32) {
+        let value = value.clamp(-100, 100);
+        match property {
+            PictureProperty::Contrast => self.contrast = value,
+            PictureProperty::Brightness => self.brightness = value,
+            PictureProperty::Saturation => self.saturation = value,
+            PictureProperty::Gamma => self.gamma = value,
+        }
+    }
+
+    /// Filter description for the current settings, `None` when they're all neutral.
+    pub fn filter_description(&self) -> Option<String> {
+        if *self == Self::default() {
+            return None;
+        }
+
+        // contrast and saturation from 0 to 2 times, gamma from 0.1 to 10 times
+        Some(format!(
+            "eq=contrast={:.2}:brightness={:.2}:saturation={:.2}:gamma={:.2}",
+            1_f64 + self.contrast as f64 / 100_f64,
+            self.brightness as f64 / 100_f64,
+            1_f64 + self.saturation as f64 / 100_f64,
+            10_f64.powf(self.gamma as f64 / 100_f64),
+        ))
+    }
+}
@@ -0,0 +1,672 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cast")]
+use crate::cast::CastCommand;
+use crate::{
+    audio_sink::AudioSink,
+    clock::PlaybackClock,
+    osd::Osd,
+    player::{PlaybackState, PlayerCommand},
+};
+
+/// Trick play speeds, as multiples of normal speed.
+const SCAN_SPEEDS: [f64; 3] = [2_f64, 4_f64, 8_f64];
+
+/// How often scanning moves on to the next keyframe.
+const SCAN_STEP: Duration = Duration::from_millis(250);
+
+/// What play and pause asked for last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Intent {
+    Play,
+    Pause,
+}
+
+/// What the transport is doing, which opening, seeking and buffering carry on with once
+/// they're done.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transport {
+    Normal(Intent),
+    /// Trick play: the clock stays paused and each step seeks `speed` times as far as playing
+    /// would have gone from `position`, where the last step went, then playback carries on
+    /// from wherever it got to as `resume` says.
+    Scan {
+        speed: f64,
+        position: f64,
+        last_step: Instant,
+        resume: Intent,
+    },
+    /// Playing on a cast device, paused there or not as `device` says, with playback here
+    /// paused while it lasts and then carrying on as `resume` says.
+    #[cfg(feature = "cast")]
+    Cast {
+        device: Intent,
+        resume: Intent,
+    },
+}
+
+/// The next speed after `speed` scanning in `direction`, 1 or -1: the next of `SCAN_SPEEDS`,
+/// or 0 after the fastest.
+fn next_scan_speed(speed: f64, direction: f64) -> f64 {
+    let current = if speed * direction > 0_f64 {
+        speed.abs()
+    } else {
+        0_f64
+    };
+    SCAN_SPEEDS
+        .iter()
+        .find(|scan| **scan > current)
+        .map_or(0_f64, |scan| scan * direction)
+}
+
+/// Playback of one input: its `PlaybackState` and what the transport asked for, with the
+/// clock and the audio output held and released to match as it moves between them.
+pub struct Playback {
+    state: PlaybackState,
+    transport: Transport,
+    clock: PlaybackClock,
+    audio_sink: Box<dyn AudioSink>,
+}
+
+impl Playback {
+    /// Starts out `Opening`, with the clock and `audio_sink` held until enough is decoded.
+    pub fn new(audio_sink: Box<dyn AudioSink>) -> Self {
+        let mut playback = Playback {
+            state: PlaybackState::Opening,
+            transport: Transport::Normal(Intent::Play),
+            clock: PlaybackClock::new(),
+            audio_sink,
+        };
+        playback.hold();
+        playback
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    /// Whether playback here is paused, which it also is while scanning or casting.
+    pub fn is_paused(&self) -> bool {
+        self.transport != Transport::Normal(Intent::Play)
+    }
+
+    /// The speed scanning at, or `None` when not scanning.
+    pub fn scan_speed(&self) -> Option<f64> {
+        match self.transport {
+            Transport::Scan { speed, .. } => Some(speed),
+            _ => None,
+        }
+    }
+
+    /// How fast playback moves through the input, as a multiple of normal speed.
+    pub fn speed(&self) -> f64 {
+        match (self.transport, self.state) {
+            (Transport::Scan { speed, .. }, _) => speed,
+            (_, PlaybackState::Playing) => 1_f64,
+            _ => 0_f64,
+        }
+    }
+
+    /// Current media position in seconds.
+    pub fn position(&self) -> f64 {
+        self.clock.position()
+    }
+
+    pub fn clock(&self) -> &PlaybackClock {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut PlaybackClock {
+        &mut self.clock
+    }
+
+    pub fn audio_sink(&mut self) -> &mut dyn AudioSink {
+        self.audio_sink.as_mut()
+    }
+
+    /// Gives the audio output back once playback is over.
+    pub fn into_audio_sink(self) -> Box<dyn AudioSink> {
+        self.audio_sink
+    }
+
+    /// Carries out transport commands, pushing the seeks they lead to and every other
+    /// command onto `commands`, for the player to carry out.
+    pub fn handle(
+        &mut self,
+        command: PlayerCommand,
+        osd: &mut Osd,
+        commands: &mut Vec<PlayerCommand>,
+    ) {
+        // resolve relative commands against the current state first
+        let command = match command {
+            PlayerCommand::ScanForward => {
+                PlayerCommand::Scan(next_scan_speed(self.scan_speed().unwrap_or(0_f64), 1_f64))
+            }
+            PlayerCommand::ScanBackward => {
+                PlayerCommand::Scan(next_scan_speed(self.scan_speed().unwrap_or(0_f64), -1_f64))
+            }
+            PlayerCommand::TogglePause if self.is_paused() => PlayerCommand::Play,
+            PlayerCommand::TogglePause => PlayerCommand::Pause,
+            command => command,
+        };
+
+        match (command, self.transport) {
+            (PlayerCommand::Scan(speed), _) if speed != 0_f64 => self.scan(speed, osd),
+            // scanning stops on play, pause or cycling past the fastest speed, by seeking to
+            // where it got to
+            (
+                PlayerCommand::Scan(_) | PlayerCommand::Play | PlayerCommand::Pause,
+                Transport::Scan {
+                    position, resume, ..
+                },
+            ) => {
+                commands.push(PlayerCommand::Seek(position));
+                let resume = match command {
+                    PlayerCommand::Scan(_) => resume,
+                    PlayerCommand::Pause => Intent::Pause,
+                    _ => Intent::Play,
+                };
+                self.stop_scan(resume, osd);
+            }
+            (PlayerCommand::Scan(_), _) => {}
+            (PlayerCommand::Play, _) => {
+                // playing again from the end starts over
+                if self.state == PlaybackState::Ended {
+                    commands.push(PlayerCommand::Seek(0_f64));
+                }
+                self.play(osd);
+            }
+            (PlayerCommand::Pause, _) => self.pause(osd),
+            (command, _) => commands.push(command),
+        }
+    }
+
+    fn play(&mut self, osd: &mut Osd) {
+        if self.transport != Transport::Normal(Intent::Pause) {
+            return;
+        }
+        self.transport = Transport::Normal(Intent::Play);
+        self.unpause();
+        osd.show_message("PLAY", Duration::from_secs(1));
+    }
+
+    fn pause(&mut self, osd: &mut Osd) {
+        if self.transport != Transport::Normal(Intent::Play) {
+            return;
+        }
+        self.transport = Transport::Normal(Intent::Pause);
+        self.hold_paused();
+        osd.show_message("PAUSE", Duration::from_secs(1));
+    }
+
+    /// Starts scanning at `speed`, or changes the speed when already scanning.
+    fn scan(&mut self, speed: f64, osd: &mut Osd) {
+        match &mut self.transport {
+            Transport::Scan {
+                speed: scanning, ..
+            } => *scanning = speed,
+            _ => {
+                let resume = if self.is_paused() {
+                    Intent::Pause
+                } else {
+                    Intent::Play
+                };
+                self.transport = Transport::Scan {
+                    speed,
+                    position: self.clock.position(),
+                    last_step: Instant::now(),
+                    resume,
+                };
+                self.hold_paused();
+            }
+        }
+        osd.show_message(&format!("SCAN {:+.0}X", speed), Duration::from_secs(1));
+    }
+
+    fn stop_scan(&mut self, resume: Intent, osd: &mut Osd) {
+        self.transport = Transport::Normal(Intent::Pause);
+        if resume == Intent::Play {
+            self.play(osd);
+        }
+    }
+
+    /// Pushes the seek to the next keyframe while scanning, once the last step's frame has
+    /// shown up, though without waiting for it for long. Scanning into either end of an
+    /// input `duration` seconds long plays normally from there.
+    pub fn step_scan(
+        &mut self,
+        duration: Option<f64>,
+        osd: &mut Osd,
+        commands: &mut Vec<PlayerCommand>,
+    ) {
+        let (speed, position, last_step, resume) = match self.transport {
+            Transport::Scan {
+                speed,
+                position,
+                last_step,
+                resume,
+            } => (speed, position, last_step, resume),
+            _ => return,
+        };
+        let since_step = last_step.elapsed();
+        if since_step < SCAN_STEP
+            || (self.state == PlaybackState::Seeking && since_step < SCAN_STEP * 4)
+        {
+            return;
+        }
+
+        let position = position + speed * since_step.as_secs_f64();
+        let end = duration.unwrap_or(f64::MAX);
+        if position > 0_f64 && position < end {
+            self.transport = Transport::Scan {
+                speed,
+                position,
+                last_step: Instant::now(),
+                resume,
+            };
+            commands.push(PlayerCommand::Seek(position));
+        } else {
+            commands.push(PlayerCommand::Seek(position.clamp(0_f64, end)));
+            self.stop_scan(resume, osd);
+        }
+    }
+
+    /// Starts seeking to `position`, waiting for its first frame to `anchor` the clock on.
+    pub fn seek(&mut self, position: f64) {
+        // opening anchors the clock on the first frame anyway
+        if self.state != PlaybackState::Opening {
+            self.state = PlaybackState::Seeking;
+        }
+        // scanning carries on from wherever a seek goes
+        if let Transport::Scan { position: from, .. } = &mut self.transport {
+            *from = position;
+        }
+    }
+
+    /// Anchors the clock on the first frame after a seek, at `position`.
+    pub fn anchor(&mut self, position: f64) {
+        self.clock.set_position(position);
+        // the clock may have been held by buffering before the seek
+        self.settle();
+    }
+
+    /// Holds the clock when decoding has fallen behind.
+    pub fn underrun(&mut self) {
+        self.state = PlaybackState::Buffering;
+        self.hold();
+    }
+
+    /// Lets the clock go once enough is decoded, when opening from the first frame, at
+    /// `first_position`.
+    pub fn buffered(&mut self, first_position: Option<f64>) {
+        // anchor the clock on the first frame instead of an arbitrary zero
+        if let (PlaybackState::Opening, Some(position)) = (self.state, first_position) {
+            self.clock.set_position(position);
+        }
+        self.settle();
+    }
+
+    /// Keeps the last frame up at the end, paused.
+    pub fn end(&mut self) {
+        self.state = PlaybackState::Ended;
+        if !self.is_paused() {
+            self.transport = Transport::Normal(Intent::Pause);
+            self.hold();
+        }
+    }
+
+    /// Stops playback once it's over.
+    pub fn close(&mut self) {
+        self.state = PlaybackState::Ended;
+    }
+
+    /// Stops playback because the pipeline failed.
+    pub fn fail(&mut self) {
+        self.state = PlaybackState::Error;
+    }
+
+    /// Pauses playback here for a cast to a device, which starts out playing.
+    #[cfg(feature = "cast")]
+    pub fn start_cast(&mut self) {
+        self.transport = Transport::Cast {
+            device: Intent::Play,
+            resume: self.intent(),
+        };
+        self.hold_paused();
+    }
+
+    /// Takes over from the cast device again, paused or not as playback here was before.
+    #[cfg(feature = "cast")]
+    pub fn stop_cast(&mut self) {
+        if let Transport::Cast { resume, .. } = self.transport {
+            self.transport = Transport::Normal(resume);
+            if resume == Intent::Play {
+                self.unpause();
+            }
+        }
+    }
+
+    /// The command for the cast device for transport `command` while casting, tracking
+    /// whether the device is paused, or `None` for anything else.
+    #[cfg(feature = "cast")]
+    pub fn cast_command(&mut self, command: &PlayerCommand) -> Option<CastCommand> {
+        let device = match &mut self.transport {
+            Transport::Cast { device, .. } => device,
+            _ => return None,
+        };
+        let forwarded = match *command {
+            PlayerCommand::Play => CastCommand::Play,
+            PlayerCommand::Pause => CastCommand::Pause,
+            PlayerCommand::TogglePause if *device == Intent::Pause => CastCommand::Play,
+            PlayerCommand::TogglePause => CastCommand::Pause,
+            PlayerCommand::Seek(position) => CastCommand::Seek(position),
+            PlayerCommand::SeekBy(offset) => CastCommand::SeekBy(offset),
+            _ => return None,
+        };
+        match forwarded {
+            CastCommand::Play => *device = Intent::Play,
+            CastCommand::Pause => *device = Intent::Pause,
+            _ => {}
+        }
+        Some(forwarded)
+    }
+
+    /// What play and pause asked for last, which casting goes back to once it stops.
+    #[cfg(feature = "cast")]
+    fn intent(&self) -> Intent {
+        match self.transport {
+            Transport::Normal(intent) => intent,
+            Transport::Scan { resume, .. } | Transport::Cast { resume, .. } => resume,
+        }
+    }
+
+    /// Whether the clock is held for opening or buffering, whatever was asked for.
+    fn is_buffering(&self) -> bool {
+        matches!(
+            self.state,
+            PlaybackState::Opening | PlaybackState::Buffering
+        )
+    }
+
+    /// Moves on from opening, seeking or buffering to playing or paused, as asked for.
+    fn settle(&mut self) {
+        self.state = if self.is_paused() {
+            PlaybackState::Paused
+        } else {
+            self.release();
+            PlaybackState::Playing
+        };
+    }
+
+    /// Lets the clock go after a pause, unless it's held for buffering, which starts it once
+    /// that's done.
+    fn unpause(&mut self) {
+        if !self.is_buffering() {
+            self.release();
+        }
+        if self.state == PlaybackState::Paused {
+            self.state = PlaybackState::Playing;
+        }
+    }
+
+    /// Holds the clock for a pause, which leaves any other state as it is.
+    fn hold_paused(&mut self) {
+        self.hold();
+        if self.state == PlaybackState::Playing {
+            self.state = PlaybackState::Paused;
+        }
+    }
+
+    fn hold(&mut self) {
+        self.clock.pause();
+        self.audio_sink.pause();
+    }
+
+    fn release(&mut self) {
+        self.clock.resume();
+        self.audio_sink.resume();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, thread};
+
+    use super::*;
+    use crate::audio_sink::AudioSpec;
+
+    /// Whether it's paused, shared with the test.
+    struct PausingSink(Rc<Cell<bool>>);
+
+    impl AudioSink for PausingSink {
+        fn preferred_spec(&self) -> AudioSpec {
+            AudioSpec {
+                sample_rate: 48000,
+                channels: 2,
+            }
+        }
+
+        fn write(&mut self, _samples: &[f32]) {}
+
+        fn latency(&self) -> f64 {
+            0_f64
+        }
+
+        fn pause(&mut self) {
+            self.0.set(true);
+        }
+
+        fn resume(&mut self) {
+            self.0.set(false);
+        }
+    }
+
+    /// Playback still opening, and whether its audio output is paused.
+    fn opening() -> (Playback, Rc<Cell<bool>>) {
+        let sink_paused = Rc::new(Cell::new(false));
+        let playback = Playback::new(Box::new(PausingSink(Rc::clone(&sink_paused))));
+        (playback, sink_paused)
+    }
+
+    /// Playback done opening at 0s, and whether its audio output is paused.
+    fn playing() -> (Playback, Rc<Cell<bool>>) {
+        let (mut playback, sink_paused) = opening();
+        playback.buffered(Some(0_f64));
+        (playback, sink_paused)
+    }
+
+    /// What `command` left for the player to carry out.
+    fn handle(playback: &mut Playback, command: PlayerCommand) -> Vec<PlayerCommand> {
+        let mut commands = Vec::new();
+        playback.handle(command, &mut Osd::new(), &mut commands);
+        commands
+    }
+
+    #[test]
+    fn holds_the_clock_until_opened() {
+        let (mut playback, sink_paused) = opening();
+        assert_eq!(playback.state(), PlaybackState::Opening);
+        assert!(!playback.is_paused());
+        assert!(sink_paused.get());
+
+        playback.buffered(Some(5_f64));
+        assert_eq!(playback.state(), PlaybackState::Playing);
+        assert!(playback.position() >= 5_f64);
+        assert!(!sink_paused.get());
+    }
+
+    #[test]
+    fn opens_paused_when_paused_while_opening() {
+        let (mut playback, sink_paused) = opening();
+        handle(&mut playback, PlayerCommand::Pause);
+        assert_eq!(playback.state(), PlaybackState::Opening);
+
+        playback.buffered(Some(5_f64));
+        assert_eq!(playback.state(), PlaybackState::Paused);
+        assert_eq!(playback.position(), 5_f64);
+        assert!(sink_paused.get());
+    }
+
+    #[test]
+    fn toggles_pause() {
+        let (mut playback, sink_paused) = playing();
+        handle(&mut playback, PlayerCommand::TogglePause);
+        assert_eq!(playback.state(), PlaybackState::Paused);
+        assert!(playback.is_paused());
+        assert!(sink_paused.get());
+
+        handle(&mut playback, PlayerCommand::TogglePause);
+        assert_eq!(playback.state(), PlaybackState::Playing);
+        assert!(!playback.is_paused());
+        assert!(!sink_paused.get());
+    }
+
+    #[test]
+    fn stays_paused_through_a_seek() {
+        let (mut playback, sink_paused) = playing();
+        handle(&mut playback, PlayerCommand::Pause);
+        let commands = handle(&mut playback, PlayerCommand::Seek(10_f64));
+        assert!(matches!(commands[..], [PlayerCommand::Seek(_)]));
+
+        playback.seek(10_f64);
+        assert_eq!(playback.state(), PlaybackState::Seeking);
+        playback.anchor(10_f64);
+        assert_eq!(playback.state(), PlaybackState::Paused);
+        assert_eq!(playback.position(), 10_f64);
+        assert!(sink_paused.get());
+    }
+
+    #[test]
+    fn plays_once_buffered_when_played_while_buffering() {
+        let (mut playback, sink_paused) = playing();
+        playback.underrun();
+        assert_eq!(playback.state(), PlaybackState::Buffering);
+        assert!(sink_paused.get());
+
+        handle(&mut playback, PlayerCommand::Pause);
+        handle(&mut playback, PlayerCommand::Play);
+        assert_eq!(playback.state(), PlaybackState::Buffering);
+        assert!(sink_paused.get());
+
+        playback.buffered(None);
+        assert_eq!(playback.state(), PlaybackState::Playing);
+        assert!(!sink_paused.get());
+    }
+
+    #[test]
+    fn scans_until_played_from_where_it_got_to() {
+        let (mut playback, sink_paused) = playing();
+        handle(&mut playback, PlayerCommand::ScanForward);
+        assert_eq!(playback.scan_speed(), Some(2_f64));
+        assert_eq!(playback.state(), PlaybackState::Paused);
+        assert!(sink_paused.get());
+
+        handle(&mut playback, PlayerCommand::ScanForward);
+        assert_eq!(playback.scan_speed(), Some(4_f64));
+        playback.seek(30_f64);
+
+        let commands = handle(&mut playback, PlayerCommand::Play);
+        assert!(matches!(commands[..], [PlayerCommand::Seek(position)] if position == 30_f64));
+        assert_eq!(playback.scan_speed(), None);
+        assert!(!playback.is_paused());
+        assert!(!sink_paused.get());
+    }
+
+    #[test]
+    fn scanning_stops_after_the_fastest_speed() {
+        let (mut playback, _) = playing();
+        for speed in [-2_f64, -4_f64, -8_f64] {
+            handle(&mut playback, PlayerCommand::ScanBackward);
+            assert_eq!(playback.scan_speed(), Some(speed));
+        }
+
+        let commands = handle(&mut playback, PlayerCommand::ScanBackward);
+        assert!(matches!(commands[..], [PlayerCommand::Seek(_)]));
+        assert_eq!(playback.scan_speed(), None);
+        assert!(!playback.is_paused());
+    }
+
+    #[test]
+    fn stays_paused_after_scanning_from_a_pause() {
+        let (mut playback, _) = playing();
+        handle(&mut playback, PlayerCommand::Pause);
+        handle(&mut playback, PlayerCommand::Scan(4_f64));
+        handle(&mut playback, PlayerCommand::Scan(0_f64));
+        assert_eq!(playback.scan_speed(), None);
+        assert!(playback.is_paused());
+    }
+
+    #[test]
+    fn plays_from_the_start_when_scanning_into_it() {
+        let (mut playback, _) = playing();
+        handle(&mut playback, PlayerCommand::ScanBackward);
+        let mut commands = Vec::new();
+        playback.step_scan(Some(60_f64), &mut Osd::new(), &mut commands);
+        assert!(commands.is_empty());
+
+        thread::sleep(SCAN_STEP);
+        playback.step_scan(Some(60_f64), &mut Osd::new(), &mut commands);
+        assert!(matches!(commands[..], [PlayerCommand::Seek(position)] if position == 0_f64));
+        assert_eq!(playback.scan_speed(), None);
+        assert!(!playback.is_paused());
+    }
+
+    #[test]
+    fn plays_from_the_start_again_after_the_end() {
+        let (mut playback, sink_paused) = playing();
+        playback.end();
+        assert_eq!(playback.state(), PlaybackState::Ended);
+        assert!(playback.is_paused());
+        assert!(sink_paused.get());
+
+        let commands = handle(&mut playback, PlayerCommand::TogglePause);
+        assert!(matches!(commands[..], [PlayerCommand::Seek(position)] if position == 0_f64));
+        assert!(!playback.is_paused());
+    }
+
+    #[cfg(feature = "cast")]
+    #[test]
+    fn pauses_while_casting_and_forwards_the_transport() {
+        let (mut playback, sink_paused) = playing();
+        playback.start_cast();
+        assert_eq!(playback.state(), PlaybackState::Paused);
+        assert!(sink_paused.get());
+
+        let toggle = PlayerCommand::TogglePause;
+        assert!(matches!(
+            playback.cast_command(&toggle),
+            Some(CastCommand::Pause)
+        ));
+        assert!(matches!(
+            playback.cast_command(&toggle),
+            Some(CastCommand::Play)
+        ));
+        assert!(playback.cast_command(&PlayerCommand::ToggleMute).is_none());
+        assert!(playback.is_paused());
+    }
+
+    #[cfg(feature = "cast")]
+    #[test]
+    fn plays_again_after_casting_from_playing() {
+        let (mut playback, sink_paused) = playing();
+        playback.start_cast();
+        playback.cast_command(&PlayerCommand::Pause);
+        playback.stop_cast();
+        assert_eq!(playback.state(), PlaybackState::Playing);
+        assert!(!playback.is_paused());
+        assert!(!sink_paused.get());
+        assert!(playback.cast_command(&PlayerCommand::Play).is_none());
+    }
+
+    #[cfg(feature = "cast")]
+    #[test]
+    fn stays_paused_after_casting_from_a_pause() {
+        let (mut playback, sink_paused) = playing();
+        handle(&mut playback, PlayerCommand::Pause);
+        playback.start_cast();
+        playback.stop_cast();
+        assert_eq!(playback.state(), PlaybackState::Paused);
+        assert!(playback.is_paused());
+        assert!(sink_paused.get());
+    }
+}
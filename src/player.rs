@@ -0,0 +1,3526 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env, mem,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ffmpeg_next::{
+    codec::decoder::audio::Audio as AudioDecoder,
+    codec::decoder::video::Video as VideoDecoder,
+    format::{sample::Type as AudioType, Sample},
+    frame::{self, Audio, Video},
+    software::resampling,
+    ChannelLayout, Frame, Packet,
+};
+
+use crate::{
+    abr::Abr,
+    ambilight::Ambilight,
+    asset::{PlaybackAsset, PlaybackAssetMetadata, Variant},
+    audio_filter::AudioFilter,
+    audio_sink::{AudioSink, AudioSpec, NullAudioSink, WavAudioSink},
+    bindings::KeyBindings,
+    budget::{self, MemoryBudget},
+    capture::CaptureDevice,
+    chapter_menu::MenuChoice,
+    clock::{format_time, ExternalClock, PlaybackClock, SystemClock},
+    compose::Compositor,
+    config,
+    controls::{ClickTracker, ControlState},
+    crop::{Crop, CropDetector},
+    equalizer::{self, Equalizer, EqualizerPreset},
+    export::{self, ExportSettings},
+    history, hwdec,
+    monitor::AudioMonitor,
+    mosaic::Mosaic,
+    options::{
+        AspectMode, AudioOutput, ClockSource, CompareView, Deinterlace, HlsBitrate, HwDecode,
+        KeepOpen, LoudnessNormalizer, Options, Projection, StereoMode, StopScreensaver,
+        VideoOutput,
+    },
+    ordered_chapters::{Editions, OrderedSource},
+    osd::Osd,
+    pacing::FrameScheduler,
+    picture::{PictureEqualizer, PictureProperty},
+    playback::Playback,
+    progress::{ProgressRecord, ProgressWriter},
+    remux::{self, Remux},
+    secondary::SecondarySource,
+    spdif::{self, SpdifPacker},
+    sync::{SyncClient, SyncMaster},
+    timeline::{FrameTimestamps, Timeline},
+    timeshift::{Next, Timeshift},
+    video_filter::VideoFilter,
+    video_sink::{NullVideoSink, TerminalVideoSink, VideoSink, WINDOW_TITLE},
+    visualizer::Visualizer,
+};
+
+#[cfg(feature = "cpal")]
+use crate::audio_sink::CpalAudioSink;
+#[cfg(feature = "cast")]
+use crate::cast::{CastSession, CastState};
+#[cfg(feature = "now-playing")]
+use crate::now_playing::NowPlaying;
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+#[cfg(feature = "winit")]
+use crate::video_sink::WinitVideoSink;
+#[cfg(feature = "sdl")]
+use crate::{
+    audio_sink::SdlAudioSink,
+    video_sink::{GlVideoSink, SdlVideoSink},
+};
+#[cfg(feature = "plugins")]
+use crate::{options::config_dir, plugin::Plugins};
+
+/// Converts decoded audio to the interleaved `f32` samples, rate and channel count the sink
+/// asked for.
+struct AudioConverter {
+    spec: AudioSpec,
+    resampler: Option<resampling::Context>,
+    gain: f32,
+}
+
+impl AudioConverter {
+    pub fn new(spec: AudioSpec) -> Self {
+        AudioConverter {
+            spec,
+            resampler: None,
+            gain: 1_f32,
+        }
+    }
+
+    /// Scales every sample by `gain`, e.g. for ReplayGain.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    pub fn convert(&mut self, frame: &Audio) -> Option<Vec<f32>> {
+        if frame.rate() == 0 {
+            return None;
+        }
+
+        let layout = if frame.channel_layout().is_empty() {
+            ChannelLayout::default(frame.channels() as i32)
+        } else {
+            frame.channel_layout()
+        };
+        let output_layout = ChannelLayout::default(self.spec.channels as i32);
+        let output_format = Sample::F32(AudioType::Packed);
+
+        // (re)create the resampler whenever the decoded format changes
+        let matches = self.resampler.as_ref().map_or(false, |resampler| {
+            let input = resampler.input();
+            input.format == frame.format()
+                && input.channel_layout == layout
+                && input.rate == frame.rate()
+        });
+        if !matches {
+            self.resampler = match resampling::Context::get(
+                frame.format(),
+                layout,
+                frame.rate(),
+                output_format,
+                output_layout,
+                self.spec.sample_rate,
+            ) {
+                Ok(resampler) => Some(resampler),
+                Err(e) => {
+                    eprintln!("failed to create audio resampler: {}", e);
+                    None
+                }
+            };
+        }
+        let resampler = self.resampler.as_mut()?;
+
+        // room for the resampled frame plus whatever the resampler held back last time
+        let capacity =
+            frame.samples() * self.spec.sample_rate as usize / frame.rate() as usize + 256;
+        let mut output = frame::Audio::new(output_format, capacity, output_layout);
+        resampler.run(frame, &mut output).ok()?;
+
+        let len = output.samples() * self.spec.channels as usize;
+        let gain = self.gain;
+        Some(
+            output.data(0)[..len * 4]
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) * gain)
+                .collect(),
+        )
+    }
+}
+
+/// What goes down the pipeline's channels, from the demuxer to the decode threads and on to
+/// the main loop. Packets and frames carry the serial of the seek they come after, so every
+/// stage can drop what a later seek made stale.
+enum Message<T> {
+    /// The demuxer seeked: decoders flush what they held back from before.
+    Seek(SeekRequest),
+    Item(usize, T),
+    /// Everything of the stream after the seek with this serial has been sent.
+    End(usize),
+}
+
+/// Where the buffer thread reads packets from.
+enum PacketSource {
+    /// The input itself, its timestamps rebased onto timelines that keep going forward.
+    Input {
+        asset: PlaybackAsset,
+        video_timeline: Timeline,
+        audio_timeline: Timeline,
+        /// Switches between the variants of an adaptive stream.
+        abr: Option<Abr>,
+        /// An internet radio station's new song title, until the main loop takes it.
+        stream_title: Arc<Mutex<Option<String>>>,
+    },
+    /// The ring a live input is recorded into, see `Timeshift`.
+    Timeshift(Timeshift),
+    /// A Matroska file's ordered chapters, see `OrderedSource`.
+    Ordered(OrderedSource),
+}
+
+impl PacketSource {
+    /// Plays `asset` itself, or the timeline of the edition it plays when that's ordered.
+    fn from_asset(
+        asset: PlaybackAsset,
+        abr: Option<Abr>,
+        stream_title: &Arc<Mutex<Option<String>>>,
+        options: &Options,
+    ) -> Self {
+        if let Some(chapters) = asset.ordered_chapters() {
+            return PacketSource::Ordered(OrderedSource::new(asset, chapters, options));
+        }
+        let metadata = asset.metadata();
+        PacketSource::Input {
+            asset,
+            video_timeline: Timeline::new(metadata.video_time_base()),
+            audio_timeline: Timeline::new(metadata.audio_time_base()),
+            abr,
+            stream_title: Arc::clone(stream_title),
+        }
+    }
+
+    /// The same input playing the edition `editions` has selected, from its start until
+    /// the seek that follows.
+    fn with_editions(
+        self,
+        editions: Editions,
+        stream_title: &Arc<Mutex<Option<String>>>,
+        options: &Options,
+    ) -> Self {
+        let (mut asset, abr) = match self {
+            PacketSource::Input { asset, abr, .. } => (asset, abr),
+            PacketSource::Ordered(ordered) => (ordered.into_asset(), None),
+            // live inputs have no editions
+            PacketSource::Timeshift(_) => return self,
+        };
+        asset.set_editions(editions);
+        PacketSource::from_asset(asset, abr, stream_title, options)
+    }
+
+    fn seek(&mut self, position: f64) {
+        match self {
+            PacketSource::Input {
+                asset,
+                video_timeline,
+                audio_timeline,
+                ..
+            } => {
+                if let Err(e) = asset.seek(position) {
+                    eprintln!("failed to seek to {:.2}s: {}", position, e);
+                }
+                video_timeline.reset();
+                audio_timeline.reset();
+            }
+            PacketSource::Timeshift(timeshift) => timeshift.seek(position),
+            PacketSource::Ordered(ordered) => ordered.seek(position),
+        }
+    }
+
+    /// Whether stream `index` is the video of one of an adaptive stream's variants.
+    fn is_variant(&self, index: usize) -> bool {
+        match self {
+            PacketSource::Input { abr: Some(abr), .. } => abr.is_variant(index),
+            _ => false,
+        }
+    }
+
+    /// The next packet with its stream index, its timestamps rebased.
+    fn next(&mut self) -> Next {
+        match self {
+            PacketSource::Input {
+                asset,
+                video_timeline,
+                audio_timeline,
+                abr,
+                stream_title,
+            } => loop {
+                let metadata = asset.metadata();
+                let read = Instant::now();
+                let (stream, mut packet) = match asset.packets().next() {
+                    Some(next) => next,
+                    None => return Next::End,
+                };
+                let index = stream.index();
+                let video = Some(index) == metadata.video_stream_index()
+                    || abr.as_ref().map_or(false, |abr| abr.is_variant(index));
+                if video {
+                    video_timeline.rebase(&stream, &mut packet);
+                } else {
+                    audio_timeline.rebase(&stream, &mut packet);
+                }
+
+                if let Some(title) = asset.take_stream_title() {
+                    *stream_title.lock().unwrap() = Some(title);
+                }
+                if let Some(abr) = abr {
+                    if let Some(variant) = abr.measure(packet.size(), read.elapsed()) {
+                        asset.select_variant(&variant);
+                    }
+                    // packets of the variant switched from that were already downloaded
+                    if !abr.accept(index, packet.is_key()) {
+                        continue;
+                    }
+                    return Next::Packet(abr.output_index(index), packet);
+                }
+                return Next::Packet(index, packet);
+            },
+            PacketSource::Timeshift(timeshift) => timeshift.next().unwrap_or_else(|e| {
+                eprintln!("failed to read the timeshift buffer: {}", e);
+                Next::End
+            }),
+            PacketSource::Ordered(ordered) => ordered.next(),
+        }
+    }
+}
+
+/// Packets queued for each decoder. Streams are interleaved closely enough in any sane input
+/// that the demuxer never waits on one decoder while the other starves.
+const PACKET_QUEUE: usize = 512;
+
+/// Decoded frames in flight between a decode thread and the main loop.
+const FRAME_QUEUE: usize = 4;
+
+/// Frames the main loop takes off the decode threads ahead of the clock, at least.
+const VIDEO_FRAMES_AHEAD: usize = 16;
+const AUDIO_FRAMES_AHEAD: usize = 64;
+
+/// Decoded frames taken off a decode thread's channel, owned by the main loop so presenting
+/// them never waits on a decoder.
+struct FrameQueue<T> {
+    frames: VecDeque<T>,
+    receiver: Receiver<Message<T>>,
+    ahead: usize,
+    /// Set once every frame of the stream has been taken off the channel.
+    drained: bool,
+    budget: Arc<MemoryBudget>,
+    size: fn(&T) -> usize,
+}
+
+impl<T> FrameQueue<T> {
+    pub fn new(
+        receiver: Receiver<Message<T>>,
+        ahead: usize,
+        budget: Arc<MemoryBudget>,
+        size: fn(&T) -> usize,
+    ) -> Self {
+        FrameQueue {
+            frames: VecDeque::new(),
+            receiver,
+            ahead,
+            drained: false,
+            budget,
+            size,
+        }
+    }
+
+    /// Takes what the decode thread sent since, up to `ahead` frames, dropping any from
+    /// before the seek numbered `serial`.
+    pub fn receive(&mut self, serial: usize) {
+        while !self.is_full() {
+            match self.receiver.try_recv() {
+                Ok(Message::Item(sent, frame)) if sent == serial => self.frames.push_back(frame),
+                Ok(Message::Item(_, frame)) => self.budget.remove_frame((self.size)(&frame)),
+                Ok(Message::End(sent)) => self.drained |= sent == serial,
+                Ok(Message::Seek(_)) => {}
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let frame = self.frames.pop_front()?;
+        self.budget.remove_frame((self.size)(&frame));
+        Some(frame)
+    }
+
+    /// Drops everything from before a seek.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+        self.drained = false;
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.frames.len() >= self.ahead
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Whether every frame of the stream has been decoded, as opposed to the demuxer just
+    /// having reached its end.
+    pub fn is_drained(&self) -> bool {
+        self.drained
+    }
+}
+
+impl FrameQueue<Audio> {
+    /// Seconds of audio currently decoded and waiting to be played.
+    pub fn duration(&self) -> f64 {
+        self.frames
+            .iter()
+            .filter(|frame| frame.rate() > 0)
+            .map(|frame| frame.samples() as f64 / frame.rate() as f64)
+            .sum()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SeekRequest {
+    position: f64,
+    serial: usize,
+    /// Scanning: buffer just the keyframe seeked to and no audio, then end the streams
+    /// there so the frame is drained out of the decoder.
+    keyframe_only: bool,
+}
+
+impl SeekRequest {
+    /// Where decoders start handing out frames with an exact seek; a scanned to keyframe is
+    /// shown as it is, wherever exactly it lands.
+    fn target(&self) -> Option<f64> {
+        Some(self.position).filter(|_| !self.keyframe_only)
+    }
+}
+
+/// Where the video decode thread gets pictures from: the video stream, or for inputs
+/// without one, the audio stream drawn by a `Visualizer`.
+enum PlayerVideoDecoder {
+    Video(VideoDecoder),
+    Visualizer(PlayerAudioDecoder, Visualizer),
+}
+
+struct PlayerAudioDecoder {
+    audio_decoder: AudioDecoder,
+}
+
+impl PlayerVideoDecoder {
+    pub fn new(video_decoder: VideoDecoder) -> Self {
+        PlayerVideoDecoder::Video(video_decoder)
+    }
+
+    /// Decodes `packet`, returning the frames it completed: none while the decoder is
+    /// still filling up, for as many packets as it has threads with frame threading, or
+    /// several.
+    pub fn decode_video_packet(&mut self, packet: Packet) -> Vec<Video> {
+        match self {
+            PlayerVideoDecoder::Video(video_decoder) => {
+                // Send packet to the decoder; a corrupt packet, or one of a linked segment
+                // encoded unlike the file, only costs the frames that depend on it
+                if let Err(e) = video_decoder.send_packet(&packet) {
+                    eprintln!("failed to decode a video packet: {}", e);
+                }
+
+                Self::receive_frames(video_decoder)
+            }
+            PlayerVideoDecoder::Visualizer(audio_decoder, visualizer) => audio_decoder
+                .decode_audio_packet(packet)
+                .iter()
+                .flat_map(|frame| visualizer.draw(frame))
+                .collect(),
+        }
+    }
+
+    /// Signals the end of the stream, returning the frames the decoder still held back,
+    /// e.g. for reordering.
+    pub fn drain(&mut self) -> Vec<Video> {
+        match self {
+            PlayerVideoDecoder::Video(video_decoder) => {
+                if video_decoder.send_eof().is_err() {
+                    return Vec::new();
+                }
+                Self::receive_frames(video_decoder)
+            }
+            PlayerVideoDecoder::Visualizer(audio_decoder, visualizer) => {
+                let mut frames: Vec<Video> = audio_decoder
+                    .drain()
+                    .iter()
+                    .flat_map(|frame| visualizer.draw(frame))
+                    .collect();
+                frames.extend(visualizer.flush());
+                frames
+            }
+        }
+    }
+
+    fn receive_frames(video_decoder: &mut VideoDecoder) -> Vec<Video> {
+        let mut frames = Vec::new();
+        let mut frame = frame::Video::empty();
+        while video_decoder.receive_frame(&mut frame).is_ok() {
+            frames.push(frame);
+            frame = frame::Video::empty();
+        }
+        frames
+    }
+
+    pub fn flush(&mut self) {
+        match self {
+            PlayerVideoDecoder::Video(video_decoder) => video_decoder.flush(),
+            PlayerVideoDecoder::Visualizer(audio_decoder, visualizer) => {
+                audio_decoder.flush();
+                visualizer.reset();
+            }
+        }
+    }
+}
+
+impl PlayerAudioDecoder {
+    pub fn new(audio_decoder: AudioDecoder) -> Self {
+        Self { audio_decoder }
+    }
+
+    /// Decodes `packet`, returning the frames it completed, if any.
+    pub fn decode_audio_packet(&mut self, packet: Packet) -> Vec<Audio> {
+        // Send packet to the decoder, skipping it when it's corrupt
+        if let Err(e) = self.audio_decoder.send_packet(&packet) {
+            eprintln!("failed to decode an audio packet: {}", e);
+        }
+
+        self.receive_frames()
+    }
+
+    /// Signals the end of the stream, returning the frames the decoder still held back.
+    pub fn drain(&mut self) -> Vec<Audio> {
+        if self.audio_decoder.send_eof().is_err() {
+            return Vec::new();
+        }
+        self.receive_frames()
+    }
+
+    fn receive_frames(&mut self) -> Vec<Audio> {
+        let mut frames = Vec::new();
+        let mut frame = frame::Audio::empty();
+        frame.set_format(Sample::F32(AudioType::Packed));
+        while self.audio_decoder.receive_frame(&mut frame).is_ok() {
+            frames.push(frame);
+            frame = frame::Audio::empty();
+            frame.set_format(Sample::F32(AudioType::Packed));
+        }
+        frames
+    }
+
+    pub fn flush(&mut self) {
+        self.audio_decoder.flush();
+    }
+}
+
+/// Commands an embedder can send to a running player through a `PlayerController`.
+#[derive(Clone, Copy, Debug)]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    TogglePause,
+    /// Seek to an absolute position in seconds.
+    Seek(f64),
+    /// Seek relative to the current position, in seconds.
+    SeekBy(f64),
+    /// Scan through at this multiple of normal speed, backwards when negative, showing
+    /// only keyframes with the audio muted. 0 plays normally again from where it got to.
+    Scan(f64),
+    /// Scan forward at the next of `SCAN_SPEEDS`, or play normally again after the fastest.
+    ScanForward,
+    /// Scan backward at the next of `SCAN_SPEEDS`, or play normally again after the
+    /// fastest.
+    ScanBackward,
+    /// Play audio this many seconds late, or early when negative.
+    AudioDelay(f64),
+    /// Change the audio delay by this many seconds.
+    AudioDelayBy(f64),
+    /// Mark the start of the range to export.
+    MarkIn,
+    /// Mark the end of the range to export.
+    MarkOut,
+    /// Export the marked range in the background.
+    Export,
+    EqualizerPreset(EqualizerPreset),
+    CycleEqualizerPreset,
+    ToggleDeinterlace,
+    /// Crop black bars found in the picture, or stop cropping them.
+    ToggleAutocrop,
+    /// Show a scrolling spectrogram of the audio over the picture, or hide it.
+    ToggleSpectrogram,
+    /// Show a level meter per channel of the audio, or hide it.
+    ToggleLevelMeter,
+    /// Move the focus, and the audio played, to the next `--mosaic` tile.
+    FocusNextTile,
+    /// Show the focused `--mosaic` tile alone, or all of them again.
+    ToggleTileExpanded,
+    /// Show a menu of the chapters and editions to jump to, or hide it.
+    ToggleChapterMenu,
+    /// Move the selection of the menu shown up or down.
+    MenuUp,
+    MenuDown,
+    /// Pick what's selected in the menu shown.
+    MenuSelect,
+    /// Cast the input to a Chromecast or DLNA renderer, or stop casting and carry on here.
+    ToggleCast,
+    /// Record the packets played to a file, or stop recording.
+    ToggleRecording,
+    /// Jump back to the live edge of a live stream played with `--timeshift`.
+    GoLive,
+    AspectMode(AspectMode),
+    CycleAspectMode,
+    /// Swap which input of `--pip` is shown in the corner.
+    SwapPictureInPicture,
+    /// Move the `--compare` wipe line to this fraction of the picture's width, and show
+    /// the wipe.
+    Wipe(f64),
+    CompareView(CompareView),
+    /// Show the other `--compare` input full-frame.
+    ToggleCompareSource,
+    /// Turn the picture another 90° clockwise.
+    Rotate,
+    FlipHorizontal,
+    FlipVertical,
+    /// Set a picture equalizer property, from -100 to 100.
+    Picture(PictureProperty, i32),
+    /// Change a picture equalizer property by this much.
+    PictureBy(PictureProperty, i32),
+    /// A key press no binding took, for scripts.
+    Key(char),
+    /// Set the volume, linear with 1 being the file's own level.
+    Volume(f64),
+    /// Change the volume by this much.
+    VolumeBy(f64),
+    ToggleMute,
+    /// Keep the control bar shown, or let it hide again.
+    ToggleControls,
+    ToggleFullscreen,
+    /// Keep the window above other applications', or stop keeping it there.
+    ToggleOnTop,
+    /// Resize the window to this multiple of the video's size in pixels.
+    WindowScale(f64),
+    /// Play the most recently played other file, from the history.
+    OpenLast,
+    /// Play the path or URL on the clipboard.
+    OpenClipboard,
+    /// The mouse moved over the window or its left button changed, at `x` and `y` as
+    /// fractions of the window's width and height.
+    Mouse {
+        action: MouseAction,
+        x: f64,
+        y: f64,
+    },
+    /// Set one equalizer band, by index into `equalizer::BANDS`, to a gain in dB.
+    EqualizerGain {
+        band: usize,
+        gain: f64,
+    },
+    Stop,
+}
+
+/// What the mouse did, for `PlayerCommand::Mouse`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseAction {
+    Move,
+    Press,
+    Release,
+}
+
+/// How much the audio delay keys change it by, in seconds.
+pub const AUDIO_DELAY_STEP: f64 = 0.025;
+
+/// How much the volume keys change it by.
+pub const VOLUME_STEP: f64 = 0.05;
+
+/// How far the next track media key skips ahead, in seconds, as there's no next track.
+pub const MEDIA_KEY_SKIP: f64 = 60_f64;
+
+/// Where playback of an input is at. It goes `Opening → Playing ⇄ Paused`, through
+/// `Seeking` on every seek and `Buffering` whenever decoding falls behind, and ends kept open
+/// in `Ended` or back in `Idle`, or in `Error` when the pipeline failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackState {
+    /// Nothing to play.
+    Idle,
+    /// The input is opened, with the clock held until enough is decoded to start.
+    Opening,
+    /// Decoding fell behind, the clock is held until enough is decoded again.
+    Buffering,
+    Playing,
+    Paused,
+    /// Waiting for the first frame at the position seeked to.
+    Seeking,
+    /// Every frame has been played, with the last one kept up.
+    Ended,
+    /// The pipeline stopped working, so playback was stopped.
+    Error,
+}
+
+impl PlaybackState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlaybackState::Idle => "idle",
+            PlaybackState::Opening => "opening",
+            PlaybackState::Buffering => "buffering",
+            PlaybackState::Playing => "playing",
+            PlaybackState::Paused => "paused",
+            PlaybackState::Seeking => "seeking",
+            PlaybackState::Ended => "ended",
+            PlaybackState::Error => "error",
+        }
+    }
+}
+
+impl Default for PlaybackState {
+    fn default() -> Self {
+        PlaybackState::Idle
+    }
+}
+
+/// How `Player::play` finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackOutcome {
+    /// Every frame was played.
+    Ended,
+    /// Stopped before the end, by a key press, closing the window or a `Stop` command.
+    Stopped,
+    /// The pipeline failed, e.g. on a broken decoder.
+    Failed,
+}
+
+/// What a running player is doing, as of its main loop's last pass.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerStatus {
+    /// Input being played, `None` before the first and after the last.
+    pub path: Option<String>,
+    pub state: PlaybackState,
+    pub position: f64,
+    pub duration: Option<f64>,
+    pub paused: bool,
+    pub volume: f64,
+    /// Counts seeks, to tell them apart from playback moving on.
+    pub seeks: usize,
+}
+
+/// What a player tells subscribers from `Player::events`, so they needn't poll its status.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerEvent {
+    /// Where playback is, in seconds: sent every `POSITION_EVENT_INTERVAL` while it moves
+    /// on, and right away after a seek.
+    PositionChanged(f64),
+    StateChanged(PlaybackState),
+    /// The inputs queued to play next, after one was loaded, queued or taken to play.
+    TrackListChanged(Vec<String>),
+    /// The song an internet radio station is playing, from its ICY metadata.
+    TrackChanged(String),
+    /// How far buffering is, from 0 to 1, while opening or after decoding fell behind.
+    BufferingProgress(f64),
+    /// Every frame of the input has been played.
+    EndOfFile,
+    Error(String),
+}
+
+/// How often `PlayerEvent::PositionChanged` is sent while playing.
+pub const POSITION_EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Handle for driving a player from another thread, e.g. a UI.
+#[derive(Clone)]
+pub struct PlayerController {
+    sender: Sender<PlayerCommand>,
+    status: Arc<Mutex<PlayerStatus>>,
+    playlist: Arc<Mutex<VecDeque<String>>>,
+    subscribers: Arc<Mutex<Vec<Sender<PlayerEvent>>>>,
+}
+
+impl PlayerController {
+    pub fn send(&self, command: PlayerCommand) {
+        // the player may already have stopped, nothing left to control then
+        self.sender.send(command).ok();
+    }
+
+    pub fn play(&self) {
+        self.send(PlayerCommand::Play);
+    }
+
+    pub fn pause(&self) {
+        self.send(PlayerCommand::Pause);
+    }
+
+    pub fn seek(&self, position: f64) {
+        self.send(PlayerCommand::Seek(position));
+    }
+
+    pub fn stop(&self) {
+        self.send(PlayerCommand::Stop);
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Stops the current input so `path` is played next, see `Player::take_next_input`.
+    pub fn load(&self, path: &str) {
+        self.playlist.lock().unwrap().push_front(path.to_string());
+        self.playlist_changed();
+        self.stop();
+    }
+
+    /// Queues `path` to play after the current input and any queued before it.
+    pub fn enqueue(&self, path: &str) {
+        self.playlist.lock().unwrap().push_back(path.to_string());
+        self.playlist_changed();
+    }
+
+    /// Events from now on, for as long as the receiver is kept.
+    pub fn events(&self) -> Receiver<PlayerEvent> {
+        let (sender, events) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        events
+    }
+
+    fn emit(&self, event: PlayerEvent) {
+        // subscribers that dropped their receiver are let go
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn playlist_changed(&self) {
+        let playlist = self.playlist.lock().unwrap().iter().cloned().collect();
+        self.emit(PlayerEvent::TrackListChanged(playlist));
+    }
+}
+
+/// EBU R128 normalization to -16 LUFS, the usual target for listening on consumer devices.
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:LRA=11:TP=-1.5";
+
+/// Shorter frames and window than the defaults, which would hold back ~15s of audio.
+const DYNAUDNORM_FILTER: &str = "dynaudnorm=f=200:g=11";
+
+/// Filter chain for the audio decode thread: the user's `--af` chain, then the equalizer,
+/// then the normalizer, so it evens out whatever the others boosted.
+fn audio_filter_description(
+    user: Option<&str>,
+    equalizer: &Equalizer,
+    normalizer: Option<&str>,
+) -> String {
+    user.map(str::to_string)
+        .into_iter()
+        .chain(equalizer.filter_description())
+        .chain(normalizer.map(str::to_string))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Saves `equalizer` to `config_file` after it's changed, for the next run.
+fn save_equalizer(config_file: Option<&Path>, equalizer: &Equalizer) {
+    if let Some(path) = config_file {
+        if let Err(e) = config::save_equalizer(path, equalizer) {
+            eprintln!("failed to save the equalizer to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Deinterlaces with bwdif, one frame out per frame in; the field order comes from the
+/// frames.
+const DEINTERLACE_FILTER: &str = "bwdif=mode=send_frame:deint=all";
+
+/// Like `DEINTERLACE_FILTER`, but leaves frames that aren't flagged as interlaced alone, for
+/// content that mixes both.
+const DEINTERLACE_FLAGGED_FILTER: &str = "bwdif=mode=send_frame:deint=interlaced";
+
+/// `stereo3d` filter turning a 3D `width` x `height` picture into one for a 2D display.
+///
+/// Full-size layouts (two 1920x1080 eyes in 3840x1080) are told apart from half-size ones
+/// (two squeezed eyes in 1920x1080) by the picture's shape; the filter fixes up the pixel
+/// aspect of the latter.
+fn stereo_filter(mode: StereoMode, width: u32, height: u32) -> String {
+    let ratio = width as f64 / height.max(1) as f64;
+    let side_by_side = if ratio > 2.5 { "sbsl" } else { "sbs2l" };
+    let top_bottom = if ratio < 1_f64 { "tbl" } else { "tb2l" };
+
+    let (input, output) = match mode {
+        StereoMode::SideBySide => (side_by_side, "ml"),
+        StereoMode::TopBottom => (top_bottom, "ml"),
+        // a taller than wide picture can only be top-bottom
+        StereoMode::Anaglyph if ratio < 1_f64 => (top_bottom, "arcd"),
+        StereoMode::Anaglyph => (side_by_side, "arcd"),
+        StereoMode::LeftOnly if ratio < 1_f64 => (top_bottom, "ml"),
+        StereoMode::LeftOnly => (side_by_side, "ml"),
+    };
+    format!("stereo3d={}:{}", input, output)
+}
+
+/// How the second input is combined with the main one.
+#[derive(Clone, Copy)]
+enum Composition {
+    /// `--pip`: one input in a corner of the other, the second one in front when `swapped`.
+    PictureInPicture { swapped: bool },
+    /// `--compare`: both inputs as `view` says, with the wipe line at `split` of the width.
+    Compare { view: CompareView, split: f64 },
+}
+
+/// What the OSD says of the variant of an adaptive stream playing, e.g. `720P 2.5 MBPS AUTO`.
+fn variant_label(variant: &Variant, policy: HlsBitrate) -> String {
+    format!(
+        "{}P {:.1} MBPS{}",
+        variant.height,
+        variant.bitrate as f64 / 1_000_000_f64,
+        if policy == HlsBitrate::Auto {
+            " AUTO"
+        } else {
+            ""
+        }
+    )
+}
+
+/// How long `frame` stays up, in seconds: until `next` shows, which follows variable frame
+/// rates, or for its packet's duration without a next frame to go by.
+fn frame_duration(frame: &Video, next: Option<&Video>, time_base: f64) -> Option<f64> {
+    match (frame.pts(), next.and_then(|next| next.pts())) {
+        (Some(pts), Some(next)) if next > pts => Some((next - pts) as f64 * time_base),
+        _ => Some(frame.packet().duration)
+            .filter(|duration| *duration > 0)
+            .map(|duration| duration as f64 * time_base),
+    }
+}
+
+/// The main input's `frame` combined with the second input's as `composition` says, `None`
+/// to show `frame` as it is.
+fn compose(
+    compositor: &mut Compositor,
+    composition: Composition,
+    frame: &Video,
+    second: &Video,
+) -> Option<Video> {
+    let mut picture = match composition {
+        Composition::PictureInPicture { swapped: false } => {
+            compositor.picture_in_picture(frame, second)
+        }
+        Composition::PictureInPicture { swapped: true } => {
+            compositor.picture_in_picture(second, frame)
+        }
+        Composition::Compare {
+            view: CompareView::Wipe,
+            split,
+        } => compositor.wipe(frame, second, split),
+        Composition::Compare {
+            view: CompareView::First,
+            ..
+        } => return None,
+        Composition::Compare {
+            view: CompareView::Second,
+            ..
+        } => compositor.full(second, frame),
+        Composition::Compare {
+            view: CompareView::Difference,
+            ..
+        } => compositor.difference(frame, second),
+    }?;
+    picture.set_pts(frame.pts());
+    Some(picture)
+}
+
+/// Interpolation target when neither `--interpolate-fps` nor the video output gives one.
+const DEFAULT_INTERPOLATE_FPS: u32 = 60;
+
+/// Video filters the main loop can switch while the decode thread applies them.
+struct VideoFilterSettings {
+    user: Option<String>,
+    deinterlace: Deinterlace,
+    /// Set by the decode thread once it has seen a frame flagged as interlaced.
+    interlaced: bool,
+    autocrop: bool,
+    /// Black bars found so far by the decode thread, while `autocrop` is on.
+    crop: Option<Crop>,
+    stereo: Option<StereoMode>,
+    picture: PictureEqualizer,
+    /// Frame rate to interpolate motion up to.
+    interpolate: Option<u32>,
+    /// Clockwise quarter turns, 0 to 3.
+    rotation: u32,
+    hflip: bool,
+    vflip: bool,
+}
+
+impl VideoFilterSettings {
+    fn is_deinterlacing(&self) -> bool {
+        match self.deinterlace {
+            Deinterlace::On => true,
+            Deinterlace::Auto => self.interlaced,
+            Deinterlace::Off => false,
+        }
+    }
+
+    /// Deinterlacing goes first, the user's `--vf` chain may scale away the field structure,
+    /// and cropping before it so its scaling fills the picture. 3D conversion sits between
+    /// the two, as cropping is detected on the decoded picture. Picture adjustments and
+    /// interpolation follow so they work on the smallest picture, then rotating and flipping,
+    /// which are about how the result is shown.
+    fn description(&self, width: u32, height: u32) -> String {
+        let deinterlace = match self.deinterlace {
+            Deinterlace::On => Some(DEINTERLACE_FILTER),
+            // progressive content doesn't pay for the filter until it turns out not to be
+            Deinterlace::Auto if self.interlaced => Some(DEINTERLACE_FLAGGED_FILTER),
+            _ => None,
+        };
+
+        let (crop, width, height) = match self.crop {
+            Some(crop) if self.autocrop => {
+                (Some(crop.filter_description()), crop.width, crop.height)
+            }
+            _ => (None, width, height),
+        };
+
+        let interpolate = self.interpolate.map(|fps| {
+            format!(
+                "minterpolate=fps={}:mi_mode=mci:mc_mode=aobmc:me_mode=bidir:vsbmc=1",
+                fps
+            )
+        });
+
+        let rotate = match self.rotation {
+            1 => Some("transpose=clock"),
+            2 => Some("hflip,vflip"),
+            3 => Some("transpose=cclock"),
+            _ => None,
+        };
+
+        deinterlace
+            .map(str::to_string)
+            .into_iter()
+            .chain(crop)
+            .chain(self.stereo.map(|mode| stereo_filter(mode, width, height)))
+            .chain(self.user.clone())
+            .chain(self.picture.filter_description())
+            .chain(interpolate)
+            .chain(rotate.map(str::to_string))
+            .chain(self.hflip.then(|| "hflip".to_string()))
+            .chain(self.vflip.then(|| "vflip".to_string()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// How far playback may trail the newest decoded data in `--low-latency` mode, in seconds,
+/// unless `--monitor-latency` says otherwise.
+const LOW_LATENCY_MAX_DELAY: f64 = 0.1;
+
+/// Audio is written this far ahead of the clock, in seconds, so the sink has about this much
+/// queued and each sample comes out when it's due. Writing holds off while more than this is
+/// queued, so the sink's queue can't grow away from the video.
+const AUDIO_BUFFER: f64 = 0.1;
+
+/// A `--sync-join` client seeks to the master's position once it drifts further than this
+/// away, in seconds, and then not again for a while so seeks can settle.
+const SYNC_MAX_DRIFT: f64 = 0.25;
+const SYNC_SEEK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Playback seeks when it is this far off an external clock, in seconds, and otherwise just
+/// moves its clock.
+const EXTERNAL_CLOCK_MAX_DRIFT: f64 = 1_f64;
+
+/// Longest the main loop sleeps with nothing due, so input and commands stay responsive.
+const IDLE_WAKEUP: Duration = Duration::from_millis(10);
+
+/// How long the mouse has to be left alone while playing for the cursor to hide.
+const CURSOR_HIDE_AFTER: Duration = Duration::from_secs(1);
+
+/// How often plugins' `on_tick` hook runs and their OSD text is fetched.
+#[cfg(feature = "plugins")]
+const PLUGIN_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often scripts' `on_tick` hook runs.
+#[cfg(feature = "scripting")]
+const SCRIPT_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What clipboard `text` names to play, if anything: a URL like `https://...` or
+/// `rtsp://...`, a `file://` URL or the path of an existing file.
+fn clipboard_input(text: &str) -> Option<String> {
+    let text = text.trim().trim_matches(|c| c == '"' || c == '\'');
+    if text.is_empty() || text.contains('\n') {
+        return None;
+    }
+
+    if let Some(path) = text.strip_prefix("file://") {
+        return Some(path.to_string()).filter(|path| Path::new(path).is_file());
+    }
+    let is_url = text.split_once("://").map_or(false, |(scheme, rest)| {
+        !rest.is_empty()
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    if is_url || Path::new(text).is_file() {
+        Some(text.to_string())
+    } else {
+        None
+    }
+}
+
+/// Whether the clock is running through the input as it's presented, not held for buffering
+/// or about to jump to where a seek lands.
+fn is_settled(state: PlaybackState) -> bool {
+    !matches!(
+        state,
+        PlaybackState::Opening | PlaybackState::Buffering | PlaybackState::Seeking
+    )
+}
+
+/// Transport commands, which are ignored while something else drives playback.
+fn is_transport(command: &PlayerCommand) -> bool {
+    matches!(
+        command,
+        PlayerCommand::Play
+            | PlayerCommand::Pause
+            | PlayerCommand::TogglePause
+            | PlayerCommand::Seek(_)
+            | PlayerCommand::SeekBy(_)
+            | PlayerCommand::GoLive
+            | PlayerCommand::Scan(_)
+            | PlayerCommand::ScanForward
+            | PlayerCommand::ScanBackward
+    )
+}
+
+pub struct Player {
+    options: Options,
+    video_sink: Option<Box<dyn VideoSink>>,
+    audio_sink: Option<Box<dyn AudioSink>>,
+    external_clock: Option<Box<dyn ExternalClock>>,
+    volume: f64,
+    muted: bool,
+    /// Kept for the inputs played next as it's changed.
+    equalizer: Equalizer,
+    controller: PlayerController,
+    commands: Receiver<PlayerCommand>,
+    /// Opened on the first input played and kept for the ones after it.
+    progress: Option<ProgressWriter>,
+}
+
+impl Player {
+    pub fn new(options: Options) -> Self {
+        let (sender, commands) = mpsc::channel();
+        let mut equalizer = Equalizer::new(options.equalizer_preset);
+        if let Some(gains) = options.equalizer_gains {
+            equalizer.set_gains(gains);
+        }
+
+        Player {
+            volume: options.volume.clamp(0_f64, 2_f64),
+            muted: false,
+            equalizer,
+            options,
+            video_sink: None,
+            audio_sink: None,
+            external_clock: None,
+            controller: PlayerController {
+                sender,
+                status: Arc::default(),
+                playlist: Arc::default(),
+                subscribers: Arc::default(),
+            },
+            commands,
+            progress: None,
+        }
+    }
+
+    pub fn controller(&self) -> PlayerController {
+        self.controller.clone()
+    }
+
+    /// Events from now on, see `PlayerController::events`.
+    pub fn events(&self) -> Receiver<PlayerEvent> {
+        self.controller.events()
+    }
+
+    /// The volume audio is played at, nothing while muted.
+    fn output_volume(&self) -> f32 {
+        if self.muted {
+            0_f32
+        } else {
+            self.volume as f32
+        }
+    }
+
+    /// Uses `sink` for video instead of the one picked by `--vo`, for every input played.
+    pub fn set_video_sink(&mut self, sink: Box<dyn VideoSink>) {
+        self.video_sink = Some(sink);
+    }
+
+    /// Uses `sink` for audio instead of the one picked by `--ao`, for every input played.
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+
+    /// Follows `clock` instead of the player's own, or the one picked by `--clock`. Play,
+    /// pause and seek commands are ignored then.
+    pub fn set_external_clock(&mut self, clock: Box<dyn ExternalClock>) {
+        self.external_clock = Some(clock);
+    }
+
+    /// Input to play next: one a controller asked to `load` while the last one played, or
+    /// else the first one it queued with `enqueue`.
+    pub fn take_next_input(&self) -> Option<String> {
+        let input = self.controller.playlist.lock().unwrap().pop_front();
+        if input.is_some() {
+            self.controller.playlist_changed();
+        }
+        input
+    }
+
+    pub fn play(&mut self, asset: PlaybackAsset) -> PlaybackOutcome {
+        // Extract asset metadata, the duration changing with the edition played
+        let mut metadata = asset.metadata();
+        let path = asset.path().to_string();
+        let opened = SystemTime::now();
+        *self.controller.status.lock().unwrap() = PlayerStatus {
+            path: Some(path.clone()),
+            state: PlaybackState::Opening,
+            duration: metadata.duration(),
+            volume: self.volume,
+            ..PlayerStatus::default()
+        };
+        self.controller
+            .emit(PlayerEvent::StateChanged(PlaybackState::Opening));
+
+        // Memory cap shared by every buffer below
+        let budget = Arc::new(MemoryBudget::new(
+            self.options.cache_mem.map(|mb| mb * 1024 * 1024),
+        ));
+
+        // Encoded packets, from the buffer thread to the decode threads
+        let (video_packets, video_packet_receiver) = mpsc::sync_channel(PACKET_QUEUE);
+        let (audio_packets, audio_packet_receiver) = mpsc::sync_channel(PACKET_QUEUE);
+
+        // Decoded frames, from the decode threads to the main loop
+        let (video_frames, video_frame_receiver) = mpsc::sync_channel(FRAME_QUEUE);
+        let (audio_frames, audio_frame_receiver) = mpsc::sync_channel(FRAME_QUEUE);
+        let mut video_queue = FrameQueue::new(
+            video_frame_receiver,
+            VIDEO_FRAMES_AHEAD.max(self.options.prebuffer_frames),
+            Arc::clone(&budget),
+            budget::video_frame_size,
+        );
+        let mut audio_queue = FrameQueue::new(
+            audio_frame_receiver,
+            AUDIO_FRAMES_AHEAD,
+            Arc::clone(&budget),
+            budget::audio_frame_size,
+        );
+
+        // Seeks for the buffer thread, and the latest one's serial, so decode threads can
+        // skip packets still queued from before it
+        let (seeks, seek_receiver) = mpsc::channel();
+        let latest_seek = Arc::new(AtomicUsize::new(0));
+        // Editions picked from the menu, played from the seek sent with them
+        let (edition_sender, edition_receiver) = mpsc::channel();
+        // Set once playback ends, so the threads below don't outlive it
+        let stopped = Arc::new(AtomicBool::new(false));
+        // An external clock says exactly where playback should be, so seeks can't stop at
+        // the keyframe before. One set by an embedder is handed back for the next input.
+        let custom_clock = self.external_clock.is_some();
+        let mut external_clock = self
+            .external_clock
+            .take()
+            .or_else(|| match self.options.clock {
+                ClockSource::System => Some(Box::new(SystemClock::new(
+                    self.options.clock_epoch,
+                    metadata.duration(),
+                )) as Box<dyn ExternalClock>),
+                ClockSource::Internal => None,
+            });
+        let hr_seek = self.options.hr_seek || external_clock.is_some();
+        let no_audio = self.options.no_audio;
+
+        // Volume from the file's tags, read before the asset moves to the buffer thread
+        let replay_gain = asset.replay_gain(self.options.replaygain);
+        let chapters = asset.chapters();
+        let mut editions = asset.editions();
+        let file_gain = replay_gain.unwrap_or(1_f64) as f32;
+
+        // Decoders, the video's once the sink says whether it shows hardware decoded frames
+        let mut audio_decoder = asset.audio_decoder(&self.options);
+
+        // Outputs; ones set by an embedder are handed back for the next input
+        let custom_video_sink = self.video_sink.is_some();
+        let custom_audio_sink = self.audio_sink.is_some();
+        let (mut video_sink, mut audio_sink) = self.create_sinks();
+        video_sink.configure(&metadata);
+        video_sink.set_aspect_mode(self.options.aspect);
+        if self.options.on_top {
+            video_sink.set_on_top(true);
+        }
+        if let Some(scale) = self.options.window_scale {
+            video_sink.set_window_scale(scale);
+        }
+        let bindings = KeyBindings::new(&self.options.bindings);
+        video_sink.set_key_bindings(bindings.clone());
+        let audio_spec = audio_sink.preferred_spec();
+        let mut audio_converter = AudioConverter::new(audio_spec);
+        if let Some(gain) = replay_gain {
+            info!("replaygain: {:+.2} dB", 20_f64 * gain.log10());
+        }
+        audio_converter.set_gain(file_gain * self.output_volume());
+
+        // Compressed audio skips the decoder when both the codec and the output allow it
+        let packer = if self.options.audio_passthrough && !no_audio {
+            match SpdifPacker::new(audio_decoder.id(), audio_decoder.rate()) {
+                Some(packer) if audio_sink.passthrough(packer.sample_rate()) => Some(packer),
+                Some(_) => {
+                    info!("audio output can't do passthrough, decoding instead");
+                    None
+                }
+                None => {
+                    info!(
+                        "{:?} can't be passed through, decoding instead",
+                        audio_decoder.id()
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let passthrough = packer.is_some();
+
+        // Second input, decoded alongside and composed in on presentation
+        let mut composition = if self.options.compare.is_some() {
+            Composition::Compare {
+                view: CompareView::Wipe,
+                split: 0.5,
+            }
+        } else {
+            Composition::PictureInPicture { swapped: false }
+        };
+        // A microphone or line input monitored in place of the input's own audio
+        let mut monitor = self
+            .options
+            .audio_input
+            .as_ref()
+            .filter(|_| !passthrough && !no_audio)
+            .and_then(|device| {
+                let device: CaptureDevice = device.parse().ok()?;
+                match AudioMonitor::open(&device, &self.options) {
+                    Ok(monitor) => Some(monitor),
+                    Err(e) => {
+                        eprintln!("failed to open audio input {}: {}", device, e);
+                        None
+                    }
+                }
+            });
+        let second_audio = self.options.pip_audio
+            && self.options.compare.is_none()
+            && !passthrough
+            && !no_audio
+            && monitor.is_none();
+        let mut second = self
+            .options
+            .compare
+            .as_ref()
+            .or_else(|| self.options.pip.as_ref())
+            .map(|path| SecondarySource::open(path, &self.options, second_audio));
+        let second_audio = second_audio && second.is_some();
+        // More inputs tiled with the main one, each decoded at its own pace
+        let mut mosaic = if self.options.mosaic.is_empty() {
+            None
+        } else {
+            Some(Mosaic::open(&self.options.mosaic, &self.options))
+        };
+        // Hardware decoded frames the sink shows straight from the GPU skip the copy to
+        // system memory, unless other inputs are composed onto them or the LEDs follow them
+        let hardware_formats =
+            if second.is_some() || mosaic.is_some() || self.options.ambilight.is_some() {
+                Vec::new()
+            } else {
+                video_sink.hardware_formats()
+            };
+        // Hardware decoding only pays off when the sink shows the surfaces as they are, each
+        // frame would be copied back to memory otherwise
+        let hwdec = match hwdec::surface_format(self.options.hwdec) {
+            Some(format) if hardware_formats.contains(&format) => self.options.hwdec,
+            Some(_) => {
+                info!("the video output can't show hardware decoded frames, decoding in software");
+                HwDecode::No
+            }
+            None => HwDecode::No,
+        };
+        // inputs without video get their audio drawn instead, from a decoder of their own
+        let video_decoder = if metadata.has_video() {
+            PlayerVideoDecoder::new(asset.hardware_video_decoder(&self.options, hwdec))
+        } else {
+            PlayerVideoDecoder::Visualizer(
+                PlayerAudioDecoder::new(asset.audio_decoder(&self.options)),
+                Visualizer::new(self.options.visualizer, metadata.audio_time_base()),
+            )
+        };
+
+        // Adaptive streams switch variants on the buffer thread, each variant's video with a
+        // decoder of its own
+        let variants = asset.variants();
+        let adaptive = self.options.hls_bitrate == HlsBitrate::Auto;
+        let variant_decoders: HashMap<usize, PlayerVideoDecoder> = variants
+            .iter()
+            .filter(|variant| {
+                adaptive && Some(variant.stream_index) != metadata.video_stream_index()
+            })
+            .map(|variant| {
+                let decoder = asset.variant_decoder(variant, &self.options, hwdec);
+                (variant.stream_index, PlayerVideoDecoder::new(decoder))
+            })
+            .collect();
+
+        // the second input's audio, the audio input's or a mosaic tile's
+        let mut second_converter = AudioConverter::new(audio_spec);
+        second_converter.set_gain(self.output_volume());
+        let mut compositor = Compositor::new();
+        // kept to compose again when the composition changes, or the second input catches
+        // up with a seek, while paused
+        let mut last_frame: Option<Video> = None;
+
+        // Watch party: lead other instances playing the same file, or follow one
+        let mut sync_master =
+            self.options
+                .sync_master
+                .as_ref()
+                .and_then(|address| match SyncMaster::bind(address) {
+                    Ok(master) => {
+                        info!("sync: waiting for clients on {}", address);
+                        Some(master)
+                    }
+                    Err(e) => {
+                        eprintln!("failed to listen for sync clients on {}: {}", address, e);
+                        None
+                    }
+                });
+        let mut sync_client = self.options.sync_join.as_ref().and_then(|address| {
+            match SyncClient::connect(address) {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    eprintln!("failed to join sync master {}: {}", address, e);
+                    None
+                }
+            }
+        });
+        let mut last_sync_seek: Option<Instant> = None;
+
+        // System media controls, e.g. macOS's Now Playing
+        #[cfg(feature = "now-playing")]
+        let mut now_playing = NowPlaying::new(self.controller(), &path, metadata.duration());
+
+        // Filters run on the decode thread, ahead of scheduling; the equalizer can be changed
+        // from the main loop while playing
+        let normalizer = match self.options.loudnorm {
+            Some(LoudnessNormalizer::Loudnorm) => Some(LOUDNORM_FILTER),
+            Some(LoudnessNormalizer::Dynaudnorm) => Some(DYNAUDNORM_FILTER),
+            None => None,
+        };
+        let equalizer = Arc::new(Mutex::new(self.equalizer.clone()));
+
+        // Motion interpolation goes up to the display's refresh rate unless told otherwise
+        let interpolate = if self.options.interpolate {
+            let fps = self
+                .options
+                .interpolate_fps
+                .or_else(|| video_sink.refresh_rate())
+                .unwrap_or(DEFAULT_INTERPOLATE_FPS);
+            info!("interpolating motion to {} fps", fps);
+            Some(fps)
+        } else {
+            None
+        };
+
+        // Video filters, switched from the main loop and applied on the decode thread
+        let video_filter_settings = Arc::new(Mutex::new(VideoFilterSettings {
+            user: self.options.video_filter.clone(),
+            deinterlace: self.options.deinterlace,
+            interlaced: false,
+            autocrop: self.options.autocrop,
+            crop: None,
+            stereo: self.options.stereo,
+            picture: PictureEqualizer::new(),
+            interpolate,
+            rotation: 0,
+            hflip: false,
+            vflip: false,
+        }));
+
+        // Remuxed elsewhere as they're played: restreamed for the whole input, recorded
+        // while toggled on
+        let remux_streams = asset.remux_streams();
+        let mut restream = self
+            .options
+            .restream
+            .as_ref()
+            .map(|url| Remux::start(url, remux_streams.clone()));
+        let recording = Arc::new(Mutex::new(self.options.record.as_ref().map(|output| {
+            info!("recording to {}", output.display());
+            Remux::start(&output.to_string_lossy(), remux_streams.clone())
+        })));
+
+        // Live streams are played from a ring recorded on disk to pause and seek back in
+        let timeshift = self
+            .options
+            .timeshift
+            .filter(|_| metadata.duration().is_none() && path.contains("://"))
+            .and_then(|length| {
+                Timeshift::new(length)
+                    .map_err(|e| eprintln!("failed to start the timeshift buffer: {}", e))
+                    .ok()
+            });
+        let timeshift_window = timeshift.as_ref().map(Timeshift::window);
+        let abr = metadata
+            .video_stream_index()
+            .filter(|_| adaptive && timeshift.is_none())
+            .and_then(|index| Abr::new(variants.clone(), index, self.options.hls_bitrate));
+        let playing_variant = match &abr {
+            Some(abr) => abr.playing(),
+            None => Arc::new(Mutex::new(
+                variants
+                    .iter()
+                    .find(|variant| Some(variant.stream_index) == metadata.video_stream_index())
+                    .copied(),
+            )),
+        };
+        let stream_title = Arc::new(Mutex::new(None));
+        let mut source = match timeshift {
+            Some(timeshift) => {
+                timeshift.record(asset, Arc::clone(&stopped));
+                PacketSource::Timeshift(timeshift)
+            }
+            None => PacketSource::from_asset(asset, abr, &stream_title, &self.options),
+        };
+
+        // Buffer packets
+        let buffer_thread = thread::spawn({
+            info!("starting buffer thread");
+            let budget = Arc::clone(&budget);
+            let stopped = Arc::clone(&stopped);
+            let recording = Arc::clone(&recording);
+            let stream_title = Arc::clone(&stream_title);
+            let options = self.options.clone();
+
+            move || {
+                let mut serial = 0;
+                let mut ended = false;
+                // scanning buffers one keyframe per seek
+                let mut keyframe_only = false;
+
+                // the decode threads stop once their channel disconnects
+                let end = |serial| {
+                    video_packets.send(Message::End(serial)).is_ok()
+                        && audio_packets.send(Message::End(serial)).is_ok()
+                };
+
+                // Buffer packets
+                while !stopped.load(Ordering::Relaxed) {
+                    // only the latest seek matters
+                    if let Some(seek) = seek_receiver.try_iter().last() {
+                        // another edition is played from the seek that maps the position
+                        // onto its timeline
+                        if let Some(editions) = edition_receiver.try_iter().last() {
+                            source = source.with_editions(editions, &stream_title, &options);
+                        }
+                        info!("seeking to {:.2}s", seek.position);
+                        source.seek(seek.position);
+
+                        let sent = video_packets.send(Message::Seek(seek)).is_ok()
+                            && audio_packets.send(Message::Seek(seek)).is_ok();
+                        if !sent {
+                            return;
+                        }
+                        for remux in restream
+                            .iter_mut()
+                            .chain(recording.lock().unwrap().as_mut())
+                        {
+                            remux.discontinuity();
+                        }
+                        serial = seek.serial;
+                        ended = false;
+                        keyframe_only = seek.keyframe_only;
+                    }
+
+                    // the input, or the keyframe when scanning, is all sent: wait for a seek
+                    if ended {
+                        thread::sleep(Duration::from_millis(5));
+                        continue;
+                    }
+
+                    // Backpressure: wait for the pipeline to drain below the memory budget
+                    if budget.is_full() {
+                        thread::sleep(Duration::from_millis(5));
+                        continue;
+                    }
+
+                    let sent = match source.next() {
+                        Next::Packet(index, packet) => match index {
+                            idx if keyframe_only
+                                && (Some(idx) == metadata.video_stream_index()
+                                    || source.is_variant(idx))
+                                && !packet.is_key() =>
+                            {
+                                true
+                            }
+                            idx if Some(idx) == metadata.video_stream_index()
+                                || source.is_variant(idx) =>
+                            {
+                                // other variants go out as the stream outputs were set up with
+                                let output_index = metadata.video_stream_index().unwrap_or(idx);
+                                for remux in restream
+                                    .iter_mut()
+                                    .chain(recording.lock().unwrap().as_mut())
+                                {
+                                    remux.write(output_index, &packet);
+                                }
+                                budget.add_packet(packet.size());
+                                let sent =
+                                    video_packets.send(Message::Item(serial, packet)).is_ok();
+
+                                // ending the streams here drains the keyframe out of the
+                                // decoder
+                                if keyframe_only {
+                                    ended = true;
+                                    sent && end(serial)
+                                } else {
+                                    sent
+                                }
+                            }
+                            idx if keyframe_only && Some(idx) == metadata.audio_stream_index() => {
+                                true
+                            }
+                            idx if no_audio && Some(idx) == metadata.audio_stream_index() => {
+                                // not played here, but still restreamed and recorded
+                                for remux in restream
+                                    .iter_mut()
+                                    .chain(recording.lock().unwrap().as_mut())
+                                {
+                                    remux.write(idx, &packet);
+                                }
+                                true
+                            }
+                            idx if Some(idx) == metadata.audio_stream_index() => {
+                                for remux in restream
+                                    .iter_mut()
+                                    .chain(recording.lock().unwrap().as_mut())
+                                {
+                                    remux.write(idx, &packet);
+                                }
+                                // without video, the video decode thread draws the audio
+                                let visualized = metadata.has_video() || {
+                                    budget.add_packet(packet.size());
+                                    let packet = packet.clone();
+                                    video_packets.send(Message::Item(serial, packet)).is_ok()
+                                };
+                                budget.add_packet(packet.size());
+                                visualized
+                                    && audio_packets.send(Message::Item(serial, packet)).is_ok()
+                            }
+                            // subtitles and data the demuxer still reads
+                            _ => true,
+                        },
+                        // caught up with the live edge of a time-shifted stream
+                        Next::Waiting => {
+                            thread::sleep(Duration::from_millis(5));
+                            true
+                        }
+                        Next::End => {
+                            ended = true;
+                            end(serial)
+                        }
+                    };
+                    if !sent {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let decode_video_thread = thread::spawn({
+            info!("starting decode_video_thread");
+            let mut decoder = video_decoder;
+            let mut variant_decoders = variant_decoders;
+            let mut video_filter = VideoFilter::new("", metadata.video_time_base());
+            let video_filter_settings = Arc::clone(&video_filter_settings);
+            let mut crop_detector = CropDetector::new();
+            let budget = Arc::clone(&budget);
+            let latest_seek = Arc::clone(&latest_seek);
+            let stopped = Arc::clone(&stopped);
+            // frames without a duration of their own last this long, in the time base
+            let frame_ticks = metadata.frame_rate().map_or(1, |fps| {
+                (1_f64 / (fps * metadata.video_time_base())).round() as i64
+            });
+
+            move || {
+                let mut serial = 0;
+                let mut discard_before = None;
+                let mut timestamps = FrameTimestamps::default();
+                let mut decoding = metadata.video_stream_index();
+
+                // the channel disconnects once the buffer thread stops
+                while let Ok(message) = video_packet_receiver.recv() {
+                    let (decoded, draining) = match message {
+                        // A seek happened, drop everything decoded for the old position
+                        Message::Seek(seek) => {
+                            serial = seek.serial;
+                            decoder.flush();
+                            video_filter.reset();
+                            timestamps.reset();
+                            discard_before = if hr_seek { seek.target() } else { None };
+                            continue;
+                        }
+                        Message::Item(sent, packet) => {
+                            budget.remove_packet(packet.size());
+                            // queued before a seek the buffer thread has yet to get to
+                            if sent != latest_seek.load(Ordering::Relaxed) {
+                                continue;
+                            }
+
+                            // let presentation catch up before decoding more
+                            while budget.frames_full() {
+                                if stopped.load(Ordering::Relaxed) {
+                                    return;
+                                }
+                                thread::sleep(Duration::from_millis(5));
+                            }
+                            // a switch to another variant gets out what the old decoder
+                            // held back first
+                            let mut decoded = Vec::new();
+                            let stream = packet.stream();
+                            if let Some(next) = variant_decoders.remove(&stream) {
+                                decoded = decoder.drain();
+                                decoder.flush();
+                                let previous = mem::replace(&mut decoder, next);
+                                variant_decoders.extend(decoding.map(|index| (index, previous)));
+                                decoding = Some(stream);
+                            }
+                            decoded.extend(decoder.decode_video_packet(packet));
+                            (decoded, false)
+                        }
+                        // the input ended: get out what the decoder and filters held back
+                        Message::End(sent) if sent == serial => (decoder.drain(), true),
+                        Message::End(_) => continue,
+                    };
+
+                    let mut frames = Vec::new();
+                    for mut frame in decoded {
+                        let duration = Some(frame.packet().duration)
+                            .filter(|duration| *duration > 0)
+                            .unwrap_or(frame_ticks);
+                        timestamps.stamp(&mut frame, duration);
+
+                        // Exact seek: skip frames between the keyframe and the target
+                        if let Some(target) = discard_before {
+                            match frame.pts() {
+                                Some(pts) if pts as f64 * metadata.video_time_base() >= target => {
+                                    discard_before = None;
+                                }
+                                _ => continue,
+                            }
+                        }
+
+                        let mut downloaded = false;
+                        let description = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            if frame.is_interlaced() && !settings.interlaced {
+                                settings.interlaced = true;
+                                if settings.deinterlace == Deinterlace::Auto {
+                                    info!("interlaced video detected, deinterlacing");
+                                }
+                            }
+                            // a surface the sink can show stays on the GPU while there's
+                            // nothing to filter or crop
+                            if hwdec::is_hardware(&frame) {
+                                let unfiltered = !settings.autocrop
+                                    && settings
+                                        .description(frame.width(), frame.height())
+                                        .is_empty();
+                                if unfiltered && hardware_formats.contains(&frame.format()) {
+                                    frames.push(frame);
+                                    continue;
+                                }
+                                frame = match hwdec::download(&frame) {
+                                    Ok(frame) => frame,
+                                    Err(e) => {
+                                        eprintln!("failed to download a hardware frame: {}", e);
+                                        continue;
+                                    }
+                                };
+                                downloaded = true;
+                            }
+                            if settings.autocrop {
+                                crop_detector.sample(&frame);
+                                settings.crop = crop_detector.crop(frame.width(), frame.height());
+                            }
+                            settings.description(frame.width(), frame.height())
+                        };
+                        // downloaded frames are NV12 or the like, which the graph converts
+                        // even when there's nothing else to do
+                        if downloaded && description.is_empty() {
+                            video_filter.set_description("null");
+                        } else {
+                            video_filter.set_description(&description);
+                        }
+                        frames.extend(video_filter.filter(frame));
+                    }
+                    if draining {
+                        frames.extend(video_filter.flush());
+                    }
+
+                    for frame in frames {
+                        budget.add_frame(budget::video_frame_size(&frame));
+                        if video_frames.send(Message::Item(serial, frame)).is_err() {
+                            return;
+                        }
+                    }
+                    // only after the last frames, so the main loop never sees the stream
+                    // drained before it has them
+                    if draining && video_frames.send(Message::End(serial)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let decode_audio_thread = thread::spawn({
+            info!("starting decode_audio_thread");
+            let mut decoder = PlayerAudioDecoder::new(audio_decoder);
+            let mut packer = packer;
+            let mut audio_filter = AudioFilter::new("", metadata.audio_time_base());
+            let equalizer = Arc::clone(&equalizer);
+            let user_filter = self.options.audio_filter.clone();
+            let budget = Arc::clone(&budget);
+            let latest_seek = Arc::clone(&latest_seek);
+
+            move || {
+                let mut serial = 0;
+                let mut discard_before = None;
+                let mut timestamps = FrameTimestamps::default();
+
+                // the channel disconnects once the buffer thread stops
+                while let Ok(message) = audio_packet_receiver.recv() {
+                    let (decoded, draining) = match message {
+                        // A seek happened, drop everything decoded for the old position
+                        Message::Seek(seek) => {
+                            serial = seek.serial;
+                            decoder.flush();
+                            if let Some(packer) = &mut packer {
+                                packer.reset();
+                            }
+                            audio_filter.reset();
+                            timestamps.reset();
+                            discard_before = if hr_seek { seek.target() } else { None };
+                            continue;
+                        }
+                        Message::Item(sent, packet) => {
+                            budget.remove_packet(packet.size());
+                            // queued before a seek the buffer thread has yet to get to
+                            if sent != latest_seek.load(Ordering::Relaxed) {
+                                continue;
+                            }
+
+                            let decoded = match &mut packer {
+                                Some(packer) => match packer.pack(&packet) {
+                                    Some(frame) => vec![frame],
+                                    None => continue,
+                                },
+                                None => decoder.decode_audio_packet(packet),
+                            };
+                            (decoded, false)
+                        }
+                        Message::End(sent) if sent == serial => {
+                            // passthrough bursts are packed whole, nothing's held back then
+                            let decoded = if packer.is_some() {
+                                Vec::new()
+                            } else {
+                                decoder.drain()
+                            };
+                            (decoded, true)
+                        }
+                        Message::End(_) => continue,
+                    };
+
+                    let mut frames = Vec::new();
+                    for mut frame in decoded {
+                        let duration = frame.samples() as f64
+                            / frame.rate().max(1) as f64
+                            / metadata.audio_time_base();
+                        timestamps.stamp(&mut frame, duration.round() as i64);
+
+                        // Exact seek: skip audio before the target
+                        if let Some(target) = discard_before {
+                            match frame.pts() {
+                                Some(pts) if pts as f64 * metadata.audio_time_base() >= target => {
+                                    discard_before = None;
+                                }
+                                _ => continue,
+                            }
+                        }
+
+                        // bursts of compressed audio can't be filtered
+                        if packer.is_some() {
+                            frames.push(frame);
+                        } else {
+                            let description = audio_filter_description(
+                                user_filter.as_deref(),
+                                &equalizer.lock().unwrap(),
+                                normalizer,
+                            );
+                            audio_filter.set_description(&description);
+                            frames.extend(audio_filter.filter(frame));
+                        }
+                    }
+                    if draining && packer.is_none() {
+                        frames.extend(audio_filter.flush());
+                    }
+
+                    for frame in frames {
+                        budget.add_frame(budget::audio_frame_size(&frame));
+                        if audio_frames.send(Message::Item(serial, frame)).is_err() {
+                            return;
+                        }
+                    }
+                    if draining && audio_frames.send(Message::End(serial)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        // Seeks are numbered so frames decoded before a seek are never presented
+        let mut serial = 0;
+
+        // opening anchors the clock on the first frame, wherever that is
+        if let Some(start) = self.options.start {
+            serial += 1;
+            self.request_seek(&seeks, &latest_seek, serial, start, false);
+            if let Some(second) = &mut second {
+                second.seek(start);
+            }
+        }
+
+        // Startup and underrun handling: the clock is held until enough audio and video is
+        // decoded, so playback doesn't begin with a burst of late frames
+        let prebuffer_frames = self.options.prebuffer_frames.max(1);
+        // inputs without audio, e.g. cameras, have none to wait for
+        let prebuffer_audio = if metadata.has_audio() {
+            self.options.prebuffer_audio_ms as f64 / 1000_f64
+        } else {
+            0_f64
+        };
+        // Playback time, with the state it's in and what the transport asked for
+        let mut playback = Playback::new(audio_sink);
+
+        let low_latency = self.options.low_latency;
+        let max_delay = self
+            .options
+            .monitor_latency
+            .unwrap_or(LOW_LATENCY_MAX_DELAY);
+
+        // Audio is scheduled this many seconds after (or before, when negative) its timestamp
+        let mut audio_delay = self.options.audio_delay;
+        let mut aspect_mode = self.options.aspect;
+        let mut on_top = self.options.on_top;
+
+        // With --keep-open the last frame stays up at the end, as long as there's a window
+        // to wait in
+        let keep_open = match self.options.video_output {
+            VideoOutput::Terminal | VideoOutput::Tct | VideoOutput::Null => KeepOpen::No,
+            _ => self.options.keep_open,
+        };
+
+        // Where the audio written so far ends, on the clock
+        let mut audio_end = 0_f64;
+
+        // Screensaver and sleep are held off as `--stop-screensaver` says, by default only
+        // while video plays. SDL holds them off from the start, so this begins as set to be
+        // released while buffering.
+        let mut inhibiting_idle = true;
+
+        // The cursor hides over playing video until the mouse moves again
+        let mut cursor_shown = true;
+        let mut last_mouse_activity = Instant::now();
+
+        // LEDs around the screen following the picture's edges
+        let mut ambilight = self.options.ambilight.as_ref().and_then(|target| {
+            Ambilight::open(target, self.options.ambilight_leds)
+                .map_err(|e| eprintln!("failed to open ambilight output: {}", e))
+                .ok()
+        });
+
+        // Native plugins, hooked into the main loop like scripts
+        #[cfg(feature = "plugins")]
+        let mut plugins = self
+            .options
+            .plugins_dir
+            .clone()
+            .or_else(|| config_dir().map(|dir| dir.join("plugins")))
+            .map(|dir| Plugins::load_dir(&dir))
+            .unwrap_or_default();
+        #[cfg(feature = "plugins")]
+        plugins.on_load(&path);
+        #[cfg(feature = "plugins")]
+        let mut last_plugin_tick = Instant::now();
+
+        // User scripts, hooked into the main loop
+        #[cfg(feature = "scripting")]
+        let mut scripts: Vec<Script> = self
+            .options
+            .scripts
+            .iter()
+            .filter_map(|script| match Script::load(script) {
+                Ok(script) => Some(script),
+                Err(e) => {
+                    eprintln!("failed to load script {}: {}", script.display(), e);
+                    None
+                }
+            })
+            .collect();
+        #[cfg(feature = "scripting")]
+        for script in &mut scripts {
+            script.set_state(0_f64, metadata.duration(), playback.is_paused());
+            script.on_load(&path);
+        }
+        #[cfg(feature = "scripting")]
+        let mut last_script_tick = Instant::now();
+
+        // What event subscribers were told last
+        let mut reported_state = PlaybackState::Opening;
+        let mut reported_position = None;
+        let mut reported_seeks = serial;
+        let mut last_position_event = Instant::now();
+        let mut reported_buffering = None;
+
+        // Progress records for scripts
+        let mut progress = self.progress.take().or_else(|| {
+            let format = self.options.progress?;
+            ProgressWriter::open(format, self.options.progress_fd)
+                .map_err(|e| eprintln!("failed to open progress output: {}", e))
+                .ok()
+        });
+        let mut dropped_frames = 0;
+        let mut presented_frames = 0;
+
+        // Drawing
+        let mut osd = Osd::new();
+        osd.chapter_menu_mut().set_entries(
+            &editions.as_ref().map_or_else(Vec::new, Editions::names),
+            editions.as_ref().map(Editions::selected),
+            &chapters,
+        );
+        // monitoring a microphone or line input, its level is what there is to see
+        let audio_capture = !metadata.has_video() && path.parse::<CaptureDevice>().is_ok();
+        if monitor.is_some() || audio_capture {
+            osd.level_meter_mut().set_visible(true);
+        }
+        let mut clicks = ClickTracker::default();
+        let mut needs_redraw = false;
+
+        // Casting, with playback here paused while it lasts
+        #[cfg(feature = "cast")]
+        let mut cast: Option<CastSession> = None;
+        #[cfg(feature = "cast")]
+        let mut reported_cast: Option<CastState> = None;
+        let mut last_redraw = Instant::now();
+        let scheduler = FrameScheduler::new(video_sink.refresh_rate());
+
+        // Range marked for export
+        let mut range_start = None;
+        let mut range_end = None;
+
+        'running: loop {
+            video_queue.receive(serial);
+            audio_queue.receive(serial);
+
+            // maybe render video frame
+            {
+                let b = &mut video_queue;
+                let seeking = playback.state() == PlaybackState::Seeking;
+                if low_latency && !seeking {
+                    dropped_frames +=
+                        self.catch_up_to_live_edge(b, &metadata, playback.clock_mut(), max_delay);
+                }
+
+                if !seeking {
+                    // frames whose time on screen is already over are dropped rather than
+                    // flashed up one after the other, so late variable frame rate content
+                    // catches up without stuttering
+                    while b.frames.len() > 1 {
+                        let time_base = metadata.video_time_base();
+                        let ended = b.frames[0].pts().and_then(|pts| {
+                            frame_duration(&b.frames[0], b.frames.get(1), time_base)
+                                .map(|duration| pts as f64 * time_base + duration)
+                        });
+                        if ended.map_or(false, |ended| ended <= playback.position()) {
+                            b.pop();
+                            dropped_frames += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(frame) = b.frames.front() {
+                    // Anchor the clock on the first frame after a seek, and show that frame
+                    // right away so seeking while paused updates the picture
+                    let mut anchored = false;
+                    if seeking {
+                        if let Some(pts) = frame.pts() {
+                            playback.anchor(pts as f64 * metadata.video_time_base());
+                            anchored = true;
+                        }
+                    }
+
+                    if anchored
+                        || self.should_render_video_frame(frame, &metadata, playback.clock())
+                    {
+                        let frame = b.pop().unwrap();
+                        let pts = frame.pts().unwrap_or(0) as f64 * metadata.video_time_base();
+                        let composed = if let Some(mosaic) = &mut mosaic {
+                            mosaic.compose(&mut compositor, &frame)
+                        } else {
+                            match second.as_mut().and_then(|second| second.video_at(pts)) {
+                                Some(second) => {
+                                    compose(&mut compositor, composition, &frame, second)
+                                }
+                                None => None,
+                            }
+                        };
+                        let shown = composed.as_ref().unwrap_or(&frame);
+                        video_sink.submit(shown, pts);
+                        presented_frames += 1;
+                        #[cfg(feature = "plugins")]
+                        plugins.on_frame(shown, pts);
+                        if let Some(ambilight) = &mut ambilight {
+                            ambilight.on_frame(shown);
+                        }
+                        if second.is_some() || mosaic.is_some() {
+                            last_frame = Some(frame);
+                        }
+                        needs_redraw = true;
+                    }
+                }
+            }
+
+            // maybe render audio frame
+            {
+                let b = &mut audio_queue;
+                let seeking = playback.state() == PlaybackState::Seeking;
+                if low_latency && !seeking {
+                    // late audio is dropped rather than queued behind the live edge
+                    while b.frames.front().map_or(false, |frame| {
+                        frame.pts().map_or(false, |pts| {
+                            (pts as f64 * metadata.audio_time_base() + audio_delay)
+                                < playback.position() - max_delay
+                        })
+                    }) {
+                        b.pop();
+                    }
+                }
+
+                let tile_audio = mosaic.as_ref().map_or(false, |mosaic| mosaic.focus() > 0);
+                if seeking {
+                    // wait until the video side has anchored the clock
+                } else if let Some(frame) = b.frames.front() {
+                    let offset = audio_delay - AUDIO_BUFFER;
+                    if playback.audio_sink().latency() < AUDIO_BUFFER
+                        && self.should_render_audio_frame(
+                            frame,
+                            &metadata,
+                            playback.clock(),
+                            offset,
+                        )
+                    {
+                        let frame = b.pop().unwrap();
+                        if let (Some(pts), true) = (frame.pts(), frame.rate() > 0) {
+                            audio_end = pts as f64 * metadata.audio_time_base()
+                                + audio_delay
+                                + frame.samples() as f64 / frame.rate() as f64;
+                        }
+                        if passthrough {
+                            playback
+                                .audio_sink()
+                                .write_bitstream(&spdif::burst_samples(&frame));
+                        } else if second_audio || monitor.is_some() || tile_audio {
+                            // the second input's audio, the audio input or the focused
+                            // tile's plays instead
+                        } else if let Some(samples) = audio_converter.convert(&frame) {
+                            osd.spectrogram_mut().push(&samples, audio_spec);
+                            osd.level_meter_mut().push(&samples, audio_spec);
+                            playback.audio_sink().write(&samples);
+                        }
+                    }
+                }
+            }
+
+            let seeking = playback.state() == PlaybackState::Seeking;
+            let audio_queued = playback.audio_sink().latency() >= AUDIO_BUFFER;
+            if let (true, Some(second)) = (second_audio && !seeking && !audio_queued, &mut second) {
+                for frame in second.audio_until(playback.position() + AUDIO_BUFFER - audio_delay) {
+                    if let Some(samples) = second_converter.convert(&frame) {
+                        osd.spectrogram_mut().push(&samples, audio_spec);
+                        osd.level_meter_mut().push(&samples, audio_spec);
+                        playback.audio_sink().write(&samples);
+                    }
+                }
+            }
+            let tile_audio = !passthrough && monitor.is_none() && !seeking && !audio_queued;
+            if let (true, Some(mosaic)) = (tile_audio, &mut mosaic) {
+                for frame in mosaic.take_audio() {
+                    if let Some(samples) = second_converter.convert(&frame) {
+                        osd.spectrogram_mut().push(&samples, audio_spec);
+                        osd.level_meter_mut().push(&samples, audio_spec);
+                        playback.audio_sink().write(&samples);
+                    }
+                }
+            }
+            if let (true, Some(monitor)) = (!seeking && !audio_queued, &mut monitor) {
+                // played as it's captured, however far the clock is
+                for frame in monitor.take(max_delay) {
+                    if let Some(samples) = second_converter.convert(&frame) {
+                        osd.spectrogram_mut().push(&samples, audio_spec);
+                        osd.level_meter_mut().push(&samples, audio_spec);
+                        playback.audio_sink().write(&samples);
+                    }
+                }
+            }
+
+            // handle events, key presses and embedder commands both end up as commands
+            let mut commands: Vec<PlayerCommand> = self.commands.try_iter().collect();
+            video_sink.poll_events(&mut commands);
+            // the last frame has to be drawn again while paused, nothing else would
+            if video_sink.take_exposed() {
+                needs_redraw = true;
+            }
+            for dropped in video_sink.take_dropped_files() {
+                if dropped.append {
+                    self.controller.enqueue(&dropped.path);
+                    osd.show_message(&format!("QUEUED {}", dropped.path), Duration::from_secs(2));
+                } else {
+                    self.controller.load(&dropped.path);
+                }
+            }
+
+            // transport follows the sync master or external clock, not the player's own keys
+            if sync_client.is_some() || external_clock.is_some() {
+                commands.retain(|command| !is_transport(command));
+            }
+
+            #[cfg(feature = "cast")]
+            if let Some(session) = &cast {
+                let state = session.status().state;
+                if reported_cast.as_ref() != Some(&state) {
+                    match &state {
+                        CastState::Casting(device) => osd.show_message(
+                            &format!("CASTING TO {}", device.to_uppercase()),
+                            Duration::from_secs(2),
+                        ),
+                        CastState::Failed(_) => {
+                            osd.show_message("CASTING FAILED", Duration::from_secs(2))
+                        }
+                        _ => {}
+                    }
+                    needs_redraw = true;
+                    reported_cast = Some(state.clone());
+                }
+                // the session ended without being stopped here, e.g. the device went away
+                if matches!(state, CastState::Stopped | CastState::Failed(_)) {
+                    self.hand_back_cast(session, &mut playback);
+                    cast = None;
+                    reported_cast = None;
+                }
+            }
+
+            if let Some(client) = &mut sync_client {
+                if let Some(state) = client.poll() {
+                    if state.is_paused() != playback.is_paused() {
+                        commands.push(if state.is_paused() {
+                            PlayerCommand::Pause
+                        } else {
+                            PlayerCommand::Play
+                        });
+                    }
+
+                    let drift = (playback.position() - state.position()).abs();
+                    let settled =
+                        last_sync_seek.map_or(true, |seek| seek.elapsed() >= SYNC_SEEK_INTERVAL);
+                    if is_settled(playback.state()) && settled && drift > SYNC_MAX_DRIFT {
+                        commands.push(PlayerCommand::Seek(state.position()));
+                        last_sync_seek = Some(Instant::now());
+                    }
+                }
+            }
+
+            if let Some(external) = &mut external_clock {
+                match external.position() {
+                    Some(position) => {
+                        if playback.is_paused() {
+                            commands.push(PlayerCommand::Play);
+                        }
+                        if is_settled(playback.state()) {
+                            if (playback.position() - position).abs() > EXTERNAL_CLOCK_MAX_DRIFT {
+                                commands.push(PlayerCommand::Seek(position));
+                            } else {
+                                playback.clock_mut().set_position(position);
+                            }
+                        }
+                    }
+                    None if !playback.is_paused() => commands.push(PlayerCommand::Pause),
+                    None => {}
+                }
+            }
+
+            if commands
+                .iter()
+                .any(|command| matches!(command, PlayerCommand::Mouse { .. }))
+            {
+                last_mouse_activity = Instant::now();
+            }
+
+            // the control bar takes mouse events over it, and everything while dragging on it
+            let mut from_controls = Vec::new();
+            let mut captured = false;
+            commands.retain(|command| match *command {
+                PlayerCommand::Mouse { action, x, y } => {
+                    let taken = osd.controls_mut().handle(action, x, y, &mut from_controls);
+                    captured |= taken;
+                    !taken
+                }
+                _ => true,
+            });
+            if captured {
+                commands.retain(|command| !matches!(command, PlayerCommand::Wipe(_)));
+            }
+            commands.extend(from_controls);
+
+            // clicks elsewhere pause and double-clicks go fullscreen, except where clicking
+            // moves the compare wipe
+            if !matches!(composition, Composition::Compare { .. }) {
+                let mut from_clicks = Vec::new();
+                for command in &commands {
+                    if let PlayerCommand::Mouse { action, x, y } = *command {
+                        clicks.handle(action, x, y, &mut from_clicks);
+                    }
+                }
+                clicks.update(&mut from_clicks);
+                commands.extend(from_clicks);
+            }
+
+            let variant = *playing_variant.lock().unwrap();
+            let label = variant.map(|variant| variant_label(&variant, self.options.hls_bitrate));
+            if osd.set_indicator(label) {
+                needs_redraw = true;
+            }
+
+            // shown as the buffer thread reads it, a little ahead of the audio playing
+            let title = stream_title.lock().unwrap().take();
+            if let Some(title) = title {
+                info!("now playing {}", title);
+                osd.show_message(
+                    &format!("NOW PLAYING {}", title.to_uppercase()),
+                    Duration::from_secs(4),
+                );
+                needs_redraw = true;
+                video_sink.set_title(&format!("{} - {}", title, WINDOW_TITLE));
+                #[cfg(feature = "now-playing")]
+                if let Some(now_playing) = &mut now_playing {
+                    now_playing.set_title(&title);
+                }
+                self.controller.emit(PlayerEvent::TrackChanged(title));
+            }
+
+            #[cfg(feature = "plugins")]
+            if !plugins.is_empty() {
+                // keys a plugin bound don't go any further
+                commands.retain(|command| match command {
+                    PlayerCommand::Key(key) => !plugins.on_key(*key),
+                    _ => true,
+                });
+                if last_plugin_tick.elapsed() >= PLUGIN_TICK_INTERVAL {
+                    last_plugin_tick = Instant::now();
+                    plugins.on_tick(playback.position(), playback.is_paused());
+                    if osd.set_overlay(plugins.osd_lines()) {
+                        needs_redraw = true;
+                    }
+                }
+                commands.extend(plugins.take_commands());
+            }
+
+            #[cfg(feature = "scripting")]
+            if !scripts.is_empty() {
+                let keys: Vec<char> = commands
+                    .iter()
+                    .filter_map(|command| match command {
+                        PlayerCommand::Key(key) => Some(*key),
+                        _ => None,
+                    })
+                    .collect();
+                let tick = last_script_tick.elapsed() >= SCRIPT_TICK_INTERVAL;
+                if tick {
+                    last_script_tick = Instant::now();
+                }
+
+                for script in &mut scripts {
+                    script.set_state(
+                        playback.position(),
+                        metadata.duration(),
+                        playback.is_paused(),
+                    );
+                    for key in &keys {
+                        script.on_key(*key);
+                    }
+                    if tick {
+                        script.on_tick(playback.position());
+                    }
+
+                    let actions = script.take_actions();
+                    commands.extend(actions.commands);
+                    for (text, duration) in actions.messages {
+                        osd.show_message(&text, duration);
+                    }
+                }
+            }
+
+            // while casting, transport drives the device and playback here stays paused
+            #[cfg(feature = "cast")]
+            if let Some(session) = &cast {
+                commands.retain(|command| {
+                    // devices can't scan
+                    if matches!(
+                        command,
+                        PlayerCommand::Scan(_)
+                            | PlayerCommand::ScanForward
+                            | PlayerCommand::ScanBackward
+                    ) {
+                        return false;
+                    }
+                    match playback.cast_command(command) {
+                        Some(forwarded) => {
+                            session.send(forwarded);
+                            false
+                        }
+                        None => true,
+                    }
+                });
+            }
+
+            // play, pause and scanning go through the playback state, leaving the seeks they
+            // lead to and everything else to carry out here
+            let mut transported = Vec::with_capacity(commands.len());
+            for command in commands {
+                playback.handle(command, &mut osd, &mut transported);
+            }
+            playback.step_scan(metadata.duration(), &mut osd, &mut transported);
+
+            let mut recompose = false;
+            for command in transported {
+                // resolve relative commands against the current state first
+                let command = match command {
+                    PlayerCommand::SeekBy(offset) => {
+                        PlayerCommand::Seek(playback.position() + offset)
+                    }
+                    PlayerCommand::GoLive => match &timeshift_window {
+                        Some(window) => {
+                            osd.show_message("LIVE", Duration::from_secs(1));
+                            PlayerCommand::Seek(window.live_edge())
+                        }
+                        None => continue,
+                    },
+                    PlayerCommand::AudioDelayBy(offset) => {
+                        PlayerCommand::AudioDelay(audio_delay + offset)
+                    }
+                    PlayerCommand::VolumeBy(offset) => PlayerCommand::Volume(self.volume + offset),
+                    PlayerCommand::CycleAspectMode => PlayerCommand::AspectMode(aspect_mode.next()),
+                    // both inputs keep decoding, so flipping between them is instant
+                    PlayerCommand::ToggleCompareSource => match composition {
+                        Composition::Compare {
+                            view: CompareView::First,
+                            ..
+                        } => PlayerCommand::CompareView(CompareView::Second),
+                        _ => PlayerCommand::CompareView(CompareView::First),
+                    },
+                    PlayerCommand::PictureBy(property, offset) => {
+                        let value = video_filter_settings.lock().unwrap().picture.get(property);
+                        PlayerCommand::Picture(property, value + offset)
+                    }
+                    PlayerCommand::MenuSelect => {
+                        let choice = osd.chapter_menu_mut().choice();
+                        osd.chapter_menu_mut().hide();
+                        video_sink.set_key_bindings(bindings.clone());
+                        needs_redraw = true;
+                        match choice {
+                            Some(MenuChoice::Chapter(position)) => PlayerCommand::Seek(position),
+                            // another edition may have a timeline of its own, which the buffer
+                            // thread plays from where the position falls on it
+                            Some(MenuChoice::Edition(index)) => {
+                                let editions = match &mut editions {
+                                    Some(editions) if index != editions.selected() => editions,
+                                    _ => continue,
+                                };
+                                let position = match editions.switch(index, playback.position()) {
+                                    Some(position) => position,
+                                    None => continue,
+                                };
+                                metadata = metadata.with_duration(editions.duration());
+                                osd.chapter_menu_mut().set_entries(
+                                    &editions.names(),
+                                    Some(index),
+                                    &editions.chapters(),
+                                );
+                                if edition_sender.send(editions.clone()).is_err() {
+                                    continue;
+                                }
+                                osd.show_message(
+                                    &format!("EDITION {}", index + 1),
+                                    Duration::from_secs(2),
+                                );
+                                PlayerCommand::Seek(position)
+                            }
+                            _ => continue,
+                        }
+                    }
+                    command => command,
+                };
+
+                match command {
+                    PlayerCommand::Stop => break 'running,
+                    PlayerCommand::Seek(position) => {
+                        // a time-shifted stream only goes back as far as the ring does
+                        let position = match &timeshift_window {
+                            Some(window) => window.clamp(position),
+                            None => position.max(0_f64),
+                        };
+                        audio_end = 0_f64;
+                        serial += 1;
+                        playback.seek(position);
+                        let scan_speed = playback.scan_speed();
+                        let scanning = scan_speed.is_some();
+                        self.request_seek(&seeks, &latest_seek, serial, position, scanning);
+                        video_queue.clear();
+                        audio_queue.clear();
+                        if let Some(second) = &mut second {
+                            second.seek(position);
+                        }
+                        #[cfg(feature = "now-playing")]
+                        if let Some(now_playing) = &mut now_playing {
+                            now_playing.invalidate();
+                        }
+                        let message = match scan_speed {
+                            Some(speed) => format!("SCAN {:+.0}X {}", speed, format_time(position)),
+                            None => format!("SEEK {}", format_time(position)),
+                        };
+                        osd.show_message(&message, Duration::from_secs(1));
+                    }
+                    PlayerCommand::Volume(volume) => {
+                        self.volume = volume.clamp(0_f64, 2_f64);
+                        self.muted = false;
+                        audio_converter.set_gain(file_gain * self.output_volume());
+                        second_converter.set_gain(self.output_volume());
+                        osd.show_message(
+                            &format!("VOLUME {:.0}%", self.volume * 100_f64),
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::ToggleMute => {
+                        self.muted = !self.muted;
+                        audio_converter.set_gain(file_gain * self.output_volume());
+                        second_converter.set_gain(self.output_volume());
+                        let message = if self.muted { "MUTE" } else { "UNMUTE" };
+                        osd.show_message(message, Duration::from_secs(1));
+                    }
+                    PlayerCommand::ToggleControls => osd.controls_mut().toggle_pinned(),
+                    PlayerCommand::ToggleFullscreen => video_sink.toggle_fullscreen(),
+                    PlayerCommand::ToggleOnTop => {
+                        on_top = !on_top;
+                        video_sink.set_on_top(on_top);
+                        let message = if on_top { "ON TOP" } else { "NOT ON TOP" };
+                        osd.show_message(message, Duration::from_secs(1));
+                    }
+                    PlayerCommand::WindowScale(scale) => {
+                        video_sink.set_window_scale(scale);
+                        osd.show_message(&format!("WINDOW {}X", scale), Duration::from_secs(1));
+                    }
+                    PlayerCommand::AudioDelay(delay) => {
+                        audio_delay = delay;
+                        osd.show_message(
+                            &format!("AUDIO DELAY {:+.0}MS", delay * 1000_f64),
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::ToggleDeinterlace => {
+                        let deinterlacing = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            settings.deinterlace = if settings.is_deinterlacing() {
+                                Deinterlace::Off
+                            } else {
+                                Deinterlace::On
+                            };
+                            settings.is_deinterlacing()
+                        };
+                        osd.show_message(
+                            if deinterlacing {
+                                "DEINTERLACE ON"
+                            } else {
+                                "DEINTERLACE OFF"
+                            },
+                            Duration::from_secs(1),
+                        );
+                    }
+                    #[cfg(feature = "cast")]
+                    PlayerCommand::ToggleCast => match cast.take() {
+                        Some(session) => {
+                            self.hand_back_cast(&session, &mut playback);
+                            osd.show_message("CASTING STOPPED", Duration::from_secs(1));
+                        }
+                        None => {
+                            cast = Some(CastSession::start(
+                                &path,
+                                playback.position(),
+                                self.options.cast_device.as_deref(),
+                            ));
+                            playback.start_cast();
+                            osd.show_message("LOOKING FOR CAST DEVICES", Duration::from_secs(2));
+                        }
+                    },
+                    PlayerCommand::ToggleRecording => {
+                        let mut recording = recording.lock().unwrap();
+                        match recording.take() {
+                            // dropping it finishes the file
+                            Some(_) => {
+                                info!("stopped recording");
+                                osd.show_message("RECORDING STOPPED", Duration::from_secs(1));
+                            }
+                            None => {
+                                let output = remux::default_recording(&path);
+                                info!("recording to {}", output.display());
+                                osd.show_message(
+                                    &format!("RECORDING TO {}", output.display()),
+                                    Duration::from_secs(2),
+                                );
+                                *recording = Some(Remux::start(
+                                    &output.to_string_lossy(),
+                                    remux_streams.clone(),
+                                ));
+                            }
+                        }
+                    }
+                    PlayerCommand::ToggleSpectrogram => {
+                        let shown = osd.spectrogram_mut().toggle();
+                        osd.show_message(
+                            if shown {
+                                "SPECTROGRAM ON"
+                            } else {
+                                "SPECTROGRAM OFF"
+                            },
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::ToggleLevelMeter => {
+                        let shown = osd.level_meter_mut().toggle();
+                        osd.show_message(
+                            if shown {
+                                "LEVEL METER ON"
+                            } else {
+                                "LEVEL METER OFF"
+                            },
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::FocusNextTile => {
+                        if let Some(mosaic) = &mut mosaic {
+                            let focus = mosaic.focus_next();
+                            let name = mosaic.path(focus).unwrap_or(path.as_str());
+                            osd.show_message(
+                                &format!("TILE {} OF {}: {}", focus + 1, mosaic.count(), name),
+                                Duration::from_secs(2),
+                            );
+                            recompose = true;
+                        }
+                    }
+                    PlayerCommand::ToggleChapterMenu => {
+                        let menu = osd.chapter_menu_mut();
+                        if menu.is_empty() {
+                            osd.show_message("NO CHAPTERS", Duration::from_secs(1));
+                        } else if menu.toggle(playback.position()) {
+                            video_sink.set_key_bindings(bindings.menu());
+                        } else {
+                            video_sink.set_key_bindings(bindings.clone());
+                        }
+                        needs_redraw = true;
+                    }
+                    PlayerCommand::MenuUp => {
+                        osd.chapter_menu_mut().move_by(-1);
+                        needs_redraw = true;
+                    }
+                    PlayerCommand::MenuDown => {
+                        osd.chapter_menu_mut().move_by(1);
+                        needs_redraw = true;
+                    }
+                    PlayerCommand::ToggleTileExpanded => {
+                        if let Some(mosaic) = &mut mosaic {
+                            let message = if mosaic.toggle_expanded() {
+                                format!("TILE {} EXPANDED", mosaic.focus() + 1)
+                            } else {
+                                "MOSAIC".to_string()
+                            };
+                            osd.show_message(&message, Duration::from_secs(1));
+                            recompose = true;
+                        }
+                    }
+                    PlayerCommand::ToggleAutocrop => {
+                        let autocrop = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            settings.autocrop = !settings.autocrop;
+                            settings.autocrop
+                        };
+                        osd.show_message(
+                            if autocrop {
+                                "AUTOCROP ON"
+                            } else {
+                                "AUTOCROP OFF"
+                            },
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::SwapPictureInPicture => {
+                        if let Composition::PictureInPicture { swapped } = composition {
+                            composition = Composition::PictureInPicture { swapped: !swapped };
+                            osd.show_message("PIP SWAPPED", Duration::from_secs(1));
+                        }
+                    }
+                    PlayerCommand::Wipe(split) => {
+                        if let Composition::Compare { .. } = composition {
+                            composition = Composition::Compare {
+                                view: CompareView::Wipe,
+                                split: split.clamp(0_f64, 1_f64),
+                            };
+                            recompose = true;
+                        }
+                    }
+                    PlayerCommand::CompareView(view) => {
+                        if let Composition::Compare { split, .. } = composition {
+                            composition = Composition::Compare { view, split };
+                            recompose = true;
+                            osd.show_message(
+                                &view.name().to_uppercase(),
+                                Duration::from_millis(500),
+                            );
+                        }
+                    }
+                    PlayerCommand::Rotate => {
+                        let rotation = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            settings.rotation = (settings.rotation + 1) % 4;
+                            settings.rotation
+                        };
+                        osd.show_message(
+                            &format!("ROTATE {}", rotation * 90),
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::FlipHorizontal => {
+                        let flipped = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            settings.hflip = !settings.hflip;
+                            settings.hflip
+                        };
+                        osd.show_message(
+                            if flipped { "HFLIP ON" } else { "HFLIP OFF" },
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::FlipVertical => {
+                        let flipped = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            settings.vflip = !settings.vflip;
+                            settings.vflip
+                        };
+                        osd.show_message(
+                            if flipped { "VFLIP ON" } else { "VFLIP OFF" },
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::AspectMode(mode) => {
+                        aspect_mode = mode;
+                        video_sink.set_aspect_mode(mode);
+                        osd.show_message(
+                            &format!("ASPECT {}", mode.name().to_uppercase()),
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::EqualizerPreset(preset) => {
+                        self.equalizer = {
+                            let mut equalizer = equalizer.lock().unwrap();
+                            equalizer.set_preset(preset);
+                            equalizer.clone()
+                        };
+                        save_equalizer(self.options.config_file.as_deref(), &self.equalizer);
+                        osd.show_message(&format!("EQ {}", preset.name()), Duration::from_secs(1));
+                    }
+                    PlayerCommand::CycleEqualizerPreset => {
+                        let preset = self
+                            .equalizer
+                            .preset()
+                            .map_or(EqualizerPreset::Flat, |preset| preset.next());
+                        self.equalizer = {
+                            let mut equalizer = equalizer.lock().unwrap();
+                            equalizer.set_preset(preset);
+                            equalizer.clone()
+                        };
+                        save_equalizer(self.options.config_file.as_deref(), &self.equalizer);
+                        osd.show_message(&format!("EQ {}", preset.name()), Duration::from_secs(1));
+                    }
+                    PlayerCommand::Picture(property, value) => {
+                        let value = {
+                            let mut settings = video_filter_settings.lock().unwrap();
+                            settings.picture.set(property, value);
+                            settings.picture.get(property)
+                        };
+                        osd.show_message(
+                            &format!("{} {:+}", property.name().to_uppercase(), value),
+                            Duration::from_secs(1),
+                        );
+                    }
+                    PlayerCommand::EqualizerGain { band, gain } => {
+                        if let Some(frequency) = equalizer::BANDS.get(band) {
+                            self.equalizer = {
+                                let mut equalizer = equalizer.lock().unwrap();
+                                equalizer.set_gain(band, gain);
+                                equalizer.clone()
+                            };
+                            save_equalizer(self.options.config_file.as_deref(), &self.equalizer);
+                            osd.show_message(
+                                &format!(
+                                    "EQ {}HZ {:+.1}DB",
+                                    frequency,
+                                    self.equalizer.gains()[band]
+                                ),
+                                Duration::from_secs(1),
+                            );
+                        }
+                    }
+                    PlayerCommand::MarkIn => {
+                        let position = playback.position();
+                        info!("marked export range start at {:.2}s", position);
+                        osd.show_message(
+                            &format!("IN {}", format_time(position)),
+                            Duration::from_secs(1),
+                        );
+                        range_start = Some(position);
+                    }
+                    PlayerCommand::MarkOut => {
+                        let position = playback.position();
+                        info!("marked export range end at {:.2}s", position);
+                        osd.show_message(
+                            &format!("OUT {}", format_time(position)),
+                            Duration::from_secs(1),
+                        );
+                        range_end = Some(position);
+                    }
+                    // the most recent other file, the current one is only recorded when it ends
+                    PlayerCommand::OpenLast => {
+                        match history::load().into_iter().find(|entry| entry.path != path) {
+                            Some(entry) => self.controller.load(&entry.path),
+                            None => osd.show_message("NO HISTORY", Duration::from_secs(1)),
+                        }
+                    }
+                    PlayerCommand::OpenClipboard => {
+                        match video_sink
+                            .clipboard_text()
+                            .as_deref()
+                            .and_then(clipboard_input)
+                        {
+                            Some(input) => self.controller.load(&input),
+                            None => osd.show_message(
+                                "NOTHING TO OPEN ON THE CLIPBOARD",
+                                Duration::from_secs(2),
+                            ),
+                        }
+                    }
+                    PlayerCommand::Export => match (range_start, range_end) {
+                        (Some(start), Some(end)) if start < end => {
+                            self.export_range(&path, start, end);
+                            osd.show_message("EXPORTING", Duration::from_secs(2));
+                        }
+                        _ => info!("mark a range with 'i' and 'o' before exporting"),
+                    },
+                    _ => {}
+                }
+            }
+
+            if let Some(master) = &mut sync_master {
+                master.publish(playback.position(), playback.is_paused());
+            }
+            #[cfg(feature = "now-playing")]
+            if let Some(now_playing) = &mut now_playing {
+                now_playing.update(playback.position(), playback.is_paused());
+            }
+
+            // show composition changes right away, also while paused; mosaic tiles go on
+            // while the main input is paused or buffering
+            if let (Some(frame), Some(mosaic)) = (&last_frame, &mut mosaic) {
+                let updated = playback.state() != PlaybackState::Playing && mosaic.take_updated();
+                if recompose || updated {
+                    let pts = frame.pts().unwrap_or(0) as f64 * metadata.video_time_base();
+                    let composed = mosaic.compose(&mut compositor, frame);
+                    video_sink.submit(composed.as_ref().unwrap_or(frame), pts);
+                    needs_redraw = true;
+                }
+            }
+            if let (Some(frame), Some(second)) = (&last_frame, &mut second) {
+                let pts = frame.pts().unwrap_or(0) as f64 * metadata.video_time_base();
+                if recompose {
+                    let composed = second
+                        .video_at(pts)
+                        .and_then(|second| compose(&mut compositor, composition, frame, second));
+                    video_sink.submit(composed.as_ref().unwrap_or(frame), pts);
+                    needs_redraw = true;
+                } else if playback.is_paused() {
+                    // nothing else shows the second input once it catches up with a seek
+                    if let Some(second) = second.caught_up(pts) {
+                        let composed = compose(&mut compositor, composition, frame, second);
+                        video_sink.submit(composed.as_ref().unwrap_or(frame), pts);
+                        needs_redraw = true;
+                    }
+                }
+            }
+
+            let vb = video_queue.is_drained();
+            let ab = audio_queue.is_drained();
+
+            // pause the clock on underrun, resume once the prebuffer threshold is refilled;
+            // scanning has no more than a frame buffered at a time
+            if playback.state() != PlaybackState::Seeking && playback.scan_speed().is_none() {
+                let buffered_frames = video_queue.frames.len();
+                let first_pts = video_queue.frames.front().and_then(|frame| frame.pts());
+                let buffered_audio = audio_queue.duration();
+                let progress = (buffered_frames as f64 / prebuffer_frames as f64)
+                    .min(if prebuffer_audio > 0_f64 {
+                        buffered_audio / prebuffer_audio
+                    } else {
+                        1_f64
+                    })
+                    .min(1_f64);
+
+                let buffering = matches!(
+                    playback.state(),
+                    PlaybackState::Opening | PlaybackState::Buffering
+                );
+                let running = matches!(
+                    playback.state(),
+                    PlaybackState::Playing | PlaybackState::Paused
+                );
+                if running && buffered_frames == 0 && !vb {
+                    info!("buffer underrun, buffering");
+                    playback.underrun();
+                } else if buffering
+                    && (progress >= 1_f64
+                        || vb
+                        || budget.is_full()
+                        || video_queue.is_full()
+                        || audio_queue.is_full())
+                {
+                    // (a full memory budget won't let the buffers grow any further, and a
+                    // full queue holds up the decode thread, and so the other stream, too)
+                    info!("buffering done");
+                    playback.buffered(first_pts.map(|pts| pts as f64 * metadata.video_time_base()));
+                    osd.set_status(None, false);
+                    reported_buffering = None;
+                    needs_redraw = true;
+                } else if buffering {
+                    let progress = (progress * 100_f64) as u32;
+                    osd.set_status(Some(format!("BUFFERING {}%", progress)), true);
+                    if reported_buffering != Some(progress) {
+                        reported_buffering = Some(progress);
+                        let progress = progress as f64 / 100_f64;
+                        self.controller
+                            .emit(PlayerEvent::BufferingProgress(progress));
+                    }
+                }
+            }
+
+            let playing = !playback.is_paused()
+                && !matches!(
+                    playback.state(),
+                    PlaybackState::Opening | PlaybackState::Buffering
+                );
+            let inhibit = match self.options.stop_screensaver {
+                StopScreensaver::Always => !matches!(
+                    playback.state(),
+                    PlaybackState::Ended | PlaybackState::Error
+                ),
+                StopScreensaver::Playing => playing,
+                StopScreensaver::Video => playing && !metadata.is_audio_only(),
+                StopScreensaver::No => false,
+            };
+            if inhibit != inhibiting_idle {
+                video_sink.inhibit_idle(inhibit);
+                inhibiting_idle = inhibit;
+            }
+
+            video_sink.set_progress(
+                metadata
+                    .duration()
+                    .filter(|duration| *duration > 0_f64)
+                    .map(|duration| playback.position() / duration),
+                playback.is_paused(),
+            );
+
+            let show_cursor = !playing || last_mouse_activity.elapsed() < CURSOR_HIDE_AFTER;
+            if show_cursor != cursor_shown {
+                video_sink.show_cursor(show_cursor);
+                cursor_shown = show_cursor;
+            }
+
+            // the pipeline threads only stop before playback does by panicking
+            if buffer_thread.is_finished()
+                || decode_video_thread.is_finished()
+                || decode_audio_thread.is_finished()
+            {
+                eprintln!("playback failed, stopping");
+                self.controller.emit(PlayerEvent::Error(
+                    "the decoding pipeline stopped".to_string(),
+                ));
+                playback.fail();
+                break 'running;
+            }
+
+            // stop once as much was played as asked for, the same as playing to the end
+            let frames_played = self
+                .options
+                .frames
+                .map_or(false, |frames| presented_frames >= frames);
+            let until_reached = self.options.until.map_or(false, |until| {
+                playback.state() != PlaybackState::Seeking && playback.position() >= until
+            });
+            if frames_played || until_reached {
+                playback.close();
+                break 'running;
+            }
+
+            // close if we reached EOF
+            {
+                // the end is every frame decoded, shown and, for audio, heard. Scanning
+                // ends the streams after every keyframe, so it's never the end.
+                let audio_played =
+                    playback.position() >= audio_end + playback.audio_sink().latency();
+                let ended = playback.state() != PlaybackState::Seeking
+                    && video_queue.is_empty()
+                    && audio_queue.is_empty()
+                    && vb
+                    && ab;
+                if ended && audio_played && playback.scan_speed().is_none() {
+                    if playback.state() != PlaybackState::Ended {
+                        self.controller.emit(PlayerEvent::EndOfFile);
+                    }
+
+                    // plugins and scripts may keep playback going, e.g. by seeking back
+                    // to loop
+                    #[cfg(feature = "plugins")]
+                    if playback.state() != PlaybackState::Ended {
+                        plugins.on_eof();
+                        let commands = plugins.take_commands();
+                        if !commands.is_empty() {
+                            for command in commands {
+                                self.controller.send(command);
+                            }
+                            continue 'running;
+                        }
+                    }
+                    #[cfg(feature = "scripting")]
+                    if playback.state() != PlaybackState::Ended {
+                        let mut resumed = false;
+                        for script in &mut scripts {
+                            script.on_eof();
+                            let actions = script.take_actions();
+                            for command in actions.commands {
+                                self.controller.send(command);
+                                resumed = true;
+                            }
+                            for (text, duration) in actions.messages {
+                                osd.show_message(&text, duration);
+                            }
+                        }
+                        if resumed {
+                            continue 'running;
+                        }
+                    }
+
+                    // end playback, unless kept open with nothing else queued to play, or
+                    // kept open always
+                    let queued = !self.controller.playlist.lock().unwrap().is_empty();
+                    let close = match keep_open {
+                        KeepOpen::Yes => queued,
+                        KeepOpen::Always => false,
+                        KeepOpen::No => true,
+                    };
+                    if close {
+                        playback.close();
+                        break 'running;
+                    }
+                    if playback.state() != PlaybackState::Ended {
+                        playback.end();
+                        osd.show_message("END", Duration::from_secs(2));
+                        needs_redraw = true;
+                    }
+                }
+            }
+
+            let buffered = video_queue
+                .frames
+                .back()
+                .and_then(|frame| frame.pts())
+                .map_or(playback.position(), |pts| {
+                    pts as f64 * metadata.video_time_base()
+                });
+            osd.controls_mut().set_state(ControlState {
+                position: playback.position(),
+                duration: metadata.duration(),
+                buffered,
+                paused: playback.is_paused(),
+                volume: self.volume,
+            });
+
+            // redraw the last frame with the OSD on top; animated OSD elements are
+            // refreshed at ~30fps
+            if osd.update() {
+                needs_redraw = true;
+            }
+            if needs_redraw
+                || (osd.is_visible() && last_redraw.elapsed() >= Duration::from_millis(33))
+            {
+                video_sink.present(&osd);
+
+                needs_redraw = false;
+                last_redraw = Instant::now();
+            }
+
+            *self.controller.status.lock().unwrap() = PlayerStatus {
+                path: Some(path.clone()),
+                state: playback.state(),
+                position: playback.position(),
+                duration: metadata.duration(),
+                paused: playback.is_paused(),
+                volume: self.volume,
+                seeks: serial,
+            };
+
+            if playback.state() != reported_state {
+                reported_state = playback.state();
+                self.controller
+                    .emit(PlayerEvent::StateChanged(reported_state));
+            }
+            // a seek is reported once it has landed, anything else as playback moves on
+            let position = playback.position();
+            let seeked = serial != reported_seeks && playback.state() != PlaybackState::Seeking;
+            if reported_position != Some(position)
+                && (seeked || last_position_event.elapsed() >= POSITION_EVENT_INTERVAL)
+            {
+                reported_position = Some(position);
+                reported_seeks = serial;
+                last_position_event = Instant::now();
+                self.controller.emit(PlayerEvent::PositionChanged(position));
+            }
+            if let Some(progress) = &mut progress {
+                progress.update(&ProgressRecord {
+                    position,
+                    duration: metadata.duration(),
+                    speed: playback.speed(),
+                    dropped_frames,
+                    state: playback.state(),
+                });
+            }
+
+            // sleep until the next frame is due, on the vblank it's shown on for video;
+            // opening, seeking and buffering wait on the decode threads, so they're polled
+            let now = Instant::now();
+            let deadline = if playback.state() == PlaybackState::Playing {
+                let due = |time: f64| now + Duration::from_secs_f64((time - position).max(0_f64));
+                let video = video_queue
+                    .frames
+                    .front()
+                    .and_then(|frame| frame.pts())
+                    .map(|pts| scheduler.next_vblank(due(pts as f64 * metadata.video_time_base())));
+                let audio = audio_queue
+                    .frames
+                    .front()
+                    .and_then(|frame| frame.pts())
+                    .map(|pts| {
+                        // or once the sink has played its queue down to the target
+                        let queued = playback.audio_sink().latency() - AUDIO_BUFFER;
+                        due(pts as f64 * metadata.audio_time_base() + audio_delay - AUDIO_BUFFER)
+                            .max(now + Duration::from_secs_f64(queued.max(0_f64)))
+                    });
+                let osd_redraw =
+                    Some(last_redraw + Duration::from_millis(33)).filter(|_| osd.is_visible());
+                [video, audio, osd_redraw]
+                    .iter()
+                    .flatten()
+                    .fold(now + IDLE_WAKEUP, |deadline, due| deadline.min(*due))
+            } else if is_settled(playback.state()) {
+                now + IDLE_WAKEUP
+            } else {
+                now + Duration::from_millis(1)
+            };
+            scheduler.sleep_until(deadline);
+        }
+
+        // wind the pipeline down before returning, so nothing of this input is still
+        // decoding when the next one opens or the process exits: without the frame queues
+        // the decode threads' sends fail, and without them the buffer thread's do
+        stopped.store(true, Ordering::Relaxed);
+        drop(video_queue);
+        drop(audio_queue);
+        for thread in [buffer_thread, decode_video_thread, decode_audio_thread] {
+            // a thread that panicked already said why
+            thread.join().ok();
+        }
+
+        let position = playback.position();
+        let outcome = match playback.state() {
+            PlaybackState::Ended => PlaybackOutcome::Ended,
+            PlaybackState::Error => PlaybackOutcome::Failed,
+            _ => PlaybackOutcome::Stopped,
+        };
+        let state = match playback.state() {
+            PlaybackState::Error => PlaybackState::Error,
+            _ => PlaybackState::Idle,
+        };
+        {
+            let mut status = self.controller.status.lock().unwrap();
+            status.path = None;
+            status.state = state;
+        }
+        self.controller.emit(PlayerEvent::StateChanged(state));
+        if let Some(progress) = &mut progress {
+            progress.write(&ProgressRecord {
+                position,
+                duration: metadata.duration(),
+                speed: 0_f64,
+                dropped_frames,
+                state: playback.state(),
+            });
+        }
+        self.progress = progress;
+        if !cursor_shown {
+            video_sink.show_cursor(true);
+        }
+        video_sink.set_progress(None, false);
+        if custom_video_sink {
+            self.video_sink = Some(video_sink);
+        }
+        if custom_audio_sink {
+            self.audio_sink = Some(playback.into_audio_sink());
+        }
+        if custom_clock {
+            self.external_clock = external_clock;
+        }
+
+        let completion = match metadata.duration() {
+            Some(duration) if duration > 0_f64 => position / duration,
+            _ => 0_f64,
+        };
+        if let Err(e) = history::record(&path, opened, completion) {
+            eprintln!("failed to record history: {}", e);
+        }
+        outcome
+    }
+
+    /// Keeps at most `max_delay` seconds of video queued ahead of the clock by moving the
+    /// clock forward, then drops every due frame except the newest one, returning how many.
+    fn catch_up_to_live_edge(
+        &self,
+        buffer: &mut FrameQueue<Video>,
+        metadata: &PlaybackAssetMetadata,
+        clock: &mut PlaybackClock,
+        max_delay: f64,
+    ) -> usize {
+        if let Some(pts) = buffer.frames.back().and_then(|frame| frame.pts()) {
+            let newest = pts as f64 * metadata.video_time_base();
+            if newest - clock.position() > max_delay {
+                clock.set_position(newest - max_delay);
+            }
+        }
+
+        let mut dropped = 0;
+        while buffer.frames.get(1).map_or(false, |next| {
+            self.should_render_video_frame(next, metadata, clock)
+        }) {
+            buffer.pop();
+            dropped += 1;
+        }
+        dropped
+    }
+
+    fn request_seek(
+        &self,
+        seeks: &Sender<SeekRequest>,
+        latest_seek: &AtomicUsize,
+        serial: usize,
+        position: f64,
+        keyframe_only: bool,
+    ) {
+        latest_seek.store(serial, Ordering::Relaxed);
+        // the buffer thread is gone once playback stopped, nothing to seek then
+        seeks
+            .send(SeekRequest {
+                position: position.max(0_f64),
+                serial,
+                keyframe_only,
+            })
+            .ok();
+    }
+
+    /// Takes playback back over from a cast `session`, carrying on here from where the device
+    /// got to, paused or not as before casting.
+    #[cfg(feature = "cast")]
+    fn hand_back_cast(&self, session: &CastSession, playback: &mut Playback) {
+        playback.stop_cast();
+        self.controller.seek(session.status().position);
+    }
+
+    fn export_range(&self, path: &str, start: f64, end: f64) {
+        let format = self.options.export_format;
+        let settings = ExportSettings {
+            output: ExportSettings::default_output(path, format, start, end),
+            format,
+            start,
+            end,
+            fps: self.options.export_fps,
+            width: self.options.export_width,
+        };
+        let path = path.to_string();
+
+        // Export from a separate demuxer so playback keeps going
+        thread::spawn(move || {
+            info!("exporting {}", settings.output.display());
+            match export::export_range(&path, &settings) {
+                Ok(()) => info!("exported {}", settings.output.display()),
+                Err(e) => eprintln!("failed to export {}: {}", settings.output.display(), e),
+            }
+        });
+    }
+
+    pub fn should_render_video_frame(
+        &self,
+        frame: &Video,
+        asset: &PlaybackAssetMetadata,
+        clock: &PlaybackClock,
+    ) -> bool {
+        self.should_render_frame(frame, asset.video_time_base(), clock, 0_f64)
+    }
+
+    /// `delay` shifts the audio against the clock, e.g. for Bluetooth output latency.
+    pub fn should_render_audio_frame(
+        &self,
+        frame: &Audio,
+        asset: &PlaybackAssetMetadata,
+        clock: &PlaybackClock,
+        delay: f64,
+    ) -> bool {
+        self.should_render_frame(frame, asset.audio_time_base(), clock, delay)
+    }
+
+    fn should_render_frame(
+        &self,
+        frame: &Frame,
+        time_base: f64,
+        clock: &PlaybackClock,
+        offset: f64,
+    ) -> bool {
+        // in seconds: rounding to milliseconds judders frames at rates that don't divide
+        // evenly into them
+        match frame.pts() {
+            Some(pts) => clock.position() >= (pts as f64 * time_base + offset).max(0_f64),
+            None => false,
+        }
+    }
+
+    /// Creates the outputs picked on the command line, unless an embedder supplied its own,
+    /// which `play` puts back when it's done with them.
+    fn create_sinks(&mut self) -> (Box<dyn VideoSink>, Box<dyn AudioSink>) {
+        // SDL is only brought up when one of the outputs needs it
+        #[cfg(feature = "sdl")]
+        let sdl_context = {
+            let needs_sdl = (self.video_sink.is_none()
+                && matches!(
+                    self.options.video_output,
+                    VideoOutput::Sdl | VideoOutput::Gl
+                ))
+                || (self.audio_sink.is_none()
+                    && self.options.audio_output == AudioOutput::Sdl
+                    && !self.options.no_audio);
+            if needs_sdl {
+                Some(sdl2::init().unwrap())
+            } else {
+                None
+            }
+        };
+
+        // only the GL output runs shaders
+        #[cfg(feature = "sdl")]
+        let gl_output = self.options.video_output == VideoOutput::Gl;
+        #[cfg(not(feature = "sdl"))]
+        let gl_output = false;
+        if !self.options.shaders.is_empty() && (self.video_sink.is_some() || !gl_output) {
+            info!("--shader needs --vo gl, ignoring it");
+        }
+        if self.options.projection == Some(Projection::Equirect)
+            && (self.video_sink.is_some() || !gl_output)
+        {
+            info!("--projection equirect needs --vo gl, ignoring it");
+        }
+
+        let video_sink: Box<dyn VideoSink> = match self.video_sink.take() {
+            Some(video_sink) => video_sink,
+            None => match self.options.video_output {
+                #[cfg(feature = "sdl")]
+                VideoOutput::Sdl => Box::new(SdlVideoSink::new(sdl_context.as_ref().unwrap())),
+                #[cfg(feature = "sdl")]
+                VideoOutput::Gl => {
+                    let mut sink = GlVideoSink::new(sdl_context.as_ref().unwrap());
+                    sink.set_shaders(self.options.shaders.clone());
+                    sink.set_projection(self.options.projection);
+                    Box::new(sink)
+                }
+                #[cfg(feature = "winit")]
+                VideoOutput::Winit => Box::new(WinitVideoSink::new()),
+                VideoOutput::Terminal => {
+                    Box::new(TerminalVideoSink::new(self.options.terminal_width))
+                }
+                VideoOutput::Tct => {
+                    // fill the terminal's width unless told otherwise
+                    let columns = self.options.terminal_columns.unwrap_or_else(|| {
+                        env::var("COLUMNS")
+                            .ok()
+                            .and_then(|columns| columns.parse().ok())
+                            .unwrap_or(80)
+                    });
+                    Box::new(TerminalVideoSink::text(columns))
+                }
+                VideoOutput::Null => Box::new(NullVideoSink),
+            },
+        };
+
+        // decoded audio is still consumed on schedule when it isn't played
+        let audio_sink: Box<dyn AudioSink> = match self.audio_sink.take() {
+            Some(audio_sink) => audio_sink,
+            None if self.options.no_audio => Box::new(NullAudioSink),
+            None => match self.options.audio_output {
+                #[cfg(feature = "sdl")]
+                AudioOutput::Sdl => Box::new(SdlAudioSink::new(
+                    &sdl_context.as_ref().unwrap().audio().unwrap(),
+                    self.options.low_latency,
+                )),
+                #[cfg(feature = "cpal")]
+                AudioOutput::Cpal => match CpalAudioSink::new(
+                    self.options.audio_device.as_deref(),
+                    self.options.low_latency,
+                ) {
+                    Ok(audio_sink) => Box::new(audio_sink),
+                    Err(e) => {
+                        eprintln!("failed to open audio output: {}", e);
+                        Box::new(NullAudioSink)
+                    }
+                },
+                AudioOutput::Wav => Box::new(
+                    WavAudioSink::create(
+                        &self.options.audio_file,
+                        AudioSpec {
+                            sample_rate: 48000,
+                            channels: 2,
+                        },
+                    )
+                    .expect("Failed to create audio file"),
+                ),
+                AudioOutput::Null => Box::new(NullAudioSink),
+            },
+        };
+
+        (video_sink, audio_sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, iter, path::PathBuf, process};
+
+    use super::*;
+
+    /// Counts the frames submitted after each `configure`, shared with the test.
+    struct CountingSink(Arc<Mutex<Vec<usize>>>);
+
+    impl VideoSink for CountingSink {
+        fn configure(&mut self, _metadata: &PlaybackAssetMetadata) {
+            self.0.lock().unwrap().push(0);
+        }
+
+        fn submit(&mut self, _frame: &Video, _pts: f64) {
+            if let Some(frames) = self.0.lock().unwrap().last_mut() {
+                *frames += 1;
+            }
+        }
+    }
+
+    fn directory() -> PathBuf {
+        env::temp_dir().join(format!("video-player-player-{}", process::id()))
+    }
+
+    /// Writes a 16x16 Y4M clip of `frames` grey frames at 25 fps.
+    fn write_clip(name: &str, frames: usize) -> String {
+        fs::create_dir_all(directory()).unwrap();
+        let mut bytes = b"YUV4MPEG2 W16 H16 F25:1 Ip A1:1 C420jpeg\n".to_vec();
+        for _ in 0..frames {
+            bytes.extend_from_slice(b"FRAME\n");
+            bytes.extend(iter::repeat(128).take(16 * 16 * 3 / 2));
+        }
+        let path = directory().join(name);
+        fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn plays_every_input_through_a_custom_video_sink() {
+        // the history is kept in the config directory, away from the user's
+        env::set_var("XDG_CONFIG_HOME", directory());
+        let options = Options {
+            video_output: VideoOutput::Null,
+            no_audio: true,
+            prebuffer_frames: 1,
+            prebuffer_audio_ms: 0,
+            keep_open: KeepOpen::No,
+            ..Options::default()
+        };
+        let configures = Arc::new(Mutex::new(Vec::new()));
+        let mut player = Player::new(options.clone());
+        player.set_video_sink(Box::new(CountingSink(Arc::clone(&configures))));
+
+        for name in ["first.y4m", "second.y4m"] {
+            let asset = PlaybackAsset::open(&write_clip(name, 5), &options).unwrap();
+            assert_eq!(player.play(asset), PlaybackOutcome::Ended);
+        }
+
+        let configures = configures.lock().unwrap();
+        assert_eq!(configures.len(), 2);
+        assert!(configures.iter().all(|frames| *frames > 0));
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    env::consts::DLL_EXTENSION,
+    ffi::{c_void, CStr, CString},
+    fs,
+    os::raw::c_char,
+    path::Path,
+};
+
+use ffmpeg_next::frame::Video;
+use libloading::Library;
+
+use crate::player::PlayerCommand;
+
+/// Version of the interface below. Plugins built against another one aren't loaded.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// Commands a plugin can send through `PluginHost::send_command`, with the value they take.
+pub const COMMAND_PLAY: u32 = 0;
+pub const COMMAND_PAUSE: u32 = 1;
+pub const COMMAND_TOGGLE_PAUSE: u32 = 2;
+/// To `value` seconds.
+pub const COMMAND_SEEK: u32 = 3;
+/// By `value` seconds.
+pub const COMMAND_SEEK_BY: u32 = 4;
+pub const COMMAND_STOP: u32 = 5;
+/// Linear, 1 being the file's own level.
+pub const COMMAND_VOLUME: u32 = 6;
+
+/// Every plugin exports this: `const VpPlugin *vp_plugin_init(const VpPluginHost *host)`,
+/// returning `NULL` to not load.
+const INIT_SYMBOL: &[u8] = b"vp_plugin_init\0";
+
+type InitFn = unsafe extern "C" fn(host: *const PluginHost) -> *const PluginDescriptor;
+
+/// What the player offers a plugin. It stays valid while the plugin is loaded, and its
+/// functions may be called from `vp_plugin_init` and any hook, on the thread calling them.
+#[repr(C)]
+pub struct PluginHost {
+    pub api_version: u32,
+    pub context: *mut c_void,
+    /// Takes presses of `key`, a character no built-in binding uses, for `on_key`.
+    pub bind_key: extern "C" fn(context: *mut c_void, key: u32),
+    /// One of the `COMMAND_*` constants.
+    pub send_command: extern "C" fn(context: *mut c_void, command: u32, value: f64),
+}
+
+/// A frame as shown, in its `AVPixelFormat`; the planes are only valid during `on_frame`,
+/// and null for hardware surfaces shown straight from the GPU.
+#[repr(C)]
+pub struct PluginFrame {
+    pub format: i32,
+    pub width: u32,
+    pub height: u32,
+    pub data: [*const u8; 3],
+    pub stride: [i32; 3],
+    pub pts: f64,
+}
+
+/// What a plugin returns from `vp_plugin_init`. Every hook is optional, and gets `state`
+/// back. `osd_text` returns a line to keep on screen, or `NULL`; the string must stay valid
+/// until the next call.
+#[repr(C)]
+pub struct PluginDescriptor {
+    pub api_version: u32,
+    pub name: *const c_char,
+    pub state: *mut c_void,
+    pub on_load: Option<extern "C" fn(state: *mut c_void, path: *const c_char)>,
+    pub on_tick: Option<extern "C" fn(state: *mut c_void, position: f64, paused: bool)>,
+    pub on_key: Option<extern "C" fn(state: *mut c_void, key: u32)>,
+    pub on_eof: Option<extern "C" fn(state: *mut c_void)>,
+    pub on_frame: Option<extern "C" fn(state: *mut c_void, frame: *const PluginFrame)>,
+    pub osd_text: Option<extern "C" fn(state: *mut c_void) -> *const c_char>,
+    pub destroy: Option<extern "C" fn(state: *mut c_void)>,
+}
+
+/// What a plugin asked of the player, through its `PluginHost`.
+#[derive(Default)]
+struct Context {
+    keys: Vec<char>,
+    commands: Vec<PlayerCommand>,
+}
+
+extern "C" fn bind_key(context: *mut c_void, key: u32) {
+    let context = unsafe { &mut *(context as *mut Context) };
+    context.keys.extend(char::from_u32(key));
+}
+
+extern "C" fn send_command(context: *mut c_void, command: u32, value: f64) {
+    let context = unsafe { &mut *(context as *mut Context) };
+    let command = match command {
+        COMMAND_PLAY => PlayerCommand::Play,
+        COMMAND_PAUSE => PlayerCommand::Pause,
+        COMMAND_TOGGLE_PAUSE => PlayerCommand::TogglePause,
+        COMMAND_SEEK => PlayerCommand::Seek(value),
+        COMMAND_SEEK_BY => PlayerCommand::SeekBy(value),
+        COMMAND_STOP => PlayerCommand::Stop,
+        COMMAND_VOLUME => PlayerCommand::Volume(value),
+        _ => return,
+    };
+    context.commands.push(command);
+}
+
+struct LoadedPlugin {
+    name: String,
+    descriptor: *const PluginDescriptor,
+    context: Box<Context>,
+    _host: Box<PluginHost>,
+    // dropped last, the pointers above point into it
+    _library: Library,
+}
+
+impl LoadedPlugin {
+    fn load(path: &Path) -> Result<Self, String> {
+        let library = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
+        let init = unsafe { library.get::<InitFn>(INIT_SYMBOL) }.map_err(|e| e.to_string())?;
+
+        let mut context = Box::new(Context::default());
+        let host = Box::new(PluginHost {
+            api_version: PLUGIN_API_VERSION,
+            context: &mut *context as *mut Context as *mut c_void,
+            bind_key,
+            send_command,
+        });
+
+        let descriptor = unsafe { init(&*host) };
+        if descriptor.is_null() {
+            return Err("plugin declined to load".to_string());
+        }
+        let api_version = unsafe { (*descriptor).api_version };
+        if api_version != PLUGIN_API_VERSION {
+            return Err(format!(
+                "built for plugin API {}, not {}",
+                api_version, PLUGIN_API_VERSION
+            ));
+        }
+
+        let name = unsafe { (*descriptor).name };
+        let name = if name.is_null() {
+            path.display().to_string()
+        } else {
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        Ok(LoadedPlugin {
+            name,
+            descriptor,
+            context,
+            _host: host,
+            _library: library,
+        })
+    }
+
+    fn descriptor(&self) -> &PluginDescriptor {
+        unsafe { &*self.descriptor }
+    }
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        let descriptor = self.descriptor();
+        if let Some(destroy) = descriptor.destroy {
+            destroy(descriptor.state);
+        }
+    }
+}
+
+/// Native plugins loaded from a directory, each a shared library exporting
+/// `vp_plugin_init`. Plugins can bind keys, keep a line of text on the OSD, send player
+/// commands, and follow playback and the frames shown, e.g. to scrobble what's watched.
+#[derive(Default)]
+pub struct Plugins {
+    loaded: Vec<LoadedPlugin>,
+}
+
+impl Plugins {
+    /// Loads every shared library in `directory`, none if it doesn't exist.
+    pub fn load_dir(directory: &Path) -> Self {
+        let mut paths: Vec<_> = fs::read_dir(directory)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().map_or(false, |ext| ext == DLL_EXTENSION))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        let loaded = paths
+            .iter()
+            .filter_map(|path| match LoadedPlugin::load(path) {
+                Ok(plugin) => {
+                    info!("loaded plugin {}", plugin.name);
+                    Some(plugin)
+                }
+                Err(e) => {
+                    eprintln!("failed to load plugin {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+
+        Plugins { loaded }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loaded.is_empty()
+    }
+
+    pub fn on_load(&mut self, path: &str) {
+        let path = CString::new(path).unwrap_or_default();
+        for plugin in &self.loaded {
+            let descriptor = plugin.descriptor();
+            if let Some(on_load) = descriptor.on_load {
+                on_load(descriptor.state, path.as_ptr());
+            }
+        }
+    }
+
+    pub fn on_tick(&mut self, position: f64, paused: bool) {
+        for plugin in &self.loaded {
+            let descriptor = plugin.descriptor();
+            if let Some(on_tick) = descriptor.on_tick {
+                on_tick(descriptor.state, position, paused);
+            }
+        }
+    }
+
+    /// Hands `key` to the plugins that bound it, returning whether any did.
+    pub fn on_key(&mut self, key: char) -> bool {
+        let mut taken = false;
+        for plugin in &self.loaded {
+            let descriptor = plugin.descriptor();
+            if let (true, Some(on_key)) = (plugin.context.keys.contains(&key), descriptor.on_key) {
+                on_key(descriptor.state, key as u32);
+                taken = true;
+            }
+        }
+        taken
+    }
+
+    pub fn on_eof(&mut self) {
+        for plugin in &self.loaded {
+            let descriptor = plugin.descriptor();
+            if let Some(on_eof) = descriptor.on_eof {
+                on_eof(descriptor.state);
+            }
+        }
+    }
+
+    pub fn on_frame(&mut self, frame: &Video, pts: f64) {
+        let planes = frame.planes().min(3);
+        let mut shown = PluginFrame {
+            format: ffmpeg_next::ffi::AVPixelFormat::from(frame.format()) as i32,
+            width: frame.width(),
+            height: frame.height(),
+            data: [std::ptr::null(); 3],
+            stride: [0; 3],
+            pts,
+        };
+        for plane in 0..planes {
+            shown.data[plane] = frame.data(plane).as_ptr();
+            shown.stride[plane] = frame.stride(plane) as i32;
+        }
+
+        for plugin in &self.loaded {
+            let descriptor = plugin.descriptor();
+            if let Some(on_frame) = descriptor.on_frame {
+                on_frame(descriptor.state, &shown);
+            }
+        }
+    }
+
+    /// The lines plugins want on screen right now.
+    pub fn osd_lines(&mut self) -> Vec<String> {
+        self.loaded
+            .iter()
+            .filter_map(|plugin| {
+                let descriptor = plugin.descriptor();
+                let text = descriptor.osd_text?(descriptor.state);
+                if text.is_null() {
+                    return None;
+                }
+                Some(
+                    unsafe { CStr::from_ptr(text) }
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    /// Takes the commands plugins sent since the last call.
+    pub fn take_commands(&mut self) -> Vec<PlayerCommand> {
+        self.loaded
+            .iter_mut()
+            .flat_map(|plugin| plugin.context.commands.drain(..).collect::<Vec<_>>())
+            .collect()
+    }
+}
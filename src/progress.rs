@@ -0,0 +1,123 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::player::PlaybackState;
+
+/// How often a record is written while nothing else changes.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How progress records are written, picked with `--progress`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(ProgressFormat::Json),
+            _ => Err(format!("unknown progress format '{}'", value)),
+        }
+    }
+}
+
+/// Where playback is, as of one pass of the player's main loop.
+#[derive(Clone, Debug)]
+pub struct ProgressRecord {
+    pub position: f64,
+    pub duration: Option<f64>,
+    /// Multiple of normal speed playback moves on at: 0 while held, negative scanning back.
+    pub speed: f64,
+    /// Video frames dropped for being late since the input was opened.
+    pub dropped_frames: usize,
+    pub state: PlaybackState,
+}
+
+impl ProgressRecord {
+    pub fn to_json(&self) -> String {
+        let duration = self
+            .duration
+            .map_or("null".to_string(), |duration| format!("{:.3}", duration));
+        format!(
+            "{{\"position\": {:.3}, \"duration\": {}, \"speed\": {}, \"dropped_frames\": {}, \"state\": \"{}\"}}",
+            self.position,
+            duration,
+            self.speed,
+            self.dropped_frames,
+            self.state.name()
+        )
+    }
+}
+
+/// Writes progress records for wrappers and test harnesses to follow playback by, every
+/// `PROGRESS_INTERVAL` and whenever the state changes.
+pub struct ProgressWriter {
+    format: ProgressFormat,
+    output: Box<dyn Write + Send>,
+    last: Option<(Instant, PlaybackState)>,
+}
+
+impl ProgressWriter {
+    /// Writes to stdout, or to the already open file descriptor `fd`, e.g. a pipe set up by
+    /// the parent process.
+    pub fn open(format: ProgressFormat, fd: Option<i32>) -> io::Result<Self> {
+        let output: Box<dyn Write + Send> = match fd {
+            None => Box::new(io::stdout()),
+            Some(fd) => Box::new(open_fd(fd)?),
+        };
+        Ok(ProgressWriter {
+            format,
+            output,
+            last: None,
+        })
+    }
+
+    /// Writes `record` if one is due; a reader gone away is no reason to stop playing.
+    pub fn update(&mut self, record: &ProgressRecord) {
+        let due = self.last.map_or(true, |(at, state)| {
+            state != record.state || at.elapsed() >= PROGRESS_INTERVAL
+        });
+        if due {
+            self.write(record);
+        }
+    }
+
+    /// Writes `record` right away, e.g. the last one when playback stops.
+    pub fn write(&mut self, record: &ProgressRecord) {
+        let line = match self.format {
+            ProgressFormat::Json => record.to_json(),
+        };
+        writeln!(self.output, "{}", line).ok();
+        self.output.flush().ok();
+        self.last = Some((Instant::now(), record.state));
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> io::Result<File> {
+    use std::os::unix::io::FromRawFd;
+
+    if fd < 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid file descriptor",
+        ));
+    }
+    // the descriptor is ours from here on, closed along with the player
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "writing progress to a file descriptor is only supported on unix",
+    ))
+}
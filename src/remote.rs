@@ -0,0 +1,375 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Error, Message, WebSocket};
+
+use crate::{
+    control::{self, Access},
+    player::PlayerController,
+};
+
+/// The page served at `/`, talking to the player over a WebSocket. `{{token}}` is replaced
+/// with the token it authenticates with.
+const PAGE: &str = include_str!("remote.html");
+
+/// Connected pages get the player's status this often.
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Requests with a longer head than this are refused.
+const MAX_HEAD_LENGTH: u64 = 8 * 1024;
+
+/// Serves a small remote control page over HTTP on `address`, e.g. `0.0.0.0:8080`, in the
+/// background, so the player can be driven from a phone's browser.
+///
+/// The page is only served at `/?token=...`, with a token made up for this run and printed
+/// with the address. It opens a WebSocket on the same port with the same token, and sends the
+/// same JSON requests as the IPC socket but `load`, see `control::respond`. It gets the
+/// position, duration and pause state back as `{"event": "status", ...}` messages.
+///
+/// WebSockets opened from pages on other sites are refused.
+pub fn serve(address: &str, controller: PlayerController) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let token: Arc<str> = new_token().into();
+    info!(
+        "remote control on http://{}/?token={}",
+        listener.local_addr()?,
+        token
+    );
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("remote: failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+
+            let controller = controller.clone();
+            let token = Arc::clone(&token);
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &controller, &token) {
+                    eprintln!("remote: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// A token nobody else can guess: the standard library seeds every `RandomState` from the
+/// system's random number generator.
+fn new_token() -> String {
+    let state = RandomState::new();
+    (0..2_u8)
+        .map(|index| {
+            let mut hasher = state.build_hasher();
+            hasher.write_u8(index);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+/// The parts of an HTTP request head the server looks at.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Parses a request line and its header lines, without the empty line ending them.
+    fn parse(lines: &[String]) -> Option<Self> {
+        let (request_line, header_lines) = lines.split_first()?;
+        let mut parts = request_line.split(' ');
+        let method = parts.next()?.to_string();
+        let target = parts.next()?;
+        if !parts.next()?.starts_with("HTTP/") {
+            return None;
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (target, ""),
+        };
+
+        let headers = header_lines
+            .iter()
+            .map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Request {
+            method,
+            path: path.to_string(),
+            query: query.to_string(),
+            headers,
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether header `name` lists `token` among its comma-separated values, like
+    /// `Connection: keep-alive, Upgrade`.
+    fn header_lists(&self, name: &str, token: &str) -> bool {
+        self.header(name).map_or(false, |value| {
+            value
+                .split(',')
+                .any(|value| value.trim().eq_ignore_ascii_case(token))
+        })
+    }
+
+    fn is_websocket_upgrade(&self) -> bool {
+        self.header_lists("connection", "upgrade") && self.header_lists("upgrade", "websocket")
+    }
+
+    /// Whether the `token` query parameter is `token`.
+    fn has_token(&self, token: &str) -> bool {
+        self.query
+            .split('&')
+            .filter_map(|parameter| parameter.split_once('='))
+            .any(|(name, value)| name == "token" && same(value.as_bytes(), token.as_bytes()))
+    }
+
+    /// Whether the request comes from a page served by this host, or from outside a
+    /// browser, which doesn't send an `Origin`.
+    fn is_same_origin(&self) -> bool {
+        let origin = match self.header("origin") {
+            Some(origin) => origin,
+            None => return true,
+        };
+        let origin_host = origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"));
+        match (origin_host, self.header("host")) {
+            (Some(origin_host), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+            _ => false,
+        }
+    }
+}
+
+/// Compares `a` and `b` in a time that doesn't depend on where they differ.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Reads a request head up to the empty line ending it, `None` if the connection closes
+/// first or it's malformed or too long.
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut lines = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .by_ref()
+            .take(MAX_HEAD_LENGTH - length)
+            .read_line(&mut line)?;
+        length += read as u64;
+        if !line.ends_with('\n') {
+            // closed or too long
+            return Ok(None);
+        }
+
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        if line.is_empty() {
+            return Ok(Request::parse(&lines));
+        }
+        lines.push(line.to_string());
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    controller: &PlayerController,
+    token: &str,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = match read_request(&mut reader)? {
+        Some(request) => request,
+        None => return write_status(reader.get_mut(), "400 Bad Request"),
+    };
+
+    if request.method != "GET" {
+        return write_status(reader.get_mut(), "405 Method Not Allowed");
+    }
+    match request.path.as_str() {
+        "/" | "/ws" if !request.has_token(token) => write_status(reader.get_mut(), "403 Forbidden"),
+        "/" => {
+            let page = PAGE.replace("{{token}}", token);
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                 Cache-Control: no-store\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                page.len(),
+                page
+            )
+        }
+        "/ws" if !request.is_same_origin() => write_status(reader.get_mut(), "403 Forbidden"),
+        "/ws" => match request.header("sec-websocket-key") {
+            Some(key)
+                if request.is_websocket_upgrade()
+                    && request.header("sec-websocket-version") == Some("13") =>
+            {
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    derive_accept_key(key.as_bytes())
+                )?;
+                // frames the client sent right after the head may be in the reader's buffer
+                let read_ahead = reader.buffer().to_vec();
+                let socket = WebSocket::from_partially_read(
+                    reader.into_inner(),
+                    read_ahead,
+                    Role::Server,
+                    None,
+                );
+                run_socket(socket, controller)
+            }
+            _ => write_status(reader.get_mut(), "400 Bad Request"),
+        },
+        _ => write_status(reader.get_mut(), "404 Not Found"),
+    }
+}
+
+/// Answers with `status` and nothing else, closing the connection.
+fn write_status(stream: &mut TcpStream, status: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )
+}
+
+fn run_socket(mut socket: WebSocket<TcpStream>, controller: &PlayerController) -> io::Result<()> {
+    // wake up regularly to send the status between requests
+    socket
+        .get_mut()
+        .set_read_timeout(Some(STATUS_INTERVAL / 5))?;
+    let mut last_status: Option<Instant> = None;
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let response = match serde_json::from_str::<Value>(&text) {
+                    Ok(request) => control::respond(&request, controller, Access::Remote),
+                    Err(e) => json!({ "error": format!("invalid request: {}", e) }),
+                };
+                send(&mut socket, response)?;
+            }
+            Ok(Message::Close(_)) | Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => {
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(Error::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => return Err(io::Error::new(ErrorKind::Other, e.to_string())),
+        }
+
+        if last_status.map_or(true, |sent| sent.elapsed() >= STATUS_INTERVAL) {
+            let status = controller.status();
+            send(
+                &mut socket,
+                json!({
+                    "event": "status",
+                    "path": status.path,
+                    "position": status.position,
+                    "duration": status.duration,
+                    "paused": status.paused,
+                    "volume": status.volume,
+                }),
+            )?;
+            last_status = Some(Instant::now());
+        }
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: Value) -> io::Result<()> {
+    socket
+        .send(Message::Text(message.to_string()))
+        .map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(head: &str) -> Request {
+        let lines: Vec<String> = head.split("\r\n").map(str::to_string).collect();
+        Request::parse(&lines).unwrap()
+    }
+
+    #[test]
+    fn parses_the_path_query_and_headers() {
+        let request =
+            request("GET /ws?token=abc HTTP/1.1\r\nHost: player:8080\r\nUpgrade: WebSocket");
+
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/ws");
+        assert!(request.has_token("abc"));
+        assert!(!request.has_token("abd"));
+        assert_eq!(request.header("host"), Some("player:8080"));
+    }
+
+    #[test]
+    fn refuses_malformed_heads() {
+        let lines = |head: &str| head.split("\r\n").map(str::to_string).collect::<Vec<_>>();
+
+        assert!(Request::parse(&lines("GET /")).is_none());
+        assert!(Request::parse(&lines("GET / HTTP/1.1\r\nno colon")).is_none());
+    }
+
+    #[test]
+    fn finds_upgrades_among_listed_values() {
+        let upgrade = request(
+            "GET /ws HTTP/1.1\r\nConnection: keep-alive, Upgrade\r\nUpgrade: WebSocket, foo",
+        );
+        let plain = request("GET /ws HTTP/1.1\r\nConnection: keep-alive\r\nUpgrade: websocket");
+
+        assert!(upgrade.is_websocket_upgrade());
+        assert!(!plain.is_websocket_upgrade());
+    }
+
+    #[test]
+    fn accepts_only_origins_on_the_same_host() {
+        let from = |origin: &str| {
+            request(&format!(
+                "GET /ws HTTP/1.1\r\nHost: player:8080\r\nOrigin: {}",
+                origin
+            ))
+        };
+
+        assert!(from("http://player:8080").is_same_origin());
+        assert!(from("http://PLAYER:8080").is_same_origin());
+        assert!(!from("http://player:8081").is_same_origin());
+        assert!(!from("https://evil.example").is_same_origin());
+        assert!(!from("null").is_same_origin());
+        assert!(request("GET /ws HTTP/1.1\r\nHost: player:8080").is_same_origin());
+    }
+
+    #[test]
+    fn makes_up_a_different_token_each_time() {
+        let token = new_token();
+
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, new_token());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ffmpeg_next::{codec, encoder, format, Dictionary, Error, Packet, Rational};
+
+/// Packets waiting for the output. An output further behind than this drops packets until
+/// the next keyframe rather than holding playback back.
+const QUEUE: usize = 512;
+
+/// An input stream carried over to a remux output.
+#[derive(Clone)]
+pub struct RemuxStream {
+    pub index: usize,
+    pub parameters: codec::Parameters,
+    pub time_base: Rational,
+    pub video: bool,
+}
+
+struct Queued {
+    index: usize,
+    packet: Packet,
+    /// The input's timestamps jumped before this packet, e.g. for a seek.
+    rebase: bool,
+}
+
+/// Writes the packets being played to another output as they are, without re-encoding:
+/// an RTMP server, a single HTTP client, or a file.
+///
+/// The output is written on a thread of its own, so one that's slow to connect or keep up
+/// never holds playback back. Its timestamps carry on across seeks and start at zero.
+pub struct Remux {
+    packets: SyncSender<Queued>,
+    video_index: Option<usize>,
+    /// Packets are dropped until the next video keyframe, at the start and after packets
+    /// had to be dropped.
+    skipping: bool,
+    rebase: bool,
+}
+
+impl Remux {
+    /// Starts writing `streams` to `url`. `http://host:port` is served to the first client
+    /// connecting there, as MPEG-TS.
+    pub fn start(url: &str, streams: Vec<RemuxStream>) -> Self {
+        let (packets, receiver) = mpsc::sync_channel(QUEUE);
+        let video_index = streams
+            .iter()
+            .find(|stream| stream.video)
+            .map(|stream| stream.index);
+
+        thread::spawn({
+            let url = url.to_string();
+            move || {
+                if let Err(e) = run(&url, &streams, receiver) {
+                    eprintln!("failed to remux to {}: {}", url, e);
+                }
+            }
+        });
+
+        Remux {
+            packets,
+            video_index,
+            skipping: video_index.is_some(),
+            rebase: false,
+        }
+    }
+
+    /// Queues `packet` from input stream `index`, in that stream's time base.
+    pub fn write(&mut self, index: usize, packet: &Packet) {
+        if self.skipping {
+            if self.video_index != Some(index) || !packet.is_key() {
+                return;
+            }
+            self.skipping = false;
+        }
+
+        let queued = Queued {
+            index,
+            packet: packet.clone(),
+            rebase: self.rebase,
+        };
+        match self.packets.try_send(queued) {
+            Ok(()) => self.rebase = false,
+            Err(TrySendError::Full(_)) => self.skipping = self.video_index.is_some(),
+            // the output failed, which was reported
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Marks a jump in the input's timestamps, e.g. a seek. The output carries on from
+    /// where it was, at the next keyframe.
+    pub fn discontinuity(&mut self) {
+        self.rebase = true;
+        self.skipping = self.video_index.is_some();
+    }
+}
+
+/// A file name like `camera_1718000000.mkv` in the current directory, for recording
+/// `input` from now on.
+pub fn default_recording(input: &str) -> PathBuf {
+    let stem = Path::new(input)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("recording");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    PathBuf::from(format!("{}_{}.mkv", stem, now))
+}
+
+/// The muxer for `url`, where its name doesn't say.
+fn output_format(url: &str) -> Option<&'static str> {
+    let scheme = url.split("://").next().unwrap_or("");
+    match scheme {
+        "rtmp" | "rtmps" => Some("flv"),
+        "http" | "udp" | "srt" | "tcp" => Some("mpegts"),
+        _ => None,
+    }
+}
+
+fn run(url: &str, streams: &[RemuxStream], packets: Receiver<Queued>) -> Result<(), Error> {
+    let mut options = Dictionary::new();
+    if url.starts_with("http://") {
+        // wait for a client rather than posting to a server
+        options.set("listen", "1");
+    }
+    // opening blocks until the server or client is there
+    let mut output = match output_format(url) {
+        Some(format) => format::output_as_with(&url, format, options)?,
+        None => format::output_with(&url, options)?,
+    };
+
+    for stream in streams {
+        let mut output_stream = output.add_stream(encoder::find(codec::Id::None))?;
+        output_stream.set_parameters(stream.parameters.clone());
+        // the input container's codec tag may mean nothing to the output's
+        unsafe {
+            (*output_stream.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+    }
+    output.write_header()?;
+    info!("remuxing to {}", url);
+
+    // seconds added to the input's timestamps, and where the output got to
+    let mut offset = 0_f64;
+    let mut end = 0_f64;
+    let mut last_dts = vec![None; streams.len()];
+    let mut first = true;
+
+    for Queued {
+        index,
+        mut packet,
+        rebase,
+    } in packets
+    {
+        let slot = match streams.iter().position(|stream| stream.index == index) {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let time_base = streams[slot].time_base;
+        let dts = match packet.dts().or_else(|| packet.pts()) {
+            Some(dts) => dts,
+            None => continue,
+        };
+
+        if first || rebase {
+            offset = end - dts as f64 * f64::from(time_base);
+            first = false;
+        }
+        let shift = (offset / f64::from(time_base)).round() as i64;
+        let dts = dts + shift;
+        // packets of other streams from before where the output carries on from
+        if dts < 0 || last_dts[slot].map_or(false, |last| dts <= last) {
+            continue;
+        }
+        last_dts[slot] = Some(dts);
+        end = end.max((dts + packet.duration().max(0)) as f64 * f64::from(time_base));
+
+        packet.set_pts(packet.pts().map(|pts| pts + shift));
+        packet.set_dts(Some(dts));
+        packet.rescale_ts(time_base, output.stream(slot).unwrap().time_base());
+        packet.set_position(-1);
+        packet.set_stream(slot);
+        packet.write_interleaved(&mut output)?;
+    }
+
+    // the player is done with the input
+    output.write_trailer()
+}
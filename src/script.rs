@@ -0,0 +1,190 @@
+use std::{cell::RefCell, path::Path, rc::Rc, time::Duration};
+
+use rhai::{Dynamic, Engine, FuncArgs, ImmutableString, Scope, AST};
+
+use crate::player::PlayerCommand;
+
+/// How long `osd(text)` shows its text, in seconds, without a duration given.
+const DEFAULT_MESSAGE_SECONDS: f64 = 2_f64;
+
+/// What a script sees of the player, and what it asked for since it was last asked.
+#[derive(Default)]
+struct State {
+    position: f64,
+    duration: f64,
+    paused: bool,
+    commands: Vec<PlayerCommand>,
+    messages: Vec<(String, Duration)>,
+}
+
+/// Commands and OSD messages a script's hooks issued.
+#[derive(Default)]
+pub struct ScriptActions {
+    pub commands: Vec<PlayerCommand>,
+    pub messages: Vec<(String, Duration)>,
+}
+
+/// A Rhai script run alongside playback. It can define any of these hooks:
+///
+/// - `on_load(path)` when playback of a file starts
+/// - `on_tick(position)` a few times a second
+/// - `on_key(key)` for a key press no binding took, as a one character string
+/// - `on_eof()` when playback reaches the end; any command issued there keeps it going
+///
+/// and call `play()`, `pause()`, `toggle_pause()`, `seek(seconds)`, `seek_by(seconds)`,
+/// `stop()`, `osd(text)` and `osd(text, seconds)`, and read `position()`, `duration()` and
+/// `paused()`. E.g. skipping an intro:
+///
+/// ```text
+/// fn on_tick(position) {
+///     if position < 85.0 {
+///         seek(85.0);
+///         osd("Skipped intro");
+///     }
+/// }
+/// ```
+pub struct Script {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Rc<RefCell<State>>,
+}
+
+impl Script {
+    /// Compiles the script at `path` and runs its top level once.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let state = Rc::new(RefCell::new(State::default()));
+        let mut engine = Engine::new();
+
+        for (name, command) in [
+            ("play", PlayerCommand::Play),
+            ("pause", PlayerCommand::Pause),
+            ("toggle_pause", PlayerCommand::TogglePause),
+            ("stop", PlayerCommand::Stop),
+        ] {
+            let state = Rc::clone(&state);
+            engine.register_fn(name, move || state.borrow_mut().commands.push(command));
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("seek", move |position: Dynamic| {
+                let command = PlayerCommand::Seek(number(&position));
+                state.borrow_mut().commands.push(command);
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("seek_by", move |offset: Dynamic| {
+                let command = PlayerCommand::SeekBy(number(&offset));
+                state.borrow_mut().commands.push(command);
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("osd", move |text: ImmutableString| {
+                let duration = Duration::from_secs_f64(DEFAULT_MESSAGE_SECONDS);
+                state
+                    .borrow_mut()
+                    .messages
+                    .push((text.to_string(), duration));
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("osd", move |text: ImmutableString, seconds: Dynamic| {
+                let duration = Duration::from_secs_f64(number(&seconds).max(0_f64));
+                state
+                    .borrow_mut()
+                    .messages
+                    .push((text.to_string(), duration));
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("position", move || state.borrow().position);
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("duration", move || state.borrow().duration);
+        }
+        {
+            let state = Rc::clone(&state);
+            engine.register_fn("paused", move || state.borrow().paused);
+        }
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Script {
+            name: path.display().to_string(),
+            engine,
+            ast,
+            scope,
+            state,
+        })
+    }
+
+    /// Updates what `position()`, `duration()` and `paused()` return.
+    pub fn set_state(&mut self, position: f64, duration: Option<f64>, paused: bool) {
+        let mut state = self.state.borrow_mut();
+        state.position = position;
+        state.duration = duration.unwrap_or(0_f64);
+        state.paused = paused;
+    }
+
+    pub fn on_load(&mut self, path: &str) {
+        self.call("on_load", (path.to_string(),));
+    }
+
+    pub fn on_tick(&mut self, position: f64) {
+        self.call("on_tick", (position,));
+    }
+
+    pub fn on_key(&mut self, key: char) {
+        self.call("on_key", (key.to_string(),));
+    }
+
+    pub fn on_eof(&mut self) {
+        self.call("on_eof", ());
+    }
+
+    /// Takes the commands and messages issued since the last call.
+    pub fn take_actions(&mut self) -> ScriptActions {
+        let mut state = self.state.borrow_mut();
+        ScriptActions {
+            commands: state.commands.drain(..).collect(),
+            messages: state.messages.drain(..).collect(),
+        }
+    }
+
+    fn call(&mut self, hook: &str, args: impl FuncArgs) {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == hook)
+        {
+            return;
+        }
+
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut self.scope, &self.ast, hook, args);
+        if let Err(e) = result {
+            eprintln!("script {}: {} failed: {}", self.name, hook, e);
+        }
+    }
+}
+
+/// Scripts may pass whole numbers where seconds are expected.
+fn number(value: &Dynamic) -> f64 {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|value| value as f64))
+        .unwrap_or(0_f64)
+}
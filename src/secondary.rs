@@ -0,0 +1,231 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use ffmpeg_next::{
+    decoder,
+    frame::{Audio, Video},
+};
+
+use crate::{
+    asset::{PlaybackAsset, PlaybackAssetMetadata},
+    options::Options,
+};
+
+/// Decoded frames held ahead of the clock; a second input only needs to stay a little ahead.
+const MAX_VIDEO_FRAMES: usize = 8;
+const MAX_AUDIO_FRAMES: usize = 32;
+
+struct Shared {
+    video: VecDeque<Video>,
+    audio: VecDeque<Audio>,
+    seek: Option<f64>,
+    stopped: bool,
+}
+
+/// A second input decoded on its own thread and taken frame by frame against the main
+/// clock, for picture-in-picture and comparisons.
+///
+/// It has no buffering or clock of its own: frames are handed out once the main clock
+/// reaches them, and seeks follow the main input's.
+pub struct SecondarySource {
+    metadata: PlaybackAssetMetadata,
+    shared: Arc<Mutex<Shared>>,
+    current: Option<Video>,
+}
+
+impl SecondarySource {
+    /// Opens `path` and starts decoding its video, if it has any, and its audio too when
+    /// `with_audio`.
+    pub fn open(path: &str, options: &Options, with_audio: bool) -> Self {
+        let mut asset = PlaybackAsset::new(path, options);
+        let metadata = asset.metadata();
+        let mut video_decoder = if metadata.has_video() {
+            Some(asset.video_decoder(options))
+        } else {
+            None
+        };
+        let mut audio_decoder = asset.audio_decoder(options);
+
+        let shared = Arc::new(Mutex::new(Shared {
+            video: VecDeque::new(),
+            audio: VecDeque::new(),
+            seek: None,
+            stopped: false,
+        }));
+
+        thread::spawn({
+            let shared = Arc::clone(&shared);
+
+            move || {
+                let mut discard_before = None;
+
+                loop {
+                    {
+                        let mut shared = shared.lock().unwrap();
+                        if shared.stopped {
+                            return;
+                        }
+                        if let Some(position) = shared.seek.take() {
+                            if let Err(e) = asset.seek(position) {
+                                eprintln!(
+                                    "failed to seek {} to {:.2}s: {}",
+                                    asset.path(),
+                                    position,
+                                    e
+                                );
+                            }
+                            if let Some(decoder) = &mut video_decoder {
+                                decoder.flush();
+                            }
+                            audio_decoder.flush();
+                            shared.video.clear();
+                            shared.audio.clear();
+                            discard_before = Some(position);
+                        }
+
+                        let full = shared.video.len() >= MAX_VIDEO_FRAMES
+                            || (with_audio && shared.audio.len() >= MAX_AUDIO_FRAMES);
+                        if full {
+                            drop(shared);
+                            thread::sleep(Duration::from_millis(5));
+                            continue;
+                        }
+                    }
+
+                    let (stream, packet) = match asset.packets().next() {
+                        Some(packet) => packet,
+                        None => {
+                            // keep showing the last frame once the input ends
+                            thread::sleep(Duration::from_millis(20));
+                            continue;
+                        }
+                    };
+
+                    if Some(stream.index()) == metadata.video_stream_index() {
+                        let decoder = match &mut video_decoder {
+                            Some(decoder) => decoder,
+                            None => continue,
+                        };
+                        let frames = decode_video(decoder, &packet);
+                        let mut shared = shared.lock().unwrap();
+                        for frame in frames {
+                            if !is_before(frame.pts(), metadata.video_time_base(), discard_before) {
+                                shared.video.push_back(frame);
+                            }
+                        }
+                    } else if with_audio && Some(stream.index()) == metadata.audio_stream_index() {
+                        let frames = decode_audio(&mut audio_decoder, &packet);
+                        let mut shared = shared.lock().unwrap();
+                        for frame in frames {
+                            if !is_before(frame.pts(), metadata.audio_time_base(), discard_before) {
+                                shared.audio.push_back(frame);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        SecondarySource {
+            metadata,
+            shared,
+            current: None,
+        }
+    }
+
+    pub fn metadata(&self) -> PlaybackAssetMetadata {
+        self.metadata
+    }
+
+    /// Follows a seek of the main input.
+    pub fn seek(&mut self, position: f64) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.seek = Some(position);
+        shared.video.clear();
+        shared.audio.clear();
+        self.current = None;
+    }
+
+    /// The newest frame due at `position` seconds, or the last one shown while the next
+    /// isn't due yet.
+    pub fn video_at(&mut self, position: f64) -> Option<&Video> {
+        let time_base = self.metadata.video_time_base();
+        {
+            let mut shared = self.shared.lock().unwrap();
+            while shared.video.front().map_or(false, |frame| {
+                frame
+                    .pts()
+                    .map_or(true, |pts| pts as f64 * time_base <= position)
+            }) {
+                self.current = shared.video.pop_front();
+            }
+        }
+        self.current.as_ref()
+    }
+
+    /// The frame due at `position` seconds if it's the first since a seek, i.e. frames shown
+    /// before were shown without this input, which hadn't caught up yet.
+    pub fn caught_up(&mut self, position: f64) -> Option<&Video> {
+        if self.current.is_some() {
+            return None;
+        }
+        self.video_at(position)
+    }
+
+    /// Audio frames due at `position` seconds, in order.
+    pub fn audio_until(&mut self, position: f64) -> Vec<Audio> {
+        let time_base = self.metadata.audio_time_base();
+        let mut shared = self.shared.lock().unwrap();
+        let mut frames = Vec::new();
+        while shared.audio.front().map_or(false, |frame| {
+            frame
+                .pts()
+                .map_or(true, |pts| pts as f64 * time_base <= position)
+        }) {
+            frames.extend(shared.audio.pop_front());
+        }
+        frames
+    }
+}
+
+impl Drop for SecondarySource {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().stopped = true;
+    }
+}
+
+/// Whether a frame at `pts` comes before the seek target still being decoded up to.
+fn is_before(pts: Option<i64>, time_base: f64, target: Option<f64>) -> bool {
+    match (pts, target) {
+        (Some(pts), Some(target)) => (pts as f64 * time_base) < target,
+        _ => false,
+    }
+}
+
+fn decode_video(decoder: &mut decoder::Video, packet: &ffmpeg_next::Packet) -> Vec<Video> {
+    let mut frames = Vec::new();
+    if decoder.send_packet(packet).is_ok() {
+        let mut frame = Video::empty();
+        while decoder.receive_frame(&mut frame).is_ok() {
+            frames.push(frame);
+            frame = Video::empty();
+        }
+    }
+    frames
+}
+
+fn decode_audio(decoder: &mut decoder::Audio, packet: &ffmpeg_next::Packet) -> Vec<Audio> {
+    let mut frames = Vec::new();
+    if decoder.send_packet(packet).is_ok() {
+        let mut frame = Audio::empty();
+        while decoder.receive_frame(&mut frame).is_ok() {
+            frames.push(frame);
+            frame = Audio::empty();
+        }
+    }
+    frames
+}
@@ -0,0 +1,175 @@
+use ffmpeg_next::{
+    codec,
+    format::{sample::Type as AudioType, Sample},
+    frame, ChannelLayout, Packet,
+};
+
+/// IEC 61937 burst preamble sync words.
+const PA: u16 = 0xf872;
+const PB: u16 = 0x4e1f;
+
+/// IEC 61937 data types.
+const TYPE_AC3: u16 = 0x01;
+const TYPE_DTS1: u16 = 0x0b;
+const TYPE_DTS2: u16 = 0x0c;
+const TYPE_DTS3: u16 = 0x0d;
+const TYPE_EAC3: u16 = 0x15;
+
+/// E-AC-3 frames per burst, by the number of audio blocks per frame.
+const EAC3_REPEAT: [usize; 4] = [6, 3, 2, 1];
+
+/// Wraps compressed AC-3, E-AC-3 and DTS packets into IEC 61937 bursts, which a S/PDIF or
+/// HDMI receiver takes as if they were 16-bit stereo PCM.
+///
+/// Bursts come out as audio frames so they go through the same buffering and scheduling
+/// as decoded audio.
+pub struct SpdifPacker {
+    codec: codec::Id,
+    sample_rate: u32,
+    pending: Vec<u8>,
+    pending_frames: usize,
+    pending_pts: Option<i64>,
+}
+
+impl SpdifPacker {
+    /// Returns `None` for codecs that can't be passed through.
+    pub fn new(codec: codec::Id, sample_rate: u32) -> Option<Self> {
+        match codec {
+            codec::Id::AC3 | codec::Id::EAC3 | codec::Id::DTS => Some(SpdifPacker {
+                codec,
+                sample_rate,
+                pending: Vec::new(),
+                pending_frames: 0,
+                pending_pts: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Rate the output device has to run at. E-AC-3 needs the 4x rate of HDMI.
+    pub fn sample_rate(&self) -> u32 {
+        if self.codec == codec::Id::EAC3 {
+            self.sample_rate * 4
+        } else {
+            self.sample_rate
+        }
+    }
+
+    /// Drops a partially collected burst, after a seek.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.pending_frames = 0;
+        self.pending_pts = None;
+    }
+
+    /// Wraps `packet` into a burst; E-AC-3 only yields one every few packets.
+    pub fn pack(&mut self, packet: &Packet) -> Option<frame::Audio> {
+        let data = packet.data()?;
+
+        let (data_type, period) = match self.codec {
+            codec::Id::AC3 => {
+                // bit stream mode goes into the burst info
+                let mode = *data.get(5)? as u16 & 0x07;
+                (TYPE_AC3 | mode << 8, 1536)
+            }
+            codec::Id::DTS => {
+                // only the common big-endian 16-bit core stream
+                if data.get(..4)? != [0x7f, 0xfe, 0x80, 0x01] {
+                    return None;
+                }
+                let blocks = ((data.get(4)? & 0x01) as usize) << 6 | (data.get(5)? >> 2) as usize;
+                match (blocks + 1) * 32 {
+                    512 => (TYPE_DTS1, 512),
+                    1024 => (TYPE_DTS2, 1024),
+                    2048 => (TYPE_DTS3, 2048),
+                    _ => return None,
+                }
+            }
+            _ => {
+                let header = *data.get(4)?;
+                let repeat = if header & 0xc0 == 0xc0 {
+                    1
+                } else {
+                    EAC3_REPEAT[(header >> 4) as usize & 0x03]
+                };
+
+                if self.pending_frames == 0 {
+                    self.pending_pts = packet.pts();
+                }
+                self.pending.extend_from_slice(data);
+                self.pending_frames += 1;
+                if self.pending_frames < repeat {
+                    return None;
+                }
+
+                let payload = std::mem::take(&mut self.pending);
+                let pts = self.pending_pts.take();
+                self.pending_frames = 0;
+                return burst(
+                    TYPE_EAC3,
+                    payload.len() as u16,
+                    &payload,
+                    6144,
+                    self.sample_rate(),
+                    pts,
+                );
+            }
+        };
+
+        burst(
+            data_type,
+            (data.len() * 8) as u16,
+            data,
+            period,
+            self.sample_rate(),
+            packet.pts(),
+        )
+    }
+}
+
+/// Builds a burst of `period` stereo samples: the preamble, the payload as big-endian
+/// 16-bit words, and zero padding.
+fn burst(
+    data_type: u16,
+    length: u16,
+    payload: &[u8],
+    period: usize,
+    sample_rate: u32,
+    pts: Option<i64>,
+) -> Option<frame::Audio> {
+    let words = period * 2;
+    if 4 + (payload.len() + 1) / 2 > words {
+        // doesn't fit the repetition period, not something a receiver would accept
+        return None;
+    }
+
+    let mut samples = Vec::with_capacity(words);
+    samples.extend_from_slice(&[PA, PB, data_type, length]);
+    samples.extend(
+        payload
+            .chunks(2)
+            .map(|pair| (pair[0] as u16) << 8 | pair.get(1).copied().unwrap_or(0) as u16),
+    );
+    samples.resize(words, 0);
+
+    let mut frame = frame::Audio::new(
+        Sample::I16(AudioType::Packed),
+        period,
+        ChannelLayout::STEREO,
+    );
+    frame.set_rate(sample_rate);
+    frame.set_pts(pts);
+    for (bytes, sample) in frame.data_mut(0).chunks_exact_mut(2).zip(&samples) {
+        bytes.copy_from_slice(&sample.to_ne_bytes());
+    }
+    Some(frame)
+}
+
+/// The 16-bit words of a burst made by `SpdifPacker`, for the audio output.
+pub fn burst_samples(frame: &frame::Audio) -> Vec<i16> {
+    let len = frame.samples() * 2;
+    frame.data(0)[..len * 2]
+        .chunks_exact(2)
+        .map(|bytes| i16::from_ne_bytes([bytes[0], bytes[1]]))
+        .collect()
+}
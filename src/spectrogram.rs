@@ -0,0 +1,186 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use crate::{
+    audio_sink::AudioSpec,
+    osd::{Color, OsdTarget},
+};
+
+/// Samples per FFT, and so per column.
+const WINDOW: usize = 1024;
+/// Frequency bands, log spaced between `LOWEST` and `HIGHEST` Hz.
+const BANDS: usize = 48;
+const LOWEST: f32 = 40_f32;
+const HIGHEST: f32 = 16_000_f32;
+/// Columns kept, a few seconds' worth at common sample rates.
+const COLUMNS: usize = 160;
+/// Levels below this many dB under full scale are drawn as silence.
+const FLOOR_DB: f32 = -90_f32;
+
+const CELL: u32 = 3;
+const MARGIN: i32 = 16;
+const BACKGROUND: Color = Color::rgba(0, 0, 0, 160);
+
+/// A scrolling spectrogram of the audio being played, drawn in the bottom right corner of
+/// the OSD while shown: the newest column on the right, low frequencies at the bottom.
+///
+/// Audio is taken as it's written to the sink, so the newest column is the sink's latency
+/// ahead of what's heard.
+pub(crate) struct Spectrogram {
+    visible: bool,
+    /// Mono samples still short of a full window.
+    pending: Vec<f32>,
+    /// Levels from 0 to 1 per band, the newest last.
+    columns: VecDeque<[f32; BANDS]>,
+}
+
+impl Spectrogram {
+    pub fn new() -> Self {
+        Spectrogram {
+            visible: false,
+            pending: Vec::with_capacity(WINDOW),
+            columns: VecDeque::with_capacity(COLUMNS),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Shows or hides the spectrogram, returning whether it's shown now. It starts out empty
+    /// every time it's shown.
+    pub fn toggle(&mut self) -> bool {
+        self.visible = !self.visible;
+        self.pending.clear();
+        self.columns.clear();
+        self.visible
+    }
+
+    /// Adds interleaved `samples` in `spec`, nothing while hidden.
+    pub fn push(&mut self, samples: &[f32], spec: AudioSpec) {
+        if !self.visible || spec.channels == 0 {
+            return;
+        }
+
+        let channels = spec.channels as usize;
+        for frame in samples.chunks_exact(channels) {
+            self.pending
+                .push(frame.iter().sum::<f32>() / channels as f32);
+            if self.pending.len() == WINDOW {
+                let column = analyze(&self.pending, spec.sample_rate);
+                self.pending.clear();
+                if self.columns.len() == COLUMNS {
+                    self.columns.pop_front();
+                }
+                self.columns.push_back(column);
+            }
+        }
+    }
+
+    /// `bottom` is how much of the bottom of `target` is taken, by the control bar.
+    pub fn draw<T: OsdTarget>(&self, target: &mut T, bottom: i32) {
+        if !self.visible {
+            return;
+        }
+
+        let (width, height) = target.size();
+        let x = width as i32 - MARGIN - (COLUMNS as u32 * CELL) as i32;
+        let y = height as i32 - bottom - MARGIN - (BANDS as u32 * CELL) as i32;
+        target.fill_rect(x, y, COLUMNS as u32 * CELL, BANDS as u32 * CELL, BACKGROUND);
+
+        // the newest column on the right edge
+        let first = COLUMNS - self.columns.len();
+        for (index, column) in self.columns.iter().enumerate() {
+            let column_x = x + ((first + index) as u32 * CELL) as i32;
+            for (band, level) in column.iter().enumerate() {
+                // silence is left to the background, which saves drawing most cells
+                if *level <= 0_f32 {
+                    continue;
+                }
+                let band_y = y + ((BANDS - 1 - band) as u32 * CELL) as i32;
+                target.fill_rect(column_x, band_y, CELL, CELL, heat(*level));
+            }
+        }
+    }
+}
+
+/// Levels per band from 0 to 1 of one window of mono samples.
+fn analyze(samples: &[f32], sample_rate: u32) -> [f32; BANDS] {
+    // Hann window against leakage between bins
+    let mut bins: Vec<(f32, f32)> = samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| {
+            let weight = 0.5_f32 - 0.5_f32 * (2_f32 * PI * index as f32 / WINDOW as f32).cos();
+            (sample * weight, 0_f32)
+        })
+        .collect();
+    fft(&mut bins);
+
+    let bin_width = sample_rate as f32 / WINDOW as f32;
+    let highest = HIGHEST.min(sample_rate as f32 / 2_f32);
+    let edge = |band: usize| LOWEST * (highest / LOWEST).powf(band as f32 / BANDS as f32);
+
+    let mut levels = [0_f32; BANDS];
+    for (band, level) in levels.iter_mut().enumerate() {
+        // low bands are narrower than a bin, they take the bin they fall in
+        let start = ((edge(band) / bin_width) as usize).max(1);
+        let end = ((edge(band + 1) / bin_width) as usize)
+            .max(start + 1)
+            .min(WINDOW / 2);
+        let magnitude = bins[start.min(end - 1)..end]
+            .iter()
+            .map(|(re, im)| (re * re + im * im).sqrt())
+            .fold(0_f32, f32::max);
+
+        // a full scale sine peaks at a quarter of the window with the Hann window applied
+        let db = 20_f32 * (magnitude / (WINDOW as f32 / 4_f32)).max(1e-9).log10();
+        *level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0_f32, 1_f32);
+    }
+    levels
+}
+
+/// In place radix-2 FFT, `bins.len()` being a power of two.
+fn fft(bins: &mut [(f32, f32)]) {
+    let n = bins.len();
+
+    // bit reversed order
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            bins.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2_f32 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = bins[start + k + length / 2];
+                let twiddled = (re * cos - im * sin, re * sin + im * cos);
+                let even = bins[start + k];
+                bins[start + k] = (even.0 + twiddled.0, even.1 + twiddled.1);
+                bins[start + k + length / 2] = (even.0 - twiddled.0, even.1 - twiddled.1);
+            }
+        }
+        length <<= 1;
+    }
+}
+
+/// Dark blue through red to yellow as `level` goes from 0 to 1.
+fn heat(level: f32) -> Color {
+    let channel = |value: f32| (value.clamp(0_f32, 1_f32) * 255_f32) as u8;
+    Color::rgba(
+        channel(level * 2_f32),
+        channel(level * 2_f32 - 1_f32),
+        channel(0.5_f32 - level),
+        224,
+    )
+}
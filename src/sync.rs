@@ -0,0 +1,171 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// Every datagram starts with this, anything else on the port is ignored.
+const MAGIC: &str = "vpsync1";
+
+/// The master sends its state this often, and right away when it pauses or resumes.
+const STATE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Clients say hello this often; the master forgets those it hasn't heard from in a while.
+const HELLO_INTERVAL: Duration = Duration::from_secs(1);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Position and pause state of the master's playback, as last received.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncState {
+    position: f64,
+    paused: bool,
+    received: Instant,
+}
+
+impl SyncState {
+    /// Where the master is now, accounting for the time since the state arrived.
+    pub fn position(&self) -> f64 {
+        if self.paused {
+            self.position
+        } else {
+            self.position + self.received.elapsed().as_secs_f64()
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/// Sends this instance's playback state over UDP to every client that has said hello, so
+/// they can follow it.
+pub struct SyncMaster {
+    socket: UdpSocket,
+    clients: Vec<(SocketAddr, Instant)>,
+    last_sent: Option<(Instant, bool)>,
+}
+
+impl SyncMaster {
+    /// Listens for clients on `address`, e.g. `0.0.0.0:7788`.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(SyncMaster {
+            socket,
+            clients: Vec::new(),
+            last_sent: None,
+        })
+    }
+
+    /// Takes in new clients and sends them the current state when it's due.
+    pub fn publish(&mut self, position: f64, paused: bool) {
+        let mut buffer = [0_u8; 64];
+        while let Ok((length, address)) = self.socket.recv_from(&mut buffer) {
+            if &buffer[..length] != format!("{} hello", MAGIC).as_bytes() {
+                continue;
+            }
+            match self
+                .clients
+                .iter_mut()
+                .find(|(client, _)| *client == address)
+            {
+                Some((_, seen)) => *seen = Instant::now(),
+                None => {
+                    info!("sync: {} joined", address);
+                    self.clients.push((address, Instant::now()));
+                }
+            }
+        }
+        self.clients.retain(|(address, seen)| {
+            let alive = seen.elapsed() < CLIENT_TIMEOUT;
+            if !alive {
+                info!("sync: {} left", address);
+            }
+            alive
+        });
+
+        let due = self.last_sent.map_or(true, |(sent, was_paused)| {
+            was_paused != paused || sent.elapsed() >= STATE_INTERVAL
+        });
+        if !due {
+            return;
+        }
+
+        let message = format!("{} state {:.3} {}", MAGIC, position, paused as u8);
+        for (address, _) in &self.clients {
+            // a client that went away is dropped once it stops saying hello
+            self.socket.send_to(message.as_bytes(), address).ok();
+        }
+        self.last_sent = Some((Instant::now(), paused));
+    }
+}
+
+/// Follows a `SyncMaster`'s playback state.
+pub struct SyncClient {
+    socket: UdpSocket,
+    master: SocketAddr,
+    last_hello: Option<Instant>,
+}
+
+impl SyncClient {
+    /// Joins the master listening at `address`, e.g. `192.168.1.20:7788`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let master = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found"))?;
+        let local = if master.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(SyncClient {
+            socket,
+            master,
+            last_hello: None,
+        })
+    }
+
+    /// The newest state the master sent since the last call, if any.
+    pub fn poll(&mut self) -> Option<SyncState> {
+        if self
+            .last_hello
+            .map_or(true, |sent| sent.elapsed() >= HELLO_INTERVAL)
+        {
+            let hello = format!("{} hello", MAGIC);
+            self.socket.send_to(hello.as_bytes(), self.master).ok();
+            self.last_hello = Some(Instant::now());
+        }
+
+        let mut state = None;
+        let mut buffer = [0_u8; 64];
+        while let Ok((length, address)) = self.socket.recv_from(&mut buffer) {
+            if address != self.master {
+                continue;
+            }
+            state = parse_state(&buffer[..length]).or(state);
+        }
+        state
+    }
+}
+
+/// Reads a `vpsync1 state <position> <paused>` datagram.
+fn parse_state(message: &[u8]) -> Option<SyncState> {
+    let message = std::str::from_utf8(message).ok()?;
+    let mut parts = message.split(' ');
+    if parts.next()? != MAGIC || parts.next()? != "state" {
+        return None;
+    }
+
+    let position = parts.next()?.parse().ok()?;
+    let paused = parts.next()? == "1";
+    Some(SyncState {
+        position,
+        paused,
+        received: Instant::now(),
+    })
+}
@@ -0,0 +1,237 @@
+use ffmpeg_next::{ffi::AV_NOPTS_VALUE, Frame, Packet, Stream};
+
+/// Decode timestamps only grow, so going back by more than this many seconds is a
+/// discontinuity rather than jitter.
+const MAX_BACKWARD_JUMP: f64 = 1_f64;
+
+/// Going forward by more than this many seconds is a discontinuity rather than a gap in
+/// the stream.
+const MAX_FORWARD_JUMP: f64 = 10_f64;
+
+/// Rebases one stream's packet timestamps onto a timeline that keeps going forward, for
+/// inputs whose timestamps jump: concatenated MPEG-TS files, looping live streams, and
+/// timestamps wrapping around at 33 bits. Playback then carries on across the jump instead
+/// of waiting for a clock that will never catch up.
+pub struct Timeline {
+    time_base: f64,
+    /// Added to every timestamp, in the stream's time base.
+    offset: i64,
+    /// The last packet's rebased decode timestamp and its duration.
+    last: Option<(i64, i64)>,
+}
+
+impl Timeline {
+    /// `time_base` is that of the stream's timestamps, in seconds.
+    pub fn new(time_base: f64) -> Self {
+        Timeline {
+            time_base,
+            offset: 0,
+            last: None,
+        }
+    }
+
+    /// Starts over after a seek, which lands on the input's own timestamps.
+    pub fn reset(&mut self) {
+        self.offset = 0;
+        self.last = None;
+    }
+
+    /// Shifts `packet`'s timestamps by the current offset, first moving the offset on if
+    /// the packet jumps away from the ones before it.
+    pub fn rebase(&mut self, stream: &Stream, packet: &mut Packet) {
+        let dts = match packet.dts().or_else(|| packet.pts()) {
+            Some(dts) => dts,
+            None => return,
+        };
+
+        let offset = self.offset_for(dts, packet.duration(), wrap_period(stream));
+        if offset != 0 {
+            packet.set_pts(packet.pts().map(|pts| pts + offset));
+            packet.set_dts(packet.dts().map(|dts| dts + offset));
+        }
+    }
+
+    /// The offset for a packet decoded at `dts` lasting `duration`, moved on first if it
+    /// jumps away from the ones before it. `wrap_period` is how far the stream's timestamps
+    /// go before wrapping around.
+    fn offset_for(&mut self, dts: i64, duration: i64, wrap_period: Option<i64>) -> i64 {
+        if let Some((last, last_duration)) = self.last {
+            let time_base = self.time_base;
+            let jump = |offset: i64| (dts + offset - last) as f64 * time_base;
+            let continuous =
+                |offset: i64| (-MAX_BACKWARD_JUMP..=MAX_FORWARD_JUMP).contains(&jump(offset));
+
+            if !continuous(self.offset) {
+                let wrapped = wrap_period
+                    .map(|period| self.offset + period)
+                    .filter(|offset| continuous(*offset));
+                match wrapped {
+                    Some(offset) => {
+                        info!("timestamps wrapped around, carrying on");
+                        self.offset = offset;
+                    }
+                    None => {
+                        info!(
+                            "timestamp discontinuity of {:+.3}s, rebasing",
+                            jump(self.offset)
+                        );
+                        // carry on right after the last packet
+                        self.offset = last + last_duration.max(1) - dts;
+                    }
+                }
+            }
+        }
+
+        self.last = Some((dts + self.offset, duration));
+        self.offset
+    }
+}
+
+/// How far a stream's timestamps go before wrapping around to 0, e.g. 2^33 in MPEG-TS.
+fn wrap_period(stream: &Stream) -> Option<i64> {
+    let bits = unsafe { (*stream.as_ptr()).pts_wrap_bits };
+    if bits > 0 && bits < 63 {
+        Some(1_i64 << bits)
+    } else {
+        None
+    }
+}
+
+/// Gives decoded frames without a presentation timestamp one, so they get shown instead of
+/// clogging the rendering buffer: the decoder's best effort guess, else the packet's decode
+/// timestamp, else right after the frame before, as in raw streams without any timestamps.
+#[derive(Default)]
+pub struct FrameTimestamps {
+    /// Where the frame after the last one starts, in the stream's time base.
+    next: Option<i64>,
+}
+
+impl FrameTimestamps {
+    /// Forgets the frames before a seek.
+    pub fn reset(&mut self) {
+        self.next = None;
+    }
+
+    /// `duration` is how long `frame` lasts, in the stream's time base.
+    pub fn stamp(&mut self, frame: &mut Frame, duration: i64) {
+        let dts = Some(frame.packet().dts).filter(|dts| *dts != AV_NOPTS_VALUE);
+        let pts = self.pts_for(frame.pts(), frame.timestamp(), dts, duration);
+        frame.set_pts(Some(pts));
+    }
+
+    /// The timestamp of a frame with the `pts`, `best_effort` guess and `dts` it has, of
+    /// which the first known one is taken.
+    fn pts_for(
+        &mut self,
+        pts: Option<i64>,
+        best_effort: Option<i64>,
+        dts: Option<i64>,
+        duration: i64,
+    ) -> i64 {
+        let pts = pts.or(best_effort).or(dts).or(self.next).unwrap_or(0);
+        self.next = Some(pts + duration.max(1));
+        pts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MPEG-TS timestamps, 90kHz wrapping around at 33 bits.
+    const TIME_BASE: f64 = 1_f64 / 90_000_f64;
+    const WRAP: Option<i64> = Some(1 << 33);
+    const FRAME: i64 = 3_000;
+
+    #[test]
+    fn continuous_timestamps_stay() {
+        let mut timeline = Timeline::new(TIME_BASE);
+        assert_eq!(timeline.offset_for(0, FRAME, WRAP), 0);
+        assert_eq!(timeline.offset_for(FRAME, FRAME, WRAP), 0);
+        // B-frames and jitter go back a little
+        assert_eq!(timeline.offset_for(FRAME / 2, FRAME, WRAP), 0);
+        // a gap of a few seconds is one in the stream
+        assert_eq!(timeline.offset_for(9 * 90_000, FRAME, WRAP), 0);
+    }
+
+    #[test]
+    fn jumps_carry_on_after_the_last_packet() {
+        let mut timeline = Timeline::new(TIME_BASE);
+        timeline.offset_for(90_000, FRAME, WRAP);
+
+        // forward, as where concatenated files meet
+        let offset = timeline.offset_for(100 * 90_000, FRAME, WRAP);
+        assert_eq!(100 * 90_000 + offset, 90_000 + FRAME);
+        assert_eq!(
+            timeline.offset_for(100 * 90_000 + FRAME, FRAME, WRAP),
+            offset
+        );
+
+        // backward, as a looping stream starts over
+        let last = 100 * 90_000 + FRAME + offset;
+        let offset = timeline.offset_for(0, FRAME, WRAP);
+        assert_eq!(offset, last + FRAME);
+    }
+
+    #[test]
+    fn jumps_after_packets_without_a_duration() {
+        let mut timeline = Timeline::new(TIME_BASE);
+        timeline.offset_for(180_000, 0, None);
+        assert_eq!(timeline.offset_for(0, 0, None), 180_001);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut timeline = Timeline::new(TIME_BASE);
+        let period = WRAP.unwrap();
+        timeline.offset_for(period - FRAME, FRAME, WRAP);
+        assert_eq!(timeline.offset_for(0, FRAME, WRAP), period);
+        assert_eq!(timeline.offset_for(FRAME, FRAME, WRAP), period);
+
+        // and again, once the timestamps went all the way round once more
+        timeline.last = Some((2 * period - FRAME, FRAME));
+        assert_eq!(timeline.offset_for(0, FRAME, WRAP), 2 * period);
+    }
+
+    #[test]
+    fn discontinuities_near_the_wrap_point_rebase() {
+        let mut timeline = Timeline::new(TIME_BASE);
+        let period = WRAP.unwrap();
+        timeline.offset_for(period - FRAME, FRAME, WRAP);
+        // wrapping would land a minute ahead, which is a jump too
+        let dts = 60 * 90_000;
+        assert_eq!(timeline.offset_for(dts, FRAME, WRAP), period - dts);
+    }
+
+    #[test]
+    fn reset_goes_back_to_the_input_timestamps() {
+        let mut timeline = Timeline::new(TIME_BASE);
+        timeline.offset_for(100 * 90_000, FRAME, WRAP);
+        assert_ne!(timeline.offset_for(0, FRAME, WRAP), 0);
+        timeline.reset();
+        assert_eq!(timeline.offset_for(50 * 90_000, FRAME, WRAP), 0);
+    }
+
+    #[test]
+    fn stamps_the_first_timestamp_known() {
+        let mut timestamps = FrameTimestamps::default();
+        assert_eq!(timestamps.pts_for(Some(10), Some(20), Some(30), 5), 10);
+        assert_eq!(timestamps.pts_for(None, Some(20), Some(30), 5), 20);
+        assert_eq!(timestamps.pts_for(None, None, Some(30), 5), 30);
+    }
+
+    #[test]
+    fn stamps_frames_without_timestamps_one_after_the_other() {
+        let mut timestamps = FrameTimestamps::default();
+        assert_eq!(timestamps.pts_for(None, None, None, 5), 0);
+        assert_eq!(timestamps.pts_for(None, None, None, 5), 5);
+        // frames last a tick at least
+        assert_eq!(timestamps.pts_for(None, None, None, 0), 10);
+        assert_eq!(timestamps.pts_for(None, None, None, 5), 11);
+        assert_eq!(timestamps.pts_for(Some(100), None, None, 5), 100);
+        assert_eq!(timestamps.pts_for(None, None, None, 5), 105);
+
+        timestamps.reset();
+        assert_eq!(timestamps.pts_for(None, None, None, 5), 0);
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use ffmpeg_next::{codec::packet::Flags, Packet};
+
+use crate::{asset::PlaybackAsset, timeline::Timeline};
+
+/// Segments are cut at the first keyframe this many seconds into one, so seeks land at most
+/// a segment and a GOP before where they're aimed.
+const SEGMENT_LENGTH: f64 = 2_f64;
+
+/// Stream index, flags, pts, dts, duration and size ahead of every packet's data.
+const HEADER_SIZE: usize = 4 + 1 + 8 + 8 + 8 + 4;
+
+/// Stands in for a missing timestamp.
+const NO_TIMESTAMP: i64 = i64::MIN;
+
+/// Rings started by this process, each input played getting a directory of its own.
+static RINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// The next packet to play.
+pub enum Next {
+    Packet(usize, Packet),
+    /// Playback caught up with the live edge, more is on its way.
+    Waiting,
+    /// The live input ended and everything recorded was played.
+    End,
+}
+
+struct Segment {
+    id: u64,
+    /// Presentation time of its first packet, a keyframe, in seconds.
+    start: f64,
+    /// Bytes written so far, all of them whole packets.
+    size: u64,
+}
+
+struct Ring {
+    directory: PathBuf,
+    /// Seconds kept behind the live edge.
+    length: f64,
+    segments: VecDeque<Segment>,
+    writer: Option<BufWriter<File>>,
+    /// Presentation time of the newest packet, in seconds.
+    live_edge: f64,
+    ended: bool,
+}
+
+impl Ring {
+    fn path(&self, id: u64) -> PathBuf {
+        self.directory.join(format!("{}.packets", id))
+    }
+
+    fn append(&mut self, index: usize, packet: &Packet, time: f64, cut: bool) -> io::Result<()> {
+        if cut || self.writer.is_none() {
+            let id = self.segments.back().map_or(0, |segment| segment.id + 1);
+            self.writer = Some(BufWriter::new(File::create(self.path(id))?));
+            self.segments.push_back(Segment {
+                id,
+                start: time,
+                size: 0,
+            });
+
+            // the oldest segment goes once the one after it alone covers the length
+            while self.segments.len() > 2 && self.live_edge - self.segments[1].start > self.length {
+                if let Some(segment) = self.segments.pop_front() {
+                    fs::remove_file(self.path(segment.id)).ok();
+                }
+            }
+        }
+
+        let data = packet.data().unwrap_or(&[]);
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_all(&(index as u32).to_le_bytes())?;
+        writer.write_all(&[packet.is_key() as u8])?;
+        writer.write_all(&packet.pts().unwrap_or(NO_TIMESTAMP).to_le_bytes())?;
+        writer.write_all(&packet.dts().unwrap_or(NO_TIMESTAMP).to_le_bytes())?;
+        writer.write_all(&packet.duration().to_le_bytes())?;
+        writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        writer.write_all(data)?;
+        // readers only go as far as what's flushed
+        writer.flush()?;
+
+        let segment = self.segments.back_mut().unwrap();
+        segment.size += (HEADER_SIZE + data.len()) as u64;
+        self.live_edge = self.live_edge.max(time);
+        Ok(())
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        self.writer = None;
+        fs::remove_dir_all(&self.directory).ok();
+    }
+}
+
+/// The span of a live stream that can be seeked in, for the main loop.
+#[derive(Clone)]
+pub struct TimeshiftWindow {
+    ring: Arc<Mutex<Ring>>,
+}
+
+impl TimeshiftWindow {
+    /// Presentation time of the newest packet recorded, in seconds.
+    pub fn live_edge(&self) -> f64 {
+        self.ring.lock().unwrap().live_edge
+    }
+
+    /// `position` moved into the span recorded.
+    pub fn clamp(&self, position: f64) -> f64 {
+        let ring = self.ring.lock().unwrap();
+        let oldest = ring.segments.front().map_or(0_f64, |segment| segment.start);
+        position.max(oldest).min(ring.live_edge)
+    }
+}
+
+/// Lets a live stream be paused and seeked back in: the input is recorded into a ring of
+/// segment files on disk, holding the last few minutes, and played from there.
+///
+/// Recording goes on in a thread of its own while playback is paused or behind. Packets are
+/// stored with their timestamps rebased, and without side data.
+pub struct Timeshift {
+    ring: Arc<Mutex<Ring>>,
+    /// The segment being read, `None` until the first is recorded.
+    segment: Option<u64>,
+    offset: u64,
+    file: Option<File>,
+}
+
+impl Timeshift {
+    /// An empty ring keeping `length` seconds behind the live edge, see `record`.
+    pub fn new(length: f64) -> io::Result<Self> {
+        let directory = env::temp_dir().join(format!(
+            "video-player-rs-timeshift-{}-{}",
+            process::id(),
+            RINGS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&directory)?;
+        let ring = Arc::new(Mutex::new(Ring {
+            directory,
+            length,
+            segments: VecDeque::new(),
+            writer: None,
+            live_edge: 0_f64,
+            ended: false,
+        }));
+
+        Ok(Timeshift {
+            ring,
+            segment: None,
+            offset: 0,
+            file: None,
+        })
+    }
+
+    /// Starts recording `asset` into the ring, until it ends or `stopped` is set.
+    pub fn record(&self, mut asset: PlaybackAsset, stopped: Arc<AtomicBool>) {
+        thread::spawn({
+            let ring = Arc::clone(&self.ring);
+            move || {
+                if let Err(e) = record(&mut asset, &ring, &stopped) {
+                    eprintln!("failed to record the timeshift buffer: {}", e);
+                }
+                ring.lock().unwrap().ended = true;
+            }
+        });
+    }
+
+    pub fn window(&self) -> TimeshiftWindow {
+        TimeshiftWindow {
+            ring: Arc::clone(&self.ring),
+        }
+    }
+
+    /// Carries on from the segment `position` falls in, the oldest or newest one when it's
+    /// outside what's recorded.
+    pub fn seek(&mut self, position: f64) {
+        let ring = self.ring.lock().unwrap();
+        let segment = ring
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start <= position)
+            .or_else(|| ring.segments.front());
+        self.segment = segment.map(|segment| segment.id);
+        self.offset = 0;
+        self.file = None;
+    }
+
+    pub fn next(&mut self) -> io::Result<Next> {
+        loop {
+            let (id, size, newer, path) = {
+                let ring = self.ring.lock().unwrap();
+                let oldest = match ring.segments.front() {
+                    Some(oldest) => oldest.id,
+                    None if ring.ended => return Ok(Next::End),
+                    None => return Ok(Next::Waiting),
+                };
+
+                // paused for longer than the ring holds: carry on from the oldest left
+                let id = match self.segment {
+                    Some(id) if id >= oldest => id,
+                    _ => {
+                        self.offset = 0;
+                        self.file = None;
+                        oldest
+                    }
+                };
+                self.segment = Some(id);
+
+                let segment = &ring.segments[(id - oldest) as usize];
+                let newer = ring.segments.back().map_or(false, |last| last.id > id);
+                (id, segment.size, newer, ring.path(id))
+            };
+
+            if self.offset < size {
+                if self.file.is_none() {
+                    let mut file = File::open(path)?;
+                    file.seek(SeekFrom::Start(self.offset))?;
+                    self.file = Some(file);
+                }
+                let (index, packet, length) = read_packet(self.file.as_mut().unwrap())?;
+                self.offset += length;
+                return Ok(Next::Packet(index, packet));
+            }
+
+            if newer {
+                self.segment = Some(id + 1);
+                self.offset = 0;
+                self.file = None;
+            } else if self.ring.lock().unwrap().ended {
+                return Ok(Next::End);
+            } else {
+                return Ok(Next::Waiting);
+            }
+        }
+    }
+}
+
+fn record(asset: &mut PlaybackAsset, ring: &Mutex<Ring>, stopped: &AtomicBool) -> io::Result<()> {
+    let metadata = asset.metadata();
+    let mut video_timeline = Timeline::new(metadata.video_time_base());
+    let mut audio_timeline = Timeline::new(metadata.audio_time_base());
+    // segments start on a keyframe, with the audio along with it
+    let mut started = false;
+    let mut segment_start = 0_f64;
+
+    for (stream, mut packet) in asset.packets() {
+        if stopped.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let index = stream.index();
+        let video = Some(index) == metadata.video_stream_index();
+        let (timeline, time_base) = if video {
+            (&mut video_timeline, metadata.video_time_base())
+        } else if Some(index) == metadata.audio_stream_index() {
+            (&mut audio_timeline, metadata.audio_time_base())
+        } else {
+            continue;
+        };
+        timeline.rebase(&stream, &mut packet);
+        let time = match packet.pts().or_else(|| packet.dts()) {
+            Some(pts) => pts as f64 * time_base,
+            None => continue,
+        };
+
+        // without video, any audio packet starts a segment
+        let keyframe = (video && packet.is_key()) || !metadata.has_video();
+        if !started && !keyframe {
+            continue;
+        }
+        let cut = started && keyframe && time - segment_start >= SEGMENT_LENGTH;
+        if !started || cut {
+            segment_start = time;
+            started = true;
+        }
+        ring.lock().unwrap().append(index, &packet, time, cut)?;
+    }
+    Ok(())
+}
+
+/// Reads one packet written by `Ring::append`, with the bytes it took.
+fn read_packet(file: &mut File) -> io::Result<(usize, Packet, u64)> {
+    let mut header = [0_u8; HEADER_SIZE];
+    file.read_exact(&mut header)?;
+    let int = |range: std::ops::Range<usize>| {
+        let mut bytes = [0_u8; 8];
+        bytes.copy_from_slice(&header[range]);
+        i64::from_le_bytes(bytes)
+    };
+    let timestamp = |value: i64| Some(value).filter(|value| *value != NO_TIMESTAMP);
+
+    let index = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let key = header[4] != 0;
+    let pts = timestamp(int(5..13));
+    let dts = timestamp(int(13..21));
+    let duration = int(21..29);
+    let size = u32::from_le_bytes([header[29], header[30], header[31], header[32]]) as usize;
+
+    let mut data = vec![0_u8; size];
+    file.read_exact(&mut data)?;
+    let mut packet = Packet::copy(&data);
+    packet.set_stream(index);
+    packet.set_pts(pts);
+    packet.set_dts(dts);
+    packet.set_duration(duration);
+    if key {
+        packet.set_flags(Flags::KEY);
+    }
+    Ok((index, packet, (HEADER_SIZE + size) as u64))
+}
@@ -0,0 +1,152 @@
+use ffmpeg_next::{filter, format::Pixel, frame, Error, Rational, Rescale};
+
+/// Runs decoded video through an ffmpeg filtergraph, e.g. `scale=1280:-1,hflip`.
+///
+/// Like `AudioFilter`, the graph is built from the first frame and rebuilt whenever the
+/// frame format or the description changes, or after a seek. Output is always YUV420P, the
+/// format the video sinks take, with timestamps in the input's time base even when a filter
+/// changes the frame rate. An empty description passes frames through.
+pub struct VideoFilter {
+    description: String,
+    time_base: Rational,
+    graph: Option<filter::Graph>,
+    output_time_base: Rational,
+    input: Option<(Pixel, u32, u32)>,
+    failed: bool,
+}
+
+impl VideoFilter {
+    /// `time_base` is that of the frames' timestamps, in seconds.
+    pub fn new(description: &str, time_base: f64) -> Self {
+        VideoFilter {
+            description: description.to_string(),
+            time_base: Rational::from(time_base),
+            graph: None,
+            output_time_base: Rational::from(time_base),
+            input: None,
+            failed: false,
+        }
+    }
+
+    /// Switches to another filter chain from the next frame on.
+    pub fn set_description(&mut self, description: &str) {
+        if self.description != description {
+            self.description = description.to_string();
+            self.graph = None;
+            self.failed = false;
+        }
+    }
+
+    /// Forgets the frames held by stateful filters, for after a seek.
+    pub fn reset(&mut self) {
+        self.graph = None;
+    }
+
+    /// Feeds `frame` into the graph, returning the filtered frames that are ready. Frames
+    /// pass through untouched if the graph can't be built.
+    pub fn filter(&mut self, frame: frame::Video) -> Vec<frame::Video> {
+        if self.failed || self.description.is_empty() || frame.width() == 0 {
+            return vec![frame];
+        }
+
+        let input = (frame.format(), frame.width(), frame.height());
+        if self.input != Some(input) {
+            self.graph = None;
+        }
+        if self.graph.is_none() {
+            match self.create_graph(&frame) {
+                Ok(graph) => {
+                    self.output_time_base = unsafe {
+                        Rational::from(ffmpeg_next::ffi::av_buffersink_get_time_base(
+                            graph.get("out").unwrap().as_ptr(),
+                        ))
+                    };
+                    self.graph = Some(graph);
+                    self.input = Some(input);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "failed to create video filter '{}': {}",
+                        self.description, e
+                    );
+                    self.failed = true;
+                    return vec![frame];
+                }
+            }
+        }
+        let graph = self.graph.as_mut().unwrap();
+
+        if graph.get("in").unwrap().source().add(&frame).is_err() {
+            return Vec::new();
+        }
+
+        self.take_filtered()
+    }
+
+    /// Signals the end of the input, returning what stateful filters still held back.
+    pub fn flush(&mut self) -> Vec<frame::Video> {
+        let graph = match &mut self.graph {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+        if graph.get("in").unwrap().source().flush().is_err() {
+            return Vec::new();
+        }
+        let frames = self.take_filtered();
+        // an ended graph takes no more frames, the next one builds a fresh graph
+        self.graph = None;
+        frames
+    }
+
+    fn take_filtered(&mut self) -> Vec<frame::Video> {
+        let graph = match &mut self.graph {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+
+        let mut frames = Vec::new();
+        let mut filtered = frame::Video::empty();
+        while graph
+            .get("out")
+            .unwrap()
+            .sink()
+            .frame(&mut filtered)
+            .is_ok()
+        {
+            if self.output_time_base != self.time_base {
+                let pts = filtered.pts();
+                filtered.set_pts(pts.map(|pts| pts.rescale(self.output_time_base, self.time_base)));
+            }
+            frames.push(filtered);
+            filtered = frame::Video::empty();
+        }
+        frames
+    }
+
+    fn create_graph(&self, frame: &frame::Video) -> Result<filter::Graph, Error> {
+        let mut graph = filter::Graph::new();
+
+        let aspect = frame.aspect_ratio();
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+            frame.width(),
+            frame.height(),
+            ffmpeg_next::ffi::AVPixelFormat::from(frame.format()) as i32,
+            self.time_base.numerator(),
+            self.time_base.denominator(),
+            aspect.numerator().max(1),
+            aspect.denominator().max(1),
+        );
+        graph.add(&filter::find("buffer").unwrap(), "in", &args)?;
+        graph.add(&filter::find("buffersink").unwrap(), "out", "")?;
+        graph.get("out").unwrap().set_pixel_format(Pixel::YUV420P);
+
+        graph
+            .output("in", 0)?
+            .input("out", 0)?
+            .parse(&self.description)?;
+        graph.validate()?;
+
+        Ok(graph)
+    }
+}
@@ -0,0 +1,164 @@
+use ffmpeg_next::{format::Pixel, frame::Video};
+
+use crate::{
+    asset::PlaybackAssetMetadata, bindings::KeyBindings, options::AspectMode, osd::Osd,
+    player::PlayerCommand,
+};
+
+#[cfg(all(feature = "sdl", target_os = "linux"))]
+mod dmabuf;
+#[cfg(any(feature = "sdl", feature = "winit"))]
+mod icon;
+#[cfg(feature = "sdl")]
+mod opengl;
+#[cfg(feature = "sdl")]
+mod sdl;
+#[cfg(feature = "taskbar")]
+mod taskbar;
+mod terminal;
+#[cfg(feature = "winit")]
+mod winit;
+
+pub use self::terminal::TerminalVideoSink;
+#[cfg(feature = "winit")]
+pub use self::winit::WinitVideoSink;
+#[cfg(feature = "sdl")]
+pub use self::{opengl::GlVideoSink, sdl::SdlVideoSink};
+
+/// What windows are titled until there's something better to show.
+pub const WINDOW_TITLE: &str = "Rust Video Player";
+
+/// Where decoded video frames end up.
+///
+/// The player calls `configure` once before the first frame, then `submit` for every frame
+/// as it becomes due, and `present` whenever the picture or the OSD needs to be redrawn.
+/// Embedders can hand their own implementation to `Player::set_video_sink`.
+pub trait VideoSink {
+    fn configure(&mut self, metadata: &PlaybackAssetMetadata);
+
+    /// Takes the frame due at `pts` seconds; frames arrive as YUV420P, or as hardware
+    /// surfaces in one of `hardware_formats`.
+    fn submit(&mut self, frame: &Video, pts: f64);
+
+    /// Formats of hardware decoded surfaces, e.g. `Pixel::VAAPI`, that `submit` shows
+    /// straight from the GPU, once configured. Surfaces in any other format are copied to
+    /// system memory first, as are all of them while something else needs their pixels.
+    fn hardware_formats(&self) -> Vec<Pixel> {
+        Vec::new()
+    }
+
+    /// Shows the last submitted frame with `osd` drawn on top.
+    fn present(&mut self, _osd: &Osd) {}
+
+    /// Turns input from the sink's window, if it has one, into player commands.
+    fn poll_events(&mut self, _commands: &mut Vec<PlayerCommand>) {}
+
+    /// Takes the files dropped onto the sink's window since the last call.
+    fn take_dropped_files(&mut self) -> Vec<DroppedFile> {
+        Vec::new()
+    }
+
+    /// Whether the window was uncovered, resized or refocused since the last call, so the
+    /// picture has to be drawn again even though nothing new was submitted.
+    fn take_exposed(&mut self) -> bool {
+        false
+    }
+
+    /// Sets what key presses in the sink's window do, before the first `poll_events` or while
+    /// running, e.g. to move through a menu shown.
+    fn set_key_bindings(&mut self, _bindings: KeyBindings) {}
+
+    /// Changes how the picture fits the window, from the next `present` on.
+    fn set_aspect_mode(&mut self, _mode: AspectMode) {}
+
+    /// Switches the window between fullscreen and its own size.
+    fn toggle_fullscreen(&mut self) {}
+
+    /// Keeps the window above other applications' windows, or lets them cover it again.
+    fn set_on_top(&mut self, _on_top: bool) {}
+
+    /// Retitles the window, e.g. with the song an internet radio station is playing.
+    fn set_title(&mut self, _title: &str) {}
+
+    /// Resizes the window to `scale` times the size of the video in pixels, whatever the
+    /// display's scale factor.
+    fn set_window_scale(&mut self, _scale: f64) {}
+
+    /// Shows how far along playback is, from 0 to 1, on the window's taskbar entry, or
+    /// nothing when `None`.
+    fn set_progress(&mut self, _progress: Option<f64>, _paused: bool) {}
+
+    /// Refresh rate of the display the sink shows on, in Hz, once configured.
+    fn refresh_rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Text on the system clipboard, where the sink can read it.
+    fn clipboard_text(&self) -> Option<String> {
+        None
+    }
+
+    /// Shows or hides the mouse cursor over the sink's window.
+    fn show_cursor(&mut self, _show: bool) {}
+
+    /// Keeps the screensaver and system sleep away while `inhibit` is set, by default while
+    /// video is actually playing.
+    fn inhibit_idle(&mut self, _inhibit: bool) {}
+}
+
+/// A file dragged onto a window, to play instead of the current input or, with `append`, to
+/// queue after it.
+#[derive(Clone, Debug)]
+pub struct DroppedFile {
+    pub path: String,
+    pub append: bool,
+}
+
+/// Display aspect ratio of `frame`, taking non-square pixels into account.
+pub fn display_aspect(frame: &Video) -> f64 {
+    let sample_aspect = frame.aspect_ratio();
+    let sample_aspect = if sample_aspect.numerator() > 0 && sample_aspect.denominator() > 0 {
+        f64::from(sample_aspect)
+    } else {
+        1_f64
+    };
+    frame.width() as f64 * sample_aspect / frame.height().max(1) as f64
+}
+
+/// Where a picture with display aspect ratio `aspect` goes in a `width` x `height` window:
+/// centred and letterboxed, or stretched over all of it for `AspectMode::Fill`. Returns
+/// `(x, y, width, height)` from the top left.
+pub fn destination_rect(
+    mode: AspectMode,
+    aspect: f64,
+    width: u32,
+    height: u32,
+) -> (i32, i32, u32, u32) {
+    let aspect = match mode {
+        AspectMode::Source => aspect,
+        AspectMode::Ratio4x3 => 4_f64 / 3_f64,
+        AspectMode::Ratio16x9 => 16_f64 / 9_f64,
+        AspectMode::Fill => return (0, 0, width, height),
+    };
+
+    let (fit_width, fit_height) = if width as f64 / height.max(1) as f64 > aspect {
+        ((height as f64 * aspect).round() as u32, height)
+    } else {
+        (width, (width as f64 / aspect).round() as u32)
+    };
+    (
+        ((width - fit_width.min(width)) / 2) as i32,
+        ((height - fit_height.min(height)) / 2) as i32,
+        fit_width.max(1),
+        fit_height.max(1),
+    )
+}
+
+/// Discards every frame, for running without a window (`--vo null`).
+pub struct NullVideoSink;
+
+impl VideoSink for NullVideoSink {
+    fn configure(&mut self, _metadata: &PlaybackAssetMetadata) {}
+
+    fn submit(&mut self, _frame: &Video, _pts: f64) {}
+}
@@ -0,0 +1,284 @@
+use std::{
+    ffi::CStr,
+    mem,
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+};
+
+use ffmpeg_next::{ffi, frame::Video, Error};
+use gl::types::{GLenum, GLuint};
+use sdl2::VideoSubsystem;
+
+// EGL, with EGL_EXT_image_dma_buf_import and EGL_EXT_image_dma_buf_import_modifiers
+const EGL_EXTENSIONS: i32 = 0x3055;
+const EGL_HEIGHT: i32 = 0x3056;
+const EGL_WIDTH: i32 = 0x3057;
+const EGL_NONE: i32 = 0x3038;
+const EGL_LINUX_DMA_BUF_EXT: u32 = 0x3270;
+const EGL_LINUX_DRM_FOURCC_EXT: i32 = 0x3271;
+const EGL_DMA_BUF_PLANE0_FD_EXT: i32 = 0x3272;
+const EGL_DMA_BUF_PLANE0_OFFSET_EXT: i32 = 0x3273;
+const EGL_DMA_BUF_PLANE0_PITCH_EXT: i32 = 0x3274;
+const EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT: i32 = 0x3443;
+const EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT: i32 = 0x3444;
+
+/// `AV_HWFRAME_MAP_READ`, which the bindings only have in an anonymous enum.
+const HWFRAME_MAP_READ: c_int = 0x01;
+
+/// `DRM_FORMAT_MOD_INVALID`: the exporter didn't say how the buffer is laid out.
+const MODIFIER_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+// fourcc codes from `drm_fourcc.h`
+const DRM_FORMAT_R8: u32 = u32::from_le_bytes(*b"R8  ");
+const DRM_FORMAT_GR88: u32 = u32::from_le_bytes(*b"GR88");
+const DRM_FORMAT_R16: u32 = u32::from_le_bytes(*b"R16 ");
+const DRM_FORMAT_GR1616: u32 = u32::from_le_bytes(*b"GR32");
+const DRM_FORMAT_NV12: u32 = u32::from_le_bytes(*b"NV12");
+const DRM_FORMAT_P010: u32 = u32::from_le_bytes(*b"P010");
+
+// `AVDRMFrameDescriptor` and its parts from `libavutil/hwcontext_drm.h`, which the bindings
+// leave out
+const AV_DRM_MAX_PLANES: usize = 4;
+
+#[repr(C)]
+struct DrmObject {
+    fd: c_int,
+    _size: usize,
+    format_modifier: u64,
+}
+
+#[repr(C)]
+struct DrmPlane {
+    object_index: c_int,
+    offset: isize,
+    pitch: isize,
+}
+
+#[repr(C)]
+struct DrmLayer {
+    format: u32,
+    nb_planes: c_int,
+    planes: [DrmPlane; AV_DRM_MAX_PLANES],
+}
+
+#[repr(C)]
+struct DrmFrame {
+    nb_objects: c_int,
+    objects: [DrmObject; AV_DRM_MAX_PLANES],
+    nb_layers: c_int,
+    layers: [DrmLayer; AV_DRM_MAX_PLANES],
+}
+
+type EglDisplay = *mut c_void;
+type EglImage = *mut c_void;
+
+type GetCurrentDisplay = unsafe extern "C" fn() -> EglDisplay;
+type QueryString = unsafe extern "C" fn(EglDisplay, i32) -> *const c_char;
+type CreateImage =
+    unsafe extern "C" fn(EglDisplay, *mut c_void, u32, *mut c_void, *const i32) -> EglImage;
+type DestroyImage = unsafe extern "C" fn(EglDisplay, EglImage) -> u32;
+type ImageTargetTexture = unsafe extern "C" fn(GLenum, EglImage);
+
+/// Shows VAAPI surfaces without copying them: each is exported as DMA-BUFs, which EGL
+/// imports as the textures of its luma and interleaved chroma planes.
+///
+/// Only where SDL renders through EGL, as it does on Wayland; GLX has no way in.
+pub(super) struct DmaBufImporter {
+    display: EglDisplay,
+    modifiers: bool,
+    create_image: CreateImage,
+    destroy_image: DestroyImage,
+    image_target_texture: ImageTargetTexture,
+    /// The frame shown, mapped, kept until the next one so the decoder doesn't reuse the
+    /// surface under the textures.
+    mapped: Option<Video>,
+    images: Vec<EglImage>,
+}
+
+impl DmaBufImporter {
+    /// `None` where DMA-BUFs can't be imported, with the GL context current.
+    pub(super) fn new(video_subsystem: &VideoSubsystem) -> Option<Self> {
+        if video_subsystem.current_video_driver() != "wayland" {
+            return None;
+        }
+
+        unsafe {
+            let get_current_display: GetCurrentDisplay =
+                load(video_subsystem, "eglGetCurrentDisplay")?;
+            let query_string: QueryString = load(video_subsystem, "eglQueryString")?;
+            let display = get_current_display();
+            if display.is_null() {
+                return None;
+            }
+
+            let extensions = query_string(display, EGL_EXTENSIONS);
+            if extensions.is_null() {
+                return None;
+            }
+            let extensions = CStr::from_ptr(extensions).to_string_lossy();
+            let has = |name| extensions.split(' ').any(|extension| extension == name);
+            if !has("EGL_EXT_image_dma_buf_import") {
+                return None;
+            }
+
+            Some(DmaBufImporter {
+                display,
+                modifiers: has("EGL_EXT_image_dma_buf_import_modifiers"),
+                create_image: load(video_subsystem, "eglCreateImageKHR")?,
+                destroy_image: load(video_subsystem, "eglDestroyImageKHR")?,
+                image_target_texture: load(video_subsystem, "glEGLImageTargetTexture2DOES")?,
+                mapped: None,
+                images: Vec::new(),
+            })
+        }
+    }
+
+    /// Points `textures` at the luma and chroma planes of VAAPI surface `frame`, replacing
+    /// the previous frame's.
+    pub(super) fn import(&mut self, frame: &Video, textures: &[GLuint; 2]) -> Result<(), String> {
+        let mut mapped = Video::empty();
+        unsafe {
+            (*mapped.as_mut_ptr()).format = ffi::AVPixelFormat::AV_PIX_FMT_DRM_PRIME as c_int;
+            let result = ffi::av_hwframe_map(mapped.as_mut_ptr(), frame.as_ptr(), HWFRAME_MAP_READ);
+            if result < 0 {
+                return Err(format!("failed to export surface: {}", Error::from(result)));
+            }
+        }
+
+        let planes = unsafe { drm_planes(&*((*mapped.as_ptr()).data[0] as *const DrmFrame)) };
+        if planes.len() != 2 {
+            return Err(format!(
+                "unexpected surface layout, {} planes",
+                planes.len()
+            ));
+        }
+
+        let chroma = ((frame.width() + 1) / 2, (frame.height() + 1) / 2);
+        let mut images = Vec::with_capacity(2);
+        for (index, (plane, texture)) in planes.iter().zip(textures).enumerate() {
+            let (width, height) = if index == 0 {
+                (frame.width(), frame.height())
+            } else {
+                chroma
+            };
+            let image = unsafe { self.plane_image(plane, width, height) };
+            if image.is_null() {
+                self.destroy(images);
+                return Err("failed to import surface".to_string());
+            }
+            images.push(image);
+
+            unsafe {
+                gl::BindTexture(gl::TEXTURE_2D, *texture);
+                (self.image_target_texture)(gl::TEXTURE_2D, image);
+            }
+        }
+
+        let previous = mem::replace(&mut self.images, images);
+        self.destroy(previous);
+        self.mapped = Some(mapped);
+        Ok(())
+    }
+
+    unsafe fn plane_image(&self, plane: &Plane, width: u32, height: u32) -> EglImage {
+        let mut attributes = vec![
+            EGL_WIDTH,
+            width as i32,
+            EGL_HEIGHT,
+            height as i32,
+            EGL_LINUX_DRM_FOURCC_EXT,
+            plane.format as i32,
+            EGL_DMA_BUF_PLANE0_FD_EXT,
+            plane.fd,
+            EGL_DMA_BUF_PLANE0_OFFSET_EXT,
+            plane.offset as i32,
+            EGL_DMA_BUF_PLANE0_PITCH_EXT,
+            plane.pitch as i32,
+        ];
+        if self.modifiers && plane.modifier != MODIFIER_INVALID {
+            attributes.extend_from_slice(&[
+                EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT,
+                plane.modifier as u32 as i32,
+                EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT,
+                (plane.modifier >> 32) as u32 as i32,
+            ]);
+        }
+        attributes.push(EGL_NONE);
+
+        (self.create_image)(
+            self.display,
+            ptr::null_mut(),
+            EGL_LINUX_DMA_BUF_EXT,
+            ptr::null_mut(),
+            attributes.as_ptr(),
+        )
+    }
+
+    fn destroy(&self, images: Vec<EglImage>) {
+        for image in images {
+            unsafe { (self.destroy_image)(self.display, image) };
+        }
+    }
+}
+
+impl Drop for DmaBufImporter {
+    fn drop(&mut self) {
+        self.destroy(mem::take(&mut self.images));
+    }
+}
+
+/// One plane of an exported surface, with the single-plane format it's imported as.
+struct Plane {
+    format: u32,
+    fd: c_int,
+    offset: isize,
+    pitch: isize,
+    modifier: u64,
+}
+
+/// The planes of `frame`, whether it's exported as a layer per plane, as VAAPI does, or as
+/// one NV12 or P010 layer.
+fn drm_planes(frame: &DrmFrame) -> Vec<Plane> {
+    let layers = &frame.layers[..(frame.nb_layers.max(0) as usize).min(AV_DRM_MAX_PLANES)];
+    layers
+        .iter()
+        .flat_map(|layer| {
+            let count = (layer.nb_planes.max(0) as usize).min(AV_DRM_MAX_PLANES);
+            layer.planes[..count]
+                .iter()
+                .enumerate()
+                .map(move |(index, plane)| (plane, plane_format(layer.format, index)))
+        })
+        .filter_map(|(plane, format)| {
+            let objects = (frame.nb_objects.max(0) as usize).min(AV_DRM_MAX_PLANES);
+            let object = frame.objects[..objects].get(plane.object_index as usize)?;
+            Some(Plane {
+                format,
+                fd: object.fd,
+                offset: plane.offset,
+                pitch: plane.pitch,
+                modifier: object.format_modifier,
+            })
+        })
+        .collect()
+}
+
+/// The single-plane format plane `index` of a layer in `format` is imported as.
+fn plane_format(format: u32, index: usize) -> u32 {
+    match (format, index) {
+        (DRM_FORMAT_NV12, 0) => DRM_FORMAT_R8,
+        (DRM_FORMAT_NV12, _) => DRM_FORMAT_GR88,
+        (DRM_FORMAT_P010, 0) => DRM_FORMAT_R16,
+        (DRM_FORMAT_P010, _) => DRM_FORMAT_GR1616,
+        _ => format,
+    }
+}
+
+/// Looks up EGL or GL function `name` through SDL, as a `T` function pointer.
+unsafe fn load<T>(video_subsystem: &VideoSubsystem, name: &str) -> Option<T> {
+    let function = video_subsystem.gl_get_proc_address(name);
+    if function.is_null() {
+        return None;
+    }
+    Some(mem::transmute_copy(&function))
+}
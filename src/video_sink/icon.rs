@@ -0,0 +1,38 @@
+/// Width and height of the window icon, in pixels.
+pub(super) const ICON_SIZE: u32 = 64;
+
+const CORNER_RADIUS: f64 = 12_f64;
+const BACKGROUND: [u8; 4] = [0xe8, 0x45, 0x3c, 0xff];
+const FOREGROUND: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// The window icon as RGBA rows: a white play triangle on a rounded red square, drawn here
+/// so there's no image file to ship alongside the binary.
+pub(super) fn icon_rgba() -> Vec<u8> {
+    let size = ICON_SIZE as f64;
+    let mut pixels = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+
+    for row in 0..ICON_SIZE {
+        for column in 0..ICON_SIZE {
+            let (x, y) = (column as f64 + 0.5, row as f64 + 0.5);
+
+            // distance past the rounded corners, measured from the nearest corner's centre
+            let dx = (CORNER_RADIUS - x)
+                .max(x - (size - CORNER_RADIUS))
+                .max(0_f64);
+            let dy = (CORNER_RADIUS - y)
+                .max(y - (size - CORNER_RADIUS))
+                .max(0_f64);
+            if dx * dx + dy * dy > CORNER_RADIUS * CORNER_RADIUS {
+                pixels.extend_from_slice(&[0, 0, 0, 0]);
+                continue;
+            }
+
+            // the triangle points right, its left edge at 3/8 and tip at 3/4 of the width
+            let (left, right) = (size * 0.375, size * 0.75);
+            let half_height = (right - x) / (right - left) * size * 0.25;
+            let inside = x >= left && x <= right && (y - size / 2_f64).abs() <= half_height;
+            pixels.extend_from_slice(if inside { &FOREGROUND } else { &BACKGROUND });
+        }
+    }
+    pixels
+}
@@ -0,0 +1,912 @@
+use std::{
+    ffi::CString,
+    fs, mem,
+    os::raw::c_void,
+    path::PathBuf,
+    ptr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    util::color::{Range, Space},
+};
+use gl::types::{GLenum, GLint, GLuint};
+use sdl2::{
+    event::Event,
+    video::{GLContext, GLProfile, Window},
+    EventPump, Sdl, VideoSubsystem,
+};
+
+#[cfg(target_os = "linux")]
+use super::dmabuf::DmaBufImporter;
+#[cfg(feature = "taskbar")]
+use super::taskbar::Taskbar;
+use super::{
+    destination_rect, display_aspect,
+    sdl::{
+        create_event_pump, create_window, display_refresh_rate, dropped_file, exposed,
+        mouse_command, poll_commands, scale_window, set_on_top, set_title, toggle_fullscreen,
+        wipe_command,
+    },
+    DroppedFile, VideoSink,
+};
+use crate::{
+    asset::PlaybackAssetMetadata,
+    bindings::KeyBindings,
+    hwdec,
+    options::{AspectMode, Projection},
+    osd::{Osd, OsdImage},
+    player::PlayerCommand,
+};
+
+/// Full-screen quad from four vertex ids, so no vertex buffer is needed.
+const VERTEX_SHADER: &str = "
+#version 330 core
+out vec2 uv;
+void main() {
+    vec2 position = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    uv = vec2(position.x, 1.0 - position.y);
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+/// Like `VERTEX_SHADER`, for sampling textures rendered by GL itself, which are bottom up.
+const PASS_VERTEX_SHADER: &str = "
+#version 330 core
+out vec2 uv;
+void main() {
+    vec2 position = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    uv = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+/// Put in front of `--shader` files that don't start with their own `#version`, so a pass
+/// only needs a `main` writing `color` from `image` at `uv`.
+const USER_SHADER_HEADER: &str = "#version 330 core
+in vec2 uv;
+out vec4 color;
+uniform sampler2D image;
+uniform vec2 image_size;
+uniform float time;
+";
+
+/// Field of view of the 360° view, in degrees: initial and limits for the mouse wheel.
+const DEFAULT_FOV: f32 = 90_f32;
+const MIN_FOV: f32 = 30_f32;
+const MAX_FOV: f32 = 120_f32;
+
+/// How often `--shader` files are checked for changes.
+const SHADER_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+const YUV_FRAGMENT_SHADER: &str = "
+#version 330 core
+in vec2 uv;
+out vec4 color;
+uniform sampler2D y_plane;
+uniform sampler2D u_plane;
+uniform sampler2D v_plane;
+// chroma interleaved in u_plane, as hardware surfaces have it
+uniform bool nv12;
+uniform mat3 yuv_to_rgb;
+uniform vec3 offset;
+uniform vec3 scale;
+uniform bool equirect;
+uniform vec2 view;
+uniform float fov_scale;
+uniform float aspect;
+const float PI = 3.14159265;
+
+// where the view ray through this pixel hits an equirectangular picture
+vec2 sphere_uv() {
+    vec2 ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    vec3 ray = normalize(vec3(ndc.x * fov_scale * aspect, ndc.y * fov_scale, -1.0));
+    float pitch_cos = cos(view.y), pitch_sin = sin(view.y);
+    ray = vec3(ray.x, ray.y * pitch_cos - ray.z * pitch_sin, ray.y * pitch_sin + ray.z * pitch_cos);
+    float yaw_cos = cos(view.x), yaw_sin = sin(view.x);
+    ray = vec3(ray.x * yaw_cos - ray.z * yaw_sin, ray.y, ray.x * yaw_sin + ray.z * yaw_cos);
+    float longitude = atan(ray.x, -ray.z);
+    float latitude = asin(clamp(ray.y, -1.0, 1.0));
+    return vec2(fract(longitude / (2.0 * PI) + 0.5), 0.5 - latitude / PI);
+}
+
+void main() {
+    vec2 source = equirect ? sphere_uv() : uv;
+    vec3 yuv = nv12
+        ? vec3(texture(y_plane, source).r, texture(u_plane, source).rg)
+        : vec3(
+            texture(y_plane, source).r,
+            texture(u_plane, source).r,
+            texture(v_plane, source).r
+        );
+    color = vec4(clamp(yuv_to_rgb * ((yuv - offset) * scale), 0.0, 1.0), 1.0);
+}
+";
+
+const OVERLAY_FRAGMENT_SHADER: &str = "
+#version 330 core
+in vec2 uv;
+out vec4 color;
+uniform sampler2D overlay;
+void main() {
+    color = texture(overlay, uv);
+}
+";
+
+/// A `--shader` pass, recompiled whenever its file changes.
+struct UserShader {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// 0 until the file has compiled once; a failed reload keeps the previous program.
+    program: GLuint,
+}
+
+impl UserShader {
+    /// Recompiles the shader if its file changed since the last look.
+    fn reload(&mut self) {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+
+        let source = match fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("failed to read shader {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+        let source = if source.trim_start().starts_with("#version") {
+            source
+        } else {
+            format!("{}{}", USER_SHADER_HEADER, source)
+        };
+
+        match unsafe { link_program(PASS_VERTEX_SHADER, &source) } {
+            Ok(program) => {
+                if self.program != 0 {
+                    unsafe { gl::DeleteProgram(self.program) };
+                }
+                self.program = program;
+                unsafe {
+                    gl::UseProgram(program);
+                    gl::Uniform1i(uniform(program, "image"), 0);
+                }
+                info!("loaded shader {}", self.path.display());
+            }
+            Err(e) => eprintln!("failed to build shader {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Renders through OpenGL, converting YUV to RGB in a shader with the frame's own matrix
+/// (BT.601/709/2020) and range instead of SDL's fixed YV12 conversion.
+///
+/// `--shader` passes run on the RGB picture in turn, ping-ponging between two offscreen
+/// textures of the picture's size, with the last one drawing to the window.
+///
+/// 360° video is shown as seen from the inside of a sphere, looked around by dragging with
+/// the mouse and zoomed with the wheel.
+///
+/// On Wayland, VAAPI surfaces are shown without a copy, imported as DMA-BUFs.
+pub struct GlVideoSink {
+    /// First, so its images go while the EGL display they're on is still up.
+    #[cfg(target_os = "linux")]
+    dmabuf: Option<DmaBufImporter>,
+    video_subsystem: VideoSubsystem,
+    event_pump: EventPump,
+    window: Option<Window>,
+    context: Option<GLContext>,
+    yuv_program: GLuint,
+    overlay_program: GLuint,
+    vertex_array: GLuint,
+    planes: [GLuint; 3],
+    /// Luma and chroma of the last hardware surface, imported rather than uploaded.
+    imported: [GLuint; 2],
+    /// Whether the last frame was imported, its chroma in one texture.
+    nv12: bool,
+    /// Set while surfaces fail to import, so the error is only reported once.
+    import_failing: bool,
+    overlay: GLuint,
+    osd_image: OsdImage,
+    /// Height of the last frame, in pixels.
+    height: u32,
+    aspect: f64,
+    aspect_mode: AspectMode,
+    bindings: KeyBindings,
+    dropped: Vec<DroppedFile>,
+    exposed: bool,
+    #[cfg(feature = "taskbar")]
+    taskbar: Option<Taskbar>,
+    has_frame: bool,
+    projection: Option<Projection>,
+    equirect: bool,
+    /// Yaw and pitch of the 360° view, in radians.
+    view: (f32, f32),
+    /// In degrees.
+    fov: f32,
+    shaders: Vec<UserShader>,
+    framebuffers: [GLuint; 2],
+    targets: [GLuint; 2],
+    target_size: (u32, u32),
+    last_reload_check: Instant,
+    started: Instant,
+}
+
+impl GlVideoSink {
+    pub fn new(sdl_context: &Sdl) -> Self {
+        GlVideoSink {
+            #[cfg(target_os = "linux")]
+            dmabuf: None,
+            video_subsystem: sdl_context.video().unwrap(),
+            event_pump: create_event_pump(sdl_context),
+            window: None,
+            context: None,
+            yuv_program: 0,
+            overlay_program: 0,
+            vertex_array: 0,
+            planes: [0; 3],
+            imported: [0; 2],
+            nv12: false,
+            import_failing: false,
+            overlay: 0,
+            osd_image: OsdImage::new(0, 0),
+            height: 0,
+            aspect: 1_f64,
+            aspect_mode: AspectMode::Source,
+            bindings: KeyBindings::default(),
+            dropped: Vec::new(),
+            exposed: false,
+            #[cfg(feature = "taskbar")]
+            taskbar: None,
+            has_frame: false,
+            projection: None,
+            equirect: false,
+            view: (0_f32, 0_f32),
+            fov: DEFAULT_FOV,
+            shaders: Vec::new(),
+            framebuffers: [0; 2],
+            targets: [0; 2],
+            target_size: (0, 0),
+            last_reload_check: Instant::now(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Forces a projection, `None` goes by the stream's metadata. Takes effect on `configure`.
+    pub fn set_projection(&mut self, projection: Option<Projection>) {
+        self.projection = projection;
+    }
+
+    /// Drag to look around, wheel to zoom, while showing 360° video.
+    fn handle_mouse(&mut self, event: &Event) {
+        let height = self
+            .window
+            .as_ref()
+            .map_or(1, |window| window.size().1.max(1)) as f32;
+
+        match event {
+            Event::MouseMotion {
+                mousestate,
+                xrel,
+                yrel,
+                ..
+            } if mousestate.left() => {
+                // the picture follows the mouse, so the view turns the other way
+                let radians_per_pixel = self.fov.to_radians() / height;
+                let (yaw, pitch) = self.view;
+                let limit = 89_f32.to_radians();
+                self.view = (
+                    yaw - *xrel as f32 * radians_per_pixel,
+                    (pitch + *yrel as f32 * radians_per_pixel).clamp(-limit, limit),
+                );
+            }
+            Event::MouseWheel { y, .. } => {
+                self.fov = (self.fov - *y as f32 * 5_f32).clamp(MIN_FOV, MAX_FOV);
+            }
+            _ => {}
+        }
+    }
+
+    /// Post-processing passes to run after YUV conversion, in order. Takes effect on
+    /// `configure`.
+    pub fn set_shaders(&mut self, paths: Vec<PathBuf>) {
+        self.shaders = paths
+            .into_iter()
+            .map(|path| UserShader {
+                path,
+                modified: None,
+                program: 0,
+            })
+            .collect();
+    }
+
+    /// Points the `imported` textures at hardware surface `frame`'s planes.
+    #[cfg(target_os = "linux")]
+    fn import(&mut self, frame: &Video) -> Result<(), String> {
+        match &mut self.dmabuf {
+            Some(importer) if frame.format() == hwdec::vaapi_format() => {
+                importer.import(frame, &self.imported)
+            }
+            _ => Err(format!("can't import {:?} surfaces", frame.format())),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn import(&mut self, frame: &Video) -> Result<(), String> {
+        Err(format!("can't import {:?} surfaces", frame.format()))
+    }
+
+    /// (Re)allocates the offscreen textures for the shader passes at the picture size.
+    unsafe fn resize_targets(&mut self, width: u32, height: u32) {
+        if self.target_size == (width, height) {
+            return;
+        }
+        self.target_size = (width, height);
+
+        for (framebuffer, texture) in self.framebuffers.iter().zip(&self.targets) {
+            gl::BindTexture(gl::TEXTURE_2D, *texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as GLint,
+                width as GLint,
+                height as GLint,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                ptr::null(),
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, *framebuffer);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                *texture,
+                0,
+            );
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    /// Draws the picture through the shader passes that have compiled, the last one into
+    /// the window's `viewport`.
+    fn draw_passes(&mut self, viewport: (GLint, GLint, u32, u32)) {
+        let (x, y, width, height) = viewport;
+        let programs: Vec<GLuint> = self
+            .shaders
+            .iter()
+            .map(|shader| shader.program)
+            .filter(|program| *program != 0)
+            .collect();
+
+        unsafe {
+            self.resize_targets(width, height);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffers[0]);
+            gl::Viewport(0, 0, width as GLint, height as GLint);
+        }
+        self.draw_quad(self.yuv_program);
+
+        let time = self.started.elapsed().as_secs_f32();
+        for (index, program) in programs.iter().enumerate() {
+            let last = index + 1 == programs.len();
+            unsafe {
+                if last {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                    gl::Viewport(x, y, width as GLint, height as GLint);
+                } else {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffers[(index + 1) % 2]);
+                }
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, self.targets[index % 2]);
+
+                gl::UseProgram(*program);
+                gl::Uniform2f(uniform(*program, "image_size"), width as f32, height as f32);
+                gl::Uniform1f(uniform(*program, "time"), time);
+            }
+            self.draw_quad(*program);
+        }
+    }
+
+    fn draw_quad(&self, program: GLuint) {
+        unsafe {
+            gl::UseProgram(program);
+            gl::BindVertexArray(self.vertex_array);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        }
+    }
+
+    fn draw_osd(&mut self, osd: &Osd, width: u32, height: u32) {
+        if self.osd_image.width() != width || self.osd_image.height() != height {
+            self.osd_image = OsdImage::new(width, height);
+        } else {
+            self.osd_image.clear();
+        }
+        osd.draw(&mut self.osd_image);
+
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            upload_texture(
+                self.overlay,
+                gl::RGBA8,
+                gl::RGBA,
+                width,
+                height,
+                width as usize * 4,
+                self.osd_image.pixels(),
+            );
+
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        self.draw_quad(self.overlay_program);
+        unsafe {
+            gl::Disable(gl::BLEND);
+        }
+    }
+}
+
+impl VideoSink for GlVideoSink {
+    fn configure(&mut self, metadata: &PlaybackAssetMetadata) {
+        self.equirect = match self.projection {
+            Some(projection) => projection == Projection::Equirect,
+            None => metadata.is_spherical(),
+        };
+        if self.equirect {
+            info!("showing 360° video, drag to look around");
+        }
+
+        let gl_attr = self.video_subsystem.gl_attr();
+        gl_attr.set_context_profile(GLProfile::Core);
+        gl_attr.set_context_version(3, 3);
+
+        let window = create_window(&self.video_subsystem, metadata);
+        let context = window.gl_create_context().unwrap();
+        self.height = metadata.height();
+        self.aspect = metadata.width() as f64 / metadata.height().max(1) as f64;
+        #[cfg(feature = "taskbar")]
+        {
+            self.taskbar = Taskbar::new(&window);
+        }
+        gl::load_with(|name| self.video_subsystem.gl_get_proc_address(name) as *const _);
+        // presenting must never block the main loop on vsync
+        self.video_subsystem.gl_set_swap_interval(0).ok();
+
+        unsafe {
+            self.yuv_program = link_program(VERTEX_SHADER, YUV_FRAGMENT_SHADER)
+                .unwrap_or_else(|e| panic!("failed to build YUV shader: {}", e));
+            self.overlay_program = link_program(VERTEX_SHADER, OVERLAY_FRAGMENT_SHADER)
+                .unwrap_or_else(|e| panic!("failed to build overlay shader: {}", e));
+
+            gl::UseProgram(self.yuv_program);
+            for (unit, name) in ["y_plane", "u_plane", "v_plane"].iter().enumerate() {
+                gl::Uniform1i(uniform(self.yuv_program, name), unit as GLint);
+            }
+            gl::UseProgram(self.overlay_program);
+            gl::Uniform1i(uniform(self.overlay_program, "overlay"), 0);
+
+            gl::GenVertexArrays(1, &mut self.vertex_array);
+            gl::GenTextures(3, self.planes.as_mut_ptr());
+            gl::GenTextures(2, self.imported.as_mut_ptr());
+            gl::GenTextures(1, &mut self.overlay);
+            gl::GenTextures(2, self.targets.as_mut_ptr());
+            gl::GenFramebuffers(2, self.framebuffers.as_mut_ptr());
+            for texture in self
+                .planes
+                .iter()
+                .chain(&self.imported)
+                .chain(Some(&self.overlay))
+                .chain(&self.targets)
+            {
+                gl::BindTexture(gl::TEXTURE_2D, *texture);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+                gl::TexParameteri(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_WRAP_S,
+                    gl::CLAMP_TO_EDGE as GLint,
+                );
+                gl::TexParameteri(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_WRAP_T,
+                    gl::CLAMP_TO_EDGE as GLint,
+                );
+            }
+
+            gl::ClearColor(0_f32, 0_f32, 0_f32, 1_f32);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+        window.gl_swap_window();
+
+        for shader in &mut self.shaders {
+            shader.reload();
+        }
+        self.last_reload_check = Instant::now();
+
+        #[cfg(target_os = "linux")]
+        {
+            self.dmabuf = DmaBufImporter::new(&self.video_subsystem);
+            if self.dmabuf.is_some() {
+                info!("showing hardware decoded frames through DMA-BUF import");
+            }
+        }
+
+        self.window = Some(window);
+        self.context = Some(context);
+    }
+
+    fn submit(&mut self, frame: &Video, _pts: f64) {
+        if self.context.is_none() {
+            return;
+        }
+
+        let (width, height) = (frame.width(), frame.height());
+        let chroma = ((width + 1) / 2, (height + 1) / 2);
+        let (matrix, offset, scale) = conversion(frame);
+
+        // a surface has no planes in memory to upload, it's shown where it is or not at all
+        if hwdec::is_hardware(frame) {
+            if let Err(e) = self.import(frame) {
+                if !self.import_failing {
+                    eprintln!("failed to show a hardware decoded frame: {}", e);
+                    self.import_failing = true;
+                }
+                return;
+            }
+            self.import_failing = false;
+            self.nv12 = true;
+        } else {
+            for (index, texture) in self.planes.iter().enumerate() {
+                let (plane_width, plane_height) = if index == 0 { (width, height) } else { chroma };
+                unsafe {
+                    gl::ActiveTexture(gl::TEXTURE0 + index as GLenum);
+                    upload_texture(
+                        *texture,
+                        gl::R8,
+                        gl::RED,
+                        plane_width,
+                        plane_height,
+                        frame.stride(index),
+                        frame.data(index),
+                    );
+                }
+            }
+            self.nv12 = false;
+        }
+
+        unsafe {
+            gl::UseProgram(self.yuv_program);
+            gl::UniformMatrix3fv(
+                uniform(self.yuv_program, "yuv_to_rgb"),
+                1,
+                gl::FALSE,
+                matrix.as_ptr(),
+            );
+            gl::Uniform3fv(uniform(self.yuv_program, "offset"), 1, offset.as_ptr());
+            gl::Uniform3fv(uniform(self.yuv_program, "scale"), 1, scale.as_ptr());
+        }
+
+        self.height = height;
+        self.aspect = display_aspect(frame);
+        self.has_frame = true;
+    }
+
+    fn hardware_formats(&self) -> Vec<Pixel> {
+        #[cfg(target_os = "linux")]
+        {
+            if self.dmabuf.is_some() {
+                return vec![hwdec::vaapi_format()];
+            }
+        }
+        Vec::new()
+    }
+
+    fn present(&mut self, osd: &Osd) {
+        let (width, height) = match &self.window {
+            Some(window) => window.drawable_size(),
+            None => return,
+        };
+
+        if self.last_reload_check.elapsed() >= SHADER_RELOAD_INTERVAL {
+            for shader in &mut self.shaders {
+                shader.reload();
+            }
+            self.last_reload_check = Instant::now();
+        }
+
+        unsafe {
+            gl::Viewport(0, 0, width as GLint, height as GLint);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            if self.has_frame {
+                let textures = if self.nv12 {
+                    &self.imported[..]
+                } else {
+                    &self.planes[..]
+                };
+                for (index, texture) in textures.iter().enumerate() {
+                    gl::ActiveTexture(gl::TEXTURE0 + index as GLenum);
+                    gl::BindTexture(gl::TEXTURE_2D, *texture);
+                }
+            }
+        }
+        if self.has_frame {
+            // the view of a sphere always fills the window
+            let (x, y, picture_width, picture_height) = if self.equirect {
+                (0, 0, width, height)
+            } else {
+                destination_rect(self.aspect_mode, self.aspect, width, height)
+            };
+            unsafe {
+                gl::UseProgram(self.yuv_program);
+                gl::Uniform1i(uniform(self.yuv_program, "nv12"), GLint::from(self.nv12));
+                gl::Uniform1i(
+                    uniform(self.yuv_program, "equirect"),
+                    GLint::from(self.equirect),
+                );
+                gl::Uniform2f(uniform(self.yuv_program, "view"), self.view.0, self.view.1);
+                gl::Uniform1f(
+                    uniform(self.yuv_program, "fov_scale"),
+                    (self.fov.to_radians() / 2_f32).tan(),
+                );
+                gl::Uniform1f(
+                    uniform(self.yuv_program, "aspect"),
+                    picture_width as f32 / picture_height as f32,
+                );
+            }
+            // GL counts rows from the bottom
+            let y = (height - picture_height - y as u32) as GLint;
+            if self.shaders.iter().any(|shader| shader.program != 0) {
+                self.draw_passes((x, y, picture_width, picture_height));
+            } else {
+                unsafe {
+                    gl::Viewport(x, y, picture_width as GLint, picture_height as GLint);
+                }
+                self.draw_quad(self.yuv_program);
+            }
+            unsafe {
+                gl::Viewport(0, 0, width as GLint, height as GLint);
+            }
+        }
+        if osd.is_visible() {
+            self.draw_osd(osd, width, height);
+        }
+
+        if let Some(window) = &self.window {
+            window.gl_swap_window();
+        }
+    }
+
+    fn poll_events(&mut self, commands: &mut Vec<PlayerCommand>) {
+        let mut events = Vec::new();
+        poll_commands(&mut self.event_pump, &self.bindings, commands, |event| {
+            events.push(event.clone())
+        });
+        self.exposed |= events.iter().any(exposed);
+        let event_pump = &self.event_pump;
+        self.dropped.extend(
+            events
+                .iter()
+                .filter_map(|event| dropped_file(event, event_pump)),
+        );
+
+        if self.equirect {
+            for event in &events {
+                self.handle_mouse(event);
+            }
+        } else if let Some(window) = &self.window {
+            let (width, height) = window.size();
+            let (x, _, width, _) = destination_rect(self.aspect_mode, self.aspect, width, height);
+            commands.extend(
+                events
+                    .iter()
+                    .filter_map(|event| wipe_command(event, (x, width))),
+            );
+        }
+
+        if let Some(window) = &self.window {
+            let size = window.size();
+            commands.extend(events.iter().filter_map(|event| mouse_command(event, size)));
+        }
+    }
+
+    fn take_dropped_files(&mut self) -> Vec<DroppedFile> {
+        mem::take(&mut self.dropped)
+    }
+
+    fn take_exposed(&mut self) -> bool {
+        mem::take(&mut self.exposed)
+    }
+
+    fn set_key_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    fn set_aspect_mode(&mut self, mode: AspectMode) {
+        self.aspect_mode = mode;
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        if let Some(window) = &mut self.window {
+            toggle_fullscreen(window);
+        }
+    }
+
+    fn set_on_top(&mut self, on_top: bool) {
+        if let Some(window) = &self.window {
+            set_on_top(window, on_top);
+        }
+    }
+
+    fn set_title(&mut self, title: &str) {
+        if let Some(window) = &mut self.window {
+            set_title(window, title);
+        }
+    }
+
+    fn set_window_scale(&mut self, scale: f64) {
+        let width = (self.height as f64 * self.aspect).round() as u32;
+        if let Some(window) = &mut self.window {
+            scale_window(window, width, self.height, scale);
+        }
+    }
+
+    #[cfg(feature = "taskbar")]
+    fn set_progress(&mut self, progress: Option<f64>, paused: bool) {
+        if let Some(taskbar) = &mut self.taskbar {
+            taskbar.set_progress(progress, paused);
+        }
+    }
+
+    fn refresh_rate(&self) -> Option<u32> {
+        display_refresh_rate(&self.video_subsystem, self.window.as_ref()?)
+    }
+
+    fn clipboard_text(&self) -> Option<String> {
+        let clipboard = self.video_subsystem.clipboard();
+        if clipboard.has_clipboard_text() {
+            clipboard.clipboard_text().ok()
+        } else {
+            None
+        }
+    }
+
+    fn show_cursor(&mut self, show: bool) {
+        self.video_subsystem.sdl().mouse().show_cursor(show);
+    }
+
+    fn inhibit_idle(&mut self, inhibit: bool) {
+        if inhibit {
+            self.video_subsystem.disable_screen_saver();
+        } else {
+            self.video_subsystem.enable_screen_saver();
+        }
+    }
+}
+
+/// Column-major YUV→RGB matrix, offsets and range expansion for `frame`.
+fn conversion(frame: &Video) -> ([f32; 9], [f32; 3], [f32; 3]) {
+    // (Kr, Kb) luma coefficients; untagged content is guessed from its size, like most players
+    let (kr, kb) = match frame.color_space() {
+        Space::BT709 => (0.2126, 0.0722),
+        Space::BT2020NCL | Space::BT2020CL => (0.2627, 0.0593),
+        Space::BT470BG | Space::SMPTE170M | Space::FCC => (0.299, 0.114),
+        Space::SMPTE240M => (0.212, 0.087),
+        _ if frame.height() >= 720 => (0.2126, 0.0722),
+        _ => (0.299, 0.114),
+    };
+    let kg = 1_f32 - kr - kb;
+
+    let matrix = [
+        1_f32,
+        1_f32,
+        1_f32,
+        0_f32,
+        -2_f32 * kb * (1_f32 - kb) / kg,
+        2_f32 * (1_f32 - kb),
+        2_f32 * (1_f32 - kr),
+        -2_f32 * kr * (1_f32 - kr) / kg,
+        0_f32,
+    ];
+
+    let (offset, scale) = match frame.color_range() {
+        Range::JPEG => (
+            [0_f32, 128_f32 / 255_f32, 128_f32 / 255_f32],
+            [1_f32, 1_f32, 1_f32],
+        ),
+        // limited (MPEG) range is what nearly all video uses when untagged
+        _ => (
+            [16_f32 / 255_f32, 128_f32 / 255_f32, 128_f32 / 255_f32],
+            [255_f32 / 219_f32, 255_f32 / 224_f32, 255_f32 / 224_f32],
+        ),
+    };
+
+    (matrix, offset, scale)
+}
+
+unsafe fn upload_texture(
+    texture: GLuint,
+    internal_format: GLenum,
+    format: GLenum,
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: &[u8],
+) {
+    let bytes_per_pixel = if format == gl::RGBA { 4 } else { 1 };
+
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::PixelStorei(gl::UNPACK_ROW_LENGTH, (stride / bytes_per_pixel) as GLint);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        internal_format as GLint,
+        width as GLint,
+        height as GLint,
+        0,
+        format,
+        gl::UNSIGNED_BYTE,
+        data.as_ptr() as *const c_void,
+    );
+    gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+}
+
+unsafe fn uniform(program: GLuint, name: &str) -> GLint {
+    let name = CString::new(name).unwrap();
+    gl::GetUniformLocation(program, name.as_ptr())
+}
+
+unsafe fn compile_shader(source: &str, kind: GLenum) -> Result<GLuint, String> {
+    let shader = gl::CreateShader(kind);
+    let source = CString::new(source).unwrap();
+    gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+    gl::CompileShader(shader);
+
+    let mut status = 0;
+    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+    if status == gl::TRUE as GLint {
+        return Ok(shader);
+    }
+
+    let mut length = 0;
+    gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0_u8; length.max(1) as usize];
+    gl::GetShaderInfoLog(shader, length, ptr::null_mut(), log.as_mut_ptr() as *mut _);
+    gl::DeleteShader(shader);
+    Err(String::from_utf8_lossy(&log)
+        .trim_end_matches('\0')
+        .to_string())
+}
+
+unsafe fn link_program(vertex: &str, fragment: &str) -> Result<GLuint, String> {
+    let vertex = compile_shader(vertex, gl::VERTEX_SHADER)?;
+    let fragment = compile_shader(fragment, gl::FRAGMENT_SHADER)?;
+
+    let program = gl::CreateProgram();
+    gl::AttachShader(program, vertex);
+    gl::AttachShader(program, fragment);
+    gl::LinkProgram(program);
+    gl::DeleteShader(vertex);
+    gl::DeleteShader(fragment);
+
+    let mut status = 0;
+    gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+    if status == gl::TRUE as GLint {
+        return Ok(program);
+    }
+
+    let mut length = 0;
+    gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0_u8; length.max(1) as usize];
+    gl::GetProgramInfoLog(program, length, ptr::null_mut(), log.as_mut_ptr() as *mut _);
+    gl::DeleteProgram(program);
+    Err(String::from_utf8_lossy(&log)
+        .trim_end_matches('\0')
+        .to_string())
+}
@@ -0,0 +1,521 @@
+use std::mem;
+
+use ffmpeg_next::frame::Video;
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::{Mod, Scancode},
+    mouse::{MouseButton, MouseWheelDirection},
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture},
+    surface::Surface,
+    sys,
+    video::{FullscreenType, Window},
+    EventPump, Sdl, VideoSubsystem,
+};
+
+#[cfg(feature = "taskbar")]
+use super::taskbar::Taskbar;
+use super::{
+    destination_rect, display_aspect,
+    icon::{icon_rgba, ICON_SIZE},
+    DroppedFile, VideoSink, WINDOW_TITLE,
+};
+use crate::{
+    asset::PlaybackAssetMetadata,
+    bindings::{wheel_key, KeyBindings, KeyChord},
+    options::AspectMode,
+    osd::Osd,
+    player::{MouseAction, PlayerCommand},
+};
+
+/// Renders into an SDL window through a streaming YV12 texture.
+pub struct SdlVideoSink {
+    video_subsystem: VideoSubsystem,
+    event_pump: EventPump,
+    canvas: Option<Canvas<Window>>,
+    texture: Option<Texture>,
+    width: u32,
+    height: u32,
+    aspect: f64,
+    aspect_mode: AspectMode,
+    bindings: KeyBindings,
+    dropped: Vec<DroppedFile>,
+    exposed: bool,
+    #[cfg(feature = "taskbar")]
+    taskbar: Option<Taskbar>,
+    has_frame: bool,
+}
+
+impl SdlVideoSink {
+    pub fn new(sdl_context: &Sdl) -> Self {
+        SdlVideoSink {
+            video_subsystem: sdl_context.video().unwrap(),
+            event_pump: create_event_pump(sdl_context),
+            canvas: None,
+            texture: None,
+            width: 0,
+            height: 0,
+            aspect: 1_f64,
+            aspect_mode: AspectMode::Source,
+            bindings: KeyBindings::default(),
+            dropped: Vec::new(),
+            exposed: false,
+            #[cfg(feature = "taskbar")]
+            taskbar: None,
+            has_frame: false,
+        }
+    }
+
+    fn create_canvas(&self, window: Window) -> Canvas<Window> {
+        let mut canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|e| e.to_string())
+            .unwrap();
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.present();
+
+        canvas
+    }
+}
+
+impl VideoSink for SdlVideoSink {
+    fn configure(&mut self, metadata: &PlaybackAssetMetadata) {
+        let window = create_window(&self.video_subsystem, metadata);
+        #[cfg(feature = "taskbar")]
+        {
+            self.taskbar = Taskbar::new(&window);
+        }
+        let canvas = self.create_canvas(window);
+
+        self.width = metadata.width();
+        self.height = metadata.height();
+        self.aspect = self.width as f64 / self.height.max(1) as f64;
+        self.texture = Some(
+            canvas
+                .create_texture_streaming(PixelFormatEnum::YV12, self.width, self.height)
+                .unwrap(),
+        );
+        self.canvas = Some(canvas);
+    }
+
+    fn submit(&mut self, frame: &Video, _pts: f64) {
+        // filters may have changed the frame size
+        if frame.width() != self.width || frame.height() != self.height {
+            if let Some(canvas) = &self.canvas {
+                self.width = frame.width();
+                self.height = frame.height();
+                self.texture = canvas
+                    .texture_creator()
+                    .create_texture_streaming(PixelFormatEnum::YV12, self.width, self.height)
+                    .ok();
+            }
+        }
+
+        let texture = match &mut self.texture {
+            Some(texture) => texture,
+            None => return,
+        };
+
+        // SDL wants exactly `stride * rows` bytes per plane, with the chroma rows rounded down
+        let plane =
+            |index: usize, rows: u32| &frame.data(index)[..frame.stride(index) * rows as usize];
+        texture
+            .update_yuv(
+                None,
+                plane(0, self.height),
+                frame.stride(0),
+                plane(1, self.height / 2),
+                frame.stride(1),
+                plane(2, self.height / 2),
+                frame.stride(2),
+            )
+            .unwrap();
+        self.aspect = display_aspect(frame);
+        self.has_frame = true;
+    }
+
+    fn present(&mut self, osd: &Osd) {
+        if let (Some(canvas), Some(texture)) = (&mut self.canvas, &self.texture) {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            if self.has_frame {
+                let (width, height) = canvas.output_size().unwrap();
+                let (x, y, width, height) =
+                    destination_rect(self.aspect_mode, self.aspect, width, height);
+                canvas
+                    .copy(texture, None, Rect::new(x, y, width, height))
+                    .unwrap();
+            }
+            osd.draw(canvas);
+            canvas.present();
+        }
+    }
+
+    fn poll_events(&mut self, commands: &mut Vec<PlayerCommand>) {
+        let mut events = Vec::new();
+        poll_commands(&mut self.event_pump, &self.bindings, commands, |event| {
+            events.push(event.clone())
+        });
+        self.exposed |= events.iter().any(exposed);
+        let event_pump = &self.event_pump;
+        self.dropped.extend(
+            events
+                .iter()
+                .filter_map(|event| dropped_file(event, event_pump)),
+        );
+
+        if let Some(canvas) = &self.canvas {
+            let size = canvas.window().size();
+            let (x, _, width, _) = destination_rect(self.aspect_mode, self.aspect, size.0, size.1);
+            commands.extend(
+                events
+                    .iter()
+                    .filter_map(|event| wipe_command(event, (x, width))),
+            );
+            commands.extend(events.iter().filter_map(|event| mouse_command(event, size)));
+        }
+    }
+
+    fn take_dropped_files(&mut self) -> Vec<DroppedFile> {
+        mem::take(&mut self.dropped)
+    }
+
+    fn take_exposed(&mut self) -> bool {
+        mem::take(&mut self.exposed)
+    }
+
+    fn set_key_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    fn set_aspect_mode(&mut self, mode: AspectMode) {
+        self.aspect_mode = mode;
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        if let Some(canvas) = &mut self.canvas {
+            toggle_fullscreen(canvas.window_mut());
+        }
+    }
+
+    fn set_on_top(&mut self, on_top: bool) {
+        if let Some(canvas) = &self.canvas {
+            set_on_top(canvas.window(), on_top);
+        }
+    }
+
+    fn set_title(&mut self, title: &str) {
+        if let Some(canvas) = &mut self.canvas {
+            set_title(canvas.window_mut(), title);
+        }
+    }
+
+    fn set_window_scale(&mut self, scale: f64) {
+        let width = (self.height as f64 * self.aspect).round() as u32;
+        if let Some(canvas) = &mut self.canvas {
+            scale_window(canvas.window_mut(), width, self.height, scale);
+        }
+    }
+
+    #[cfg(feature = "taskbar")]
+    fn set_progress(&mut self, progress: Option<f64>, paused: bool) {
+        if let Some(taskbar) = &mut self.taskbar {
+            taskbar.set_progress(progress, paused);
+        }
+    }
+
+    fn refresh_rate(&self) -> Option<u32> {
+        display_refresh_rate(&self.video_subsystem, self.canvas.as_ref()?.window())
+    }
+
+    fn clipboard_text(&self) -> Option<String> {
+        let clipboard = self.video_subsystem.clipboard();
+        if clipboard.has_clipboard_text() {
+            clipboard.clipboard_text().ok()
+        } else {
+            None
+        }
+    }
+
+    fn show_cursor(&mut self, show: bool) {
+        self.video_subsystem.sdl().mouse().show_cursor(show);
+    }
+
+    fn inhibit_idle(&mut self, inhibit: bool) {
+        // SDL uses each platform's own mechanism: D-Bus ScreenSaver, SetThreadExecutionState
+        // or an IOKit power assertion
+        if inhibit {
+            self.video_subsystem.disable_screen_saver();
+        } else {
+            self.video_subsystem.enable_screen_saver();
+        }
+    }
+}
+
+pub(super) fn create_window(
+    video_subsystem: &VideoSubsystem,
+    asset: &PlaybackAssetMetadata,
+) -> Window {
+    let display_bounds = video_subsystem.display_bounds(0).unwrap();
+
+    let (window_width, window_height) =
+        if display_bounds.width() > asset.width() && display_bounds.height() > asset.height() {
+            // the original video size fits on the screen
+            (asset.width(), asset.height())
+        } else {
+            // scale to the size of the screen
+            let ratio = display_bounds.width() as f32 / asset.width() as f32;
+            (
+                display_bounds.width(),
+                (display_bounds.height() as f32 * ratio) as u32,
+            )
+        };
+
+    let mut window = video_subsystem
+        .window(WINDOW_TITLE, window_width, window_height)
+        .position_centered()
+        .allow_highdpi()
+        .opengl()
+        .build()
+        .map_err(|e| e.to_string())
+        .unwrap();
+    // the size is in points on HiDPI displays, so a video's pixels map to a whole number of
+    // screen pixels only once it's divided by the scale factor
+    scale_window(&mut window, window_width, window_height, 1_f64);
+
+    let mut icon = icon_rgba();
+    if let Ok(surface) = Surface::from_data(
+        &mut icon,
+        ICON_SIZE,
+        ICON_SIZE,
+        ICON_SIZE * 4,
+        PixelFormatEnum::RGBA32,
+    ) {
+        window.set_icon(surface);
+    }
+
+    window
+}
+
+/// Screen pixels per unit of window size: 2 on most HiDPI displays, 1 elsewhere.
+fn pixel_ratio(window: &Window) -> f64 {
+    let (width, _) = window.size();
+    let (drawable_width, _) = window.drawable_size();
+    if width > 0 && drawable_width > 0 {
+        drawable_width as f64 / width as f64
+    } else {
+        1_f64
+    }
+}
+
+/// Resizes `window` to show `width` by `height` pixels at `scale` times their size, in
+/// screen pixels rather than the points window sizes are in on HiDPI displays.
+pub(super) fn scale_window(window: &mut Window, width: u32, height: u32, scale: f64) {
+    if window.fullscreen_state() != FullscreenType::Off {
+        return;
+    }
+    let factor = scale / pixel_ratio(window);
+    let size = |pixels: u32| ((pixels as f64 * factor).round() as u32).max(1);
+    if let Err(e) = window.set_size(size(width), size(height)) {
+        eprintln!("failed to resize the window: {}", e);
+    }
+}
+
+/// Switches `window` between fullscreen on its display, at the desktop's resolution, and
+/// its own size.
+pub(super) fn toggle_fullscreen(window: &mut Window) {
+    let fullscreen = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    if let Err(e) = window.set_fullscreen(fullscreen) {
+        eprintln!("failed to toggle fullscreen: {}", e);
+    }
+}
+
+extern "C" {
+    // SDL 2.0.16 and later; the bindings sdl2 0.34 comes with predate it
+    fn SDL_SetWindowAlwaysOnTop(window: *mut sys::SDL_Window, on_top: sys::SDL_bool);
+}
+
+/// Keeps `window` above other applications' windows or not, where the platform allows.
+pub(super) fn set_on_top(window: &Window, on_top: bool) {
+    let on_top = if on_top {
+        sys::SDL_bool::SDL_TRUE
+    } else {
+        sys::SDL_bool::SDL_FALSE
+    };
+    unsafe { SDL_SetWindowAlwaysOnTop(window.raw(), on_top) }
+}
+
+pub(super) fn set_title(window: &mut Window, title: &str) {
+    if let Err(e) = window.set_title(title) {
+        eprintln!("failed to set the window title: {}", e);
+    }
+}
+
+/// Refresh rate of the display `window` is on, `None` where SDL doesn't know it.
+pub(super) fn display_refresh_rate(
+    video_subsystem: &VideoSubsystem,
+    window: &Window,
+) -> Option<u32> {
+    let display = window.display_index().ok()?;
+    let mode = video_subsystem.current_display_mode(display).ok()?;
+    if mode.refresh_rate > 0 {
+        Some(mode.refresh_rate as u32)
+    } else {
+        None
+    }
+}
+
+/// Dragging with the left button moves the compare wipe line, as a fraction of the picture
+/// at `picture`, its left edge and width in window coordinates.
+pub(super) fn wipe_command(event: &Event, picture: (i32, u32)) -> Option<PlayerCommand> {
+    let x = match event {
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Left,
+            x,
+            ..
+        } => *x,
+        Event::MouseMotion { mousestate, x, .. } if mousestate.left() => *x,
+        _ => return None,
+    };
+    Some(PlayerCommand::Wipe(
+        (x - picture.0) as f64 / picture.1.max(1) as f64,
+    ))
+}
+
+/// Motion and left button events as `PlayerCommand::Mouse`, for a window of `size`.
+pub(super) fn mouse_command(event: &Event, size: (u32, u32)) -> Option<PlayerCommand> {
+    let (action, x, y) = match *event {
+        Event::MouseMotion { x, y, .. } => (MouseAction::Move, x, y),
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Left,
+            x,
+            y,
+            ..
+        } => (MouseAction::Press, x, y),
+        Event::MouseButtonUp {
+            mouse_btn: MouseButton::Left,
+            x,
+            y,
+            ..
+        } => (MouseAction::Release, x, y),
+        _ => return None,
+    };
+    Some(PlayerCommand::Mouse {
+        action,
+        x: x as f64 / size.0.max(1) as f64,
+        y: y as f64 / size.1.max(1) as f64,
+    })
+}
+
+/// Whether `event` means the window's contents were lost or have to be drawn at another size.
+pub(super) fn exposed(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Window {
+            win_event: WindowEvent::Exposed
+                | WindowEvent::FocusGained
+                | WindowEvent::Restored
+                | WindowEvent::SizeChanged(..),
+            ..
+        }
+    )
+}
+
+/// A file dropped onto the window; holding shift queues it instead of opening it.
+pub(super) fn dropped_file(event: &Event, event_pump: &EventPump) -> Option<DroppedFile> {
+    match event {
+        Event::DropFile { filename, .. } => {
+            let keyboard = event_pump.keyboard_state();
+            Some(DroppedFile {
+                path: filename.clone(),
+                append: keyboard.is_scancode_pressed(Scancode::LShift)
+                    || keyboard.is_scancode_pressed(Scancode::RShift),
+            })
+        }
+        _ => None,
+    }
+}
+
+pub(super) fn create_event_pump(sdl_context: &Sdl) -> EventPump {
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    // warm up the event pump
+    event_pump.pump_events();
+
+    event_pump
+}
+
+/// Maps SDL window, key, wheel and middle and right button events to player commands through
+/// `bindings`, handing any other events to `unhandled`.
+pub(super) fn poll_commands(
+    event_pump: &mut EventPump,
+    bindings: &KeyBindings,
+    commands: &mut Vec<PlayerCommand>,
+    mut unhandled: impl FnMut(&Event),
+) {
+    for event in event_pump.poll_iter() {
+        let command = match event {
+            Event::Quit { .. } => PlayerCommand::Stop,
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => {
+                let chord = KeyChord {
+                    key: keycode.name().to_lowercase(),
+                    ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+                    alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+                    shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+                };
+                match bindings.get(&chord) {
+                    Some(command) => command,
+                    // printable keys have their character as keycode
+                    None => match char::from_u32(keycode as u32).filter(char::is_ascii_graphic) {
+                        Some(key) => PlayerCommand::Key(key),
+                        None => continue,
+                    },
+                }
+            }
+            Event::MouseWheel {
+                x, y, direction, ..
+            } => {
+                let flip = if direction == MouseWheelDirection::Flipped {
+                    -1_f64
+                } else {
+                    1_f64
+                };
+                let key = wheel_key(x as f64 * flip, y as f64 * flip);
+                match key.and_then(|key| bindings.get(&KeyChord::new(key))) {
+                    Some(command) => command,
+                    None => continue,
+                }
+            }
+            Event::MouseButtonDown {
+                mouse_btn: button @ (MouseButton::Middle | MouseButton::Right),
+                ..
+            } => {
+                let key = if button == MouseButton::Middle {
+                    "mouse-middle"
+                } else {
+                    "mouse-right"
+                };
+                match bindings.get(&KeyChord::new(key)) {
+                    Some(command) => command,
+                    None => continue,
+                }
+            }
+            event => {
+                unhandled(&event);
+                continue;
+            }
+        };
+        commands.push(command);
+    }
+}
@@ -0,0 +1,156 @@
+use sdl2::video::Window;
+
+/// Playback progress on the window's taskbar entry: a progress bar on the Windows taskbar
+/// button, and on the launcher icon where Unity's launcher API is around (Ubuntu's dock,
+/// KDE and Plank among others).
+pub(super) struct Taskbar {
+    inner: platform::Taskbar,
+    shown: Option<(u32, bool)>,
+}
+
+impl Taskbar {
+    /// `None` where there's nothing to report progress to.
+    pub(super) fn new(window: &Window) -> Option<Self> {
+        Some(Taskbar {
+            inner: platform::Taskbar::new(window)?,
+            shown: None,
+        })
+    }
+
+    /// Shows `progress`, from 0 to 1, or hides it when `None`.
+    pub(super) fn set_progress(&mut self, progress: Option<f64>, paused: bool) {
+        // the bar is a few hundred pixels at best, so finer steps would only be noise
+        let shown =
+            progress.map(|progress| ((progress.clamp(0_f64, 1_f64) * 1000_f64) as u32, paused));
+        if shown != self.shown {
+            self.shown = shown;
+            self.inner.set_progress(shown);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+    use sdl2::video::Window;
+    use windows::Win32::{
+        Foundation::HWND,
+        System::Com::{
+            CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
+        },
+        UI::Shell::{ITaskbarList3, TaskbarList, TBPF_NOPROGRESS, TBPF_NORMAL, TBPF_PAUSED},
+    };
+
+    pub(super) struct Taskbar {
+        list: ITaskbarList3,
+        window: HWND,
+    }
+
+    impl Taskbar {
+        pub(super) fn new(window: &Window) -> Option<Self> {
+            let window = match window.raw_window_handle() {
+                RawWindowHandle::Windows(handle) => HWND(handle.hwnd as isize),
+                _ => return None,
+            };
+            unsafe {
+                // fails harmlessly when COM is already set up on this thread
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                let list: ITaskbarList3 =
+                    CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER).ok()?;
+                list.HrInit().ok()?;
+                Some(Taskbar { list, window })
+            }
+        }
+
+        /// In thousandths.
+        pub(super) fn set_progress(&mut self, progress: Option<(u32, bool)>) {
+            let result = unsafe {
+                match progress {
+                    Some((progress, paused)) => self
+                        .list
+                        .SetProgressState(
+                            self.window,
+                            if paused { TBPF_PAUSED } else { TBPF_NORMAL },
+                        )
+                        .and_then(|_| {
+                            self.list
+                                .SetProgressValue(self.window, progress as u64, 1000)
+                        }),
+                    None => self.list.SetProgressState(self.window, TBPF_NOPROGRESS),
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("failed to set taskbar progress: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use dbus::{
+        arg::{PropMap, RefArg, Variant},
+        blocking::Connection,
+        channel::Sender,
+        Message,
+    };
+    use sdl2::video::Window;
+
+    /// The desktop file launchers match the entry against.
+    const APP_URI: &str = "application://video-player-rs.desktop";
+    const OBJECT_PATH: &str = "/com/github/iolivia/VideoPlayer";
+
+    pub(super) struct Taskbar {
+        connection: Connection,
+    }
+
+    impl Taskbar {
+        pub(super) fn new(_window: &Window) -> Option<Self> {
+            // launchers only listen for signals, so there's no telling whether one is around
+            Connection::new_session()
+                .ok()
+                .map(|connection| Taskbar { connection })
+        }
+
+        /// In thousandths; Unity's launcher has no paused state.
+        pub(super) fn set_progress(&mut self, progress: Option<(u32, bool)>) {
+            let mut properties = PropMap::new();
+            let value = progress.map_or(0_f64, |(progress, _)| progress as f64 / 1000_f64);
+            properties.insert(
+                "progress".to_string(),
+                Variant(Box::new(value) as Box<dyn RefArg>),
+            );
+            properties.insert(
+                "progress-visible".to_string(),
+                Variant(Box::new(progress.is_some()) as Box<dyn RefArg>),
+            );
+
+            let message = match Message::new_signal(
+                OBJECT_PATH,
+                "com.canonical.Unity.LauncherEntry",
+                "Update",
+            ) {
+                Ok(message) => message.append2(APP_URI, properties),
+                Err(_) => return,
+            };
+            if self.connection.send(message).is_err() {
+                eprintln!("failed to set launcher progress");
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    use sdl2::video::Window;
+
+    pub(super) struct Taskbar;
+
+    impl Taskbar {
+        pub(super) fn new(_window: &Window) -> Option<Self> {
+            None
+        }
+
+        pub(super) fn set_progress(&mut self, _progress: Option<(u32, bool)>) {}
+    }
+}
@@ -0,0 +1,479 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    software::scaling::{self, Flags},
+};
+
+use super::VideoSink;
+use crate::{
+    asset::PlaybackAssetMetadata,
+    dump::{self, ImageFormat},
+    osd::{Osd, OsdImage},
+};
+
+/// Terminals can't keep up with full frame rate images, so pictures are sent at most this often.
+const MAX_FPS: f64 = 12_f64;
+
+/// Kitty splits the base64 payload into chunks of at most this many bytes.
+const KITTY_CHUNK: usize = 4096;
+
+/// Inline image protocol spoken by the terminal, or coloured half blocks for terminals
+/// without any.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Protocol {
+    Kitty,
+    Iterm2,
+    Sixel,
+    Blocks { truecolor: bool },
+}
+
+impl Protocol {
+    /// Guesses from the environment; sixel is the fallback, as the most widely supported.
+    fn detect() -> Self {
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || program == "WezTerm"
+            || program == "ghostty"
+        {
+            Protocol::Kitty
+        } else if program == "iTerm.app" {
+            Protocol::Iterm2
+        } else {
+            Protocol::Sixel
+        }
+    }
+}
+
+/// Draws downscaled frames straight into the terminal with the Kitty graphics protocol,
+/// iTerm2 inline images or sixel (`--vo terminal`), or as coloured text (`--vo tct`), for
+/// previews over SSH.
+///
+/// Pictures go to the controlling terminal rather than stdout, so the player's log can be
+/// redirected away from them.
+pub struct TerminalVideoSink {
+    protocol: Protocol,
+    output: Box<dyn Write>,
+    width: u32,
+    scaler: Option<scaling::Context>,
+    picture: Option<Video>,
+    last_pts: Option<f64>,
+    last_emit: Option<Instant>,
+    pending: bool,
+    showed_osd: bool,
+    osd_image: OsdImage,
+}
+
+impl TerminalVideoSink {
+    /// `width` is the size of the pictures sent to the terminal, in pixels.
+    pub fn new(width: u32) -> Self {
+        Self::with_protocol(Protocol::detect(), width.max(16))
+    }
+
+    /// Renders with `▀` half blocks, `columns` characters wide, in 24-bit colour where
+    /// `COLORTERM` says it's supported and the 256-colour palette otherwise.
+    pub fn text(columns: u32) -> Self {
+        let truecolor =
+            env::var("COLORTERM").map_or(false, |value| value == "truecolor" || value == "24bit");
+        Self::with_protocol(Protocol::Blocks { truecolor }, columns.max(8))
+    }
+
+    fn with_protocol(protocol: Protocol, width: u32) -> Self {
+        let output: Box<dyn Write> = match OpenOptions::new().write(true).open("/dev/tty") {
+            Ok(tty) => Box::new(tty),
+            Err(_) => Box::new(io::stdout()),
+        };
+
+        TerminalVideoSink {
+            protocol,
+            output,
+            width,
+            scaler: None,
+            picture: None,
+            last_pts: None,
+            last_emit: None,
+            pending: false,
+            showed_osd: false,
+            osd_image: OsdImage::new(0, 0),
+        }
+    }
+
+    fn emit(&mut self, rgb: &[u8], width: u32, height: u32) -> io::Result<()> {
+        let mut data = Vec::new();
+
+        // draw over the previous picture instead of scrolling
+        data.extend_from_slice(b"\x1b[H");
+        match self.protocol {
+            Protocol::Kitty => encode_kitty(&mut data, rgb, width, height),
+            Protocol::Iterm2 => {
+                let mut picture = Video::new(Pixel::RGB24, width, height);
+                let stride = picture.stride(0);
+                for (line, source) in picture
+                    .data_mut(0)
+                    .chunks_mut(stride)
+                    .zip(rgb.chunks(width as usize * 3))
+                {
+                    line[..source.len()].copy_from_slice(source);
+                }
+
+                match dump::encode_image(&picture, ImageFormat::Png) {
+                    Ok(png) => encode_iterm2(&mut data, &png, width),
+                    Err(_) => return Ok(()),
+                }
+            }
+            Protocol::Sixel => encode_sixel(&mut data, rgb, width, height),
+            Protocol::Blocks { truecolor } => {
+                encode_blocks(&mut data, rgb, width, height, truecolor)
+            }
+        }
+
+        self.output.write_all(&data)?;
+        self.output.flush()
+    }
+}
+
+impl VideoSink for TerminalVideoSink {
+    fn configure(&mut self, _metadata: &PlaybackAssetMetadata) {
+        // start from a clear screen
+        self.output.write_all(b"\x1b[2J").ok();
+        self.output.flush().ok();
+    }
+
+    fn submit(&mut self, frame: &Video, pts: f64) {
+        // drop frames beyond the rate the terminal can take (with some slack, so 24fps lands
+        // on every other frame); a jump back means a seek
+        if let Some(last_pts) = self.last_pts {
+            if pts >= last_pts && pts - last_pts < 0.9 / MAX_FPS {
+                return;
+            }
+        }
+        self.last_pts = Some(pts);
+
+        // pixels are square, keep the height even for the scaler
+        let height = self.width as u64 * frame.height() as u64 / frame.width().max(1) as u64;
+        let height = (height as u32 & !1).max(2);
+        let matches = self.scaler.as_ref().map_or(false, |scaler| {
+            let (input, output) = (scaler.input(), scaler.output());
+            input.format == frame.format()
+                && input.width == frame.width()
+                && input.height == frame.height()
+                && output.height == height
+        });
+        if !matches {
+            self.scaler = scaling::Context::get(
+                frame.format(),
+                frame.width(),
+                frame.height(),
+                Pixel::RGB24,
+                self.width,
+                height,
+                Flags::AREA,
+            )
+            .ok();
+        }
+
+        let scaler = match &mut self.scaler {
+            Some(scaler) => scaler,
+            None => return,
+        };
+        let mut picture = Video::empty();
+        if scaler.run(frame, &mut picture).is_ok() {
+            self.picture = Some(picture);
+            self.pending = true;
+        }
+    }
+
+    fn present(&mut self, osd: &Osd) {
+        let picture = match &self.picture {
+            Some(picture) => picture,
+            None => return,
+        };
+
+        // only a changed picture or OSD is worth sending again, and not too often
+        if !self.pending && !osd.is_visible() && !self.showed_osd {
+            return;
+        }
+        let interval = Duration::from_secs_f64(1_f64 / MAX_FPS);
+        if !self.pending
+            && self
+                .last_emit
+                .map_or(false, |last_emit| last_emit.elapsed() < interval)
+        {
+            return;
+        }
+
+        let (width, height) = (picture.width(), picture.height());
+        let stride = picture.stride(0);
+        let row = width as usize * 3;
+        let mut rgb: Vec<u8> = picture
+            .data(0)
+            .chunks(stride)
+            .take(height as usize)
+            .flat_map(|line| line[..row].iter().copied())
+            .collect();
+
+        if osd.is_visible() {
+            if self.osd_image.width() != width || self.osd_image.height() != height {
+                self.osd_image = OsdImage::new(width, height);
+            } else {
+                self.osd_image.clear();
+            }
+            osd.draw(&mut self.osd_image);
+            blend(&mut rgb, &self.osd_image);
+        }
+
+        if self.emit(&rgb, width, height).is_err() {
+            // the terminal went away; nothing more to draw on
+            self.picture = None;
+        }
+        self.pending = false;
+        self.showed_osd = osd.is_visible();
+        self.last_emit = Some(Instant::now());
+    }
+}
+
+impl Drop for TerminalVideoSink {
+    fn drop(&mut self) {
+        if self.protocol == Protocol::Kitty {
+            // remove the image, Kitty would otherwise keep it on screen
+            self.output.write_all(b"\x1b_Ga=d,d=I,i=1,q=2\x1b\\").ok();
+        }
+        self.output.write_all(b"\x1b[0m\x1b[2J\x1b[H").ok();
+        self.output.flush().ok();
+    }
+}
+
+/// Transmits and places RGB pixels as Kitty image 1, replacing the previous picture.
+fn encode_kitty(data: &mut Vec<u8>, rgb: &[u8], width: u32, height: u32) {
+    let payload = base64(rgb);
+    let mut chunks = payload.chunks(KITTY_CHUNK).peekable();
+    let mut first = true;
+
+    while let Some(chunk) = chunks.next() {
+        let more = u8::from(chunks.peek().is_some());
+        if first {
+            data.extend_from_slice(
+                format!(
+                    "\x1b_Ga=T,f=24,s={},v={},i=1,p=1,C=1,q=2,m={};",
+                    width, height, more
+                )
+                .as_bytes(),
+            );
+            first = false;
+        } else {
+            data.extend_from_slice(format!("\x1b_Gm={};", more).as_bytes());
+        }
+        data.extend_from_slice(chunk);
+        data.extend_from_slice(b"\x1b\\");
+    }
+}
+
+/// Shows a PNG with the iTerm2 inline image protocol.
+fn encode_iterm2(data: &mut Vec<u8>, png: &[u8], width: u32) {
+    data.extend_from_slice(
+        format!(
+            "\x1b]1337;File=inline=1;size={};width={}px;preserveAspectRatio=1:",
+            png.len(),
+            width
+        )
+        .as_bytes(),
+    );
+    data.extend_from_slice(&base64(png));
+    data.push(0x07);
+}
+
+/// Encodes RGB pixels as sixel, quantized to a 6x6x6 colour cube.
+fn encode_sixel(data: &mut Vec<u8>, rgb: &[u8], width: u32, height: u32) {
+    let (width, height) = (width as usize, height as usize);
+    let level = |value: u8| (value as usize * 5 + 127) / 255;
+    let indices: Vec<u8> = rgb
+        .chunks_exact(3)
+        .map(|pixel| (level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])) as u8)
+        .collect();
+
+    // 1:1 pixel aspect, then the palette in percentages
+    data.extend_from_slice(format!("\x1bPq\"1;1;{};{}", width, height).as_bytes());
+    for index in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        data.extend_from_slice(
+            format!(
+                "#{};2;{};{};{}",
+                index,
+                percent(index / 36),
+                percent(index / 6 % 6),
+                percent(index % 6)
+            )
+            .as_bytes(),
+        );
+    }
+
+    let mut sixels = vec![0_u8; width];
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+
+        let mut used = [false; 216];
+        for row in 0..rows {
+            let start = (band + row) * width;
+            for &index in &indices[start..start + width] {
+                used[index as usize] = true;
+            }
+        }
+
+        let mut first = true;
+        for (color, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            for (column, sixel) in sixels.iter_mut().enumerate() {
+                *sixel = (0..rows)
+                    .filter(|row| indices[(band + row) * width + column] as usize == color)
+                    .fold(0, |bits, row| bits | 1 << row);
+            }
+
+            // overprint each colour on the same band
+            if !first {
+                data.push(b'$');
+            }
+            first = false;
+            data.extend_from_slice(format!("#{}", color).as_bytes());
+            write_sixel_run(data, &sixels);
+        }
+        data.push(b'-');
+    }
+
+    data.extend_from_slice(b"\x1b\\");
+}
+
+/// Renders RGB pixels as text, two pixels per character: the upper half block takes the top
+/// one as its foreground and the bottom one as its background.
+fn encode_blocks(data: &mut Vec<u8>, rgb: &[u8], width: u32, height: u32, truecolor: bool) {
+    let (width, height) = (width as usize, height as usize);
+    let pixel = |x: usize, y: usize| {
+        let offset = (y.min(height - 1) * width + x) * 3;
+        [rgb[offset], rgb[offset + 1], rgb[offset + 2]]
+    };
+    let color = |data: &mut Vec<u8>, layer: u8, [r, g, b]: [u8; 3]| {
+        let code = if truecolor {
+            format!("\x1b[{};2;{};{};{}m", layer, r, g, b)
+        } else {
+            format!("\x1b[{};5;{}m", layer, ansi256(r, g, b))
+        };
+        data.extend_from_slice(code.as_bytes());
+    };
+
+    for y in (0..height).step_by(2) {
+        if y > 0 {
+            data.extend_from_slice(b"\x1b[0m\r\n");
+        }
+
+        // colours only need repeating when they change
+        let (mut foreground, mut background) = (None, None);
+        for x in 0..width {
+            let (top, bottom) = (pixel(x, y), pixel(x, y + 1));
+            if foreground != Some(top) {
+                color(data, 38, top);
+                foreground = Some(top);
+            }
+            if background != Some(bottom) {
+                color(data, 48, bottom);
+                background = Some(bottom);
+            }
+            data.extend_from_slice("\u{2580}".as_bytes());
+        }
+    }
+    data.extend_from_slice(b"\x1b[0m");
+}
+
+/// Nearest entry of the xterm 256-colour palette, from the 6x6x6 cube or the grey ramp.
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let level = |value: u8| match value {
+        0..=47 => 0,
+        48..=114 => 1,
+        value => (value as usize - 35) / 40,
+    };
+    let (r_level, g_level, b_level) = (level(r), level(g), level(b));
+    let cube = [LEVELS[r_level], LEVELS[g_level], LEVELS[b_level]];
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_level = (average.saturating_sub(3) / 10).min(23);
+    let grey = (8 + grey_level * 10) as u8;
+
+    let distance = |[red, green, blue]: [u8; 3]| {
+        let delta = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        delta(red, r) + delta(green, g) + delta(blue, b)
+    };
+    if distance([grey, grey, grey]) < distance(cube) {
+        232 + grey_level as u8
+    } else {
+        (16 + r_level * 36 + g_level * 6 + b_level) as u8
+    }
+}
+
+/// Writes one colour's row of sixels, run-length encoded.
+fn write_sixel_run(data: &mut Vec<u8>, sixels: &[u8]) {
+    let mut column = 0;
+    while column < sixels.len() {
+        let bits = sixels[column];
+        let run = sixels[column..]
+            .iter()
+            .take_while(|&&other| other == bits)
+            .count();
+
+        let character = b'?' + bits;
+        if run > 3 {
+            data.extend_from_slice(format!("!{}", run).as_bytes());
+            data.push(character);
+        } else {
+            data.extend(std::iter::repeat(character).take(run));
+        }
+        column += run;
+    }
+}
+
+/// Composites the OSD overlay onto packed RGB pixels.
+fn blend(rgb: &mut [u8], overlay: &OsdImage) {
+    for (pixel, source) in rgb
+        .chunks_exact_mut(3)
+        .zip(overlay.pixels().chunks_exact(4))
+    {
+        let alpha = source[3] as u32;
+        if alpha == 0 {
+            continue;
+        }
+
+        for (below, &value) in pixel.iter_mut().zip(source) {
+            *below = ((value as u32 * alpha + *below as u32 * (255 - alpha)) / 255) as u8;
+        }
+    }
+}
+
+fn base64(input: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = Vec::with_capacity(input.len() / 3 * 4 + 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3f]);
+            } else {
+                output.push(b'=');
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,498 @@
+use std::{mem, num::NonZeroU32, rc::Rc, time::Duration};
+
+use ::softbuffer::{Context, Surface};
+use ::winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::{Key, ModifiersState, NamedKey},
+    platform::pump_events::EventLoopExtPumpEvents,
+    window::{Fullscreen, Icon, Window, WindowBuilder, WindowLevel},
+};
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    software::scaling::{self, Flags},
+};
+
+use super::{
+    destination_rect, display_aspect,
+    icon::{icon_rgba, ICON_SIZE},
+    DroppedFile, VideoSink, WINDOW_TITLE,
+};
+use crate::{
+    asset::PlaybackAssetMetadata,
+    bindings::{wheel_key, KeyBindings, KeyChord},
+    options::AspectMode,
+    osd::{Osd, OsdImage},
+    player::{MouseAction, PlayerCommand},
+};
+
+/// Presents through a plain winit window and a CPU framebuffer (softbuffer), for builds
+/// without SDL. swscale does the scaling and the YUV→RGB conversion.
+pub struct WinitVideoSink {
+    event_loop: EventLoop<()>,
+    window: Option<Rc<Window>>,
+    context: Option<Context<Rc<Window>>>,
+    surface: Option<Surface<Rc<Window>, Rc<Window>>>,
+    scaler: Option<scaling::Context>,
+    frame: Option<Video>,
+    /// Size of the video in pixels, until the first frame is in.
+    video_size: (u32, u32),
+    osd_image: OsdImage,
+    aspect_mode: AspectMode,
+    /// Where the mouse was last seen, and whether the left button is down, for dragging.
+    cursor: (f64, f64),
+    dragging: bool,
+    modifiers: ModifiersState,
+    bindings: KeyBindings,
+    dropped: Vec<DroppedFile>,
+    exposed: bool,
+}
+
+impl WinitVideoSink {
+    pub fn new() -> Self {
+        WinitVideoSink {
+            event_loop: EventLoop::new().expect("Failed to create event loop"),
+            window: None,
+            context: None,
+            surface: None,
+            scaler: None,
+            frame: None,
+            video_size: (0, 0),
+            osd_image: OsdImage::new(0, 0),
+            aspect_mode: AspectMode::Source,
+            cursor: (0_f64, 0_f64),
+            dragging: false,
+            modifiers: ModifiersState::empty(),
+            bindings: KeyBindings::default(),
+            dropped: Vec::new(),
+            exposed: false,
+        }
+    }
+}
+
+impl Default for WinitVideoSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VideoSink for WinitVideoSink {
+    fn configure(&mut self, metadata: &PlaybackAssetMetadata) {
+        let window = WindowBuilder::new()
+            .with_title(WINDOW_TITLE)
+            // in pixels, so HiDPI displays don't scale the video up
+            .with_inner_size(PhysicalSize::new(metadata.width(), metadata.height()))
+            .with_window_icon(Icon::from_rgba(icon_rgba(), ICON_SIZE, ICON_SIZE).ok())
+            .build(&self.event_loop)
+            .expect("Failed to create window");
+        let window = Rc::new(window);
+        self.video_size = (metadata.width(), metadata.height());
+
+        let context = Context::new(Rc::clone(&window)).expect("Failed to create softbuffer");
+        let surface =
+            Surface::new(&context, Rc::clone(&window)).expect("Failed to create softbuffer");
+
+        self.window = Some(window);
+        self.context = Some(context);
+        self.surface = Some(surface);
+    }
+
+    fn submit(&mut self, frame: &Video, _pts: f64) {
+        // scaled on present, when the window size is known
+        self.frame = Some(frame.clone());
+    }
+
+    fn present(&mut self, osd: &Osd) {
+        let WinitVideoSink {
+            window,
+            surface,
+            scaler,
+            frame,
+            osd_image,
+            aspect_mode,
+            ..
+        } = self;
+        let (window, surface) = match (window, surface) {
+            (Some(window), Some(surface)) => (window, surface),
+            _ => return,
+        };
+
+        let size = window.inner_size();
+        let (width, height) = match (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return,
+        };
+        surface.resize(width, height).unwrap();
+        let (width, height) = (width.get(), height.get());
+
+        let mut buffer = surface.buffer_mut().unwrap();
+        buffer.fill(0);
+        let picture = frame.as_ref().and_then(|frame| {
+            let (x, y, picture_width, picture_height) =
+                destination_rect(*aspect_mode, display_aspect(frame), width, height);
+            scale(scaler, frame, picture_width, picture_height)
+                .map(|picture| (x as usize, y as usize, picture))
+        });
+        if let Some((x, y, picture)) = picture {
+            // BGR0 in memory is 0x00RRGGBB as a little-endian u32
+            let stride = picture.stride(0);
+            let lines = buffer.chunks_mut(width as usize).skip(y);
+            for (row, line) in lines.take(picture.height() as usize).enumerate() {
+                let source = &picture.data(0)[row * stride..];
+                let line = &mut line[x..x + picture.width() as usize];
+                for (column, pixel) in line.iter_mut().enumerate() {
+                    let offset = column * 4;
+                    *pixel = u32::from_le_bytes([
+                        source[offset],
+                        source[offset + 1],
+                        source[offset + 2],
+                        0,
+                    ]);
+                }
+            }
+        }
+
+        if osd.is_visible() {
+            if osd_image.width() != width || osd_image.height() != height {
+                *osd_image = OsdImage::new(width, height);
+            } else {
+                osd_image.clear();
+            }
+            osd.draw(osd_image);
+            blend(&mut buffer, osd_image);
+        }
+
+        buffer.present().unwrap();
+    }
+
+    fn poll_events(&mut self, commands: &mut Vec<PlayerCommand>) {
+        // left edge and width of the picture, for turning drags into wipe positions
+        let picture = match (&self.window, &self.frame) {
+            (Some(window), Some(frame)) => {
+                let size = window.inner_size();
+                let (x, _, width, _) = destination_rect(
+                    self.aspect_mode,
+                    display_aspect(frame),
+                    size.width,
+                    size.height,
+                );
+                Some((x as f64, width.max(1) as f64))
+            }
+            _ => None,
+        };
+        let size = self.window.as_ref().map(|window| {
+            let size = window.inner_size();
+            (size.width.max(1) as f64, size.height.max(1) as f64)
+        });
+        let mouse = |action, (x, y): (f64, f64)| {
+            size.map(|(width, height)| PlayerCommand::Mouse {
+                action,
+                x: x / width,
+                y: y / height,
+            })
+        };
+        let (mut cursor, mut dragging) = (self.cursor, self.dragging);
+        let mut modifiers = self.modifiers;
+        let bindings = &self.bindings;
+        let dropped = &mut self.dropped;
+        let exposed = &mut self.exposed;
+
+        self.event_loop
+            .pump_events(Some(Duration::ZERO), |event, _| {
+                let command = match event {
+                    Event::WindowEvent {
+                        event: WindowEvent::CursorMoved { position, .. },
+                        ..
+                    } => {
+                        cursor = (position.x, position.y);
+                        commands.extend(mouse(MouseAction::Move, cursor));
+                        match picture {
+                            Some((x, width)) if dragging => {
+                                PlayerCommand::Wipe((cursor.0 - x) / width)
+                            }
+                            _ => return,
+                        }
+                    }
+                    Event::WindowEvent {
+                        event:
+                            WindowEvent::MouseInput {
+                                state,
+                                button: MouseButton::Left,
+                                ..
+                            },
+                        ..
+                    } => {
+                        dragging = state == ElementState::Pressed;
+                        let action = if dragging {
+                            MouseAction::Press
+                        } else {
+                            MouseAction::Release
+                        };
+                        commands.extend(mouse(action, cursor));
+                        match picture {
+                            Some((x, width)) if dragging => {
+                                PlayerCommand::Wipe((cursor.0 - x) / width)
+                            }
+                            _ => return,
+                        }
+                    }
+                    Event::WindowEvent {
+                        event:
+                            WindowEvent::MouseInput {
+                                state: ElementState::Pressed,
+                                button: button @ (MouseButton::Middle | MouseButton::Right),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let key = if button == MouseButton::Middle {
+                            "mouse-middle"
+                        } else {
+                            "mouse-right"
+                        };
+                        match bindings.get(&chord(key.to_string(), modifiers)) {
+                            Some(command) => command,
+                            None => return,
+                        }
+                    }
+                    Event::WindowEvent {
+                        event: WindowEvent::MouseWheel { delta, .. },
+                        ..
+                    } => {
+                        let (x, y) = match delta {
+                            MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+                            MouseScrollDelta::PixelDelta(position) => (position.x, position.y),
+                        };
+                        let key = wheel_key(x, y);
+                        match key.and_then(|key| bindings.get(&chord(key.to_string(), modifiers))) {
+                            Some(command) => command,
+                            None => return,
+                        }
+                    }
+                    Event::WindowEvent {
+                        event: WindowEvent::CloseRequested,
+                        ..
+                    } => PlayerCommand::Stop,
+                    Event::WindowEvent {
+                        event:
+                            WindowEvent::RedrawRequested
+                            | WindowEvent::Focused(true)
+                            | WindowEvent::Resized(_),
+                        ..
+                    } => {
+                        *exposed = true;
+                        return;
+                    }
+                    // holding shift queues the file instead of opening it
+                    Event::WindowEvent {
+                        event: WindowEvent::DroppedFile(path),
+                        ..
+                    } => {
+                        dropped.push(DroppedFile {
+                            path: path.to_string_lossy().into_owned(),
+                            append: modifiers.shift_key(),
+                        });
+                        return;
+                    }
+                    Event::WindowEvent {
+                        event: WindowEvent::ModifiersChanged(changed),
+                        ..
+                    } => {
+                        modifiers = changed.state();
+                        return;
+                    }
+                    Event::WindowEvent {
+                        event:
+                            WindowEvent::KeyboardInput {
+                                event:
+                                    KeyEvent {
+                                        logical_key,
+                                        state: ElementState::Pressed,
+                                        ..
+                                    },
+                                ..
+                            },
+                        ..
+                    } => {
+                        let key = match &logical_key {
+                            Key::Named(named) => match key_name(*named) {
+                                Some(name) => name.to_string(),
+                                None => return,
+                            },
+                            Key::Character(character) => character.to_lowercase(),
+                            _ => return,
+                        };
+                        match (bindings.get(&chord(key, modifiers)), &logical_key) {
+                            (Some(command), _) => command,
+                            (None, Key::Character(character)) => match character.chars().next() {
+                                Some(key) => PlayerCommand::Key(key),
+                                None => return,
+                            },
+                            (None, _) => return,
+                        }
+                    }
+                    _ => return,
+                };
+                commands.push(command);
+            });
+
+        self.cursor = cursor;
+        self.dragging = dragging;
+        self.modifiers = modifiers;
+    }
+
+    fn take_dropped_files(&mut self) -> Vec<DroppedFile> {
+        mem::take(&mut self.dropped)
+    }
+
+    fn take_exposed(&mut self) -> bool {
+        mem::take(&mut self.exposed)
+    }
+
+    fn set_key_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    fn set_aspect_mode(&mut self, mode: AspectMode) {
+        self.aspect_mode = mode;
+    }
+
+    fn set_on_top(&mut self, on_top: bool) {
+        if let Some(window) = &self.window {
+            window.set_window_level(if on_top {
+                WindowLevel::AlwaysOnTop
+            } else {
+                WindowLevel::Normal
+            });
+        }
+    }
+
+    fn set_title(&mut self, title: &str) {
+        if let Some(window) = &self.window {
+            window.set_title(title);
+        }
+    }
+
+    fn set_window_scale(&mut self, scale: f64) {
+        let (width, height) = match &self.frame {
+            Some(frame) => (
+                (frame.height() as f64 * display_aspect(frame)).round() as u32,
+                frame.height(),
+            ),
+            None => self.video_size,
+        };
+        let window = match &self.window {
+            Some(window) if window.fullscreen().is_none() => window,
+            _ => return,
+        };
+        let size = |pixels: u32| ((pixels as f64 * scale).round() as u32).max(1);
+        // the window manager may resize the window later, or not at all
+        let _ = window.request_inner_size(PhysicalSize::new(size(width), size(height)));
+    }
+
+    fn show_cursor(&mut self, show: bool) {
+        if let Some(window) = &self.window {
+            window.set_cursor_visible(show);
+        }
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        if let Some(window) = &self.window {
+            let fullscreen = match window.fullscreen() {
+                Some(_) => None,
+                None => Some(Fullscreen::Borderless(None)),
+            };
+            window.set_fullscreen(fullscreen);
+        }
+    }
+}
+
+/// `key` pressed with the modifiers held.
+fn chord(key: String, modifiers: ModifiersState) -> KeyChord {
+    KeyChord {
+        key,
+        ctrl: modifiers.control_key(),
+        alt: modifiers.alt_key(),
+        shift: modifiers.shift_key(),
+    }
+}
+
+/// SDL's name for `key`, which key bindings go by.
+fn key_name(key: NamedKey) -> Option<&'static str> {
+    Some(match key {
+        NamedKey::Escape => "escape",
+        NamedKey::Space => "space",
+        NamedKey::Tab => "tab",
+        NamedKey::Enter => "return",
+        NamedKey::Backspace => "backspace",
+        NamedKey::ArrowLeft => "left",
+        NamedKey::ArrowRight => "right",
+        NamedKey::ArrowUp => "up",
+        NamedKey::ArrowDown => "down",
+        NamedKey::Home => "home",
+        NamedKey::End => "end",
+        NamedKey::PageUp => "pageup",
+        NamedKey::PageDown => "pagedown",
+        NamedKey::MediaPlayPause => "audioplay",
+        NamedKey::MediaPlay => "mediaplay",
+        NamedKey::MediaPause => "mediapause",
+        NamedKey::MediaStop => "audiostop",
+        NamedKey::MediaTrackPrevious => "audioprev",
+        NamedKey::MediaTrackNext => "audionext",
+        NamedKey::MediaRewind => "audiorewind",
+        NamedKey::MediaFastForward => "audiofastforward",
+        _ => return None,
+    })
+}
+
+/// Scales `frame` to `width` x `height` BGR0, reusing the scaler while sizes stay the same.
+fn scale(
+    scaler: &mut Option<scaling::Context>,
+    frame: &Video,
+    width: u32,
+    height: u32,
+) -> Option<Video> {
+    let matches = scaler.as_ref().map_or(false, |scaler| {
+        let (input, output) = (scaler.input(), scaler.output());
+        input.format == frame.format()
+            && input.width == frame.width()
+            && input.height == frame.height()
+            && output.width == width
+            && output.height == height
+    });
+    if !matches {
+        *scaler = scaling::Context::get(
+            frame.format(),
+            frame.width(),
+            frame.height(),
+            Pixel::BGRZ,
+            width,
+            height,
+            Flags::BILINEAR,
+        )
+        .ok();
+    }
+
+    let mut picture = Video::empty();
+    scaler.as_mut()?.run(frame, &mut picture).ok()?;
+    Some(picture)
+}
+
+/// Composites the OSD overlay onto 0RGB pixels.
+fn blend(buffer: &mut [u32], overlay: &OsdImage) {
+    for (pixel, source) in buffer.iter_mut().zip(overlay.pixels().chunks_exact(4)) {
+        let alpha = source[3] as u32;
+        if alpha == 0 {
+            continue;
+        }
+
+        let below = *pixel;
+        let mix = |shift: u32, value: u8| {
+            let below = (below >> shift) & 0xff;
+            ((value as u32 * alpha + below * (255 - alpha)) / 255) << shift
+        };
+        *pixel = mix(16, source[0]) | mix(8, source[1]) | mix(0, source[2]);
+    }
+}
@@ -0,0 +1,163 @@
+use ffmpeg_next::{filter, format, format::Pixel, frame, ChannelLayout, Error, Rational, Rescale};
+
+use crate::options::VisualizerStyle;
+
+/// Size of the pictures drawn for inputs without video.
+pub const VISUALIZER_WIDTH: u32 = 1280;
+pub const VISUALIZER_HEIGHT: u32 = 720;
+
+/// Pictures drawn per second, for the waveform; the bars come with every FFT window.
+const VISUALIZER_FPS: u32 = 30;
+
+/// Draws decoded audio as video through an ffmpeg filtergraph, for inputs without a video
+/// stream: FFT bars with `showfreqs` or the waveform with `showwaves`.
+///
+/// Like `AudioFilter`, the graph is built from the first frame and rebuilt whenever the
+/// sample format changes, or after a seek. Output is YUV420P with timestamps in the audio's
+/// time base, so the pictures are presented along with the audio they were drawn from.
+pub struct Visualizer {
+    style: VisualizerStyle,
+    time_base: Rational,
+    graph: Option<filter::Graph>,
+    output_time_base: Rational,
+    input: Option<(format::Sample, u32, ChannelLayout)>,
+    failed: bool,
+}
+
+impl Visualizer {
+    /// `time_base` is that of the audio frames' timestamps, in seconds.
+    pub fn new(style: VisualizerStyle, time_base: f64) -> Self {
+        Visualizer {
+            style,
+            time_base: Rational::from(time_base),
+            graph: None,
+            output_time_base: Rational::from(time_base),
+            input: None,
+            failed: false,
+        }
+    }
+
+    /// Forgets the audio held for the next picture, for after a seek.
+    pub fn reset(&mut self) {
+        self.graph = None;
+    }
+
+    /// Feeds `frame` into the graph, returning the pictures that are ready.
+    pub fn draw(&mut self, frame: &frame::Audio) -> Vec<frame::Video> {
+        if self.failed || frame.rate() == 0 {
+            return Vec::new();
+        }
+
+        let layout = if frame.channel_layout().is_empty() {
+            ChannelLayout::default(frame.channels() as i32)
+        } else {
+            frame.channel_layout()
+        };
+        let input = (frame.format(), frame.rate(), layout);
+        if self.input != Some(input) {
+            self.graph = None;
+        }
+        if self.graph.is_none() {
+            match self.create_graph(input) {
+                Ok(graph) => {
+                    self.output_time_base = unsafe {
+                        Rational::from(ffmpeg_next::ffi::av_buffersink_get_time_base(
+                            graph.get("out").unwrap().as_ptr(),
+                        ))
+                    };
+                    self.graph = Some(graph);
+                    self.input = Some(input);
+                }
+                Err(e) => {
+                    eprintln!("failed to create visualizer: {}", e);
+                    self.failed = true;
+                    return Vec::new();
+                }
+            }
+        }
+        let graph = self.graph.as_mut().unwrap();
+
+        if graph.get("in").unwrap().source().add(frame).is_err() {
+            return Vec::new();
+        }
+
+        self.take_drawn()
+    }
+
+    /// Signals the end of the audio, returning the last pictures.
+    pub fn flush(&mut self) -> Vec<frame::Video> {
+        let graph = match &mut self.graph {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+        if graph.get("in").unwrap().source().flush().is_err() {
+            return Vec::new();
+        }
+        let frames = self.take_drawn();
+        // an ended graph takes no more frames, the next one builds a fresh graph
+        self.graph = None;
+        frames
+    }
+
+    fn take_drawn(&mut self) -> Vec<frame::Video> {
+        let graph = match &mut self.graph {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+
+        let mut frames = Vec::new();
+        let mut drawn = frame::Video::empty();
+        while graph.get("out").unwrap().sink().frame(&mut drawn).is_ok() {
+            if self.output_time_base != self.time_base {
+                let pts = drawn.pts();
+                drawn.set_pts(pts.map(|pts| pts.rescale(self.output_time_base, self.time_base)));
+            }
+            frames.push(drawn);
+            drawn = frame::Video::empty();
+        }
+        frames
+    }
+
+    fn description(&self) -> String {
+        let size = format!("{}x{}", VISUALIZER_WIDTH, VISUALIZER_HEIGHT);
+        match self.style {
+            VisualizerStyle::Bars => format!(
+                "showfreqs=s={}:mode=bar:fscale=log:ascale=log:win_size=2048:overlap=0.25",
+                size
+            ),
+            VisualizerStyle::Wave => format!(
+                "showwaves=s={}:mode=cline:rate={}:split_channels=1",
+                size, VISUALIZER_FPS
+            ),
+            // a still black picture, which still gives seeks a frame to land on
+            VisualizerStyle::Off => format!("showwaves=s={}:rate=1:colors=black", size),
+        }
+    }
+
+    fn create_graph(
+        &self,
+        (format, rate, layout): (format::Sample, u32, ChannelLayout),
+    ) -> Result<filter::Graph, Error> {
+        let mut graph = filter::Graph::new();
+
+        let args = format!(
+            "time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            self.time_base.numerator(),
+            self.time_base.denominator(),
+            rate,
+            format.name(),
+            layout.bits()
+        );
+        graph.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+        graph.add(&filter::find("buffersink").unwrap(), "out", "")?;
+        graph.get("out").unwrap().set_pixel_format(Pixel::YUV420P);
+
+        graph
+            .output("in", 0)?
+            .input("out", 0)?
+            .parse(&self.description())?;
+        graph.validate()?;
+
+        Ok(graph)
+    }
+}